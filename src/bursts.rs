@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use crate::frames::first_ascii;
+use crate::organize::collect_files;
+use crate::sidecar::sidecar_base;
+use crate::writer::WriteOptions;
+use crate::{get_exif_fields, json_sidecar_path, read_json_metadata, read_metadata_of_interest, write_json_metadata_with_redaction, Redaction, VendorDetails};
+
+// Cameras shoot bursts at 3 to 30 frames a second, so a second between frames is well above
+// their interval, and below the pause between two bursts.
+const DEFAULT_MAX_GAP_MILLISECONDS : i64 = 1000;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct BurstOptions {
+    // The largest gap between consecutive frames of one burst.
+    pub max_gap: chrono::Duration,
+}
+
+impl Default for BurstOptions {
+    fn default() -> Self {
+        BurstOptions { max_gap: chrono::Duration::milliseconds( DEFAULT_MAX_GAP_MILLISECONDS ) }
+    }
+}
+
+// Where a file is in the burst it was shot in, as recorded in its JSON sidecar.
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+pub struct SequenceMembership {
+    pub sequence_id: String,
+    // From 1.
+    pub position: usize,
+    pub length: usize,
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Burst {
+    // The time the burst started at and the name of its first file, such as
+    // "20190726T133000.250_IMG_0002". The camera serial is left out, so that it doesn't survive
+    // the redaction of sidecars.
+    pub sequence_id: String,
+    pub camera_serial: String,
+    // The capture time of the first frame, with its subsecond time.
+    pub start: chrono::NaiveDateTime,
+    // From the first frame to the last.
+    pub duration: chrono::Duration,
+    // The files of each frame, in the order they were shot. A RAW and the JPEG the camera wrote
+    // with it are one frame.
+    pub frames: Vec<Vec<PathBuf>>,
+}
+
+impl Burst {
+    // The membership of the files of the frame at the index in frames.
+    pub fn membership( &self, index: usize ) -> SequenceMembership {
+        SequenceMembership { sequence_id: self.sequence_id.clone(), position: index + 1, length: self.frames.len() }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct BurstReport {
+    pub files_scanned: usize,
+    pub bursts: Vec<Burst>,
+    // The files read which are in no burst, including those without a camera serial or a capture time.
+    pub single_files: Vec<PathBuf>,
+}
+
+pub type BurstFailure = (PathBuf, Box<dyn error::Error>);
+
+struct Frame {
+    // The files of the shot, such as a RAW and its JPEG.
+    paths: Vec<PathBuf>,
+    camera_serial: String,
+    time: chrono::NaiveDateTime,
+    // Whether the time has its subsecond part, rather than only the second the frame was taken in.
+    subsecond: bool,
+    sequence_number: Option<u16>,
+}
+
+// The fraction of a second SubSecTime gives: its digits follow the decimal point, so "25" is
+// 250 milliseconds.
+fn sub_sec_duration( sub_sec_time: &str ) -> Option<chrono::Duration> {
+    if sub_sec_time.is_empty() || !sub_sec_time.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let nanoseconds : String = sub_sec_time.chars().chain( std::iter::repeat( '0' ) ).take( 9 ).collect();
+    nanoseconds.parse::<i64>().ok().map(chrono::Duration::nanoseconds)
+}
+
+fn read_frame( path: &Path ) -> Result<Option<Frame>, Box<dyn error::Error>> {
+    let metadata = read_metadata_of_interest( path )?;
+    let (camera_serial, capture_time) = match (metadata.image_metadata.camera_serial, metadata.image_metadata.capture_time) {
+        (Some(camera_serial), Some(capture_time)) => (camera_serial, capture_time),
+        _ => return Ok(None),
+    };
+    let sub_sec_time = get_exif_fields( path ).ok()
+        .and_then(|exif_fields| first_ascii( &exif_fields, exif::Tag::SubSecTimeOriginal, exif::In::PRIMARY ))
+        .and_then(|sub_sec_time| sub_sec_duration( &sub_sec_time ));
+    let sequence_number = match metadata.vendor_metadata.and_then(|vendor_metadata| vendor_metadata.details) {
+        Some(VendorDetails::Canon(canon)) => canon.sequence_number,
+        _ => None,
+    };
+    Ok( Some( Frame {
+        paths: vec![path.to_path_buf()],
+        camera_serial,
+        time: capture_time + sub_sec_time.unwrap_or_else(chrono::Duration::zero),
+        subsecond: sub_sec_time.is_some(),
+        sequence_number,
+    } ) )
+}
+
+// Whether the frame was shot right after the previous one in the same burst. Without subsecond
+// times, frames are only known to the second, so the smallest gap they could have is used. Canon
+// cameras number the frames of a burst from 1: the numbers must go up for the burst to go on,
+// and consecutive numbers keep it going even when a full buffer slowed the camera down.
+fn continues_burst( previous: &Frame, frame: &Frame, max_gap: chrono::Duration ) -> bool {
+    if frame.camera_serial != previous.camera_serial {
+        return false;
+    }
+    let mut gap = frame.time - previous.time;
+    if !(frame.subsecond && previous.subsecond) {
+        gap = (gap - chrono::Duration::seconds( 1 )).max( chrono::Duration::zero() );
+    }
+    match (previous.sequence_number, frame.sequence_number) {
+        (Some(previous_number), Some(number)) => number > previous_number && (gap < max_gap || number == previous_number + 1),
+        (None, None) => gap < max_gap,
+        _ => false,
+    }
+}
+
+// Groups consecutive frames, from the files given and those in directories recursively, which
+// one camera shot in a burst. Frames from each camera are taken in capture time order. Files
+// with the same name but for the extension, from the same camera and capture time, are one
+// frame: cameras shooting RAW+JPEG write both for each shot.
+pub fn find_bursts( paths: &[PathBuf], options: &BurstOptions ) -> (BurstReport, Vec<BurstFailure>) {
+    let mut all_paths = vec![];
+    for path in paths.iter() {
+        collect_files( path, None, &mut all_paths );
+    }
+    let mut report = BurstReport::default();
+    let mut frames : BTreeMap<(String, chrono::NaiveDateTime, PathBuf), Frame> = BTreeMap::new();
+    let mut failures : Vec<BurstFailure> = vec![];
+    for path in all_paths {
+        match read_frame( &path ) {
+            Ok(Some(frame)) => {
+                report.files_scanned += 1;
+                let key = (frame.camera_serial.clone(), frame.time, path.with_extension( "" ));
+                match frames.get_mut( &key ) {
+                    Some(same_shot) => same_shot.paths.push( path ),
+                    None => { frames.insert( key, frame ); },
+                }
+            },
+            Ok(None) => {
+                report.files_scanned += 1;
+                report.single_files.push( path );
+            },
+            Err(boxed_err) => failures.push( (path, boxed_err) ),
+        }
+    }
+
+    let mut runs : Vec<Vec<Frame>> = vec![];
+    for mut frame in frames.into_values() {
+        frame.paths.sort();
+        match runs.last_mut() {
+            Some(run) if continues_burst( run.last().expect("runs are never empty"), &frame, options.max_gap ) => run.push( frame ),
+            _ => runs.push( vec![frame] ),
+        }
+    }
+    for run in runs {
+        if run.len() < 2 {
+            report.single_files.extend( run.into_iter().flat_map(|frame| frame.paths) );
+            continue;
+        }
+        let (first, last) = (&run[0], &run[run.len() - 1]);
+        let stem = first.paths[0].file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        report.bursts.push( Burst {
+            sequence_id: format!("{}_{}", first.time.format( "%Y%m%dT%H%M%S%.3f" ), stem),
+            camera_serial: first.camera_serial.clone(),
+            start: first.time,
+            duration: last.time - first.time,
+            frames: run.into_iter().map(|frame| frame.paths).collect(),
+        } );
+    }
+    report.single_files.sort();
+    (report, failures)
+}
+
+// Records the burst a file is in, or that it is in none, in its JSON sidecar, updating an existing
+// sidecar rather than rewriting it. A sidecar is only written when this changes what it says, and
+// returns whether it was, or with dry_run set, whether it would be.
+pub fn record_sequence( path: &Path, sequence: Option<&SequenceMembership>, options: &WriteOptions, redaction: &Redaction ) -> Result<bool, Box<dyn error::Error>> {
+    if sequence.is_none() {
+        // Only a sidecar which records a burst has anything to update.
+        let recorded = json_sidecar_path( path ).filter(|sidecar_path| sidecar_path.is_file())
+            .and_then(|sidecar_path| read_json_metadata( &sidecar_path.to_string_lossy() ).ok())
+            .and_then(|sidecar| sidecar.sequence);
+        if recorded.is_none() {
+            return Ok(false);
+        }
+    }
+    let (mut metadata, sidecar_path) = sidecar_base( path, &read_metadata_of_interest( path )? )?;
+    if metadata.sequence.as_ref() == sequence {
+        return Ok(false);
+    }
+    if !options.dry_run {
+        metadata.sequence = sequence.cloned();
+        if let Some(json_path) = sidecar_path.or_else(|| json_sidecar_path( path )) {
+            write_json_metadata_with_redaction( &metadata, &json_path, redaction )?;
+        }
+    }
+    Ok(true)
+}
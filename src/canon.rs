@@ -0,0 +1,124 @@
+use serde::{Serialize, Deserialize};
+
+use crate::makernote::{self, MakerNote, VendorDetails, VendorMetadataOfInterest};
+
+const TAG_CAMERA_SETTINGS : u16 = 0x0001;
+const TAG_SHOT_INFO : u16 = 0x0004;
+const TAG_FIRMWARE_VERSION : u16 = 0x0007;
+const TAG_FILE_NUMBER : u16 = 0x0008;
+const TAG_OWNER_NAME : u16 = 0x0009;
+const TAG_CAMERA_INFO : u16 = 0x000D;
+const TAG_AF_INFO_2 : u16 = 0x0026;
+const TAG_LENS_MODEL : u16 = 0x0095;
+const TAG_INTERNAL_SERIAL_NUMBER : u16 = 0x0096;
+
+// Index of LensType in the CameraSettings array.
+const CAMERA_SETTINGS_LENS_TYPE : usize = 22;
+// Index of SequenceNumber in the ShotInfo array.
+const SHOT_INFO_SEQUENCE_NUMBER : usize = 9;
+// AFInfo2 starts with: size, area mode, number of points, valid points, and four image dimensions,
+// followed by the widths, heights, x and y positions of each point, then the in-focus bitmask.
+const AF_INFO_HEADER_LENGTH : usize = 8;
+const AF_INFO_ARRAYS_PER_POINT : usize = 4;
+
+// Where the shutter count sits in the CameraInfo record, for the bodies that record it there.
+// CameraInfo is a model-specific binary record, and most bodies, including the 5D Mark IV,
+// don't keep the shutter count in it at all.
+const CAMERA_INFO_SHUTTER_COUNT : [(&str, usize); 1] = [
+    ("Canon EOS-1D Mark III", 0x0176),
+];
+
+// Canon-specific fields, beyond those every vendor reports.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct CanonMakerNote {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_number: Option<u32>,
+    // Canon's LensType number, which identifies the lens model (and can be shared by third-party lenses).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lens_id: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub af_area_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub af_points: Option<u16>,
+    // Indices of the AF points which were in focus.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub af_points_in_focus: Vec<u16>,
+    // The position of the frame in a continuous-shooting burst, from 1; absent for single shots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence_number: Option<u16>,
+}
+
+fn af_area_mode_name( mode: u32 ) -> String {
+    match mode {
+        0 => "manual_focus".to_string(),
+        1 => "af_point_expansion_surround".to_string(),
+        2 => "single_point".to_string(),
+        4 => "auto".to_string(),
+        5 => "face_detect".to_string(),
+        6 => "face_tracking".to_string(),
+        7 => "zone".to_string(),
+        8 => "af_point_expansion_4_point".to_string(),
+        9 => "spot".to_string(),
+        10 => "af_point_expansion_8_point".to_string(),
+        11 => "flexizone_multi_49_point".to_string(),
+        12 => "flexizone_multi_9_point".to_string(),
+        13 => "flexizone_single".to_string(),
+        14 => "large_zone".to_string(),
+        other => format!("unknown_{}", other),
+    }
+}
+
+fn shutter_count( maker_note: &MakerNote, model: Option<&str> ) -> Option<u32> {
+    let model = model?;
+    let (_, offset) = CAMERA_INFO_SHUTTER_COUNT.iter().find(|(name, _)| *name == model)?;
+    let camera_info = maker_note.entry( TAG_CAMERA_INFO )?.value_bytes( maker_note.data, maker_note.order )?;
+    maker_note.order.u32( camera_info, *offset )
+}
+
+// Decodes the AF area mode, the number of AF points and which of them were in focus from AFInfo2.
+fn decode_af_info( maker_note: &MakerNote, decoded: &mut CanonMakerNote ) {
+    let af_info = match maker_note.uints( TAG_AF_INFO_2 ) {
+        Some(af_info) if af_info.len() >= AF_INFO_HEADER_LENGTH => af_info,
+        _ => return,
+    };
+    let points = af_info[2] as usize;
+    decoded.af_area_mode = Some( af_area_mode_name( af_info[1] ) );
+    decoded.af_points = Some( points as u16 );
+
+    let in_focus_start = AF_INFO_HEADER_LENGTH + AF_INFO_ARRAYS_PER_POINT * points;
+    let in_focus_words = points.div_ceil( 16 );
+    if let Some(bitmask) = af_info.get(in_focus_start..in_focus_start + in_focus_words) {
+        decoded.af_points_in_focus = (0..points)
+            .filter(|point| bitmask[point / 16] & (1 << (point % 16)) != 0)
+            .map(|point| point as u16)
+            .collect();
+    }
+}
+
+pub(crate) fn decode_maker_note( maker_note: &MakerNote, exif_fields: &exif::Exif ) -> VendorMetadataOfInterest {
+    let model = makernote::exif_ascii( exif_fields, exif::Tag::Model );
+    let mut details = CanonMakerNote {
+        owner_name: maker_note.ascii( TAG_OWNER_NAME ),
+        file_number: maker_note.uints( TAG_FILE_NUMBER ).and_then(|values| values.first().cloned()),
+        lens_id: maker_note.uints( TAG_CAMERA_SETTINGS )
+            .and_then(|settings| settings.get(CAMERA_SETTINGS_LENS_TYPE).map(|lens_type| *lens_type as u16))
+            .filter(|lens_type| *lens_type != 0 && *lens_type != 0xFFFF),
+        sequence_number: maker_note.uints( TAG_SHOT_INFO )
+            .and_then(|shot_info| shot_info.get(SHOT_INFO_SEQUENCE_NUMBER).map(|sequence_number| *sequence_number as u16))
+            .filter(|sequence_number| *sequence_number != 0),
+        ..Default::default()
+    };
+    decode_af_info( maker_note, &mut details );
+
+    VendorMetadataOfInterest {
+        make: "canon".to_string(),
+        shutter_count: shutter_count( maker_note, model.as_deref() ),
+        lens: maker_note.ascii( TAG_LENS_MODEL ),
+        internal_serial: maker_note.ascii( TAG_INTERNAL_SERIAL_NUMBER ),
+        firmware_version: maker_note.ascii( TAG_FIRMWARE_VERSION ),
+        details: Some( VendorDetails::Canon( details ) ),
+    }
+}
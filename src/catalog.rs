@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+
+use crate::organize::collect_files;
+use crate::redact::{is_redacted_value, mark_redacted, Redaction};
+use crate::writer::replace_file;
+use crate::{read_metadata_of_interest_with_options, MetadataOfInterest, ReadOptions};
+
+// Version of the catalog file layout, raised whenever it changes in a way older versions can't read.
+// Version 1 catalogs were a single JSON object holding every entry, and are still loaded.
+const CATALOG_VERSION : u32 = 2;
+const SINGLE_OBJECT_VERSION : u32 = 1;
+
+// The metadata of many files, keyed by their absolute path, so that a refresh only reads the
+// files which are new or changed since. The file is JSON Lines: a header with the version, then
+// a record per file, {"path": ..., "entry": ...} or {"path": ..., "removed": true}, the last
+// record for a path being the one which counts. Saving appends the records of the entries which
+// changed, so keeping a large catalog up to date doesn't rewrite it, and the file is only written
+// whole once most of its records are out of date. Each line is plain JSON like the sidecars, so
+// other tools can read it without this library.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Catalog {
+    entries: BTreeMap<PathBuf, MetadataOfInterest>,
+    // Entries which were loaded already redacted, so must not be redacted again when saved.
+    redacted: BTreeSet<PathBuf>,
+    // Paths whose entries were added, updated or removed since the catalog was loaded or saved.
+    changed: BTreeSet<PathBuf>,
+    // The file the catalog was loaded from or saved to, if records can be appended to it.
+    journal: Option<Journal>,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+struct Journal {
+    path: PathBuf,
+    // Records in the file, including those a later one replaced.
+    records: usize,
+    // Whether its header marks it as redacted.
+    redacted: bool,
+}
+
+// What a refresh did to the catalog.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct RefreshReport {
+    pub added: Vec<PathBuf>,
+    pub updated: Vec<PathBuf>,
+    pub unchanged: usize,
+    pub removed: Vec<PathBuf>,
+}
+
+pub type CatalogFailure = (PathBuf, Box<dyn error::Error>);
+
+#[derive(Debug, Clone)]
+pub struct CatalogVersionError {
+    path: PathBuf,
+    version: u32,
+}
+
+impl fmt::Display for CatalogVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Catalog {} has version {}, but only version {} is supported", self.path.to_string_lossy(), self.version, CATALOG_VERSION)
+    }
+}
+
+impl Error for CatalogVersionError {
+    fn description(&self) -> &str {
+        "A catalog was written by an incompatible version"
+    }
+}
+
+// The absolute form of a path, with symbolic links resolved if it still exists.
+fn absolute_path( path: &Path ) -> PathBuf {
+    fs::canonicalize( path ).or_else(|_| std::path::absolute( path )).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Whether the entry was read from the file as it is now, with the same options.
+fn is_current( entry: &MetadataOfInterest, file_metadata: &fs::Metadata, options: &ReadOptions ) -> bool {
+    entry.file_metadata.size == file_metadata.len()
+        && entry.file_metadata.modified_time == file_metadata.modified().ok().map(chrono::DateTime::<Utc>::from)
+        && entry.frames.is_some() == options.include_frames
+        && entry.file_metadata.hashes.as_ref().map(|hashes| hashes.algorithm) == options.hash
+}
+
+impl Catalog {
+    // Reads a catalog file; a file which doesn't exist yet is an empty catalog.
+    // A line cut short, as when appending was interrupted, is left out, and the file is written
+    // whole on the next save.
+    pub fn load( path: &Path ) -> Result<Catalog, Box<dyn error::Error>> {
+        let mut catalog = Catalog::default();
+        if !path.exists() {
+            return Ok(catalog);
+        }
+        let text = fs::read_to_string( path )?;
+        let mut lines = text.split_inclusive( '\n' );
+        let first_line = lines.next().unwrap_or("{}");
+        let header : serde_json::Value = match serde_json::from_str( &text ) {
+            Ok(single_object) => single_object,
+            Err(_) => serde_json::from_str( first_line )?,
+        };
+        let version = header["version"].as_u64().unwrap_or(0) as u32;
+        if version == SINGLE_OBJECT_VERSION {
+            for (entry_path, entry) in header["entries"].as_object().into_iter().flatten() {
+                catalog.insert_record( PathBuf::from( entry_path ), entry )?;
+            }
+            return Ok(catalog);
+        }
+        if version != CATALOG_VERSION {
+            return Err( Box::new( CatalogVersionError { path: path.to_path_buf(), version } ) );
+        }
+        let mut journal = Journal { path: path.to_path_buf(), records: 0, redacted: is_redacted_value( &header ) };
+        for line in lines {
+            if !line.ends_with( '\n' ) {
+                return Ok(catalog);
+            }
+            let record : serde_json::Value = serde_json::from_str( line )?;
+            let entry_path = PathBuf::from( record["path"].as_str().unwrap_or_default() );
+            match record.get( "entry" ) {
+                Some(entry) => catalog.insert_record( entry_path, entry )?,
+                None => {
+                    catalog.entries.remove( &entry_path );
+                    catalog.redacted.remove( &entry_path );
+                },
+            }
+            journal.records += 1;
+        }
+        catalog.journal = Some(journal);
+        Ok(catalog)
+    }
+
+    fn insert_record( &mut self, entry_path: PathBuf, entry: &serde_json::Value ) -> Result<(), Box<dyn error::Error>> {
+        if is_redacted_value( entry ) {
+            self.redacted.insert( entry_path.clone() );
+        } else {
+            self.redacted.remove( &entry_path );
+        }
+        self.entries.insert( entry_path, serde_json::from_value( entry.clone() )? );
+        Ok(())
+    }
+
+    // Writes the changes to the catalog file.
+    pub fn save( &mut self, path: &Path ) -> Result<(), Box<dyn error::Error>> {
+        self.save_with_redaction( path, &Redaction::default() )
+    }
+
+    // Writes the changes to the catalog file, with each entry redacted as its sidecar would be.
+    // The records of changed entries are appended to the file the catalog came from. Otherwise,
+    // or when that would leave more records out of date than up to date, or the redaction marker
+    // of the header has to change, the file is replaced only once the whole catalog is written.
+    // The catalog is marked as redacted when any entry is, including those loaded redacted.
+    pub fn save_with_redaction( &mut self, path: &Path, redaction: &Redaction ) -> Result<(), Box<dyn error::Error>> {
+        let redacted = !redaction.is_empty() || !self.redacted.is_empty();
+        let append = self.journal.as_ref().is_some_and(|journal| {
+            journal.path == path && journal.redacted == redacted && path.is_file()
+                && journal.records + self.changed.len() <= 2 * self.entries.len()
+        });
+        let mut contents = vec![];
+        let records = if append {
+            for entry_path in self.changed.iter() {
+                self.write_record( &mut contents, entry_path, redaction )?;
+            }
+            fs::OpenOptions::new().append( true ).open( path )?.write_all( &contents )?;
+            self.journal.as_ref().map(|journal| journal.records).unwrap_or(0) + self.changed.len()
+        } else {
+            let mut header = serde_json::json!({ "version": CATALOG_VERSION });
+            if redacted {
+                mark_redacted( &mut header );
+            }
+            serde_json::to_writer( &mut contents, &header )?;
+            contents.push( b'\n' );
+            for entry_path in self.entries.keys() {
+                self.write_record( &mut contents, entry_path, redaction )?;
+            }
+            replace_file( path, &contents )?;
+            self.entries.len()
+        };
+        self.journal = Some( Journal { path: path.to_path_buf(), records, redacted } );
+        self.changed.clear();
+        Ok(())
+    }
+
+    // The line recording the entry for the path, or that it was removed.
+    fn write_record( &self, contents: &mut Vec<u8>, entry_path: &Path, redaction: &Redaction ) -> Result<(), Box<dyn error::Error>> {
+        let path_string = entry_path.to_string_lossy();
+        let record = match self.entries.get( entry_path ) {
+            Some(metadata) if self.redacted.contains( entry_path ) => {
+                let mut entry = serde_json::to_value( metadata )?;
+                mark_redacted( &mut entry );
+                serde_json::json!({ "path": path_string, "entry": entry })
+            },
+            Some(metadata) => serde_json::json!({ "path": path_string, "entry": redaction.to_json_value( metadata )? }),
+            None => serde_json::json!({ "path": path_string, "removed": true }),
+        };
+        serde_json::to_writer( &mut *contents, &record )?;
+        contents.push( b'\n' );
+        Ok(())
+    }
+
+    pub fn len( &self ) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty( &self ) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get( &self, path: &Path ) -> Option<&MetadataOfInterest> {
+        self.entries.get( &absolute_path( path ) )
+    }
+
+    // Every entry, in path order.
+    pub fn entries( &self ) -> impl Iterator<Item = (&Path, &MetadataOfInterest)> {
+        self.entries.iter().map(|(path, metadata)| (path.as_path(), metadata))
+    }
+
+    // The entries the predicate holds for, in path order.
+    pub fn find<F: Fn( &Path, &MetadataOfInterest ) -> bool>( &self, predicate: F ) -> Vec<(&Path, &MetadataOfInterest)> {
+        self.entries().filter(|(path, metadata)| predicate( path, metadata )).collect()
+    }
+
+    // Brings the entries for the files given, and those in directories recursively, up to date:
+    // files whose size or modification time changed since they were read, or which were read with
+    // other options, are read again, and entries under the paths for files which no longer exist
+    // are removed. The entry of a file which can't be read any more is removed as well.
+    pub fn refresh( &mut self, paths: &[PathBuf], options: &ReadOptions ) -> (RefreshReport, Vec<CatalogFailure>) {
+        let roots : Vec<PathBuf> = paths.iter().map(|path| absolute_path( path )).collect();
+        let mut files = vec![];
+        for root in roots.iter() {
+            collect_files( root, None, &mut files );
+        }
+        let mut report = RefreshReport::default();
+        let mut failures : Vec<CatalogFailure> = vec![];
+        let mut present = BTreeSet::new();
+        for file in files {
+            let file_metadata = match fs::metadata( &file ) {
+                Ok(file_metadata) => file_metadata,
+                Err(_) => continue,
+            };
+            present.insert( file.clone() );
+            if self.entries.get( &file ).is_some_and(|entry| is_current( entry, &file_metadata, options )) {
+                report.unchanged += 1;
+                continue;
+            }
+            match read_metadata_of_interest_with_options( &file, options ) {
+                Ok(metadata) => {
+                    self.redacted.remove( &file );
+                    self.changed.insert( file.clone() );
+                    match self.entries.insert( file.clone(), metadata ) {
+                        Some(_) => report.updated.push( file ),
+                        None => report.added.push( file ),
+                    }
+                },
+                Err(boxed_err) => {
+                    if self.entries.remove( &file ).is_some() {
+                        self.changed.insert( file.clone() );
+                    }
+                    self.redacted.remove( &file );
+                    failures.push( (file, boxed_err) );
+                },
+            }
+        }
+        report.removed = self.entries.keys()
+            .filter(|path| !present.contains( *path ) && roots.iter().any(|root| path.starts_with( root )))
+            .cloned()
+            .collect();
+        for path in report.removed.iter() {
+            self.entries.remove( path );
+            self.redacted.remove( path );
+            self.changed.insert( path.clone() );
+        }
+        (report, failures)
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{read_metadata_of_interest, MetadataOfInterest};
+
+const SECONDS_PER_DAY : f64 = 86400.0;
+
+// Two files of the same moment, taken by different cameras.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct ReferencePair {
+    pub first: PathBuf,
+    pub second: PathBuf,
+}
+
+// How to bring the clocks of several cameras into line with one of them.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct ClockSync {
+    pub pairs: Vec<ReferencePair>,
+    // The camera whose clock is taken as right; the camera of the first file of the first pair if None.
+    pub reference_serial: Option<String>,
+    // Write the corrected times into the files, rather than only into the JSON sidecars.
+    pub write_back: bool,
+}
+
+// The correction for one camera: the offset at the anchor time, which changes linearly with the
+// camera's time when it was fitted from pairs taken at different times.
+#[derive(Debug,Clone,PartialEq)]
+pub struct ClockCorrection {
+    pub camera_serial: String,
+    // The camera time at the middle of the reference pairs.
+    pub anchor: chrono::NaiveDateTime,
+    pub offset: chrono::Duration,
+    pub drift_seconds_per_day: f64,
+}
+
+impl ClockCorrection {
+    // The time on the reference camera's clock for a time on this camera's clock, to the second.
+    pub fn correct( &self, camera_time: chrono::NaiveDateTime ) -> chrono::NaiveDateTime {
+        let days_from_anchor = (camera_time - self.anchor).num_seconds() as f64 / SECONDS_PER_DAY;
+        let drift = (self.drift_seconds_per_day * days_from_anchor).round() as i64;
+        camera_time + self.offset + chrono::Duration::seconds( drift )
+    }
+}
+
+impl fmt::Display for ClockCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.offset.num_seconds();
+        let sign = if seconds < 0 { '-' } else { '+' };
+        let seconds = seconds.abs();
+        write!(f, "{}: {}{}:{:02}:{:02} at {}, drifting {:+.1}s per day",
+            self.camera_serial, sign, seconds / 3600, seconds / 60 % 60, seconds % 60, self.anchor, self.drift_seconds_per_day)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClockSyncError {
+    reason: String,
+}
+
+impl fmt::Display for ClockSyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Couldn't synchronize camera clocks: {}", self.reason)
+    }
+}
+
+impl Error for ClockSyncError {
+    fn description(&self) -> &str {
+        "Camera clock offsets could not be worked out from the reference pairs"
+    }
+}
+
+fn sync_error( reason: String ) -> Box<dyn error::Error> {
+    Box::new( ClockSyncError { reason } )
+}
+
+// The camera serial and capture time of one file of a reference pair.
+fn reference_moment( path: &Path ) -> Result<(String, chrono::NaiveDateTime), Box<dyn error::Error>> {
+    let metadata = read_metadata_of_interest( path )?;
+    let image_metadata = metadata.image_metadata;
+    match (image_metadata.camera_serial, image_metadata.capture_time) {
+        (Some(camera_serial), Some(capture_time)) => Ok( (camera_serial.trim().to_string(), capture_time) ),
+        _ => Err( sync_error( format!("{} has no camera serial or capture time", path.to_string_lossy()) ) ),
+    }
+}
+
+// Fits offset = a + b * (time - anchor) by least squares to (camera time, offset in seconds) samples.
+fn fit_correction( camera_serial: &str, samples: &[(chrono::NaiveDateTime, f64)] ) -> ClockCorrection {
+    let earliest = samples.iter().map(|(time, _)| *time).min().unwrap_or_default();
+    let xs : Vec<f64> = samples.iter().map(|(time, _)| (*time - earliest).num_seconds() as f64).collect();
+    let count = samples.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / count;
+    let mean_y = samples.iter().map(|(_, offset)| offset).sum::<f64>() / count;
+    let spread : f64 = xs.iter().map(|x| (x - mean_x).powi( 2 )).sum();
+    let slope = if spread > 0.0 {
+        xs.iter().zip( samples ).map(|(x, (_, y))| (x - mean_x) * (y - mean_y)).sum::<f64>() / spread
+    } else {
+        0.0
+    };
+    ClockCorrection {
+        camera_serial: camera_serial.to_string(),
+        anchor: earliest + chrono::Duration::seconds( mean_x.round() as i64 ),
+        offset: chrono::Duration::seconds( mean_y.round() as i64 ),
+        drift_seconds_per_day: slope * SECONDS_PER_DAY,
+    }
+}
+
+// Works out a correction for every camera which the reference pairs connect to the reference
+// camera, directly or through other cameras. The reference camera itself gets none.
+pub fn compute_clock_corrections( sync: &ClockSync ) -> Result<Vec<ClockCorrection>, Box<dyn error::Error>> {
+    let mut moments = vec![];
+    for pair in sync.pairs.iter() {
+        let first = reference_moment( &pair.first )?;
+        let second = reference_moment( &pair.second )?;
+        if first.0 == second.0 {
+            return Err( sync_error( format!("{} and {} are from the same camera", pair.first.to_string_lossy(), pair.second.to_string_lossy()) ) );
+        }
+        moments.push( (first, second) );
+    }
+    let reference_serial = match (&sync.reference_serial, moments.first()) {
+        (Some(reference_serial), _) => reference_serial.trim().to_string(),
+        (None, Some(((first_serial, _), _))) => first_serial.clone(),
+        (None, None) => return Err( sync_error( "no reference pairs were given".to_string() ) ),
+    };
+
+    // Resolve the cameras one step away from those already resolved, until no more can be.
+    let mut corrections : BTreeMap<String, Option<ClockCorrection>> = BTreeMap::new();
+    corrections.insert( reference_serial.clone(), None );
+    loop {
+        let mut samples : BTreeMap<&String, Vec<(chrono::NaiveDateTime, f64)>> = BTreeMap::new();
+        for (first, second) in moments.iter() {
+            for ((known_serial, known_time), (serial, time)) in [(first, second), (second, first)].iter() {
+                if let (Some(known), false) = (corrections.get( known_serial ), corrections.contains_key( serial )) {
+                    let true_time = known.as_ref().map_or( *known_time, |correction| correction.correct( *known_time ) );
+                    samples.entry( serial ).or_default().push( (*time, (true_time - *time).num_seconds() as f64) );
+                }
+            }
+        }
+        if samples.is_empty() {
+            break;
+        }
+        let fitted : Vec<ClockCorrection> = samples.iter().map(|(serial, samples)| fit_correction( serial, samples )).collect();
+        for correction in fitted {
+            corrections.insert( correction.camera_serial.clone(), Some(correction) );
+        }
+    }
+
+    for (first, second) in moments.iter() {
+        for (serial, _) in [first, second].iter() {
+            if !corrections.contains_key( serial ) {
+                return Err( sync_error( format!("no reference pairs connect camera {} to camera {}", serial, reference_serial) ) );
+            }
+        }
+    }
+    Ok( corrections.into_values().flatten().collect() )
+}
+
+// The capture time of a file on the reference camera's clock. Files from cameras without a
+// correction, including the reference camera, keep their capture time.
+pub fn synchronized_capture_time( metadata: &MetadataOfInterest, corrections: &[ClockCorrection] ) -> Option<chrono::NaiveDateTime> {
+    let capture_time = metadata.image_metadata.capture_time?;
+    let camera_serial = metadata.image_metadata.camera_serial.as_deref().map(|serial| serial.trim());
+    let correction = corrections.iter().find(|correction| Some(correction.camera_serial.as_str()) == camera_serial);
+    Some( correction.map_or( capture_time, |correction| correction.correct( capture_time ) ) )
+}
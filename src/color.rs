@@ -0,0 +1,242 @@
+use std::convert::TryInto;
+use std::error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::format::MediaFormat;
+use crate::jpeg;
+
+const ICC_PROFILE_IDENTIFIER : &[u8] = b"ICC_PROFILE\0";
+// TIFF InterColorProfile tag, which holds a complete ICC profile.
+const TAG_INTER_COLOR_PROFILE : u16 = 0x8773;
+const ICC_HEADER_LENGTH : usize = 128;
+const ICC_SIGNATURE : &[u8] = b"acsp";
+
+// EXIF ColorSpace values. 2 is not in the standard, but some cameras write it for Adobe RGB.
+const EXIF_COLOR_SPACE_SRGB : u32 = 1;
+const EXIF_COLOR_SPACE_ADOBE_RGB : u32 = 2;
+const EXIF_COLOR_SPACE_UNCALIBRATED : u32 = 0xFFFF;
+// DCF files in Adobe RGB set ColorSpace to uncalibrated and mark the Interoperability IFD "R03".
+const INTEROPERABILITY_INDEX_ADOBE_RGB : &str = "R03";
+
+// The color space of a file, from the EXIF ColorSpace tag and any embedded ICC profile,
+// with any disagreement between the two.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct ColorMetadataOfInterest {
+    // "srgb", "adobe_rgb" or "uncalibrated", from the EXIF ColorSpace tag.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exif_color_space: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_description: Option<String>,
+    // The ICC data color space, such as "RGB", "GRAY" or "CMYK".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_color_space: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rendering_intent: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+// The parts of an ICC profile header and tag table which are reported.
+#[derive(Debug,Clone,PartialEq,Eq)]
+struct IccProfileSummary {
+    description: Option<String>,
+    color_space: String,
+    version: String,
+    rendering_intent: String,
+}
+
+fn u32_at( data: &[u8], offset: usize ) -> Option<u32> {
+    Some( u32::from_be_bytes( data.get(offset..offset + 4)?.try_into().ok()? ) )
+}
+
+fn rendering_intent_name( intent: u32 ) -> String {
+    match intent {
+        0 => "perceptual".to_string(),
+        1 => "relative_colorimetric".to_string(),
+        2 => "saturation".to_string(),
+        3 => "absolute_colorimetric".to_string(),
+        other => format!("unknown_{}", other),
+    }
+}
+
+// Reads the text of a 'desc' tag: textDescriptionType in version 2 profiles, or
+// multiLocalizedUnicodeType in version 4, where the first record is used.
+fn read_description( tag: &[u8] ) -> Option<String> {
+    let text = if tag.starts_with( b"desc" ) {
+        let length = u32_at( tag, 8 )? as usize;
+        let ascii = tag.get(12..12usize.checked_add( length )?)?;
+        String::from_utf8_lossy( ascii ).to_string()
+    } else if tag.starts_with( b"mluc" ) {
+        if u32_at( tag, 8 )? == 0 {
+            return None;
+        }
+        let length = u32_at( tag, 16 + 4 )? as usize;
+        let offset = u32_at( tag, 16 + 8 )? as usize;
+        let utf16 = tag.get(offset..offset.checked_add( length )?)?;
+        let units : Vec<u16> = utf16.chunks_exact( 2 ).map(|unit| u16::from_be_bytes( [unit[0], unit[1]] )).collect();
+        String::from_utf16_lossy( &units )
+    } else {
+        return None;
+    };
+    let text = text.trim_end_matches( '\0' ).trim().to_string();
+    if text.is_empty() { None } else { Some(text) }
+}
+
+fn parse_icc_profile( profile: &[u8] ) -> Option<IccProfileSummary> {
+    if profile.len() < ICC_HEADER_LENGTH || profile.get(36..40)? != ICC_SIGNATURE {
+        return None;
+    }
+    let version = format!("{}.{}.{}", profile[8], profile[9] >> 4, profile[9] & 0x0F);
+    let color_space = String::from_utf8_lossy( &profile[16..20] ).trim().to_string();
+    let rendering_intent = rendering_intent_name( u32_at( profile, 64 )? );
+
+    let tag_count = u32_at( profile, ICC_HEADER_LENGTH )? as usize;
+    let description = (0..tag_count)
+        .map(|index| ICC_HEADER_LENGTH + 4 + index * 12)
+        .take_while(|entry| entry + 12 <= profile.len())
+        .find(|entry| &profile[*entry..*entry + 4] == b"desc")
+        .and_then(|entry| {
+            let offset = u32_at( profile, entry + 4 )? as usize;
+            let size = u32_at( profile, entry + 8 )? as usize;
+            read_description( profile.get(offset..offset.checked_add( size )?)? )
+        });
+
+    Some( IccProfileSummary { description, color_space, version, rendering_intent } )
+}
+
+// Reassembles an ICC profile split across APP2 "ICC_PROFILE" segments. Each chunk starts with
+// its 1-based sequence number and the total number of chunks, and they need not be in order.
+fn jpeg_icc_profile( header: &[u8] ) -> Result<Option<Vec<u8>>, &'static str> {
+    let segments = match jpeg::header_segments( header ) {
+        Some(segments) => segments,
+        None => return Ok(None),
+    };
+    let mut chunks : Vec<(u8, u8, &[u8])> = segments.iter()
+        .filter(|segment| segment.marker == jpeg::MARKER_APP2)
+        .map(|segment| &header[segment.payload.clone()])
+        .filter(|payload| payload.starts_with( ICC_PROFILE_IDENTIFIER ) && payload.len() >= ICC_PROFILE_IDENTIFIER.len() + 2)
+        .map(|payload| {
+            let start = ICC_PROFILE_IDENTIFIER.len();
+            (payload[start], payload[start + 1], &payload[start + 2..])
+        })
+        .collect();
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+    chunks.sort_by_key(|(sequence, _, _)| *sequence);
+
+    let total = chunks[0].1 as usize;
+    let complete = chunks.len() == total && chunks.iter().enumerate().all(|(index, (sequence, _, _))| *sequence as usize == index + 1);
+    if !complete {
+        return Err("embedded ICC profile is missing chunks");
+    }
+    Ok( Some( chunks.into_iter().flat_map(|(_, _, data)| data.iter().cloned()).collect() ) )
+}
+
+fn tiff_icc_profile( exif_fields: &exif::Exif ) -> Option<Vec<u8>> {
+    let tag = exif::Tag(exif::Context::Tiff, TAG_INTER_COLOR_PROFILE);
+    match &exif_fields.get_field( tag, exif::In::PRIMARY )?.value {
+        exif::Value::Undefined(bytes, _) => Some(bytes.clone()),
+        exif::Value::Byte(bytes) => Some(bytes.clone()),
+        _ => None,
+    }
+}
+
+fn exif_color_space( exif_fields: &exif::Exif ) -> Option<String> {
+    let color_space = exif_fields.get_field( exif::Tag::ColorSpace, exif::In::PRIMARY )?.value.get_uint( 0 )?;
+    let interoperability_index = exif_fields.get_field( exif::Tag::InteroperabilityIndex, exif::In::PRIMARY )
+        .map(|field| field.display_value().to_string().trim_matches('"').to_string());
+    match color_space {
+        EXIF_COLOR_SPACE_SRGB => Some("srgb".to_string()),
+        EXIF_COLOR_SPACE_ADOBE_RGB => Some("adobe_rgb".to_string()),
+        EXIF_COLOR_SPACE_UNCALIBRATED if interoperability_index.as_deref() == Some(INTEROPERABILITY_INDEX_ADOBE_RGB) => Some("adobe_rgb".to_string()),
+        EXIF_COLOR_SPACE_UNCALIBRATED => Some("uncalibrated".to_string()),
+        _ => None,
+    }
+}
+
+fn is_srgb_description( description: &str ) -> bool {
+    let description = description.to_lowercase();
+    description.contains( "srgb" ) || description.contains( "61966-2" )
+}
+
+fn is_adobe_rgb_description( description: &str ) -> bool {
+    let description = description.to_lowercase();
+    description.contains( "adobe rgb" ) || description.contains( "adobergb" )
+}
+
+// Compares the EXIF ColorSpace with the embedded profile. A file which claims sRGB but
+// carries another profile is displayed and printed differently depending on the software.
+fn find_inconsistencies( color: &mut ColorMetadataOfInterest ) {
+    let description = color.profile_description.clone();
+    match (color.exif_color_space.as_deref(), description.as_deref()) {
+        (Some("srgb"), Some(description)) if !is_srgb_description( description ) => {
+            color.warnings.push( format!("EXIF ColorSpace is sRGB but the embedded ICC profile is \"{}\"", description) );
+        },
+        (Some("adobe_rgb"), Some(description)) if !is_adobe_rgb_description( description ) => {
+            color.warnings.push( format!("EXIF ColorSpace is Adobe RGB but the embedded ICC profile is \"{}\"", description) );
+        },
+        (Some("uncalibrated"), None) if color.profile_color_space.is_none() => {
+            color.warnings.push( "EXIF ColorSpace is uncalibrated and no ICC profile is embedded".to_string() );
+        },
+        _ => {},
+    }
+    if color.exif_color_space.as_deref() == Some("srgb") {
+        if let Some(color_space) = &color.profile_color_space {
+            if color_space != "RGB" {
+                color.warnings.push( format!("EXIF ColorSpace is sRGB but the embedded ICC profile is for {} data", color_space) );
+            }
+        }
+    }
+}
+
+// Reads the EXIF ColorSpace and the embedded ICC profile of an image: from the APP2 segments
+// of a JPEG, or the InterColorProfile tag of a TIFF-based file. Returns None when neither is present.
+pub(crate) fn read_color_metadata( path: &Path, format: MediaFormat, exif_fields: &exif::Exif ) -> Result<Option<ColorMetadataOfInterest>, Box<dyn error::Error>> {
+    let mut color = ColorMetadataOfInterest {
+        exif_color_space: exif_color_space( exif_fields ),
+        ..Default::default()
+    };
+
+    let profile = if format == MediaFormat::Jpeg {
+        // A truncated header is reported by the EXIF reader, so here it just means no profile.
+        let header = jpeg::read_header_from_stream( &mut BufReader::new( File::open( path )? ) ).unwrap_or_default();
+        match jpeg_icc_profile( &header ) {
+            Ok(profile) => profile,
+            Err(problem) => {
+                color.warnings.push( problem.to_string() );
+                None
+            }
+        }
+    } else {
+        tiff_icc_profile( exif_fields )
+    };
+
+    if let Some(profile) = profile {
+        match parse_icc_profile( &profile ) {
+            Some(summary) => {
+                color.profile_description = summary.description;
+                color.profile_color_space = Some(summary.color_space);
+                color.profile_version = Some(summary.version);
+                color.rendering_intent = Some(summary.rendering_intent);
+            },
+            None => {
+                eprintln!("Couldn't parse the embedded ICC profile of file: {}",path.to_string_lossy());
+                color.warnings.push( "embedded ICC profile could not be parsed".to_string() );
+            }
+        }
+    }
+
+    find_inconsistencies( &mut color );
+    if color == ColorMetadataOfInterest::default() {
+        return Ok(None);
+    }
+    Ok(Some(color))
+}
@@ -0,0 +1,212 @@
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::get_exif_fields;
+use crate::writer::{copied_field, write_fields, FieldChange, FieldWrite, IfdLocation, WriteOptions};
+
+// The tags of each group, with where they are and the name a change is reported under.
+// Dimensions and orientation are in none of them, as they describe the target's own image data.
+const CAPTURE_TIME_TAGS : [(IfdLocation, u16, &str); 9] = [
+    (IfdLocation::Exif, 0x9003, "date_time_original"),
+    (IfdLocation::Exif, 0x9004, "date_time_digitized"),
+    (IfdLocation::Primary, 0x0132, "date_time"),
+    (IfdLocation::Exif, 0x9290, "sub_sec_time"),
+    (IfdLocation::Exif, 0x9291, "sub_sec_time_original"),
+    (IfdLocation::Exif, 0x9292, "sub_sec_time_digitized"),
+    (IfdLocation::Exif, 0x9010, "offset_time"),
+    (IfdLocation::Exif, 0x9011, "offset_time_original"),
+    (IfdLocation::Exif, 0x9012, "offset_time_digitized"),
+];
+
+const CAMERA_TAGS : [(IfdLocation, u16, &str); 10] = [
+    (IfdLocation::Primary, 0x010F, "camera_make"),
+    (IfdLocation::Primary, 0x0110, "camera_model"),
+    (IfdLocation::Exif, 0xA431, "camera_serial"),
+    (IfdLocation::Exif, 0xA430, "camera_owner"),
+    (IfdLocation::Exif, 0x829A, "exposure_time"),
+    (IfdLocation::Exif, 0x8822, "exposure_program"),
+    (IfdLocation::Exif, 0x8827, "iso"),
+    (IfdLocation::Exif, 0x9204, "exposure_bias"),
+    (IfdLocation::Exif, 0x9207, "metering_mode"),
+    (IfdLocation::Exif, 0x9209, "flash"),
+];
+
+const LENS_TAGS : [(IfdLocation, u16, &str); 8] = [
+    (IfdLocation::Exif, 0xA432, "lens_specification"),
+    (IfdLocation::Exif, 0xA433, "lens_make"),
+    (IfdLocation::Exif, 0xA434, "lens_model"),
+    (IfdLocation::Exif, 0xA435, "lens_serial"),
+    (IfdLocation::Exif, 0x829D, "f_number"),
+    (IfdLocation::Exif, 0x920A, "focal_length"),
+    (IfdLocation::Exif, 0xA405, "focal_length_35mm"),
+    (IfdLocation::Exif, 0x9205, "max_aperture"),
+];
+
+const GPS_TAGS : [(IfdLocation, u16, &str); 22] = [
+    (IfdLocation::Gps, 0x0000, "gps_version"),
+    (IfdLocation::Gps, 0x0001, "gps_latitude_ref"),
+    (IfdLocation::Gps, 0x0002, "gps_latitude"),
+    (IfdLocation::Gps, 0x0003, "gps_longitude_ref"),
+    (IfdLocation::Gps, 0x0004, "gps_longitude"),
+    (IfdLocation::Gps, 0x0005, "gps_altitude_ref"),
+    (IfdLocation::Gps, 0x0006, "gps_altitude"),
+    (IfdLocation::Gps, 0x0007, "gps_time_stamp"),
+    (IfdLocation::Gps, 0x0008, "gps_satellites"),
+    (IfdLocation::Gps, 0x0009, "gps_status"),
+    (IfdLocation::Gps, 0x000A, "gps_measure_mode"),
+    (IfdLocation::Gps, 0x000B, "gps_dop"),
+    (IfdLocation::Gps, 0x000C, "gps_speed_ref"),
+    (IfdLocation::Gps, 0x000D, "gps_speed"),
+    (IfdLocation::Gps, 0x000E, "gps_track_ref"),
+    (IfdLocation::Gps, 0x000F, "gps_track"),
+    (IfdLocation::Gps, 0x0010, "gps_img_direction_ref"),
+    (IfdLocation::Gps, 0x0011, "gps_img_direction"),
+    (IfdLocation::Gps, 0x0012, "gps_map_datum"),
+    (IfdLocation::Gps, 0x001B, "gps_processing_method"),
+    (IfdLocation::Gps, 0x001D, "gps_date_stamp"),
+    (IfdLocation::Gps, 0x001F, "gps_h_positioning_error"),
+];
+
+const COPYRIGHT_TAGS : [(IfdLocation, u16, &str); 2] = [
+    (IfdLocation::Primary, 0x8298, "copyright"),
+    (IfdLocation::Primary, 0x013B, "artist"),
+];
+
+// A set of related fields which are copied together.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum MetadataGroup {
+    // The capture, digitization and modification times, with their sub-seconds and offsets.
+    CaptureTime,
+    // The body, its serial and owner, and the exposure settings.
+    Camera,
+    // The lens and the aperture and focal length it was used at.
+    Lens,
+    // Every field of the GPS IFD which describes the location and how it was taken.
+    Gps,
+    // The copyright notice and the artist.
+    Copyright,
+}
+
+impl MetadataGroup {
+    pub const ALL : [MetadataGroup; 5] = [
+        MetadataGroup::CaptureTime,
+        MetadataGroup::Camera,
+        MetadataGroup::Lens,
+        MetadataGroup::Gps,
+        MetadataGroup::Copyright,
+    ];
+
+    pub fn from_name( name: &str ) -> Option<MetadataGroup> {
+        match name {
+            "capture-time" => Some(MetadataGroup::CaptureTime),
+            "camera" => Some(MetadataGroup::Camera),
+            "lens" => Some(MetadataGroup::Lens),
+            "gps" => Some(MetadataGroup::Gps),
+            "copyright" => Some(MetadataGroup::Copyright),
+            _ => None,
+        }
+    }
+
+    // Parses a comma separated list of group names, where "all" stands for every group.
+    pub fn parse_list( list: &str ) -> Option<Vec<MetadataGroup>> {
+        let mut groups = vec![];
+        for name in list.split( ',' ).map(|name| name.trim()) {
+            let named = match name {
+                "all" => MetadataGroup::ALL.to_vec(),
+                _ => vec![MetadataGroup::from_name( name )?],
+            };
+            for group in named {
+                if !groups.contains( &group ) {
+                    groups.push( group );
+                }
+            }
+        }
+        Some(groups)
+    }
+
+    fn tags( self ) -> &'static [(IfdLocation, u16, &'static str)] {
+        match self {
+            MetadataGroup::CaptureTime => &CAPTURE_TIME_TAGS,
+            MetadataGroup::Camera => &CAMERA_TAGS,
+            MetadataGroup::Lens => &LENS_TAGS,
+            MetadataGroup::Gps => &GPS_TAGS,
+            MetadataGroup::Copyright => &COPYRIGHT_TAGS,
+        }
+    }
+}
+
+// Where the fields to copy are read from.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum CopySource {
+    // One file, copied into every target.
+    File(PathBuf),
+    // A directory, in which each target's source is the file with the same stem.
+    MatchingStem(PathBuf),
+}
+
+impl CopySource {
+    // The file to copy into the target.
+    pub fn source_for( &self, target: &Path ) -> Result<PathBuf, Box<dyn error::Error>> {
+        match self {
+            CopySource::File(path) => Ok(path.clone()),
+            CopySource::MatchingStem(source_dir) => find_source_by_stem( source_dir, target ),
+        }
+    }
+}
+
+// Raised when no file in the source directory has the stem of a target.
+#[derive(Debug, Clone)]
+pub struct MissingSourceError {
+    target: PathBuf,
+    source_dir: PathBuf,
+}
+
+impl fmt::Display for MissingSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No file in {} has the same name as {}", self.source_dir.to_string_lossy(), self.target.to_string_lossy())
+    }
+}
+
+impl Error for MissingSourceError {
+    fn description(&self) -> &str {
+        "No source file matches the target"
+    }
+}
+
+// Finds the file in a directory with the same stem as the target, whatever its extension, so
+// that IMG_0001.CR2 is the source of an exported IMG_0001.jpg.
+pub fn find_source_by_stem( source_dir: &Path, target: &Path ) -> Result<PathBuf, Box<dyn error::Error>> {
+    let stem = target.file_stem();
+    let mut candidates : Vec<PathBuf> = fs::read_dir( source_dir )?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.file_stem() == stem)
+        .filter(|path| fs::canonicalize( path ).ok() != fs::canonicalize( target ).ok())
+        .collect();
+    // Several files may share the stem, such as a RAW and its JPEG; pick one predictably.
+    candidates.sort();
+    candidates.into_iter().next().ok_or_else(|| Box::new( MissingSourceError { target: target.to_path_buf(), source_dir: source_dir.to_path_buf() } ).into())
+}
+
+// Copies the fields of the groups which the source has into the target, and returns what
+// changed. The target's other fields, including its dimensions and orientation, are kept.
+// Fields the source lacks are left as they are in the target, rather than removed.
+pub fn copy_metadata( source: &Path, target: &Path, groups: &[MetadataGroup], options: &WriteOptions ) -> Result<Vec<FieldChange>, Box<dyn error::Error>> {
+    let source_fields = get_exif_fields( source )?;
+    let copied_tags : Vec<(IfdLocation, u16, &'static str)> = groups.iter().flat_map(|group| group.tags().iter().copied()).collect();
+    write_fields( target, &|_, order| {
+        copied_tags.iter()
+            .filter_map(|(location, tag, field)| {
+                let context = match location {
+                    IfdLocation::Primary | IfdLocation::Thumbnail => exif::Context::Tiff,
+                    IfdLocation::Exif => exif::Context::Exif,
+                    IfdLocation::Gps => exif::Context::Gps,
+                };
+                let source_field = source_fields.get_field( exif::Tag(context, *tag), exif::In::PRIMARY )?;
+                copied_field( field, *location, *tag, &source_field.value, order )
+            })
+            .collect::<Vec<FieldWrite>>()
+    }, options )
+}
@@ -0,0 +1,642 @@
+use std::error;
+use std::error::Error;
+use std::fmt;
+
+use crate::jpeg::{self, JpegSegment};
+
+pub(crate) const MARKER_DHT : u8 = 0xC4;
+pub(crate) const MARKER_DQT : u8 = 0xDB;
+pub(crate) const MARKER_DRI : u8 = 0xDD;
+const MARKER_SOF0 : u8 = 0xC0;
+const MARKER_SOF1 : u8 = 0xC1;
+const MARKER_RST0 : u8 = 0xD0;
+const MARKER_RST7 : u8 = 0xD7;
+
+// The position in a block, in row-major order, of each coefficient in zigzag order.
+pub(crate) const ZIGZAG : [usize; 64] = [
+     0,  1,  8, 16,  9,  2,  3, 10, 17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// The quantized DCT coefficients of an 8x8 block, in row-major order: horizontal frequency
+// increases along a row and vertical frequency down a column.
+pub(crate) type Block = [i16; 64];
+
+#[derive(Debug, Clone)]
+pub struct JpegCodingError {
+    reason: String,
+}
+
+impl fmt::Display for JpegCodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Couldn't transcode JPEG image data: {}", self.reason)
+    }
+}
+
+impl Error for JpegCodingError {
+    fn description(&self) -> &str {
+        "The JPEG image data could not be decoded or encoded without loss"
+    }
+}
+
+pub(crate) fn coding_error( reason: &str ) -> Box<dyn error::Error> {
+    Box::new( JpegCodingError { reason: reason.to_string() } )
+}
+
+// One component of a frame, with its blocks. The blocks cover whole MCUs, so there may be
+// more of them than the image needs at the right and bottom edges.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub(crate) struct Component {
+    pub id: u8,
+    pub horizontal_sampling: usize,
+    pub vertical_sampling: usize,
+    pub quantization_table: u8,
+    pub dc_table: u8,
+    pub ac_table: u8,
+    pub blocks_wide: usize,
+    pub blocks_high: usize,
+    pub blocks: Vec<Block>,
+}
+
+// The image data of a JPEG with a single sequential scan, as DCT coefficients.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub(crate) struct CoefficientImage {
+    pub width: usize,
+    pub height: usize,
+    // In the order of the frame header.
+    pub components: Vec<Component>,
+    // Indexes into components, in the order of the scan header.
+    pub scan_order: Vec<usize>,
+}
+
+impl CoefficientImage {
+    // Sizes the block grids of the components for the image dimensions, with every block zero.
+    pub(crate) fn new( width: usize, height: usize, mut components: Vec<Component>, scan_order: Vec<usize> ) -> CoefficientImage {
+        let mut image = CoefficientImage { width, height, components: vec![], scan_order };
+        let (mcu_width, mcu_height) = image_mcu_size( &components );
+        let (mcus_wide, mcus_high) = (width.div_ceil( mcu_width ), height.div_ceil( mcu_height ));
+        for component in components.iter_mut() {
+            component.blocks_wide = mcus_wide * component.horizontal_sampling;
+            component.blocks_high = mcus_high * component.vertical_sampling;
+            component.blocks = vec![[0; 64]; component.blocks_wide * component.blocks_high];
+        }
+        image.components = components;
+        image
+    }
+
+    // The size in pixels of an MCU, which lossless transforms can't split.
+    pub(crate) fn mcu_size( &self ) -> (usize, usize) {
+        image_mcu_size( &self.components )
+    }
+
+    fn layout( &self ) -> ScanLayout {
+        let (mcu_width, mcu_height) = self.mcu_size();
+        ScanLayout {
+            mcus_wide: self.width.div_ceil( mcu_width ),
+            mcus_high: self.height.div_ceil( mcu_height ),
+            components: self.scan_order.iter()
+                .map(|index| {
+                    let component = &self.components[*index];
+                    (*index, component.horizontal_sampling, component.vertical_sampling, component.blocks_wide)
+                })
+                .collect(),
+        }
+    }
+}
+
+// Eight pixels times the largest sampling factors. A frame with a single component is coded
+// one block at a time, so read_frame gives it sampling factors of 1.
+fn image_mcu_size( components: &[Component] ) -> (usize, usize) {
+    let horizontal = components.iter().map(|component| component.horizontal_sampling).max().unwrap_or(1);
+    let vertical = components.iter().map(|component| component.vertical_sampling).max().unwrap_or(1);
+    (8 * horizontal, 8 * vertical)
+}
+
+// The order of the blocks in an interleaved scan: MCUs left to right and top to bottom, and in
+// each MCU the blocks of each component in turn, left to right and top to bottom.
+struct ScanLayout {
+    mcus_wide: usize,
+    mcus_high: usize,
+    // Component index, sampling factors and block grid width, in scan order.
+    components: Vec<(usize, usize, usize, usize)>,
+}
+
+impl ScanLayout {
+    // Each block as (MCU number, component index, block index).
+    fn blocks( &self ) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        let mcus_wide = self.mcus_wide;
+        (0..self.mcus_high).flat_map(move |row| (0..mcus_wide).map(move |column| (row, column)))
+            .enumerate()
+            .flat_map(move |(mcu, (row, column))| self.components.iter().flat_map(move |&(index, horizontal, vertical, blocks_wide)| {
+                (0..vertical).flat_map(move |y| (0..horizontal).map(move |x| (mcu, index, (row * vertical + y) * blocks_wide + column * horizontal + x)))
+            }))
+    }
+}
+
+// A JPEG decoded to coefficients, with what surrounds its image data.
+pub(crate) struct DecodedJpeg {
+    pub image: CoefficientImage,
+    // The header segments before SOS.
+    pub segments: Vec<JpegSegment>,
+    // The offset just past EOI, where any trailing data starts.
+    pub trailer_start: usize,
+}
+
+struct HuffmanDecoder {
+    // The largest code of each length, or -1 if there are none.
+    max_codes: [i32; 17],
+    // Added to a code of each length to give the index of its symbol.
+    symbol_offsets: [i32; 17],
+    symbols: Vec<u8>,
+}
+
+impl HuffmanDecoder {
+    fn new( counts: &[u8], symbols: &[u8] ) -> HuffmanDecoder {
+        let mut decoder = HuffmanDecoder { max_codes: [-1; 17], symbol_offsets: [0; 17], symbols: symbols.to_vec() };
+        let mut code = 0i32;
+        let mut index = 0i32;
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            decoder.symbol_offsets[length] = index - code;
+            if count > 0 {
+                code += count;
+                index += count;
+                decoder.max_codes[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        decoder
+    }
+
+    fn decode( &self, reader: &mut BitReader ) -> Result<u8, Box<dyn error::Error>> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | reader.read_bit() as i32;
+            if code <= self.max_codes[length] {
+                return self.symbols.get( (code + self.symbol_offsets[length]) as usize ).copied()
+                    .ok_or_else(|| coding_error( "Huffman table has fewer symbols than codes" ));
+            }
+        }
+        Err( coding_error( "image data has a code which isn't in its Huffman table" ) )
+    }
+}
+
+// Reads entropy-coded data, removing the zero bytes stuffed after 0xFF. At a marker or the end
+// of the data it reads zero bits, as decoders do for truncated files.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u8,
+    bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn next_byte( &mut self ) -> u8 {
+        match (self.data.get( self.position ), self.data.get( self.position + 1 )) {
+            (Some(0xFF), Some(0)) => {
+                self.position += 2;
+                0xFF
+            },
+            (Some(0xFF), _) | (None, _) => 0,
+            (Some(byte), _) => {
+                self.position += 1;
+                *byte
+            },
+        }
+    }
+
+    fn read_bit( &mut self ) -> u32 {
+        if self.bits == 0 {
+            self.buffer = self.next_byte();
+            self.bits = 8;
+        }
+        self.bits -= 1;
+        ((self.buffer >> self.bits) & 1) as u32
+    }
+
+    fn read_bits( &mut self, count: u32 ) -> u32 {
+        (0..count).fold( 0, |value, _| (value << 1) | self.read_bit() )
+    }
+
+    // Skips the rest of the current byte and any fill bytes, returning the marker which follows.
+    fn next_marker( &mut self ) -> Option<u8> {
+        self.bits = 0;
+        while self.data.get( self.position ) == Some(&0xFF) && self.data.get( self.position + 1 ) == Some(&0xFF) {
+            self.position += 1;
+        }
+        match (self.data.get( self.position ), self.data.get( self.position + 1 )) {
+            (Some(0xFF), Some(marker)) => Some(*marker),
+            _ => None,
+        }
+    }
+
+    fn restart( &mut self ) -> Result<(), Box<dyn error::Error>> {
+        match self.next_marker() {
+            Some(marker) if (MARKER_RST0..=MARKER_RST7).contains( &marker ) => {
+                self.position += 2;
+                Ok(())
+            },
+            _ => Err( coding_error( "a restart marker is missing from the image data" ) ),
+        }
+    }
+}
+
+// The value of a coefficient from its magnitude category and extra bits (JPEG F.2.2.1).
+fn extend( bits: u32, size: u32 ) -> i32 {
+    if size == 0 {
+        0
+    } else if bits < 1 << (size - 1) {
+        bits as i32 - (1 << size) + 1
+    } else {
+        bits as i32
+    }
+}
+
+fn read_huffman_tables( payload: &[u8], dc_tables: &mut [Option<HuffmanDecoder>; 4], ac_tables: &mut [Option<HuffmanDecoder>; 4] ) -> Result<(), Box<dyn error::Error>> {
+    let mut position = 0;
+    while position < payload.len() {
+        let class_and_id = payload[position];
+        let counts = payload.get( position + 1..position + 17 ).ok_or_else(|| coding_error( "Huffman table is truncated" ))?;
+        let total = counts.iter().map(|count| *count as usize).sum::<usize>();
+        let symbols = payload.get( position + 17..position + 17 + total ).ok_or_else(|| coding_error( "Huffman table is truncated" ))?;
+        let id = (class_and_id & 0x0F) as usize;
+        let tables = match class_and_id >> 4 {
+            0 => &mut *dc_tables,
+            1 => &mut *ac_tables,
+            _ => return Err( coding_error( "Huffman table class is invalid" ) ),
+        };
+        *tables.get_mut( id ).ok_or_else(|| coding_error( "Huffman table id is invalid" ))? = Some( HuffmanDecoder::new( counts, symbols ) );
+        position += 17 + total;
+    }
+    Ok(())
+}
+
+fn read_frame( payload: &[u8] ) -> Result<(usize, usize, Vec<Component>), Box<dyn error::Error>> {
+    let truncated = || coding_error( "frame header is truncated" );
+    if *payload.first().ok_or_else( truncated )? != 8 {
+        return Err( coding_error( "only 8-bit samples are supported" ) );
+    }
+    let height = u16::from_be_bytes( [*payload.get( 1 ).ok_or_else( truncated )?, *payload.get( 2 ).ok_or_else( truncated )?] ) as usize;
+    let width = u16::from_be_bytes( [*payload.get( 3 ).ok_or_else( truncated )?, *payload.get( 4 ).ok_or_else( truncated )?] ) as usize;
+    if width == 0 || height == 0 {
+        return Err( coding_error( "frame header has no dimensions" ) );
+    }
+    let count = *payload.get( 5 ).ok_or_else( truncated )? as usize;
+    let mut components = vec![];
+    for entry in payload.get( 6..6 + 3 * count ).ok_or_else( truncated )?.chunks_exact( 3 ) {
+        let (horizontal_sampling, vertical_sampling) = ((entry[1] >> 4) as usize, (entry[1] & 0x0F) as usize);
+        if !(1..=4).contains( &horizontal_sampling ) || !(1..=4).contains( &vertical_sampling ) {
+            return Err( coding_error( "component sampling factors are invalid" ) );
+        }
+        components.push( Component {
+            id: entry[0],
+            horizontal_sampling,
+            vertical_sampling,
+            quantization_table: entry[2],
+            dc_table: 0,
+            ac_table: 0,
+            blocks_wide: 0,
+            blocks_high: 0,
+            blocks: vec![],
+        } );
+    }
+    if components.is_empty() {
+        return Err( coding_error( "frame has no components" ) );
+    }
+    // A single component is coded block by block, so its sampling factors don't matter.
+    if let [component] = components.as_mut_slice() {
+        component.horizontal_sampling = 1;
+        component.vertical_sampling = 1;
+    }
+    Ok( (width, height, components) )
+}
+
+// Assigns the Huffman tables of the scan header to the components, returning the scan order.
+fn read_scan( payload: &[u8], components: &mut [Component] ) -> Result<Vec<usize>, Box<dyn error::Error>> {
+    let count = *payload.first().ok_or_else(|| coding_error( "scan header is truncated" ))? as usize;
+    let selectors = payload.get( 1..1 + 2 * count ).ok_or_else(|| coding_error( "scan header is truncated" ))?;
+    if count != components.len() || payload.get( 1 + 2 * count..4 + 2 * count ) != Some(&[0, 63, 0]) {
+        return Err( coding_error( "only JPEGs with a single scan of every component are supported" ) );
+    }
+    let mut scan_order = vec![];
+    for selector in selectors.chunks_exact( 2 ) {
+        let index = components.iter().position(|component| component.id == selector[0])
+            .ok_or_else(|| coding_error( "scan refers to a component which isn't in the frame" ))?;
+        components[index].dc_table = selector[1] >> 4;
+        components[index].ac_table = selector[1] & 0x0F;
+        scan_order.push( index );
+    }
+    Ok(scan_order)
+}
+
+fn decode_block( reader: &mut BitReader, dc_table: &HuffmanDecoder, ac_table: &HuffmanDecoder, prediction: &mut i32, block: &mut Block ) -> Result<(), Box<dyn error::Error>> {
+    let size = dc_table.decode( reader )? as u32;
+    if size > 15 {
+        return Err( coding_error( "DC coefficient is out of range" ) );
+    }
+    *prediction += extend( reader.read_bits( size ), size );
+    block[0] = *prediction as i16;
+
+    let mut index = 1;
+    while index < 64 {
+        let symbol = ac_table.decode( reader )?;
+        let (run, size) = ((symbol >> 4) as usize, (symbol & 0x0F) as u32);
+        if size == 0 {
+            if run != 15 {
+                break;
+            }
+            index += 16;
+            continue;
+        }
+        index += run;
+        if index > 63 {
+            return Err( coding_error( "AC coefficients run past the end of a block" ) );
+        }
+        block[ZIGZAG[index]] = extend( reader.read_bits( size ), size ) as i16;
+        index += 1;
+    }
+    Ok(())
+}
+
+// Decodes a baseline or extended sequential Huffman-coded JPEG to DCT coefficients.
+// Progressive and arithmetic-coded JPEGs, and JPEGs with more than one scan, are not supported.
+pub(crate) fn decode( data: &[u8] ) -> Result<DecodedJpeg, Box<dyn error::Error>> {
+    let mut segments = jpeg::header_segments( data ).ok_or_else(|| coding_error( "JPEG header segments could not be parsed" ))?;
+    let scan = match segments.pop() {
+        Some(segment) if segment.marker == jpeg::MARKER_SOS => segment,
+        _ => return Err( coding_error( "JPEG has no image data" ) ),
+    };
+
+    let mut dc_tables : [Option<HuffmanDecoder>; 4] = Default::default();
+    let mut ac_tables : [Option<HuffmanDecoder>; 4] = Default::default();
+    let mut restart_interval = 0;
+    let mut frame = None;
+    for segment in segments.iter() {
+        let payload = &data[segment.payload.clone()];
+        match segment.marker {
+            MARKER_DHT => read_huffman_tables( payload, &mut dc_tables, &mut ac_tables )?,
+            MARKER_DRI => restart_interval = payload.get( 0..2 ).map_or( 0, |bytes| u16::from_be_bytes( [bytes[0], bytes[1]] ) as usize ),
+            MARKER_SOF0 | MARKER_SOF1 => frame = Some( read_frame( payload )? ),
+            marker if jpeg::is_start_of_frame( marker ) => return Err( coding_error( "only baseline and extended sequential Huffman-coded JPEGs are supported" ) ),
+            _ => {},
+        }
+    }
+    let (width, height, mut components) = frame.ok_or_else(|| coding_error( "JPEG has no frame header" ))?;
+    let scan_order = read_scan( &data[scan.payload.clone()], &mut components )?;
+    let mut image = CoefficientImage::new( width, height, components, scan_order );
+
+    let mut reader = BitReader { data, position: scan.payload.end, buffer: 0, bits: 0 };
+    let mut predictions = vec![0i32; image.components.len()];
+    let mut current_mcu = 0;
+    let layout = image.layout();
+    for (mcu, index, block_index) in layout.blocks() {
+        if mcu != current_mcu {
+            current_mcu = mcu;
+            if restart_interval > 0 && mcu % restart_interval == 0 {
+                reader.restart()?;
+                predictions.iter_mut().for_each(|prediction| *prediction = 0);
+            }
+        }
+        let component = &mut image.components[index];
+        let missing = || coding_error( "scan uses a Huffman table which isn't defined" );
+        let dc_table = dc_tables.get( component.dc_table as usize ).and_then(|table| table.as_ref()).ok_or_else( missing )?;
+        let ac_table = ac_tables.get( component.ac_table as usize ).and_then(|table| table.as_ref()).ok_or_else( missing )?;
+        decode_block( &mut reader, dc_table, ac_table, &mut predictions[index], &mut component.blocks[block_index] )?;
+    }
+
+    let trailer_start = match reader.next_marker() {
+        Some(jpeg::MARKER_EOI) => reader.position + 2,
+        None => data.len(),
+        Some(_) => return Err( coding_error( "only JPEGs with a single scan of every component are supported" ) ),
+    };
+    Ok( DecodedJpeg { image, segments, trailer_start } )
+}
+
+struct HuffmanEncoder {
+    counts: [u8; 16],
+    symbols: Vec<u8>,
+    // The code and its length for each symbol.
+    codes: Vec<(u32, u32)>,
+}
+
+// Builds an optimal Huffman table for the symbol frequencies, limited to 16-bit codes and with
+// no code of all ones (JPEG K.2, as libjpeg implements it).
+fn optimal_table( frequencies: &[u64; 256] ) -> HuffmanEncoder {
+    // Symbol 256 is reserved, so that it takes the all-ones code.
+    let mut frequency = frequencies.to_vec();
+    frequency.push( 1 );
+    let mut code_sizes = [0usize; 257];
+    let mut others : [Option<usize>; 257] = [None; 257];
+    loop {
+        // The least frequent symbol, preferring the larger symbol on ties, then the next least.
+        let least = |excluded: Option<usize>| (0..257)
+            .filter(|symbol| frequency[*symbol] > 0 && Some(*symbol) != excluded)
+            .fold( None, |found: Option<usize>, symbol| match found {
+                Some(found) if frequency[found] < frequency[symbol] => Some(found),
+                _ => Some(symbol),
+            } );
+        let (mut first, mut second) = match least( None ) {
+            Some(first) => match least( Some(first) ) {
+                Some(second) => (first, second),
+                None => break,
+            },
+            None => break,
+        };
+        frequency[first] += frequency[second];
+        frequency[second] = 0;
+        code_sizes[first] += 1;
+        while let Some(next) = others[first] {
+            first = next;
+            code_sizes[first] += 1;
+        }
+        others[first] = Some(second);
+        code_sizes[second] += 1;
+        while let Some(next) = others[second] {
+            second = next;
+            code_sizes[second] += 1;
+        }
+    }
+
+    let mut lengths = vec![0usize; 258];
+    for size in code_sizes.iter().filter(|size| **size > 0) {
+        lengths[*size] += 1;
+    }
+    // Shorten codes longer than 16 bits, keeping the code complete.
+    for length in (17..lengths.len()).rev() {
+        while lengths[length] > 0 {
+            let mut shorter = length - 2;
+            while lengths[shorter] == 0 {
+                shorter -= 1;
+            }
+            lengths[length] -= 2;
+            lengths[length - 1] += 1;
+            lengths[shorter + 1] += 2;
+            lengths[shorter] -= 1;
+        }
+    }
+    // Drop the reserved symbol, which has one of the longest codes.
+    let mut longest = 16;
+    while lengths[longest] == 0 {
+        longest -= 1;
+    }
+    lengths[longest] -= 1;
+
+    let mut symbols = vec![];
+    for size in 1..code_sizes.len() {
+        symbols.extend( (0..256).filter(|symbol| code_sizes[*symbol] == size).map(|symbol| symbol as u8) );
+    }
+    let mut counts = [0u8; 16];
+    for (count, length) in counts.iter_mut().zip( lengths[1..=16].iter() ) {
+        *count = *length as u8;
+    }
+    let mut codes = vec![(0, 0); 256];
+    let mut code = 0u32;
+    let mut next_symbol = symbols.iter();
+    for length in 1..=16u32 {
+        for _ in 0..counts[length as usize - 1] {
+            if let Some(symbol) = next_symbol.next() {
+                codes[*symbol as usize] = (code, length);
+            }
+            code += 1;
+        }
+        code <<= 1;
+    }
+    HuffmanEncoder { counts, symbols, codes }
+}
+
+// Writes entropy-coded data, stuffing a zero byte after each 0xFF.
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn put( &mut self, value: u32, count: u32 ) {
+        if count == 0 {
+            return;
+        }
+        self.buffer = (self.buffer << count) | (value as u64 & ((1u64 << count) - 1));
+        self.bits += count;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            let byte = (self.buffer >> self.bits) as u8;
+            self.output.push( byte );
+            if byte == 0xFF {
+                self.output.push( 0 );
+            }
+        }
+    }
+
+    // Pads the last byte with one bits.
+    fn finish( mut self ) -> Vec<u8> {
+        let padding = (8 - self.bits % 8) % 8;
+        self.put( (1 << padding) - 1, padding );
+        self.output
+    }
+}
+
+// The magnitude category of a coefficient and its extra bits (JPEG F.1.2.1).
+fn magnitude( value: i32 ) -> (u32, u32) {
+    let size = 32 - value.unsigned_abs().leading_zeros();
+    let bits = if value < 0 { (value - 1) as u32 & ((1 << size) - 1) } else { value as u32 };
+    (size, bits)
+}
+
+// Goes through the Huffman symbols of the scan as they are coded, with their extra bits. The
+// DC tables are numbered 0 to 3 and the AC tables 4 to 7.
+fn for_each_symbol( image: &CoefficientImage, emit: &mut dyn FnMut( usize, u8, u32, u32 ) ) {
+    let mut predictions = vec![0i32; image.components.len()];
+    let layout = image.layout();
+    for (_, index, block_index) in layout.blocks() {
+        let component = &image.components[index];
+        let block = &component.blocks[block_index];
+        let (dc_table, ac_table) = (component.dc_table as usize, 4 + component.ac_table as usize);
+
+        let (size, bits) = magnitude( block[0] as i32 - predictions[index] );
+        predictions[index] = block[0] as i32;
+        emit( dc_table, size as u8, bits, size );
+
+        let mut run = 0;
+        for position in ZIGZAG[1..].iter() {
+            let value = block[*position] as i32;
+            if value == 0 {
+                run += 1;
+                continue;
+            }
+            while run > 15 {
+                emit( ac_table, 0xF0, 0, 0 );
+                run -= 16;
+            }
+            let (size, bits) = magnitude( value );
+            emit( ac_table, (run << 4) as u8 | size as u8, bits, size );
+            run = 0;
+        }
+        if run > 0 {
+            emit( ac_table, 0x00, 0, 0 );
+        }
+    }
+}
+
+fn segment( marker: u8, payload: &[u8] ) -> Vec<u8> {
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice( &((payload.len() + 2) as u16).to_be_bytes() );
+    segment.extend_from_slice( payload );
+    segment
+}
+
+// The frame header for the image, with the given start-of-frame marker.
+pub(crate) fn frame_segment( image: &CoefficientImage, marker: u8 ) -> Vec<u8> {
+    let mut payload = vec![8];
+    payload.extend_from_slice( &(image.height as u16).to_be_bytes() );
+    payload.extend_from_slice( &(image.width as u16).to_be_bytes() );
+    payload.push( image.components.len() as u8 );
+    for component in image.components.iter() {
+        payload.push( component.id );
+        payload.push( (component.horizontal_sampling << 4 | component.vertical_sampling) as u8 );
+        payload.push( component.quantization_table );
+    }
+    segment( marker, &payload )
+}
+
+// Encodes the coefficients as a single scan with Huffman tables made for them, returning the
+// DHT and SOS segments followed by the entropy-coded data. No restart markers are written.
+pub(crate) fn encode_scan( image: &CoefficientImage ) -> Vec<u8> {
+    let mut frequencies = [[0u64; 256]; 8];
+    for_each_symbol( image, &mut |table, symbol, _, _| frequencies[table][symbol as usize] += 1 );
+    let tables : Vec<Option<HuffmanEncoder>> = frequencies.iter()
+        .map(|frequencies| Some(frequencies).filter(|frequencies| frequencies.iter().any(|frequency| *frequency > 0)).map( optimal_table ))
+        .collect();
+
+    let mut table_payload = vec![];
+    for (number, table) in tables.iter().enumerate() {
+        if let Some(table) = table {
+            table_payload.push( (((number / 4) << 4) | (number % 4)) as u8 );
+            table_payload.extend_from_slice( &table.counts );
+            table_payload.extend_from_slice( &table.symbols );
+        }
+    }
+    let mut scan_payload = vec![image.scan_order.len() as u8];
+    for index in image.scan_order.iter() {
+        let component = &image.components[*index];
+        scan_payload.push( component.id );
+        scan_payload.push( component.dc_table << 4 | component.ac_table );
+    }
+    scan_payload.extend_from_slice( &[0, 63, 0] );
+
+    let mut writer = BitWriter { output: vec![], buffer: 0, bits: 0 };
+    for_each_symbol( image, &mut |table, symbol, bits, size| {
+        if let Some(table) = &tables[table] {
+            let (code, length) = table.codes[symbol as usize];
+            writer.put( code, length );
+            writer.put( bits, size );
+        }
+    } );
+
+    let mut output = segment( MARKER_DHT, &table_payload );
+    output.extend( segment( jpeg::MARKER_SOS, &scan_payload ) );
+    output.extend( writer.finish() );
+    output
+}
@@ -0,0 +1,261 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use crate::format::{self, MediaFormat};
+use crate::frames::{first_ascii, first_uint};
+use crate::hashing::{hash_file, FileHashes, HashAlgorithm};
+use crate::organize::collect_files;
+use crate::redact::{mark_redacted, Redaction};
+use crate::{get_exif_fields, read_metadata_of_interest, VendorDetails};
+
+// How the keeper of a group is chosen. Rules are applied in order, each only deciding between
+// the files the rules before it found equal; the path decides what is left.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeeperRule {
+    // A camera RAW file over any rendering of it.
+    PreferRaw,
+    // The largest file.
+    PreferLargest,
+    // The file modified longest ago, most likely the one first copied off the card.
+    PreferOldest,
+}
+
+impl KeeperRule {
+    pub const DEFAULT : [KeeperRule; 3] = [KeeperRule::PreferRaw, KeeperRule::PreferLargest, KeeperRule::PreferOldest];
+
+    pub fn from_name( name: &str ) -> Option<KeeperRule> {
+        match name {
+            "raw" => Some(KeeperRule::PreferRaw),
+            "largest" => Some(KeeperRule::PreferLargest),
+            "oldest" => Some(KeeperRule::PreferOldest),
+            _ => None,
+        }
+    }
+
+    // Rules from a comma separated list of names.
+    pub fn parse_list( names: &str ) -> Option<Vec<KeeperRule>> {
+        names.split( ',' ).map(|name| KeeperRule::from_name( name.trim() )).collect()
+    }
+
+    // Orders a before b when a is the better keeper.
+    fn compare( self, a: &DuplicateFile, b: &DuplicateFile ) -> Ordering {
+        match self {
+            KeeperRule::PreferRaw => b.raw.cmp( &a.raw ),
+            KeeperRule::PreferLargest => b.size.cmp( &a.size ),
+            KeeperRule::PreferOldest => match (a.modified_time, b.modified_time) {
+                (Some(a_time), Some(b_time)) => a_time.cmp( &b_time ),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct DedupeOptions {
+    pub algorithm: HashAlgorithm,
+    pub keeper_rules: Vec<KeeperRule>,
+}
+
+impl Default for DedupeOptions {
+    fn default() -> Self {
+        DedupeOptions { algorithm: HashAlgorithm::Blake3, keeper_rules: KeeperRule::DEFAULT.to_vec() }
+    }
+}
+
+// Why the files of a group were grouped: the same bytes, or the same frame from the camera in
+// another container or with other edits.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKind {
+    Identical,
+    Probable,
+}
+
+// The shot a file holds, as far as the camera says: files with the same one are probably the
+// same frame, even as a RAW and a JPEG, or before and after an edit.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+#[derive(Serialize,Deserialize)]
+pub struct CaptureKey {
+    pub camera_serial: String,
+    pub capture_time: chrono::NaiveDateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_sec_time: Option<String>,
+    // Canon's file number, which the RAW and JPEG of one shot share.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_number: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl CaptureKey {
+    // Whether the key tells one shot from the others taken in the same second: without a
+    // subsecond time or a file number, every frame of a burst would have the same key.
+    fn identifies_shot( &self ) -> bool {
+        self.sub_sec_time.is_some() || self.file_number.is_some()
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+pub struct DuplicateFile {
+    pub path: PathBuf,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified_time: Option<chrono::DateTime<Utc>>,
+    pub raw: bool,
+    pub hashes: FileHashes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture: Option<CaptureKey>,
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    // The suggested file to keep; the others are the candidates for removal.
+    pub keeper: PathBuf,
+    pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct DedupeReport {
+    pub files_scanned: usize,
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DedupeReport {
+    // The report as JSON, with the serials and removed fields of each file redacted as they
+    // would be in its sidecar; field names are those of a file in the report, such as
+    // "capture.camera_serial".
+    pub fn to_json_value( &self, redaction: &Redaction ) -> serde_json::Result<serde_json::Value> {
+        let mut report = self.clone();
+        for capture in report.groups.iter_mut().flat_map(|group| group.files.iter_mut()).filter_map(|file| file.capture.as_mut()) {
+            capture.camera_serial = redaction.redact_serial( &capture.camera_serial );
+        }
+        let mut value = serde_json::to_value( report )?;
+        if redaction.is_empty() {
+            return Ok(value);
+        }
+        let files = value["groups"].as_array_mut().into_iter().flatten()
+            .filter_map(|group| group["files"].as_array_mut()).flatten();
+        for file in files {
+            redaction.remove_fields( file );
+        }
+        mark_redacted( &mut value );
+        Ok(value)
+    }
+}
+
+pub type DedupeFailure = (PathBuf, Box<dyn error::Error>);
+
+fn capture_key( path: &Path ) -> Option<CaptureKey> {
+    let metadata = read_metadata_of_interest( path ).ok()?;
+    let image_metadata = metadata.image_metadata;
+    let exif_fields = get_exif_fields( path ).ok();
+    let exif_fields = exif_fields.as_ref();
+    let uint = |tags: [exif::Tag; 2]| exif_fields.and_then(|exif_fields| tags.iter().find_map(|tag| first_uint( exif_fields, *tag, exif::In::PRIMARY )));
+    let width = uint( [exif::Tag::PixelXDimension, exif::Tag::ImageWidth] );
+    let height = uint( [exif::Tag::PixelYDimension, exif::Tag::ImageLength] );
+    let sub_sec_time = exif_fields.and_then(|exif_fields| first_ascii( exif_fields, exif::Tag::SubSecTimeOriginal, exif::In::PRIMARY ));
+    let file_number = match metadata.vendor_metadata.and_then(|vendor| vendor.details) {
+        Some(VendorDetails::Canon(canon)) => canon.file_number,
+        _ => None,
+    };
+    Some( CaptureKey {
+        camera_serial: image_metadata.camera_serial?,
+        capture_time: image_metadata.capture_time?,
+        sub_sec_time,
+        file_number,
+        dimensions: width.zip( height ),
+    } )
+}
+
+fn scan_file( path: &Path, algorithm: HashAlgorithm ) -> Result<DuplicateFile, Box<dyn error::Error>> {
+    let file_metadata = fs::metadata( path )?;
+    let format = format::detect_format( path )?;
+    Ok( DuplicateFile {
+        path: path.to_path_buf(),
+        size: file_metadata.len(),
+        mime_type: Some(format.mime_type().to_string()).filter(|_| format != MediaFormat::Unknown),
+        modified_time: file_metadata.modified().ok().map(chrono::DateTime::<Utc>::from),
+        raw: format.is_raw(),
+        hashes: hash_file( path, algorithm )?,
+        capture: if format.is_supported() { capture_key( path ) } else { None },
+    } )
+}
+
+fn find_root( parents: &mut [usize], index: usize ) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[index] = root;
+    root
+}
+
+// Links every file to the first one before it with the same key.
+fn link_by<K: std::hash::Hash + Eq>( parents: &mut [usize], keys: impl Iterator<Item = Option<K>> ) {
+    let mut first_with : HashMap<K, usize> = HashMap::new();
+    for (index, key) in keys.enumerate() {
+        if let Some(key) = key {
+            let first = *first_with.entry( key ).or_insert( index );
+            let (root, first_root) = (find_root( parents, index ), find_root( parents, first ));
+            parents[root] = first_root;
+        }
+    }
+}
+
+// Groups the files, and those in directories recursively, which hold the same content or
+// probably the same frame: files with the same hash, the same image payload, or the same
+// capture key. Capture keys without a subsecond time or a file number only give the second a
+// frame was taken in, which the other frames of a burst share, so files aren't grouped by them.
+pub fn find_duplicates( paths: &[PathBuf], options: &DedupeOptions ) -> (DedupeReport, Vec<DedupeFailure>) {
+    let mut all_paths = vec![];
+    for path in paths.iter() {
+        collect_files( path, None, &mut all_paths );
+    }
+    let mut files = vec![];
+    let mut failures : Vec<DedupeFailure> = vec![];
+    for path in all_paths {
+        match scan_file( &path, options.algorithm ) {
+            Ok(file) => files.push( file ),
+            Err(boxed_err) => failures.push( (path, boxed_err) ),
+        }
+    }
+
+    let mut parents : Vec<usize> = (0..files.len()).collect();
+    link_by( &mut parents, files.iter().map(|file| Some(&file.hashes.file)) );
+    link_by( &mut parents, files.iter().map(|file| file.hashes.payload.as_ref()) );
+    link_by( &mut parents, files.iter().map(|file| file.capture.as_ref().filter(|capture| capture.identifies_shot())) );
+    let mut members : Vec<Vec<usize>> = vec![vec![]; files.len()];
+    for index in 0..files.len() {
+        let root = find_root( &mut parents, index );
+        members[root].push( index );
+    }
+
+    let mut groups = vec![];
+    for indexes in members.into_iter().filter(|indexes| indexes.len() > 1) {
+        let mut group_files : Vec<DuplicateFile> = indexes.iter().map(|index| files[*index].clone()).collect();
+        group_files.sort_by(|a, b| {
+            options.keeper_rules.iter().map(|rule| rule.compare( a, b )).find(|order| *order != Ordering::Equal)
+                .unwrap_or_else(|| a.path.cmp( &b.path ))
+        });
+        let kind = if group_files.iter().all(|file| file.hashes.file == group_files[0].hashes.file) { DuplicateKind::Identical } else { DuplicateKind::Probable };
+        groups.push( DuplicateGroup { kind, keeper: group_files[0].path.clone(), files: group_files } );
+    }
+    (DedupeReport { files_scanned: files.len(), groups }, failures)
+}
@@ -0,0 +1,308 @@
+use std::convert::TryInto;
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str;
+
+// Enough of the file to see the TIFF IFD0 of typical RAW files as well as any magic bytes.
+const SNIFF_LENGTH : u64 = 64 * 1024;
+
+const HEIF_BRANDS : [&[u8; 4]; 7] = [b"heic", b"heix", b"heim", b"heis", b"hevc", b"mif1", b"msf1"];
+
+const TIFF_TAG_NEW_SUBFILE_TYPE : u16 = 0x00FE;
+const TIFF_TAG_MAKE : u16 = 0x010F;
+const TIFF_TAG_SUB_IFDS : u16 = 0x014A;
+const TIFF_TAG_DNG_VERSION : u16 = 0xC612;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum MediaFormat {
+    // Still images
+    Jpeg,
+    Tiff,
+    Png,
+    Heif,
+    Avif,
+    Webp,
+    Gif,
+    Bmp,
+    // Camera RAW formats
+    CanonCr2,
+    CanonCr3,
+    NikonNef,
+    SonyArw,
+    AdobeDng,
+    PentaxPef,
+    OlympusOrf,
+    PanasonicRw2,
+    FujifilmRaf,
+    // Video
+    QuickTime,
+    Mp4,
+    Avi,
+    // Known non-media files
+    Mp3,
+    Wav,
+    Pdf,
+    Zip,
+    Xml,
+    Xmp,
+    Json,
+    Text,
+    Unknown,
+}
+
+impl MediaFormat {
+    pub fn mime_type( &self ) -> &'static str {
+        match self {
+            MediaFormat::Jpeg => "image/jpeg",
+            MediaFormat::Tiff => "image/tiff",
+            MediaFormat::Png => "image/png",
+            MediaFormat::Heif => "image/heif",
+            MediaFormat::Avif => "image/avif",
+            MediaFormat::Webp => "image/webp",
+            MediaFormat::Gif => "image/gif",
+            MediaFormat::Bmp => "image/bmp",
+            MediaFormat::CanonCr2 => "image/x-canon-cr2",
+            MediaFormat::CanonCr3 => "image/x-canon-cr3",
+            MediaFormat::NikonNef => "image/x-nikon-nef",
+            MediaFormat::SonyArw => "image/x-sony-arw",
+            MediaFormat::AdobeDng => "image/x-adobe-dng",
+            MediaFormat::PentaxPef => "image/x-pentax-pef",
+            MediaFormat::OlympusOrf => "image/x-olympus-orf",
+            MediaFormat::PanasonicRw2 => "image/x-panasonic-rw2",
+            MediaFormat::FujifilmRaf => "image/x-fuji-raf",
+            MediaFormat::QuickTime => "video/quicktime",
+            MediaFormat::Mp4 => "video/mp4",
+            MediaFormat::Avi => "video/x-msvideo",
+            MediaFormat::Mp3 => "audio/mpeg",
+            MediaFormat::Wav => "audio/wav",
+            MediaFormat::Pdf => "application/pdf",
+            MediaFormat::Zip => "application/zip",
+            MediaFormat::Xml => "application/xml",
+            MediaFormat::Xmp => "application/rdf+xml",
+            MediaFormat::Json => "application/json",
+            MediaFormat::Text => "text/plain",
+            MediaFormat::Unknown => "application/octet-stream",
+        }
+    }
+
+    pub fn is_raw( &self ) -> bool {
+        matches!(self,
+            MediaFormat::CanonCr2 | MediaFormat::CanonCr3 | MediaFormat::NikonNef | MediaFormat::SonyArw |
+            MediaFormat::AdobeDng | MediaFormat::PentaxPef | MediaFormat::OlympusOrf |
+            MediaFormat::PanasonicRw2 | MediaFormat::FujifilmRaf)
+    }
+
+    pub fn is_video( &self ) -> bool {
+        matches!(self, MediaFormat::QuickTime | MediaFormat::Mp4)
+    }
+
+    // Whether metadata can actually be read from this format, either through the EXIF reader
+    // (which understands standard TIFF structures, JPEG, PNG, HEIF and WebP) or the video reader.
+    // Recognized formats outside this list are skipped rather than reported as read failures.
+    pub fn is_supported( &self ) -> bool {
+        matches!(self,
+            MediaFormat::Jpeg | MediaFormat::Tiff | MediaFormat::Png | MediaFormat::Heif |
+            MediaFormat::Avif | MediaFormat::Webp |
+            MediaFormat::CanonCr2 | MediaFormat::NikonNef | MediaFormat::SonyArw |
+            MediaFormat::AdobeDng | MediaFormat::PentaxPef |
+            MediaFormat::QuickTime | MediaFormat::Mp4)
+    }
+}
+
+impl fmt::Display for MediaFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mime_type())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnsupportedFormatError {
+    pub format: MediaFormat,
+}
+
+impl fmt::Display for UnsupportedFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unsupported type: {}", self.format.mime_type())
+    }
+}
+
+impl Error for UnsupportedFormatError {
+    fn description(&self) -> &str {
+        "The file is not of a type whose metadata can be read"
+    }
+}
+
+pub fn detect_format( path: &Path ) -> Result<MediaFormat, Box<dyn error::Error>> {
+    let file = File::open( path )?;
+    let mut header = Vec::new();
+    file.take( SNIFF_LENGTH ).read_to_end( &mut header )?;
+    Ok( detect_format_from_bytes( &header ) )
+}
+
+// Classifies a file from its leading bytes alone; file extensions are never consulted.
+pub fn detect_format_from_bytes( header: &[u8] ) -> MediaFormat {
+    if header.starts_with( b"\xFF\xD8\xFF" ) {
+        MediaFormat::Jpeg
+    } else if header.starts_with( b"\x89PNG\r\n\x1A\n" ) {
+        MediaFormat::Png
+    } else if header.starts_with( b"II*\0" ) || header.starts_with( b"MM\0*" ) {
+        classify_tiff( header )
+    } else if header.starts_with( b"IIRO" ) || header.starts_with( b"IIRS" ) || header.starts_with( b"MMOR" ) {
+        MediaFormat::OlympusOrf
+    } else if header.starts_with( b"IIU\0" ) {
+        MediaFormat::PanasonicRw2
+    } else if header.starts_with( b"FUJIFILMCCD-RAW" ) {
+        MediaFormat::FujifilmRaf
+    } else if header.get(4..8) == Some(b"ftyp") {
+        classify_iso_media( header )
+    } else if header.starts_with( b"RIFF" ) && header.len() >= 12 {
+        match &header[8..12] {
+            b"WEBP" => MediaFormat::Webp,
+            b"AVI " => MediaFormat::Avi,
+            b"WAVE" => MediaFormat::Wav,
+            _ => MediaFormat::Unknown,
+        }
+    } else if header.starts_with( b"GIF87a" ) || header.starts_with( b"GIF89a" ) {
+        MediaFormat::Gif
+    } else if header.starts_with( b"BM" ) && header.len() >= 14 && header.get(6..10) == Some(&[0, 0, 0, 0]) {
+        MediaFormat::Bmp
+    } else if header.starts_with( b"ID3" ) || (header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0) {
+        MediaFormat::Mp3
+    } else if header.starts_with( b"%PDF" ) {
+        MediaFormat::Pdf
+    } else if header.starts_with( b"PK\x03\x04" ) {
+        MediaFormat::Zip
+    } else if is_legacy_quicktime( header ) {
+        MediaFormat::QuickTime
+    } else if let Some(text) = as_text( header ) {
+        classify_text( text )
+    } else {
+        MediaFormat::Unknown
+    }
+}
+
+fn classify_iso_media( header: &[u8] ) -> MediaFormat {
+    let major_brand = match header.get(8..12) {
+        Some(brand) => brand,
+        None => return MediaFormat::Unknown,
+    };
+    // The ftyp atom holds the major brand, a minor version, and then the compatible brands.
+    let ftyp_size = header.get(0..4)
+        .and_then(|size| size.try_into().ok())
+        .map(|size: [u8; 4]| u32::from_be_bytes( size ) as usize)
+        .unwrap_or(0)
+        .min( header.len() );
+    let compatible_brands : Vec<&[u8]> = header.get(16..ftyp_size).unwrap_or(&[]).chunks_exact(4).collect();
+
+    match major_brand {
+        b"crx " => MediaFormat::CanonCr3,
+        b"avif" | b"avis" => MediaFormat::Avif,
+        b"qt  " => MediaFormat::QuickTime,
+        brand if HEIF_BRANDS.iter().any(|heif| &heif[..] == brand) => MediaFormat::Heif,
+        _ if compatible_brands.iter().any(|brand| HEIF_BRANDS.iter().any(|heif| &heif[..] == *brand)) => MediaFormat::Heif,
+        _ => MediaFormat::Mp4,
+    }
+}
+
+// QuickTime files written before the 'ftyp' atom was introduced start directly with other atoms.
+fn is_legacy_quicktime( header: &[u8] ) -> bool {
+    match header.get(4..8) {
+        Some(kind) => [&b"moov"[..], b"mdat", b"wide", b"free", b"skip", b"pnot"].contains(&kind),
+        None => false,
+    }
+}
+
+// Tells plain TIFF images from the TIFF-based RAW formats by looking at IFD0.
+fn classify_tiff( header: &[u8] ) -> MediaFormat {
+    if header.get(8..11) == Some(b"CR\x02") {
+        return MediaFormat::CanonCr2;
+    }
+
+    let big_endian = header.starts_with( b"MM" );
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes : [u8; 2] = header.get(offset..offset + 2)?.try_into().ok()?;
+        Some( if big_endian { u16::from_be_bytes( bytes ) } else { u16::from_le_bytes( bytes ) } )
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes : [u8; 4] = header.get(offset..offset + 4)?.try_into().ok()?;
+        Some( if big_endian { u32::from_be_bytes( bytes ) } else { u32::from_le_bytes( bytes ) } )
+    };
+
+    let ifd0_offset = match read_u32( 4 ) {
+        Some(offset) => offset as usize,
+        None => return MediaFormat::Tiff,
+    };
+    let entry_count = read_u16( ifd0_offset ).unwrap_or(0) as usize;
+
+    let mut make = None;
+    let mut has_sub_ifds = false;
+    let mut is_reduced_resolution = false;
+    for index in 0..entry_count {
+        let entry = ifd0_offset + 2 + index * 12;
+        let tag = match read_u16( entry ) {
+            Some(tag) => tag,
+            None => break,
+        };
+        match tag {
+            TIFF_TAG_DNG_VERSION => return MediaFormat::AdobeDng,
+            TIFF_TAG_SUB_IFDS => has_sub_ifds = true,
+            TIFF_TAG_NEW_SUBFILE_TYPE => is_reduced_resolution = read_u32( entry + 8 ) == Some(1),
+            TIFF_TAG_MAKE => {
+                let count = read_u32( entry + 4 ).unwrap_or(0) as usize;
+                let value = if count <= 4 {
+                    header.get(entry + 8..entry + 8 + count)
+                } else {
+                    read_u32( entry + 8 ).and_then(|offset| header.get(offset as usize..offset as usize + count))
+                };
+                make = value.map(|bytes| String::from_utf8_lossy( bytes ).to_uppercase());
+            },
+            _ => {}
+        }
+    }
+
+    // A TIFF exported from an editor keeps the camera's Make, so the vendor alone is not
+    // enough; RAW files also carry the full image in a SubIFD behind a reduced-size IFD0.
+    if !(has_sub_ifds || is_reduced_resolution) {
+        return MediaFormat::Tiff;
+    }
+    match make {
+        Some(make) if make.starts_with("NIKON") => MediaFormat::NikonNef,
+        Some(make) if make.starts_with("SONY") => MediaFormat::SonyArw,
+        Some(make) if make.starts_with("PENTAX") || make.starts_with("RICOH") => MediaFormat::PentaxPef,
+        _ => MediaFormat::Tiff,
+    }
+}
+
+// Returns the header as text if it looks like a text file: valid UTF-8 (allowing a
+// character cut in half at the end of the sniffed range) without binary control characters.
+fn as_text( header: &[u8] ) -> Option<&str> {
+    if header.is_empty() {
+        return None;
+    }
+    let text = match str::from_utf8( header ) {
+        Ok(text) => text,
+        Err(error) if error.error_len().is_none() => str::from_utf8( &header[..error.valid_up_to()] ).ok()?,
+        Err(_) => return None,
+    };
+    if text.chars().any(|character| character.is_control() && !matches!(character, '\t' | '\n' | '\r' | '\x0C')) {
+        return None;
+    }
+    Some(text)
+}
+
+fn classify_text( text: &str ) -> MediaFormat {
+    let trimmed = text.trim_start_matches('\u{FEFF}').trim_start();
+    if trimmed.starts_with("<?xpacket") || trimmed.starts_with("<x:xmpmeta") || trimmed.contains("<x:xmpmeta") {
+        MediaFormat::Xmp
+    } else if trimmed.starts_with("<?xml") {
+        MediaFormat::Xml
+    } else if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        MediaFormat::Json
+    } else {
+        MediaFormat::Text
+    }
+}
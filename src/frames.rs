@@ -0,0 +1,277 @@
+use std::collections::BTreeSet;
+use std::error;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::format::MediaFormat;
+use crate::jpeg;
+use crate::previews;
+use crate::tiff::{self, ByteOrder};
+use crate::ImageMetadataOfInterest;
+
+// Tags which kamadak-exif does not name.
+const TAG_NEW_SUBFILE_TYPE : exif::Tag = exif::Tag(exif::Context::Tiff, 0x00FE);
+const TAG_SUB_IFDS : exif::Tag = exif::Tag(exif::Context::Tiff, 0x014A);
+
+// Tag of the MP Entry field in the MP Index IFD of an MPO file (CIPA DC-007).
+const MPF_TAG_MP_ENTRY : u16 = 0xB002;
+const MPF_ENTRY_LENGTH : usize = 16;
+
+// Bound on the SubIFDs followed from one IFD, as a guard against corrupt counts.
+const MAX_SUB_IFDS : usize = 8;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameKind {
+    // IFD0, the main image of the file.
+    Primary,
+    // IFD1 of a JPEG/HEIF/PNG/WebP file: the small embedded JPEG thumbnail.
+    Thumbnail,
+    // A further IFD in the chain of a TIFF-based file, such as a page of a multi-page TIFF.
+    Page,
+    // A further IFD flagged as a reduced-resolution version of another image.
+    Preview,
+    // An IFD referenced through the SubIFDs tag, e.g. the full-size image or large preview of a RAW file.
+    SubImage,
+    // One of the additional images of a multi-picture (MPO) JPEG, such as the second eye of a stereo pair.
+    MpoImage,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+#[derive(Serialize,Deserialize)]
+pub struct FrameMetadataOfInterest {
+    pub index: usize,
+    pub kind: FrameKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    // The MP type of the image from the MPF index, only present for MPO files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mp_type: Option<String>,
+    // Location of the frame's encoded data within the file, where it is a separate byte range.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_offset: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_length: Option<u64>,
+    #[serde(flatten)]
+    pub image_metadata: ImageMetadataOfInterest,
+}
+
+impl FrameMetadataOfInterest {
+    fn new( kind: FrameKind ) -> FrameMetadataOfInterest {
+        FrameMetadataOfInterest {
+            index: 0,
+            kind,
+            width: None,
+            height: None,
+            mp_type: None,
+            byte_offset: None,
+            byte_length: None,
+            image_metadata: ImageMetadataOfInterest::default(),
+        }
+    }
+}
+
+pub(crate) fn first_uint( exif_fields: &exif::Exif, tag: exif::Tag, ifd: exif::In ) -> Option<u32> {
+    exif_fields.get_field( tag, ifd ).and_then(|field| field.value.get_uint( 0 ))
+}
+
+// The first string of an ASCII field, trimmed, if it isn't blank.
+pub(crate) fn first_ascii( exif_fields: &exif::Exif, tag: exif::Tag, ifd: exif::In ) -> Option<String> {
+    match &exif_fields.get_field( tag, ifd )?.value {
+        exif::Value::Ascii(values) => values.first().map(|value| String::from_utf8_lossy( value ).trim().to_string()).filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+fn frame_from_ifd( exif_fields: &exif::Exif, ifd: exif::In, kind: FrameKind, tiff_position: Option<usize>, path: &Path ) -> FrameMetadataOfInterest {
+    let mut frame = FrameMetadataOfInterest::new( kind );
+    frame.image_metadata = crate::image_metadata_from_exif( exif_fields, ifd, path );
+
+    frame.width = first_uint( exif_fields, exif::Tag::ImageWidth, ifd )
+        .or_else(|| first_uint( exif_fields, exif::Tag::PixelXDimension, ifd ));
+    frame.height = first_uint( exif_fields, exif::Tag::ImageLength, ifd )
+        .or_else(|| first_uint( exif_fields, exif::Tag::PixelYDimension, ifd ));
+
+    // JPEG-compressed thumbnails and previews are stored as a complete JPEG inside the TIFF structure.
+    let jpeg_offset = first_uint( exif_fields, exif::Tag::JPEGInterchangeFormat, ifd );
+    let jpeg_length = first_uint( exif_fields, exif::Tag::JPEGInterchangeFormatLength, ifd );
+    // Some RAW formats instead store a JPEG preview as the single strip of an old-style JPEG IFD.
+    let single_strip = || {
+        let compression = first_uint( exif_fields, exif::Tag::Compression, ifd )?;
+        let offsets = exif_fields.get_field( exif::Tag::StripOffsets, ifd )?;
+        let counts = exif_fields.get_field( exif::Tag::StripByteCounts, ifd )?;
+        if (compression == 6 || compression == 7) && offsets.value.iter_uint().map(Iterator::count) == Some(1) && counts.value.iter_uint().map(Iterator::count) == Some(1) {
+            Some( (offsets.value.get_uint( 0 )?, counts.value.get_uint( 0 )?) )
+        } else {
+            None
+        }
+    };
+    let jpeg_location = match (jpeg_offset, jpeg_length) {
+        (Some(offset), Some(length)) => Some( (offset, length) ),
+        _ => single_strip(),
+    };
+    if let Some((offset, length)) = jpeg_location {
+        let embedded = exif_fields.buf().get(offset as usize..(offset as usize).saturating_add(length as usize));
+        if let Some((width, height)) = embedded.and_then(jpeg::jpeg_dimensions) {
+            frame.width = frame.width.or(Some(width));
+            frame.height = frame.height.or(Some(height));
+        }
+        if let Some(position) = tiff_position {
+            frame.byte_offset = Some( (position + offset as usize) as u64 );
+            frame.byte_length = Some( length as u64 );
+        }
+    }
+    frame
+}
+
+// Reads a SubIFD by pointing a copy of the TIFF header at it, so that kamadak-exif parses it as IFD0.
+fn read_sub_ifd( exif_fields: &exif::Exif, offset: u32 ) -> Option<exif::Exif> {
+    let mut buffer = exif_fields.buf().to_vec();
+    let offset_bytes = if exif_fields.little_endian() { offset.to_le_bytes() } else { offset.to_be_bytes() };
+    buffer.get_mut(4..8)?.copy_from_slice( &offset_bytes );
+    exif::Reader::new().read_raw( buffer ).ok()
+}
+
+fn sub_ifd_offsets( exif_fields: &exif::Exif, ifd: exif::In ) -> Vec<u32> {
+    match exif_fields.get_field( TAG_SUB_IFDS, ifd ) {
+        Some(field) => field.value.iter_uint().map(|offsets| offsets.take(MAX_SUB_IFDS).collect()).unwrap_or_default(),
+        None => vec![],
+    }
+}
+
+fn mp_type_name( attribute: u32 ) -> String {
+    match attribute & 0x00FF_FFFF {
+        0x03_0000 => "baseline_primary".to_string(),
+        0x01_0001 => "large_thumbnail_vga".to_string(),
+        0x01_0002 => "large_thumbnail_full_hd".to_string(),
+        0x02_0001 => "panorama".to_string(),
+        0x02_0002 => "disparity".to_string(),
+        0x02_0003 => "multi_angle".to_string(),
+        0x00_0000 => "undefined".to_string(),
+        other => format!("unknown_{:06x}", other),
+    }
+}
+
+struct MpEntry {
+    attribute: u32,
+    size: u32,
+    offset: u32,
+}
+
+// Parses the MP Entry list from the MP Index IFD in the APP2 "MPF" segment.
+fn read_mp_entries( mpf: &[u8] ) -> Option<Vec<MpEntry>> {
+    let order = ByteOrder::from_tiff_header( mpf )?;
+    let index_ifd_offset = order.u32( mpf, 4 )? as usize;
+    let (entries, _) = tiff::read_ifd( mpf, index_ifd_offset, order )?;
+    let mp_entry = entries.iter().find(|entry| entry.tag == MPF_TAG_MP_ENTRY)?;
+    let bytes = mp_entry.value_bytes( mpf, order )?;
+    Some( bytes.chunks_exact( MPF_ENTRY_LENGTH )
+        .filter_map(|entry| Some( MpEntry {
+            attribute: order.u32( entry, 0 )?,
+            size: order.u32( entry, 4 )?,
+            offset: order.u32( entry, 8 )?,
+        } ))
+        .collect() )
+}
+
+pub(crate) fn read_exif_from_bytes( data: &[u8] ) -> Result<Option<exif::Exif>, Box<dyn error::Error>> {
+    match exif::Reader::new().read_from_container( &mut Cursor::new( data ) ) {
+        Ok(exif_fields) => Ok(Some(exif_fields)),
+        Err(exif::Error::NotFound(_)) => Ok(None),
+        Err(unboxed_err) => Err(Box::new(unboxed_err)),
+    }
+}
+
+// Lists every image held in the file with its own metadata: each IFD in the main chain,
+// the SubIFDs they reference, and for MPO files the additional images from the MPF index.
+pub(crate) fn read_frames( path: &Path, format: MediaFormat ) -> Result<Vec<FrameMetadataOfInterest>, Box<dyn error::Error>> {
+    let data = fs::read( path )?;
+    let exif_fields = read_exif_from_bytes( &data )?;
+    let exif_position = exif_fields.as_ref().and_then(|exif_fields| previews::exif_block_position( &data, format, exif_fields ));
+    Ok( frames_from_data( &data, format, exif_fields.as_ref(), exif_position, path ) )
+}
+
+// As read_frames, for a file which has already been read along with its EXIF data.
+pub(crate) fn frames_from_data( data: &[u8], format: MediaFormat, exif_fields: Option<&exif::Exif>, exif_position: Option<usize>, path: &Path ) -> Vec<FrameMetadataOfInterest> {
+    let mut frames = vec![];
+    let tiff_based = ByteOrder::from_tiff_header( data ).is_some();
+
+    if let Some(exif_fields) = exif_fields {
+        let position = exif_position;
+        let ifd_numbers : BTreeSet<u16> = exif_fields.fields().map(|field| field.ifd_num.0).collect();
+        for ifd_number in ifd_numbers {
+            let ifd = exif::In(ifd_number);
+            let kind = if ifd_number == 0 {
+                FrameKind::Primary
+            } else if !tiff_based {
+                if ifd_number == 1 { FrameKind::Thumbnail } else { FrameKind::Page }
+            } else if first_uint( exif_fields, TAG_NEW_SUBFILE_TYPE, ifd ).is_some_and(|subfile_type| subfile_type & 1 == 1) {
+                FrameKind::Preview
+            } else {
+                FrameKind::Page
+            };
+            frames.push( frame_from_ifd( exif_fields, ifd, kind, position, path ) );
+
+            for offset in sub_ifd_offsets( exif_fields, ifd ) {
+                if let Some(sub_ifd) = read_sub_ifd( exif_fields, offset ) {
+                    frames.push( frame_from_ifd( &sub_ifd, exif::In::PRIMARY, FrameKind::SubImage, position, path ) );
+                }
+            }
+        }
+    }
+
+    if format == MediaFormat::Jpeg {
+        if let Some(primary) = frames.iter_mut().find(|frame| frame.kind == FrameKind::Primary) {
+            if let Some((width, height)) = jpeg::jpeg_dimensions( data ) {
+                primary.width = Some(width);
+                primary.height = Some(height);
+            }
+        }
+        if let Some((mpf_position, mpf)) = jpeg::find_mpf_segment( data ) {
+            let mp_entries = read_mp_entries( mpf ).unwrap_or_default();
+            for (entry_index, entry) in mp_entries.iter().enumerate() {
+                if entry_index == 0 {
+                    // The first entry is the file's own primary image, which is already listed.
+                    if let Some(primary) = frames.iter_mut().find(|frame| frame.kind == FrameKind::Primary) {
+                        primary.mp_type = Some( mp_type_name( entry.attribute ) );
+                        primary.byte_offset = Some(0);
+                        primary.byte_length = Some( entry.size as u64 );
+                    }
+                    continue;
+                }
+                // Offsets of the other images are relative to the MPF header, not the start of the file.
+                let start = mpf_position + entry.offset as usize;
+                let image = match data.get(start..start.saturating_add(entry.size as usize)) {
+                    Some(image) => image,
+                    None => {
+                        eprintln!("MPF entry {} points outside of the file: {}",entry_index,path.to_string_lossy());
+                        continue;
+                    }
+                };
+                let mut frame = match read_exif_from_bytes( image ) {
+                    Ok(Some(exif_fields)) => frame_from_ifd( &exif_fields, exif::In::PRIMARY, FrameKind::MpoImage, None, path ),
+                    _ => FrameMetadataOfInterest::new( FrameKind::MpoImage ),
+                };
+                if let Some((width, height)) = jpeg::jpeg_dimensions( image ) {
+                    frame.width = Some(width);
+                    frame.height = Some(height);
+                }
+                frame.mp_type = Some( mp_type_name( entry.attribute ) );
+                frame.byte_offset = Some( start as u64 );
+                frame.byte_length = Some( entry.size as u64 );
+                frames.push( frame );
+            }
+        }
+    }
+
+    for (index, frame) in frames.iter_mut().enumerate() {
+        frame.index = index;
+    }
+    frames
+}
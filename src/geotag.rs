@@ -0,0 +1,399 @@
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, TimeZone, Timelike, Utc};
+
+use crate::sidecar::sidecar_base;
+use crate::writer::{copied_field, write_fields, FieldChange, FieldWrite, IfdLocation, WriteOptions};
+use crate::tiff::ByteOrder;
+use crate::{json_sidecar_path, read_metadata_of_interest, write_json_metadata_with_redaction, GpsLocation, Redaction};
+
+const TAG_GPS_VERSION : u16 = 0x0000;
+const TAG_GPS_LATITUDE_REF : u16 = 0x0001;
+const TAG_GPS_LATITUDE : u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF : u16 = 0x0003;
+const TAG_GPS_LONGITUDE : u16 = 0x0004;
+const TAG_GPS_ALTITUDE_REF : u16 = 0x0005;
+const TAG_GPS_ALTITUDE : u16 = 0x0006;
+const TAG_GPS_TIME_STAMP : u16 = 0x0007;
+const TAG_GPS_MAP_DATUM : u16 = 0x0012;
+const TAG_GPS_DATE_STAMP : u16 = 0x001D;
+
+// Seconds of arc are written in ten-thousandths, which is a few millimetres.
+const ARC_SECOND_DENOMINATOR : u32 = 10000;
+
+// One logged position.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct TrackPoint {
+    pub time: chrono::DateTime<Utc>,
+    pub location: GpsLocation,
+}
+
+// The positions of one or more GPS logs, in time order.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Track {
+    points: Vec<TrackPoint>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackParseError {
+    path: Option<PathBuf>,
+    reason: String,
+}
+
+impl fmt::Display for TrackParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "Couldn't read GPS track {}: {}", path.to_string_lossy(), self.reason),
+            None => write!(f, "Couldn't read GPS track: {}", self.reason),
+        }
+    }
+}
+
+impl Error for TrackParseError {
+    fn description(&self) -> &str {
+        "A GPS track could not be parsed"
+    }
+}
+
+fn track_error( reason: &str ) -> TrackParseError {
+    TrackParseError { path: None, reason: reason.to_string() }
+}
+
+// The value of an attribute of an XML start tag, such as lat in `<trkpt lat="51.5" lon="-0.1">`.
+fn attribute( start_tag: &str, name: &str ) -> Option<String> {
+    let mut rest = start_tag;
+    while let Some(position) = rest.find( name ) {
+        let preceded_by_space = rest[..position].ends_with(|c: char| c.is_whitespace());
+        let after = rest[position + name.len()..].trim_start();
+        rest = &rest[position + name.len()..];
+        if !preceded_by_space || !after.starts_with( '=' ) {
+            continue;
+        }
+        let value = after[1..].trim_start();
+        let quote = value.chars().next().filter(|quote| *quote == '"' || *quote == '\'')?;
+        let end = value[1..].find( quote )?;
+        return Some( value[1..1 + end].to_string() );
+    }
+    None
+}
+
+// The text of the first child element with the given name, such as `<time>...</time>`.
+fn child_text<'a>( body: &'a str, name: &str ) -> Option<&'a str> {
+    let start = body.find( &format!("<{}>", name) )? + name.len() + 2;
+    let end = body[start..].find( &format!("</{}>", name) )?;
+    Some( body[start..start + end].trim() )
+}
+
+// GPX times are UTC, usually with a Z and sometimes with fractions of a second.
+fn parse_gpx_time( text: &str ) -> Option<chrono::DateTime<Utc>> {
+    chrono::DateTime::parse_from_rfc3339( text ).ok().map(|time| time.with_timezone( &Utc ))
+        .or_else(|| chrono::NaiveDateTime::parse_from_str( text, "%Y-%m-%dT%H:%M:%S%.f" ).ok().map(|time| Utc.from_utc_datetime( &time )))
+}
+
+// Degrees and minutes as NMEA writes them, such as 4807.038 for 48 degrees 7.038 minutes.
+fn parse_nmea_coordinate( value: &str, hemisphere: &str, negative_hemisphere: &str ) -> Option<f64> {
+    let raw : f64 = value.parse().ok()?;
+    let degrees = (raw / 100.0).trunc();
+    let coordinate = degrees + (raw - degrees * 100.0) / 60.0;
+    Some( if hemisphere == negative_hemisphere { -coordinate } else { coordinate } )
+}
+
+fn parse_nmea_time_of_day( value: &str ) -> Option<chrono::NaiveTime> {
+    chrono::NaiveTime::parse_from_str( value, "%H%M%S%.f" ).ok()
+}
+
+// The fields of an NMEA sentence, if its checksum matches or it has none.
+fn nmea_fields( line: &str ) -> Option<Vec<&str>> {
+    let sentence = line.trim().strip_prefix( '$' )?;
+    let (body, checksum) = match sentence.split_once( '*' ) {
+        Some((body, checksum)) => (body, Some(checksum)),
+        None => (sentence, None),
+    };
+    if let Some(checksum) = checksum {
+        let expected = u8::from_str_radix( checksum.trim(), 16 ).ok()?;
+        if body.bytes().fold( 0, |sum, byte| sum ^ byte ) != expected {
+            return None;
+        }
+    }
+    Some( body.split( ',' ).collect() )
+}
+
+impl Track {
+    pub fn new( mut points: Vec<TrackPoint> ) -> Track {
+        points.sort_by_key(|point| point.time);
+        // Loggers which write several sentences per fix give the same time twice; keep one
+        // point, with an altitude if any of them has one.
+        points.dedup_by(|later, earlier| {
+            if later.time == earlier.time {
+                earlier.location.altitude = earlier.location.altitude.or( later.location.altitude );
+                true
+            } else {
+                false
+            }
+        });
+        Track { points }
+    }
+
+    pub fn points( &self ) -> &[TrackPoint] {
+        &self.points
+    }
+
+    // Reads the track points of a GPX file. Points without a time can't be matched, so are skipped.
+    pub fn from_gpx( text: &str ) -> Result<Track, TrackParseError> {
+        let mut points = vec![];
+        let mut rest = text;
+        while let Some(start) = rest.find( "<trkpt" ) {
+            rest = &rest[start..];
+            let tag_end = rest.find( '>' ).ok_or_else(|| track_error( "unterminated trkpt element" ))?;
+            let start_tag = &rest[..tag_end];
+            let (body, next) = if start_tag.ends_with( '/' ) {
+                ("", tag_end + 1)
+            } else {
+                let end = rest.find( "</trkpt>" ).ok_or_else(|| track_error( "unterminated trkpt element" ))?;
+                (&rest[tag_end + 1..end], end + "</trkpt>".len())
+            };
+            let coordinate = |name| attribute( start_tag, name ).and_then(|value| value.trim().parse::<f64>().ok());
+            let (latitude, longitude) = match (coordinate( "lat" ), coordinate( "lon" )) {
+                (Some(latitude), Some(longitude)) => (latitude, longitude),
+                _ => return Err( track_error( "trkpt without a valid lat and lon" ) ),
+            };
+            if let Some(time) = child_text( body, "time" ).and_then(parse_gpx_time) {
+                let altitude = child_text( body, "ele" ).and_then(|value| value.parse::<f64>().ok());
+                points.push( TrackPoint { time, location: GpsLocation { latitude, longitude, altitude } } );
+            }
+            rest = &rest[next..];
+        }
+        if points.is_empty() {
+            return Err( track_error( "no timed track points" ) );
+        }
+        Ok( Track::new( points ) )
+    }
+
+    // Reads the fixes of an NMEA log from its RMC sentences, which have the date, and its GGA
+    // sentences, which have the altitude and are dated by the RMC sentence before them.
+    // Sentences with a bad checksum or without a fix are skipped.
+    pub fn from_nmea( text: &str ) -> Result<Track, TrackParseError> {
+        let mut points = vec![];
+        let mut date : Option<chrono::NaiveDate> = None;
+        for fields in text.lines().filter_map(nmea_fields) {
+            let kind = fields[0].get( 2.. ).unwrap_or("");
+            let field = |index: usize| fields.get( index ).copied().unwrap_or("");
+            let (time_of_day, latitude, longitude, altitude) = match kind {
+                "RMC" if field( 2 ) == "A" => {
+                    date = chrono::NaiveDate::parse_from_str( field( 9 ), "%d%m%y" ).ok();
+                    (parse_nmea_time_of_day( field( 1 ) ), parse_nmea_coordinate( field( 3 ), field( 4 ), "S" ), parse_nmea_coordinate( field( 5 ), field( 6 ), "W" ), None)
+                },
+                "GGA" if !field( 6 ).is_empty() && field( 6 ) != "0" => {
+                    (parse_nmea_time_of_day( field( 1 ) ), parse_nmea_coordinate( field( 2 ), field( 3 ), "S" ), parse_nmea_coordinate( field( 4 ), field( 5 ), "W" ), field( 9 ).parse::<f64>().ok())
+                },
+                _ => continue,
+            };
+            if let (Some(date), Some(time_of_day), Some(latitude), Some(longitude)) = (date, time_of_day, latitude, longitude) {
+                let time = Utc.from_utc_datetime( &date.and_time( time_of_day ) );
+                points.push( TrackPoint { time, location: GpsLocation { latitude, longitude, altitude } } );
+            }
+        }
+        if points.is_empty() {
+            return Err( track_error( "no dated fixes" ) );
+        }
+        Ok( Track::new( points ) )
+    }
+
+    // Reads a GPX or NMEA file, telling them apart by their contents.
+    pub fn load( path: &Path ) -> Result<Track, Box<dyn error::Error>> {
+        let text = String::from_utf8_lossy( &fs::read( path )? ).into_owned();
+        let parsed = if text.trim_start().starts_with( '<' ) { Track::from_gpx( &text ) } else { Track::from_nmea( &text ) };
+        parsed.map_err(|err| Box::new( TrackParseError { path: Some(path.to_path_buf()), ..err } ).into())
+    }
+
+    // Merges the points of several logs, such as one per logger or one per day.
+    pub fn load_all( paths: &[PathBuf] ) -> Result<Track, Box<dyn error::Error>> {
+        let mut points = vec![];
+        for path in paths {
+            points.extend( Track::load( path )?.points );
+        }
+        Ok( Track::new( points ) )
+    }
+
+    // The position at a time, interpolated between the points either side of it. Points further
+    // apart than max_gap aren't interpolated between, as the logger may have been off or without
+    // a fix; times before or after the track are only matched within max_gap of its ends.
+    pub fn position_at( &self, time: chrono::DateTime<Utc>, max_gap: chrono::Duration ) -> Result<GpsLocation, UnmatchedReason> {
+        let index = self.points.partition_point(|point| point.time <= time);
+        let before = index.checked_sub( 1 ).map(|before| &self.points[before]);
+        let after = self.points.get( index );
+        match (before, after) {
+            (Some(before), _) if before.time == time => Ok(before.location),
+            (Some(before), Some(after)) => {
+                let gap = after.time - before.time;
+                if gap > max_gap {
+                    return Err( UnmatchedReason::GapTooLong(gap) );
+                }
+                let fraction = (time - before.time).num_milliseconds() as f64 / gap.num_milliseconds() as f64;
+                let between = |from: f64, to: f64| from + (to - from) * fraction;
+                // Take the short way round when the track crosses the antimeridian.
+                let mut longitude_to = after.location.longitude;
+                if longitude_to - before.location.longitude > 180.0 {
+                    longitude_to -= 360.0;
+                } else if before.location.longitude - longitude_to > 180.0 {
+                    longitude_to += 360.0;
+                }
+                let mut longitude = between( before.location.longitude, longitude_to );
+                if longitude > 180.0 {
+                    longitude -= 360.0;
+                } else if longitude < -180.0 {
+                    longitude += 360.0;
+                }
+                Ok( GpsLocation {
+                    latitude: between( before.location.latitude, after.location.latitude ),
+                    longitude,
+                    altitude: match (before.location.altitude, after.location.altitude) {
+                        (Some(from), Some(to)) => Some( between( from, to ) ),
+                        (from, to) => from.or( to ),
+                    },
+                } )
+            },
+            (Some(last), None) if time - last.time <= max_gap => Ok(last.location),
+            (None, Some(first)) if first.time - time <= max_gap => Ok(first.location),
+            _ => Err( UnmatchedReason::OutsideTrack ),
+        }
+    }
+}
+
+// Why no position was found for a file.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum UnmatchedReason {
+    NoCaptureTime,
+    OutsideTrack,
+    // The points either side of the capture time are this far apart.
+    GapTooLong(chrono::Duration),
+}
+
+impl fmt::Display for UnmatchedReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnmatchedReason::NoCaptureTime => write!(f, "no capture time"),
+            UnmatchedReason::OutsideTrack => write!(f, "taken outside the tracks"),
+            UnmatchedReason::GapTooLong(gap) => write!(f, "taken during a {} second gap in the tracks", gap.num_seconds()),
+        }
+    }
+}
+
+// How to match files to GPS tracks.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Geotag {
+    pub tracks: Vec<PathBuf>,
+    // The offset from UTC of the camera clocks, which write local time without a zone.
+    pub utc_offset: chrono::Duration,
+    // Added to the capture times before matching, for a camera clock which was off.
+    pub clock_offset: chrono::Duration,
+    // The longest time between track points which is interpolated across.
+    pub max_gap: chrono::Duration,
+    // Write the positions into the files, rather than only into the JSON sidecars.
+    pub write_back: bool,
+}
+
+impl Default for Geotag {
+    fn default() -> Geotag {
+        Geotag {
+            tracks: vec![],
+            utc_offset: chrono::Duration::zero(),
+            clock_offset: chrono::Duration::zero(),
+            max_gap: chrono::Duration::minutes( 5 ),
+            write_back: false,
+        }
+    }
+}
+
+impl Geotag {
+    // The UTC time a capture time stands for.
+    pub fn utc_time( &self, capture_time: chrono::NaiveDateTime ) -> Option<chrono::DateTime<Utc>> {
+        let corrected = capture_time.checked_add_signed( self.clock_offset )?.checked_sub_signed( self.utc_offset )?;
+        Some( Utc.from_utc_datetime( &corrected ) )
+    }
+}
+
+// What geotagging did to a file.
+#[derive(Debug,Clone,PartialEq)]
+pub enum GeotagOutcome {
+    // The position found, and the fields changed in the file if it was written.
+    Tagged { location: GpsLocation, changes: Vec<FieldChange> },
+    // The file has a position of its own, which is kept.
+    AlreadyTagged(GpsLocation),
+    Unmatched(UnmatchedReason),
+}
+
+// Degrees as the three rationals of degrees, minutes and seconds which EXIF uses.
+fn degrees_minutes_seconds( degrees: f64 ) -> exif::Value {
+    let units = (degrees.abs() * 3600.0 * ARC_SECOND_DENOMINATOR as f64).round() as u64;
+    let per_degree = 3600 * ARC_SECOND_DENOMINATOR as u64;
+    exif::Value::Rational( vec![
+        exif::Rational::from( ((units / per_degree) as u32, 1) ),
+        exif::Rational::from( ((units / (60 * ARC_SECOND_DENOMINATOR as u64) % 60) as u32, 1) ),
+        exif::Rational::from( ((units % (60 * ARC_SECOND_DENOMINATOR as u64)) as u32, ARC_SECOND_DENOMINATOR) ),
+    ] )
+}
+
+// The GPS fields for a position logged at a time, in the byte order of the file being written.
+fn gps_writes( location: &GpsLocation, time: chrono::DateTime<Utc>, order: ByteOrder ) -> Vec<FieldWrite> {
+    let ascii = |text: &str| exif::Value::Ascii( vec![text.as_bytes().to_vec()] );
+    let mut fields = vec![
+        ("gps_version", TAG_GPS_VERSION, exif::Value::Byte( vec![2, 3, 0, 0] )),
+        ("gps_latitude_ref", TAG_GPS_LATITUDE_REF, ascii( if location.latitude < 0.0 { "S" } else { "N" } )),
+        ("gps_latitude", TAG_GPS_LATITUDE, degrees_minutes_seconds( location.latitude )),
+        ("gps_longitude_ref", TAG_GPS_LONGITUDE_REF, ascii( if location.longitude < 0.0 { "W" } else { "E" } )),
+        ("gps_longitude", TAG_GPS_LONGITUDE, degrees_minutes_seconds( location.longitude )),
+    ];
+    if let Some(altitude) = location.altitude {
+        let centimetres = (altitude.abs() * 100.0).round() as u32;
+        fields.push( ("gps_altitude_ref", TAG_GPS_ALTITUDE_REF, exif::Value::Byte( vec![if altitude < 0.0 { 1 } else { 0 }] )) );
+        fields.push( ("gps_altitude", TAG_GPS_ALTITUDE, exif::Value::Rational( vec![exif::Rational::from( (centimetres, 100) )] )) );
+    }
+    fields.push( ("gps_time_stamp", TAG_GPS_TIME_STAMP, exif::Value::Rational( vec![
+        exif::Rational::from( (time.hour(), 1) ),
+        exif::Rational::from( (time.minute(), 1) ),
+        exif::Rational::from( (time.second(), 1) ),
+    ] )) );
+    fields.push( ("gps_date_stamp", TAG_GPS_DATE_STAMP, ascii( &format!("{:04}:{:02}:{:02}", time.year(), time.month(), time.day()) )) );
+    fields.push( ("gps_map_datum", TAG_GPS_MAP_DATUM, ascii( "WGS-84" )) );
+    fields.iter()
+        .filter_map(|(field, tag, value)| copied_field( field, IfdLocation::Gps, *tag, value, order ))
+        .collect()
+}
+
+// Finds the position of a file in the track from its capture time, and writes it into the JSON
+// sidecar, and into the file as well with write_back. Files which already have a position are
+// left alone. An existing sidecar is updated rather than rewritten, so that times corrected in
+// it by sync are the ones matched, and edits made in it are kept.
+pub fn geotag_image( path: &Path, track: &Track, geotag: &Geotag, options: &WriteOptions, redaction: &Redaction ) -> Result<GeotagOutcome, Box<dyn error::Error>> {
+    let current = read_metadata_of_interest( path )?;
+    let (mut metadata, sidecar_path) = sidecar_base( path, &current )?;
+    if let Some(location) = current.image_metadata.location.or( metadata.image_metadata.location ) {
+        return Ok( GeotagOutcome::AlreadyTagged(location) );
+    }
+    let time = match metadata.image_metadata.capture_time.and_then(|capture_time| geotag.utc_time( capture_time )) {
+        Some(time) => time,
+        None => return Ok( GeotagOutcome::Unmatched(UnmatchedReason::NoCaptureTime) ),
+    };
+    let location = match track.position_at( time, geotag.max_gap ) {
+        Ok(location) => location,
+        Err(reason) => return Ok( GeotagOutcome::Unmatched(reason) ),
+    };
+
+    let changes = if geotag.write_back {
+        write_fields( path, &|_, order| gps_writes( &location, time, order ), options )?
+    } else {
+        vec![]
+    };
+    if !options.dry_run {
+        metadata.image_metadata.location = Some(location);
+        if let Some(json_path) = sidecar_path.or_else(|| json_sidecar_path( path )) {
+            write_json_metadata_with_redaction( &metadata, &json_path, redaction )?;
+        }
+    }
+    Ok( GeotagOutcome::Tagged { location, changes } )
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeSet;
+use std::error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::format::{self, MediaFormat};
+use crate::tiff::{self, ByteOrder};
+use crate::{jpeg, png, webp};
+
+const MARKER_COM : u8 = 0xFE;
+const MARKER_APP15 : u8 = 0xEF;
+
+// Tags of a TIFF IFD which locate its image data, as (offsets, byte counts).
+const DATA_LOCATION_TAGS : [(u16, u16); 2] = [(0x0111, 0x0117), (0x0144, 0x0145)];
+const TAG_SUB_IFDS : u16 = 0x014A;
+
+// Bound on the IFDs followed in one file, as a guard against loops in corrupt files.
+const MAX_IFDS : usize = 32;
+
+const READ_BUFFER_LENGTH : usize = 1 << 16;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn from_name( name: &str ) -> Option<HashAlgorithm> {
+        match name {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    fn hasher( self ) -> Hasher {
+        match self {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new( blake3::Hasher::new() )),
+        }
+    }
+}
+
+// The BLAKE3 state is boxed, as it is far larger than the SHA-256 one.
+enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn update( &mut self, data: &[u8] ) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update( data ),
+            Hasher::Blake3(hasher) => { hasher.update( data ); },
+        }
+    }
+
+    fn finalize( self ) -> String {
+        match self {
+            Hasher::Sha256(hasher) => to_hex( &hasher.finalize() ),
+            Hasher::Blake3(hasher) => to_hex( hasher.finalize().as_bytes() ),
+        }
+    }
+}
+
+pub(crate) fn to_hex( bytes: &[u8] ) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The SHA-256 of a file, read a buffer at a time.
+pub(crate) fn sha256_file( path: &Path ) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    read_in_pieces( path, &mut |piece| hasher.update( piece ) )?;
+    Ok( hasher.finalize().into() )
+}
+
+// Feeds a file to the sink in pieces, through a buffered reader.
+fn read_in_pieces( path: &Path, sink: &mut dyn FnMut( &[u8] ) ) -> io::Result<()> {
+    let mut reader = BufReader::with_capacity( READ_BUFFER_LENGTH, File::open( path )? );
+    loop {
+        let piece = reader.fill_buf()?;
+        if piece.is_empty() {
+            return Ok(());
+        }
+        let length = piece.len();
+        sink( piece );
+        reader.consume( length );
+    }
+}
+
+// Hex digests of the whole file, and of its image payload alone: the file with its metadata
+// left out, so that a copy which was tagged differently still has the same payload hash. The
+// payload hash is only given for JPEG, PNG, WebP and TIFF-based files.
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+pub struct FileHashes {
+    pub algorithm: HashAlgorithm,
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+}
+
+// Files hashed as a whole are read a buffer at a time, so videos are never held in memory. Images
+// with a payload are read once and both hashes are fed from that copy, as finding the payload
+// needs the whole file at hand.
+pub fn hash_file( path: &Path, algorithm: HashAlgorithm ) -> Result<FileHashes, Box<dyn error::Error>> {
+    let format = format::detect_format( path )?;
+    let mut file_hasher = algorithm.hasher();
+    let mut payload = None;
+    if has_payload( format ) {
+        let data = fs::read( path )?;
+        file_hasher.update( &data );
+        let mut payload_hasher = algorithm.hasher();
+        if image_payload( &data, format, &mut |bytes| payload_hasher.update( bytes ) ).is_some() {
+            payload = Some(payload_hasher.finalize());
+        }
+    } else {
+        read_in_pieces( path, &mut |piece| file_hasher.update( piece ) )?;
+    }
+    Ok( FileHashes { algorithm, file: file_hasher.finalize(), payload } )
+}
+
+fn has_payload( format: MediaFormat ) -> bool {
+    matches!(format, MediaFormat::Jpeg | MediaFormat::Png | MediaFormat::Webp | MediaFormat::Tiff | MediaFormat::CanonCr2
+        | MediaFormat::NikonNef | MediaFormat::SonyArw | MediaFormat::AdobeDng | MediaFormat::PentaxPef)
+}
+
+// Feeds the bytes of a file which make up its image to the sink, in file order. Nothing is fed
+// when the file's structure can't be followed.
+fn image_payload( data: &[u8], format: MediaFormat, sink: &mut dyn FnMut( &[u8] ) ) -> Option<()> {
+    match format {
+        MediaFormat::Jpeg => jpeg_payload( data, sink ),
+        MediaFormat::Png => png_payload( data, sink ),
+        MediaFormat::Webp => webp_payload( data, sink ),
+        MediaFormat::Tiff | MediaFormat::CanonCr2 | MediaFormat::NikonNef | MediaFormat::SonyArw
+            | MediaFormat::AdobeDng | MediaFormat::PentaxPef => tiff_payload( data, sink ),
+        _ => None,
+    }
+}
+
+// Every segment but the APPn and COM ones, which hold EXIF, XMP, IPTC, MPF indexes and the
+// like, followed by the scan data and anything after it as it is.
+fn jpeg_payload( data: &[u8], sink: &mut dyn FnMut( &[u8] ) ) -> Option<()> {
+    let segments = jpeg::header_segments( data )?;
+    sink( &data[..2] );
+    for segment in segments.iter() {
+        if !(jpeg::MARKER_APP0..=MARKER_APP15).contains( &segment.marker ) && segment.marker != MARKER_COM {
+            sink( &data[segment.position..segment.payload.end] );
+        }
+    }
+    let image_data_start = segments.last().map(|segment| segment.payload.end).unwrap_or(2);
+    sink( &data[image_data_start..] );
+    Some(())
+}
+
+fn png_payload( data: &[u8], sink: &mut dyn FnMut( &[u8] ) ) -> Option<()> {
+    for chunk in png::chunks( data )?.iter() {
+        if !matches!(&chunk.kind, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            sink( &data[chunk.position..chunk.end()] );
+        }
+    }
+    Some(())
+}
+
+// The extended header is kept with its EXIF and XMP flags cleared, as they only say whether
+// those chunks are present.
+fn webp_payload( data: &[u8], sink: &mut dyn FnMut( &[u8] ) ) -> Option<()> {
+    for chunk in webp::chunks( data )?.iter() {
+        match &chunk.fourcc {
+            b"EXIF" | b"XMP " => {},
+            b"VP8X" if !chunk.payload.is_empty() => {
+                let mut header = data[chunk.position..chunk.end()].to_vec();
+                header[8] &= !(webp::FLAG_EXIF | webp::FLAG_XMP);
+                sink( &header );
+            },
+            _ => sink( &data[chunk.position..chunk.end()] ),
+        }
+    }
+    Some(())
+}
+
+// The strips and tiles of every IFD in the chain and of their SubIFDs. Embedded JPEG thumbnails
+// are metadata tools regenerate, and are left out.
+fn tiff_payload( data: &[u8], sink: &mut dyn FnMut( &[u8] ) ) -> Option<()> {
+    let order = ByteOrder::from_tiff_header( data )?;
+    let mut pending = vec![order.u32( data, 4 )? as usize];
+    let mut visited = BTreeSet::new();
+    let mut ranges = BTreeSet::new();
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || visited.len() >= MAX_IFDS || !visited.insert( offset ) {
+            continue;
+        }
+        let (entries, next) = tiff::read_ifd( data, offset, order )?;
+        pending.push( next as usize );
+        let values = |tag: u16| entries.iter().find(|entry| entry.tag == tag).and_then(|entry| entry_values( entry, data, order ));
+        pending.extend( values( TAG_SUB_IFDS ).unwrap_or_default().into_iter().map(|offset| offset as usize) );
+        for (offsets_tag, counts_tag) in DATA_LOCATION_TAGS.iter() {
+            if let (Some(offsets), Some(counts)) = (values( *offsets_tag ), values( *counts_tag )) {
+                ranges.extend( offsets.into_iter().zip( counts ).map(|(start, length)| (start as usize, length as usize)) );
+            }
+        }
+    }
+    if ranges.is_empty() {
+        return None;
+    }
+    let slices = ranges.into_iter().map(|(start, length)| data.get(start..start.checked_add( length )?)).collect::<Option<Vec<&[u8]>>>()?;
+    for slice in slices {
+        sink( slice );
+    }
+    Some(())
+}
+
+// The values of a SHORT or LONG field.
+fn entry_values( entry: &tiff::IfdEntry, data: &[u8], order: ByteOrder ) -> Option<Vec<u32>> {
+    let bytes = entry.value_bytes( data, order )?;
+    match entry.field_type {
+        3 => Some( (0..bytes.len() / 2).filter_map(|index| order.u16( bytes, index * 2 ).map(u32::from)).collect() ),
+        4 | 13 => Some( (0..bytes.len() / 4).filter_map(|index| order.u32( bytes, index * 4 )).collect() ),
+        _ => None,
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::io;
+use std::os::raw::{c_char, c_int, c_ulong};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// From <sys/inotify.h>, <poll.h> and <signal.h> on Linux.
+const IN_MODIFY : u32 = 0x0000_0002;
+const IN_CLOSE_WRITE : u32 = 0x0000_0008;
+pub(crate) const IN_MOVED_FROM : u32 = 0x0000_0040;
+pub(crate) const IN_MOVED_TO : u32 = 0x0000_0080;
+pub(crate) const IN_CREATE : u32 = 0x0000_0100;
+pub(crate) const IN_DELETE : u32 = 0x0000_0200;
+pub(crate) const IN_Q_OVERFLOW : u32 = 0x0000_4000;
+pub(crate) const IN_ISDIR : u32 = 0x4000_0000;
+const IN_NONBLOCK : c_int = 0o4000;
+const IN_CLOEXEC : c_int = 0o2000000;
+const POLLIN : i16 = 0x0001;
+const SIGINT : c_int = 2;
+const SIGTERM : c_int = 15;
+const SIG_DFL : usize = 0;
+
+const WATCH_MASK : u32 = IN_MODIFY | IN_CLOSE_WRITE | IN_MOVED_FROM | IN_MOVED_TO | IN_CREATE | IN_DELETE;
+// Size of struct inotify_event before its name.
+const EVENT_HEADER_SIZE : usize = 16;
+const EVENT_BUFFER_SIZE : usize = 64 * 1024;
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: i16,
+    revents: i16,
+}
+
+extern "C" {
+    fn inotify_init1( flags: c_int ) -> c_int;
+    fn inotify_add_watch( fd: c_int, pathname: *const c_char, mask: u32 ) -> c_int;
+    fn poll( fds: *mut PollFd, nfds: c_ulong, timeout: c_int ) -> c_int;
+    fn read( fd: c_int, buf: *mut u8, count: usize ) -> isize;
+    fn close( fd: c_int ) -> c_int;
+    fn signal( signum: c_int, handler: usize ) -> usize;
+}
+
+// Set by SIGINT and SIGTERM while watching.
+static SIGNALLED : AtomicBool = AtomicBool::new( false );
+
+extern "C" fn on_signal( _signum: c_int ) {
+    SIGNALLED.store( true, Ordering::SeqCst );
+}
+
+// Catches SIGINT and SIGTERM, so that they set a flag rather than end the process.
+pub(crate) fn catch_stop_signals() {
+    SIGNALLED.store( false, Ordering::SeqCst );
+    unsafe {
+        signal( SIGINT, on_signal as extern "C" fn( c_int ) as usize );
+        signal( SIGTERM, on_signal as extern "C" fn( c_int ) as usize );
+    }
+}
+
+pub(crate) fn restore_stop_signals() {
+    unsafe {
+        signal( SIGINT, SIG_DFL );
+        signal( SIGTERM, SIG_DFL );
+    }
+}
+
+pub(crate) fn stop_signalled() -> bool {
+    SIGNALLED.load( Ordering::SeqCst )
+}
+
+// The inotify instance watching a tree of directories.
+pub(crate) struct Inotify {
+    fd: c_int,
+    directories: HashMap<c_int, PathBuf>,
+}
+
+impl Inotify {
+    pub(crate) fn new() -> io::Result<Inotify> {
+        let fd = unsafe { inotify_init1( IN_NONBLOCK | IN_CLOEXEC ) };
+        if fd < 0 {
+            return Err( io::Error::last_os_error() );
+        }
+        Ok( Inotify { fd, directories: HashMap::new() } )
+    }
+
+    // Watches the directory and those in it, recursively, leaving out hidden ones.
+    pub(crate) fn add_directory( &mut self, directory: &Path ) -> io::Result<()> {
+        let path = CString::new( directory.as_os_str().as_bytes() ).map_err(|_| io::Error::from( io::ErrorKind::InvalidInput ))?;
+        let watch_descriptor = unsafe { inotify_add_watch( self.fd, path.as_ptr(), WATCH_MASK ) };
+        if watch_descriptor < 0 {
+            return Err( io::Error::last_os_error() );
+        }
+        self.directories.insert( watch_descriptor, directory.to_path_buf() );
+        for entry in fs::read_dir( directory )?.filter_map(|entry| entry.ok()) {
+            if entry.path().is_dir() && !entry.file_name().to_string_lossy().starts_with( '.' ) {
+                self.add_directory( &entry.path() )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Waits for events up to the timeout, giving the path and mask of each. An overflow of the
+    // event queue is given with an empty path.
+    pub(crate) fn read_events( &self, timeout_milliseconds: c_int ) -> io::Result<Vec<(PathBuf, u32)>> {
+        let mut poll_fd = PollFd { fd: self.fd, events: POLLIN, revents: 0 };
+        let ready = unsafe { poll( &mut poll_fd, 1, timeout_milliseconds ) };
+        if ready < 0 {
+            let poll_err = io::Error::last_os_error();
+            return if poll_err.kind() == io::ErrorKind::Interrupted { Ok(vec![]) } else { Err(poll_err) };
+        }
+        if ready == 0 {
+            return Ok(vec![]);
+        }
+        let mut buffer = vec![0u8; EVENT_BUFFER_SIZE];
+        let length = unsafe { read( self.fd, buffer.as_mut_ptr(), buffer.len() ) };
+        if length < 0 {
+            let read_err = io::Error::last_os_error();
+            return if read_err.kind() == io::ErrorKind::WouldBlock { Ok(vec![]) } else { Err(read_err) };
+        }
+
+        let mut events = vec![];
+        let mut offset = 0;
+        while offset + EVENT_HEADER_SIZE <= length as usize {
+            let field = |index: usize| u32::from_ne_bytes( [buffer[offset + index], buffer[offset + index + 1], buffer[offset + index + 2], buffer[offset + index + 3]] );
+            let (watch_descriptor, mask, name_length) = (field( 0 ) as c_int, field( 4 ), field( 12 ) as usize);
+            let name = &buffer[offset + EVENT_HEADER_SIZE..(offset + EVENT_HEADER_SIZE + name_length).min( length as usize )];
+            let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or( name.len() )];
+            if mask & IN_Q_OVERFLOW != 0 {
+                events.push( (PathBuf::new(), mask) );
+            } else if let (Some(directory), false) = (self.directories.get( &watch_descriptor ), name.is_empty()) {
+                events.push( (directory.join( OsStr::from_bytes( name ) ), mask) );
+            }
+            offset += EVENT_HEADER_SIZE + name_length;
+        }
+        Ok(events)
+    }
+}
+
+impl Drop for Inotify {
+    fn drop( &mut self ) {
+        unsafe { close( self.fd ) };
+    }
+}
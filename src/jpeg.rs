@@ -0,0 +1,178 @@
+use std::convert::TryInto;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+
+pub(crate) const MARKER_SOI : u8 = 0xD8;
+pub(crate) const MARKER_EOI : u8 = 0xD9;
+pub(crate) const MARKER_SOS : u8 = 0xDA;
+pub(crate) const MARKER_APP0 : u8 = 0xE0;
+pub(crate) const MARKER_APP1 : u8 = 0xE1;
+pub(crate) const MARKER_APP2 : u8 = 0xE2;
+
+pub(crate) const EXIF_IDENTIFIER : &[u8] = b"Exif\0\0";
+pub(crate) const MPF_IDENTIFIER : &[u8] = b"MPF\0";
+
+// A marker segment in the header of a JPEG file. `position` is the offset of the 0xFF
+// which starts the marker, and `payload` is the range of the bytes after the length field.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub(crate) struct JpegSegment {
+    pub marker: u8,
+    pub position: usize,
+    pub payload: Range<usize>,
+}
+
+// Lists the marker segments from SOI up to and including the first SOS.
+// Everything after the SOS segment is entropy-coded image data.
+pub(crate) fn header_segments( data: &[u8] ) -> Option<Vec<JpegSegment>> {
+    if !data.starts_with( &[0xFF, MARKER_SOI] ) {
+        return None;
+    }
+    let mut segments = vec![];
+    let mut position = 2;
+    loop {
+        // Markers may be preceded by any number of 0xFF fill bytes.
+        while data.get(position + 1) == Some(&0xFF) && data.get(position) == Some(&0xFF) {
+            position += 1;
+        }
+        if *data.get(position)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(position + 1)?;
+        if marker == MARKER_EOI {
+            return Some(segments);
+        }
+        let length = u16::from_be_bytes( data.get(position + 2..position + 4)?.try_into().ok()? ) as usize;
+        if length < 2 || position + 2 + length > data.len() {
+            return None;
+        }
+        segments.push( JpegSegment { marker, position, payload: position + 4..position + 2 + length } );
+        if marker == MARKER_SOS {
+            return Some(segments);
+        }
+        position += 2 + length;
+    }
+}
+
+pub(crate) fn is_start_of_frame( marker: u8 ) -> bool {
+    // SOF0 to SOF15, excluding DHT (0xC4), JPG (0xC8) and DAC (0xCC) which share the range.
+    (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker)
+}
+
+// Reads the pixel dimensions from the first start-of-frame segment.
+pub(crate) fn jpeg_dimensions( data: &[u8] ) -> Option<(u32, u32)> {
+    let segments = header_segments( data )?;
+    let frame = segments.iter().find(|segment| is_start_of_frame( segment.marker ))?;
+    let payload = &data[frame.payload.clone()];
+    let height = u16::from_be_bytes( payload.get(1..3)?.try_into().ok()? ) as u32;
+    let width = u16::from_be_bytes( payload.get(3..5)?.try_into().ok()? ) as u32;
+    Some( (width, height) )
+}
+
+// Returns the APP2 "MPF" segment payload and its offset in the file, if the JPEG has one.
+pub(crate) fn find_mpf_segment( data: &[u8] ) -> Option<(usize, &[u8])> {
+    header_segments( data )?.into_iter()
+        .filter(|segment| segment.marker == MARKER_APP2)
+        .map(|segment| (segment.payload.start, &data[segment.payload]))
+        .find(|(_, payload)| payload.starts_with( MPF_IDENTIFIER ))
+        .map(|(start, payload)| (start + MPF_IDENTIFIER.len(), &payload[MPF_IDENTIFIER.len()..]))
+}
+
+// Whether the data is a complete JPEG which ordinary viewers can decode: baseline,
+// extended or progressive Huffman coding, and ending with EOI. Lossless JPEG, as used
+// for the raw data of many RAW files, is excluded.
+pub(crate) fn is_viewable_jpeg( data: &[u8] ) -> bool {
+    let segments = match header_segments( data ) {
+        Some(segments) => segments,
+        None => return false,
+    };
+    let frame_marker = segments.iter().map(|segment| segment.marker).find(|marker| is_start_of_frame( *marker ));
+    // Some writers pad the data after EOI, so allow a little slack at the end.
+    let tail = &data[data.len().saturating_sub(16)..];
+    let has_end = tail.windows( 2 ).any(|window| window == [0xFF, MARKER_EOI]);
+    matches!(frame_marker, Some(0xC0) | Some(0xC1) | Some(0xC2)) && has_end
+}
+
+// Finds the position of the EXIF TIFF header in a JPEG stream, reading only segment headers.
+pub(crate) fn find_exif_in_stream<R: Read + Seek>( reader: &mut R ) -> io::Result<Option<u64>> {
+    let mut soi = [0u8; 2];
+    reader.read_exact( &mut soi )?;
+    if soi != [0xFF, MARKER_SOI] {
+        return Ok(None);
+    }
+    let mut position : u64 = 2;
+    loop {
+        let mut marker = [0u8; 2];
+        reader.read_exact( &mut marker )?;
+        if marker[0] != 0xFF {
+            return Ok(None);
+        }
+        if marker[1] == 0xFF {
+            // Fill byte; the marker code follows.
+            reader.seek( SeekFrom::Current( -1 ) )?;
+            position += 1;
+            continue;
+        }
+        if marker[1] == MARKER_SOS || marker[1] == MARKER_EOI {
+            return Ok(None);
+        }
+        let mut length = [0u8; 2];
+        reader.read_exact( &mut length )?;
+        let length = u16::from_be_bytes( length ) as u64;
+        if length < 2 {
+            return Ok(None);
+        }
+        if marker[1] == MARKER_APP1 && length >= 2 + EXIF_IDENTIFIER.len() as u64 {
+            let mut identifier = [0u8; 6];
+            reader.read_exact( &mut identifier )?;
+            if identifier == EXIF_IDENTIFIER {
+                return Ok( Some( position + 4 + EXIF_IDENTIFIER.len() as u64 ) );
+            }
+            reader.seek( SeekFrom::Current( length as i64 - 2 - EXIF_IDENTIFIER.len() as i64 ) )?;
+        } else {
+            reader.seek( SeekFrom::Current( length as i64 - 2 ) )?;
+        }
+        position += 2 + length;
+    }
+}
+
+// Reads the header of a JPEG stream, from SOI up to and including the SOS segment, so that
+// header_segments can be used without reading the entropy-coded image data.
+pub(crate) fn read_header_from_stream<R: Read>( reader: &mut R ) -> io::Result<Vec<u8>> {
+    let mut header = vec![0u8; 2];
+    reader.read_exact( &mut header )?;
+    if header != [0xFF, MARKER_SOI] {
+        return Ok(header);
+    }
+    loop {
+        let mut byte = [0u8; 1];
+        reader.read_exact( &mut byte )?;
+        header.push( byte[0] );
+        if byte[0] != 0xFF {
+            return Ok(header);
+        }
+        // Skip fill bytes; header_segments skips them too.
+        let mut marker = 0xFF;
+        while marker == 0xFF {
+            reader.read_exact( &mut byte )?;
+            header.push( byte[0] );
+            marker = byte[0];
+        }
+        if marker == MARKER_EOI {
+            return Ok(header);
+        }
+        let mut length = [0u8; 2];
+        reader.read_exact( &mut length )?;
+        header.extend_from_slice( &length );
+        let length = u16::from_be_bytes( length ) as usize;
+        if length < 2 {
+            return Ok(header);
+        }
+        let start = header.len();
+        header.resize( start + length - 2, 0 );
+        reader.read_exact( &mut header[start..] )?;
+        if marker == MARKER_SOS {
+            return Ok(header);
+        }
+    }
+}
@@ -7,14 +7,74 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::str;
 
-use chrono;
 use chrono::Utc;
-use exif;
 
-use serde_json;
 use serde::{Serialize, Deserialize};
 use serde::de::{self,Visitor}; // for custom deserializer on Orientation
 
+mod bursts;
+mod canon;
+mod catalog;
+mod clocksync;
+mod color;
+mod copy;
+mod dct;
+mod dedupe;
+mod format;
+mod frames;
+mod geotag;
+mod hashing;
+#[cfg(target_os = "linux")]
+mod inotify;
+mod jpeg;
+mod makernote;
+mod nikon;
+mod orient;
+mod orientation;
+mod organize;
+mod png;
+mod previews;
+mod query;
+mod redact;
+mod rename;
+mod scrub;
+mod sidecar;
+mod sony;
+mod tiff;
+mod timeshift;
+mod video;
+mod watch;
+mod webp;
+mod writer;
+
+pub use bursts::{find_bursts, record_sequence, Burst, BurstFailure, BurstOptions, BurstReport, SequenceMembership};
+pub use color::ColorMetadataOfInterest;
+pub use canon::CanonMakerNote;
+pub use catalog::{Catalog, CatalogFailure, CatalogVersionError, RefreshReport};
+pub use copy::{copy_metadata, find_source_by_stem, CopySource, MetadataGroup, MissingSourceError};
+pub use clocksync::{compute_clock_corrections, synchronized_capture_time, ClockCorrection, ClockSync, ClockSyncError, ReferencePair};
+pub use dedupe::{find_duplicates, CaptureKey, DedupeFailure, DedupeOptions, DedupeReport, DuplicateFile, DuplicateGroup, DuplicateKind, KeeperRule};
+pub use format::{detect_format, detect_format_from_bytes, MediaFormat, UnsupportedFormatError};
+pub use frames::{FrameKind, FrameMetadataOfInterest};
+pub use hashing::{hash_file, FileHashes, HashAlgorithm};
+pub use geotag::{geotag_image, Geotag, GeotagOutcome, Track, TrackParseError, TrackPoint, UnmatchedReason};
+pub use makernote::{VendorDetails, VendorMetadataOfInterest};
+pub use nikon::NikonMakerNote;
+pub use orient::normalize_orientation;
+pub use orientation::{OrientationMatrix, ParseOrientationError};
+pub use organize::{files_to_organize, organize_file, CopyVerificationError, Organize, OrganizeOutcome, DEFAULT_ORGANIZE_LAYOUT};
+pub use previews::{extract_embedded_previews, list_embedded_previews, write_embedded_previews, EmbeddedPreview, ExtractedPreview, PreviewSource, ThumbnailInfo};
+pub use query::{query_files, CompareOp, Filter, FilterParseError, FilterValue, QueryFailure, QueryReport};
+pub use redact::{RedactedMetadata, Redaction};
+pub use rename::{plan_renames, rename_files, undo_renames, MissingFieldError, NameTemplate, RenameConflictError, RenameFailure, RenameJournal, RenamePlan, RenamedFile, TemplateError, DEFAULT_RENAME_TEMPLATE};
+pub use scrub::{scrub_metadata, ScrubPolicy, ScrubVerificationError};
+pub use sidecar::{import_json_sidecar, ImportReport, MissingSidecarError, NotApplicableField, RedactedSidecarError};
+pub use sony::SonyMakerNote;
+pub use timeshift::{shift_capture_times, TimeShift};
+pub use video::VideoMetadataOfInterest;
+pub use watch::{watch_folders, Watch, WatchEvent};
+pub use writer::{write_image_metadata, FieldChange, ImageMetadataPatch, MetadataWriteError, WriteOptions};
+
 const CAPTURE_TIME_FORMAT : &str = "%Y:%m:%d %H:%M:%S";
 
 #[derive(Debug,Clone,PartialEq)]
@@ -23,9 +83,15 @@ pub struct FileMetadataOfInterest {
     pub filename: String,
     pub size: u64, // in bytes
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>, // detected from the file contents, not the extension
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub created_time: Option<chrono::DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub modified_time: Option<chrono::DateTime<Utc>>,
+    // Fingerprints of the file and of its image payload, only filled when requested through
+    // ReadOptions since they mean reading the whole file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hashes: Option<FileHashes>,
 }
 
 #[derive(Debug,Clone,Copy,PartialEq,Eq)]
@@ -40,20 +106,6 @@ pub enum Orientation {
     QuarterRotationCW = 8,
 }
 
-// This is ugly but I'm not finding an easier way to get the number out of the enum.
-pub fn orientation_as_u16( orientation: Orientation ) -> u16 {
-    match orientation {
-        Orientation::Normal => 1,
-        Orientation::Mirrored => 2,
-        Orientation::UpsideDown => 3,
-        Orientation::UpsideDownMirrored => 4,
-        Orientation::QuarterRotationCCWMirrored => 5,
-        Orientation::QuarterRotationCCW => 6,
-        Orientation::QuarterRotationCWMirrored => 7,
-        Orientation::QuarterRotationCW => 8
-    }
-}
-
 impl TryFrom<u16> for Orientation {
     type Error = ();
 
@@ -116,16 +168,33 @@ impl<'a> Deserialize<'a> for Orientation {
     }
 }
 
+// Kept for callers from before Orientation converted into u16 directly.
+#[deprecated(since = "0.1.0", note = "use u16::from( orientation ) instead")]
+pub fn orientation_as_u16( orientation: Orientation ) -> u16 {
+    u16::from( orientation )
+}
+
 impl Serialize for Orientation {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer
     {
-        serializer.serialize_u16(orientation_as_u16(*self))
+        serializer.serialize_u16(u16::from(*self))
     }
 }
 
-#[derive(Debug,Clone,PartialEq)]
+// Degrees are signed decimal: positive for north/east, negative for south/west.
+// Altitude is in metres above sea level.
+#[derive(Debug,Clone,Copy,PartialEq)]
+#[derive(Serialize,Deserialize)]
+pub struct GpsLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+}
+
+#[derive(Debug,Clone,PartialEq,Default)]
 #[derive(Serialize,Deserialize)]
 pub struct ImageMetadataOfInterest {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -136,6 +205,9 @@ pub struct ImageMetadataOfInterest {
     pub camera_model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub camera_serial: Option<String>,
+    // Where the image was taken, from the GPS IFD, or from a track when geotagged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<GpsLocation>,
 }
 
 #[derive(Debug,Clone,PartialEq)]
@@ -145,40 +217,631 @@ pub struct MetadataOfInterest {
     pub file_metadata: FileMetadataOfInterest,
     #[serde(flatten)]
     pub image_metadata: ImageMetadataOfInterest,
+    // Only present for video files; the capture time and camera model of a video are
+    // reported in image_metadata so that stills and clips can be catalogued together.
+    #[serde(rename = "video", default, skip_serializing_if = "Option::is_none")]
+    pub video_metadata: Option<VideoMetadataOfInterest>,
+    // The embedded EXIF (IFD1) thumbnail, if the file has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<ThumbnailInfo>,
+    // The EXIF color space and embedded ICC profile, if the file declares either.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<ColorMetadataOfInterest>,
+    // Fields decoded from the camera vendor's MakerNote, for the makes which have a decoder.
+    #[serde(rename = "vendor", default, skip_serializing_if = "Option::is_none")]
+    pub vendor_metadata: Option<VendorMetadataOfInterest>,
+    // Every image held in the file (pages, thumbnails, previews, MPO images) with its own metadata.
+    // Only filled when requested through ReadOptions, since most files hold a single image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frames: Option<Vec<FrameMetadataOfInterest>>,
+    // The burst the file was shot in, as found by the bursts command; only ever read from a sidecar.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<SequenceMembership>,
+}
+
+// Opt-in behaviour for reading metadata.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct ReadOptions {
+    pub include_frames: bool,
+    pub hash: Option<HashAlgorithm>,
+}
+
+// What run does with each of the given paths.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Command {
+    // Read the metadata of interest and write it to a JSON file next to each input.
+    Read,
+    // Extract the embedded thumbnails and previews of each input as JPEG files.
+    ExtractPreviews { output_dir: Option<PathBuf> },
+    // Write the patched fields into the EXIF data of each input.
+    Set { patch: ImageMetadataPatch, options: WriteOptions },
+    // Write the fields edited in the JSON sidecar of each input back into the input.
+    Import { options: WriteOptions },
+    // Shift the date/time fields of each input taken by the matching camera.
+    Shift { shift: TimeShift, options: WriteOptions },
+    // Bring the capture times of each input onto the clock of the reference camera.
+    Sync { sync: ClockSync, options: WriteOptions },
+    // Remove the metadata the policy targets from each input.
+    Scrub { policy: ScrubPolicy, options: WriteOptions },
+    // Losslessly rotate the image data of each JPEG input to match its Orientation tag.
+    Normalize { options: WriteOptions },
+    // Copy the fields of the groups from the source into each input.
+    Copy { source: Option<CopySource>, groups: Vec<MetadataGroup>, options: WriteOptions },
+    // Find the position of each input in GPS tracks from its capture time, and record it.
+    Geotag { geotag: Geotag, options: WriteOptions },
+    // Rename each input, and its sidecars, from its metadata, recording the renames in a journal.
+    Rename { template: NameTemplate, journal: Option<PathBuf>, options: WriteOptions },
+    // Put back the files renamed according to each input journal.
+    UndoRename { options: WriteOptions },
+    // Copy or move each input, and the files in each input directory, into folders under the
+    // destination laid out from their metadata.
+    Organize { destination: Option<PathBuf>, organize: Organize, options: WriteOptions },
+    // Report the groups of duplicate files among the inputs, and those in input directories,
+    // with the file to keep of each, optionally also as JSON.
+    Dedupe { dedupe: DedupeOptions, report: Option<PathBuf> },
+    // List the bursts among the inputs, and those in input directories, and record the burst
+    // each file is in in its JSON sidecar.
+    Bursts { bursts: BurstOptions, options: WriteOptions },
+    // Bring the entries of the catalog for each input, and the files in each input directory,
+    // up to date, reading only the files which are new or changed.
+    Index { catalog: Option<PathBuf> },
+    // List the inputs, files in input directories and entries of the catalog whose metadata
+    // the filter matches. JSON inputs are matched as sidecars.
+    Query { filter: Option<Filter>, catalog: Option<PathBuf> },
+    // Keep the JSON sidecars, or the catalog, of the files in each input directory in sync with
+    // them as they are added, changed and deleted, until interrupted. Only on Linux.
+    Watch { watch: Watch },
 }
 
 pub struct Config {
+    command: Command,
     image_paths: Vec<PathBuf>,
     print_help: bool,
+    read_options: ReadOptions,
+    redaction: Redaction,
+}
+
+// Counts of how each input path was handled by run.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct RunSummary {
+    pub read: usize,
+    pub skipped_unsupported: usize,
+    pub failed: usize,
 }
 
-pub fn run( config : Config ) -> Result<(), Box<dyn error::Error>> {
+pub fn run( config : Config ) -> Result<RunSummary, Box<dyn error::Error>> {
+    let summary = match &config.command {
+        Command::Read => run_read( &config ),
+        Command::ExtractPreviews { output_dir } => run_extract_previews( &config, output_dir.as_deref() ),
+        Command::Set { patch, options } => run_set( &config, patch, options ),
+        Command::Import { options } => run_import( &config, options ),
+        Command::Shift { shift, options } => run_shift( &config, shift, options ),
+        Command::Sync { sync, options } => run_sync( &config, sync, options )?,
+        Command::Scrub { policy, options } => run_scrub( &config, *policy, options ),
+        Command::Normalize { options } => run_normalize( &config, options ),
+        Command::Copy { source, groups, options } => run_copy( &config, source.as_ref(), groups, options ),
+        Command::Geotag { geotag, options } => run_geotag( &config, geotag, options )?,
+        Command::Rename { template, journal, options } => run_rename( &config, template, journal.as_deref(), options ),
+        Command::UndoRename { options } => run_undo_rename( &config, options ),
+        Command::Organize { destination, organize, options } => run_organize( &config, destination.as_deref(), organize, options ),
+        Command::Dedupe { dedupe, report } => run_dedupe( &config, dedupe, report.as_deref() ),
+        Command::Bursts { bursts, options } => run_bursts( &config, bursts, options ),
+        Command::Index { catalog } => run_index( &config, catalog.as_deref() )?,
+        Command::Query { filter, catalog } => run_query( &config, filter.as_ref(), catalog.as_deref() )?,
+        Command::Watch { watch } => run_watch( &config, watch )?,
+    };
+
+    println!("Processed {} file(s), skipped {} unsupported file(s), failed to process {} file(s).",
+        summary.read,summary.skipped_unsupported,summary.failed);
+
+    Ok(summary)
+}
+
+// Counts an error for a path, telling unsupported files apart from real failures.
+fn report_error( summary: &mut RunSummary, path: &Path, action: &str, boxed_err: Box<dyn error::Error> ) {
+    if let Some(unsupported) = boxed_err.downcast_ref::<UnsupportedFormatError>() {
+        println!("Skipping file at path: {} ({})",path.to_string_lossy(),unsupported);
+        summary.skipped_unsupported += 1;
+    } else {
+        eprintln!("Failed to {} for file at path: {}",action,path.to_string_lossy());
+        eprintln!("Error details: {:?}",boxed_err);
+        summary.failed += 1;
+    }
+}
+
+fn run_read( config : &Config ) -> RunSummary {
+    let mut summary = RunSummary::default();
+
     for image_path in config.image_paths.iter() {
-        let maybe_metadata = read_metadata_of_interest( image_path );
+        let maybe_metadata = read_metadata_of_interest_with_options( image_path, &config.read_options );
         if let Ok( metadata ) = maybe_metadata {
-            let path_stem_os = image_path.file_stem().expect("Couldn't get path stem!");
-            let path_parent_os = image_path.parent().expect("Couldn't get path parent!");
-            if let Some(path_stem) = path_stem_os.to_str() {
-                if let Some(path_parent) = path_parent_os.to_str() {
-                    let json_file_name : String = [path_stem, r".json"].iter().cloned().collect();
-                    let json_path = PathBuf::from( path_parent ).join( json_file_name );
-                    if let Err(boxed_err) = write_json_metadata( &metadata, &json_path ) {
-                        eprintln!("Failed to write metadata to JSON for image at path: {}",image_path.to_string_lossy());
-                        eprintln!("Error details: {:?}",boxed_err);
-                    }
+            if let Some(json_path) = json_sidecar_path( image_path ) {
+                if let Err(boxed_err) = write_json_metadata_with_redaction( &metadata, &json_path, &config.redaction ) {
+                    report_error( &mut summary, image_path, "write metadata to JSON", boxed_err );
+                    continue;
                 }
             }
+            summary.read += 1;
         } else {
-            eprintln!("Failed to read metadata for image at path: {}",image_path.to_string_lossy());
-            eprintln!("Error details: {:?}",maybe_metadata.unwrap_err());
+            report_error( &mut summary, image_path, "read metadata", maybe_metadata.unwrap_err() );
+        }
+    }
+
+    summary
+}
+
+fn run_extract_previews( config : &Config, output_dir : Option<&Path> ) -> RunSummary {
+    let mut summary = RunSummary::default();
+
+    for image_path in config.image_paths.iter() {
+        match write_embedded_previews( image_path, output_dir ) {
+            Ok(written) => {
+                if written.is_empty() {
+                    println!("No embedded previews found in file: {}",image_path.to_string_lossy());
+                }
+                for preview_path in written {
+                    println!("Wrote preview: {}",preview_path.to_string_lossy());
+                }
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "extract previews", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+fn run_set( config : &Config, patch : &ImageMetadataPatch, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would change" } else { "Changed" };
+
+    for image_path in config.image_paths.iter() {
+        match write_image_metadata( image_path, patch, options ) {
+            Ok(changes) => {
+                if changes.is_empty() {
+                    println!("No changes needed for file: {}",image_path.to_string_lossy());
+                }
+                for change in changes {
+                    println!("{} {} in file: {}",verb,change,image_path.to_string_lossy());
+                }
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "write metadata", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+// The JSON file written next to an image: the same name with a .json extension.
+pub fn json_sidecar_path( image_path: &Path ) -> Option<PathBuf> {
+    let path_stem = image_path.file_stem()?.to_str()?;
+    let path_parent = image_path.parent()?.to_str()?;
+    let json_file_name : String = [path_stem, r".json"].iter().cloned().collect();
+    Some( PathBuf::from( path_parent ).join( json_file_name ) )
+}
+
+fn run_import( config : &Config, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would change" } else { "Changed" };
+
+    for image_path in config.image_paths.iter() {
+        match import_json_sidecar( image_path, options ) {
+            Ok(report) => {
+                println!("Importing {} into file: {}",report.sidecar_path.to_string_lossy(),image_path.to_string_lossy());
+                if report.changes.is_empty() {
+                    println!("  No changes to write");
+                }
+                for change in report.changes {
+                    println!("  {} {}",verb,change);
+                }
+                for field in report.not_applicable {
+                    println!("  Not applicable: {} ({})",field.field,field.reason);
+                }
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "import JSON sidecar", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+fn run_shift( config : &Config, shift : &TimeShift, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would change" } else { "Changed" };
+
+    for image_path in config.image_paths.iter() {
+        match shift_capture_times( image_path, shift, options, &config.redaction ) {
+            Ok(Some(changes)) => {
+                if changes.is_empty() {
+                    println!("No times to shift in file: {}",image_path.to_string_lossy());
+                }
+                for change in changes {
+                    println!("{} {} in file: {}",verb,change,image_path.to_string_lossy());
+                }
+                summary.read += 1;
+            },
+            Ok(None) => {
+                println!("Not shifting file from another camera: {}",image_path.to_string_lossy());
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "shift times", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+fn run_sync( config : &Config, sync : &ClockSync, options : &WriteOptions ) -> Result<RunSummary, Box<dyn error::Error>> {
+    let mut summary = RunSummary::default();
+    let corrections = compute_clock_corrections( sync )?;
+    for correction in corrections.iter() {
+        println!("Clock correction for camera {}",correction);
+    }
+
+    let mut timeline = vec![];
+    for image_path in config.image_paths.iter() {
+        let metadata = match read_metadata_of_interest_with_options( image_path, &config.read_options ) {
+            Ok(metadata) => metadata,
+            Err(boxed_err) => {
+                report_error( &mut summary, image_path, "read metadata", boxed_err );
+                continue;
+            },
+        };
+        let synchronized = synchronized_capture_time( &metadata, &corrections );
+        let result = match (sync.write_back, metadata.image_metadata.capture_time, synchronized) {
+            (true, Some(capture_time), Some(synchronized)) if synchronized != capture_time => {
+                shift_capture_times( image_path, &TimeShift::new( synchronized - capture_time ), options, &config.redaction ).map(|_| ())
+            },
+            (false, _, _) if !options.dry_run => {
+                // Only the time of the existing sidecar is updated, so that what is only in it is kept.
+                sidecar::sidecar_base( image_path, &metadata ).and_then(|(mut base, sidecar_path)| {
+                    base.image_metadata.capture_time = synchronized;
+                    match sidecar_path.or_else(|| json_sidecar_path( image_path )) {
+                        Some(json_path) => write_json_metadata_with_redaction( &base, &json_path, &config.redaction ),
+                        None => Ok(()),
+                    }
+                })
+            },
+            _ => Ok(()),
+        };
+        if let Err(boxed_err) = result {
+            report_error( &mut summary, image_path, "synchronize capture time", boxed_err );
+            continue;
+        }
+        if let Some(synchronized) = synchronized {
+            timeline.push( (synchronized, metadata.image_metadata.camera_serial.unwrap_or_default(), image_path) );
+        }
+        summary.read += 1;
+    }
+
+    timeline.sort();
+    println!("Merged timeline:");
+    for (capture_time, camera_serial, image_path) in timeline {
+        println!("  {}  {}  {}",capture_time.format( CAPTURE_TIME_FORMAT ),camera_serial,image_path.to_string_lossy());
+    }
+
+    Ok(summary)
+}
+
+fn run_scrub( config : &Config, policy : ScrubPolicy, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would remove" } else { "Removed" };
+
+    for image_path in config.image_paths.iter() {
+        match scrub_metadata( image_path, policy, options ) {
+            Ok(removed) => {
+                if removed.is_empty() {
+                    println!("Nothing to remove from file: {}",image_path.to_string_lossy());
+                } else {
+                    println!("{} {} from file: {}",verb,removed.join(", "),image_path.to_string_lossy());
+                }
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "scrub metadata", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+fn run_normalize( config : &Config, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would change" } else { "Changed" };
+
+    for image_path in config.image_paths.iter() {
+        match normalize_orientation( image_path, options ) {
+            Ok(changes) => {
+                if changes.is_empty() {
+                    println!("Already upright: {}",image_path.to_string_lossy());
+                }
+                for change in changes {
+                    println!("{} {} in file: {}",verb,change,image_path.to_string_lossy());
+                }
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "normalize orientation", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+fn run_copy( config : &Config, source : Option<&CopySource>, groups : &[MetadataGroup], options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would change" } else { "Changed" };
+
+    for image_path in config.image_paths.iter() {
+        let copied = source.ok_or_else(|| writer::write_error( "no source to copy from" ))
+            .and_then(|source| source.source_for( image_path ))
+            .and_then(|source_path| copy_metadata( &source_path, image_path, groups, options ));
+        match copied {
+            Ok(changes) => {
+                if changes.is_empty() {
+                    println!("Nothing to copy into file: {}",image_path.to_string_lossy());
+                }
+                for change in changes {
+                    println!("{} {} in file: {}",verb,change,image_path.to_string_lossy());
+                }
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "copy metadata", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+fn run_geotag( config : &Config, geotag : &Geotag, options : &WriteOptions ) -> Result<RunSummary, Box<dyn error::Error>> {
+    let track = Track::load_all( &geotag.tracks )?;
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would tag" } else { "Tagged" };
+    let mut unmatched = vec![];
+
+    for image_path in config.image_paths.iter() {
+        match geotag_image( image_path, &track, geotag, options, &config.redaction ) {
+            Ok(GeotagOutcome::Tagged { location, .. }) => {
+                println!("{} {:.6}, {:.6}: {}",verb,location.latitude,location.longitude,image_path.to_string_lossy());
+                summary.read += 1;
+            },
+            Ok(GeotagOutcome::AlreadyTagged(_)) => {
+                println!("Keeping the position already in file: {}",image_path.to_string_lossy());
+                summary.read += 1;
+            },
+            Ok(GeotagOutcome::Unmatched(reason)) => {
+                unmatched.push( (image_path, reason) );
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "geotag", boxed_err ),
+        }
+    }
+
+    if !unmatched.is_empty() {
+        println!("Unmatched file(s):");
+        for (image_path, reason) in unmatched {
+            println!("  {}  ({})",image_path.to_string_lossy(),reason);
+        }
+    }
+
+    Ok(summary)
+}
+
+fn run_rename( config : &Config, template : &NameTemplate, journal : Option<&Path>, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would rename" } else { "Renamed" };
+
+    let (plans, failures) = plan_renames( &config.image_paths, template );
+    for (image_path, boxed_err) in failures {
+        report_error( &mut summary, &image_path, "rename", boxed_err );
+    }
+    // By default the journal goes next to the first renamed file, named after the time.
+    let journal_path = journal.map(Path::to_path_buf).or_else(|| plans.first().map(|plan| {
+        plan.image.from.with_file_name( format!("rename-journal-{}.json", Utc::now().format( "%Y%m%dT%H%M%S" )) )
+    }));
+    match rename_files( &plans, journal_path.as_deref(), options ) {
+        Ok(journal) => {
+            for rename in journal.renames.iter() {
+                println!("{} {} -> {}",verb,rename.from.to_string_lossy(),rename.to.to_string_lossy());
+            }
+            if let (Some(journal_path), false, false) = (journal_path, options.dry_run, plans.is_empty()) {
+                println!("Undo journal: {}",journal_path.to_string_lossy());
+            }
+            summary.read += plans.len();
+        },
+        Err(boxed_err) => {
+            eprintln!("Failed to rename files: {}",boxed_err);
+            summary.failed += plans.len();
+        },
+    }
+
+    summary
+}
+
+fn run_undo_rename( config : &Config, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would rename" } else { "Renamed" };
+
+    for journal_path in config.image_paths.iter() {
+        match undo_renames( journal_path, options ) {
+            Ok(undone) => {
+                for rename in undone {
+                    println!("{} {} -> {}",verb,rename.from.to_string_lossy(),rename.to.to_string_lossy());
+                }
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, journal_path, "undo renames", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+fn run_organize( config : &Config, destination : Option<&Path>, organize : &Organize, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let destination = destination.unwrap_or_else(|| Path::new("."));
+
+    for image_path in files_to_organize( &config.image_paths, destination ).iter() {
+        match organize_file( image_path, destination, organize, options, &config.redaction ) {
+            Ok(outcome) => {
+                let verb = match (&outcome, options.dry_run) {
+                    (OrganizeOutcome::Copied(_), false) => "Copied",
+                    (OrganizeOutcome::Copied(_), true) => "Would copy",
+                    (OrganizeOutcome::Moved(_), false) => "Moved",
+                    (OrganizeOutcome::Moved(_), true) => "Would move",
+                    (OrganizeOutcome::AlreadyPresent(_), _) => "Already present",
+                };
+                println!("{} {} -> {}",verb,image_path.to_string_lossy(),outcome.destination().to_string_lossy());
+                summary.read += 1;
+            },
+            Err(boxed_err) => report_error( &mut summary, image_path, "organize", boxed_err ),
+        }
+    }
+
+    summary
+}
+
+fn run_dedupe( config : &Config, dedupe : &DedupeOptions, report_path : Option<&Path> ) -> RunSummary {
+    let mut summary = RunSummary::default();
+
+    let (report, failures) = find_duplicates( &config.image_paths, dedupe );
+    for (path, boxed_err) in failures {
+        report_error( &mut summary, &path, "check for duplicates", boxed_err );
+    }
+    for (index, group) in report.groups.iter().enumerate() {
+        let kind = match group.kind {
+            DuplicateKind::Identical => "identical",
+            DuplicateKind::Probable => "probable",
+        };
+        println!("Duplicate group {} ({}, {} files):",index + 1,kind,group.files.len());
+        for file in group.files.iter() {
+            let label = if file.path == group.keeper { "keep" } else { "    " };
+            println!("  {}  {}  ({} bytes)",label,file.path.to_string_lossy(),file.size);
+        }
+    }
+    println!("Found {} group(s) of duplicates among {} file(s).",report.groups.len(),report.files_scanned);
+    if let Some(report_path) = report_path {
+        let written = report.to_json_value( &config.redaction ).and_then(|value| serde_json::to_string_pretty( &value )).map_err(|unboxed_err| unboxed_err.into())
+            .and_then(|report_json| fs::write( report_path, report_json ).map_err(|unboxed_err| unboxed_err.into()));
+        if let Err(boxed_err) = written {
+            report_error( &mut summary, report_path, "write the duplicate report", boxed_err );
+        }
+    }
+    summary.read += report.files_scanned;
+
+    summary
+}
+
+fn run_bursts( config : &Config, bursts : &BurstOptions, options : &WriteOptions ) -> RunSummary {
+    let mut summary = RunSummary::default();
+    let verb = if options.dry_run { "Would update" } else { "Updated" };
+
+    let (report, failures) = find_bursts( &config.image_paths, bursts );
+    for (path, boxed_err) in failures {
+        report_error( &mut summary, &path, "check for bursts", boxed_err );
+    }
+    let mut updated = 0;
+    for burst in report.bursts.iter() {
+        println!("Burst {} ({}, {} frames over {:.3}s):",burst.sequence_id,burst.camera_serial,burst.frames.len(),burst.duration.num_milliseconds() as f64 / 1000.0);
+        for (index, paths) in burst.frames.iter().enumerate() {
+            for path in paths.iter() {
+                println!("  {:>3}  {}",index + 1,path.to_string_lossy());
+                match record_sequence( path, Some(&burst.membership( index )), options, &config.redaction ) {
+                    Ok(changed) => updated += changed as usize,
+                    Err(boxed_err) => report_error( &mut summary, path, "record the burst of", boxed_err ),
+                }
+            }
+        }
+    }
+    for path in report.single_files.iter() {
+        match record_sequence( path, None, options, &config.redaction ) {
+            Ok(changed) => updated += changed as usize,
+            Err(boxed_err) => report_error( &mut summary, path, "record the burst of", boxed_err ),
+        }
+    }
+    println!("Found {} burst(s) among {} file(s). {} {} sidecar(s).",report.bursts.len(),report.files_scanned,verb,updated);
+    summary.read += report.files_scanned;
+
+    summary
+}
+
+fn run_index( config : &Config, catalog_path : Option<&Path> ) -> Result<RunSummary, Box<dyn error::Error>> {
+    let catalog_path = catalog_path.ok_or_else(|| writer::write_error( "no catalog file was given" ))?;
+    let mut catalog = Catalog::load( catalog_path )?;
+    let mut summary = RunSummary::default();
+
+    let (report, failures) = catalog.refresh( &config.image_paths, &config.read_options );
+    for (path, boxed_err) in failures {
+        report_error( &mut summary, &path, "index", boxed_err );
+    }
+    for (verb, paths) in [("Added", &report.added), ("Updated", &report.updated), ("Removed", &report.removed)].iter() {
+        for path in paths.iter() {
+            println!("{} {}",verb,path.to_string_lossy());
         }
     }
+    catalog.save_with_redaction( catalog_path, &config.redaction )?;
+    println!("Catalog {} holds {} file(s): {} added, {} updated, {} unchanged, {} removed.",catalog_path.to_string_lossy(),catalog.len(),
+        report.added.len(),report.updated.len(),report.unchanged,report.removed.len());
+    summary.read += report.added.len() + report.updated.len() + report.unchanged;
 
-    Ok(())
+    Ok(summary)
+}
+
+fn run_query( config : &Config, filter : Option<&Filter>, catalog_path : Option<&Path> ) -> Result<RunSummary, Box<dyn error::Error>> {
+    let filter = filter.ok_or_else(|| writer::write_error( "no filter was given" ))?;
+    let mut summary = RunSummary::default();
+    let mut matched = 0;
+
+    if let Some(catalog_path) = catalog_path {
+        let catalog = Catalog::load( catalog_path )?;
+        for (path, _) in catalog.find(|_, metadata| filter.matches( metadata )) {
+            println!("{}",path.to_string_lossy());
+            matched += 1;
+        }
+        summary.read += catalog.len();
+    }
+    if !config.image_paths.is_empty() {
+        let (report, failures) = query_files( &config.image_paths, filter );
+        for (path, boxed_err) in failures {
+            report_error( &mut summary, &path, "query", boxed_err );
+        }
+        for path in report.matches.iter() {
+            println!("{}",path.to_string_lossy());
+        }
+        matched += report.matches.len();
+        summary.read += report.files_scanned;
+    }
+    println!("{} of {} file(s) matched.",matched,summary.read);
+
+    Ok(summary)
+}
+
+fn run_watch( config : &Config, watch : &Watch ) -> Result<RunSummary, Box<dyn error::Error>> {
+    let mut summary = RunSummary::default();
+
+    println!("Watching {} folder(s); press Ctrl-C to stop.",config.image_paths.len());
+    let stop = std::sync::atomic::AtomicBool::new( false );
+    watch_folders( &config.image_paths, watch, &config.read_options, &config.redaction, &stop, &mut |event| match event {
+        WatchEvent::Updated(path) => {
+            println!("Updated {}",path.to_string_lossy());
+            summary.read += 1;
+        },
+        WatchEvent::Skipped { path, owner } => println!("Skipped {}, as its sidecar describes {}",path.to_string_lossy(),owner.to_string_lossy()),
+        WatchEvent::Removed { path, sidecar: Some(sidecar) } => println!("Removed {} with its sidecar {}",path.to_string_lossy(),sidecar.to_string_lossy()),
+        WatchEvent::Removed { path, sidecar: None } => println!("Removed {}",path.to_string_lossy()),
+        WatchEvent::Failed(path, boxed_err) => report_error( &mut summary, &path, "update the metadata of", boxed_err ),
+    } )?;
+    println!("Stopped watching.");
+
+    Ok(summary)
 }
 
 pub fn write_json_metadata( metadata: &MetadataOfInterest, path: &Path ) -> Result<(), Box<dyn error::Error>> {
-    let metadata_as_json = serde_json::to_string_pretty(metadata)?;
+    write_json_metadata_with_redaction( metadata, path, &Redaction::default() )
+}
+
+pub fn write_json_metadata_with_redaction( metadata: &MetadataOfInterest, path: &Path, redaction: &Redaction ) -> Result<(), Box<dyn error::Error>> {
+    let metadata_as_json = serde_json::to_string_pretty( &RedactedMetadata { metadata, redaction } )?;
     match fs::write(path, metadata_as_json) {
         Ok(good_write) => Ok(good_write),
         Err(unboxed_err) => Err(Box::new(unboxed_err))
@@ -236,18 +899,25 @@ fn read_file_metadata( path: &Path ) -> Result<FileMetadataOfInterest, Box<dyn e
     Ok( FileMetadataOfInterest {
         filename: path.file_name().unwrap().to_string_lossy().into_owned(),
         size: all_file_metadata.len(),
+        mime_type: None,
         created_time: created_time_utc,
         modified_time: modified_time_utc,
+        hashes: None,
     } )
 }
 
-fn get_exif_fields( path: &Path ) -> Result<exif::Exif, Box<dyn error::Error>> {
+// A TIFF header followed by an empty IFD0.
+const EMPTY_TIFF : &[u8] = b"II*\0\x08\0\0\0\0\0\0\0\0\0";
+
+// Images without any EXIF data, such as scrubbed ones, are read as if they had an empty IFD0.
+pub(crate) fn get_exif_fields( path: &Path ) -> Result<exif::Exif, Box<dyn error::Error>> {
     let file = std::fs::File::open(path)?;
     let mut bufreader = std::io::BufReader::new(&file);
     let exifreader = exif::Reader::new();
 
     match exifreader.read_from_container(&mut bufreader) {
         Ok(good_exif) => Ok(good_exif),
+        Err(exif::Error::NotFound(_)) => Ok(exifreader.read_raw( EMPTY_TIFF.to_vec() )?),
         Err(unboxed_err) => Err(Box::new(unboxed_err))
     }
 }
@@ -269,10 +939,9 @@ impl Error for WrongFieldTypeError {
     }
 }
 
-fn read_exif_metadata( path: &Path ) -> Result<ImageMetadataOfInterest, Box<dyn error::Error>> {
-    let exif_fields = get_exif_fields( path )?;
-
-    let maybe_orientation_field = exif_fields.get_field( exif::Tag::Orientation, exif::In::PRIMARY );
+// Extracts the metadata of interest from one IFD of the EXIF data. The path is only used for messages.
+fn image_metadata_from_exif( exif_fields: &exif::Exif, ifd: exif::In, path: &Path ) -> ImageMetadataOfInterest {
+    let maybe_orientation_field = exif_fields.get_field( exif::Tag::Orientation, ifd );
     let orientation = if let Some(orientation_field) = maybe_orientation_field {
         if let exif::Value::Short(orientation_raw) = &orientation_field.value {
             if let Some(first_orientation) = orientation_raw.first() {
@@ -293,7 +962,7 @@ fn read_exif_metadata( path: &Path ) -> Result<ImageMetadataOfInterest, Box<dyn
         None
     };
 
-    let maybe_dto = exif_fields.get_field( exif::Tag::DateTimeOriginal, exif::In::PRIMARY );
+    let maybe_dto = exif_fields.get_field( exif::Tag::DateTimeOriginal, ifd );
     let capture_time = if let Some(dto_field) = maybe_dto {
         if let exif::Value::Ascii(dto_raw) = &dto_field.value {
             if let Some(first_dto) = dto_raw.first() {
@@ -319,7 +988,7 @@ fn read_exif_metadata( path: &Path ) -> Result<ImageMetadataOfInterest, Box<dyn
         None
     };
 
-    let maybe_cm = exif_fields.get_field( exif::Tag::Model, exif::In::PRIMARY );
+    let maybe_cm = exif_fields.get_field( exif::Tag::Model, ifd );
     let camera_model = if let Some(cm_field) = maybe_cm {
         if let exif::Value::Ascii(cm_raw) = &cm_field.value {
             if let Some(first_cm) = cm_raw.first() {
@@ -340,7 +1009,7 @@ fn read_exif_metadata( path: &Path ) -> Result<ImageMetadataOfInterest, Box<dyn
         None
     };
 
-    let maybe_cs = exif_fields.get_field( exif::Tag::BodySerialNumber, exif::In::PRIMARY );
+    let maybe_cs = exif_fields.get_field( exif::Tag::BodySerialNumber, ifd );
     let camera_serial = if let Some(cs_field) = maybe_cs {
         if let exif::Value::Ascii(cs_raw) = &cs_field.value {
             if let Some(first_cs) = cs_raw.first() {
@@ -361,53 +1030,505 @@ fn read_exif_metadata( path: &Path ) -> Result<ImageMetadataOfInterest, Box<dyn
         None
     };
     
-    Ok( ImageMetadataOfInterest {
+    ImageMetadataOfInterest {
         orientation,
         capture_time,
         camera_model,
-        camera_serial
-    } )
+        camera_serial,
+        location: gps_location_from_exif( exif_fields, ifd ),
+    }
+}
+
+// Reads the location from the GPS IFD: degrees, minutes and seconds with a hemisphere reference,
+// and the altitude, which is below sea level when its reference is 1.
+fn gps_location_from_exif( exif_fields: &exif::Exif, ifd: exif::In ) -> Option<GpsLocation> {
+    let coordinate = |tag, reference_tag, negative_reference: &[u8]| {
+        let degrees = match &exif_fields.get_field( tag, ifd )?.value {
+            exif::Value::Rational(parts) if parts.len() == 3 && parts.iter().all(|part| part.denom != 0) => {
+                parts[0].to_f64() + parts[1].to_f64() / 60.0 + parts[2].to_f64() / 3600.0
+            },
+            _ => return None,
+        };
+        let negative = match &exif_fields.get_field( reference_tag, ifd )?.value {
+            exif::Value::Ascii(references) => references.first().map(|reference| reference.as_slice()) == Some(negative_reference),
+            _ => return None,
+        };
+        Some( if negative { -degrees } else { degrees } )
+    };
+    let latitude = coordinate( exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef, b"S" )?;
+    let longitude = coordinate( exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef, b"W" )?;
+    let altitude = match exif_fields.get_field( exif::Tag::GPSAltitude, ifd ).map(|field| &field.value) {
+        Some(exif::Value::Rational(values)) if values.first().is_some_and(|value| value.denom != 0) => {
+            let below_sea_level = exif_fields.get_field( exif::Tag::GPSAltitudeRef, ifd ).and_then(|field| field.value.get_uint( 0 )) == Some(1);
+            let metres = values[0].to_f64();
+            Some( if below_sea_level { -metres } else { metres } )
+        },
+        _ => None,
+    };
+    Some( GpsLocation { latitude, longitude, altitude } )
+}
+
+pub fn read_metadata_of_interest( path: &Path ) -> Result<MetadataOfInterest, Box<dyn error::Error>> {
+    read_metadata_of_interest_with_options( path, &ReadOptions::default() )
 }
 
-fn read_metadata_of_interest( path: &Path ) -> Result<MetadataOfInterest, Box<dyn error::Error>> {
-    let file_metadata = read_file_metadata( path )?;
-    let image_metadata = read_exif_metadata( path )?;
+pub fn read_metadata_of_interest_with_options( path: &Path, options: &ReadOptions ) -> Result<MetadataOfInterest, Box<dyn error::Error>> {
+    let mut file_metadata = read_file_metadata( path )?;
+
+    // Classify the file before trying any of the readers, so that files which are not
+    // images or videos are reported as such rather than as opaque parse errors.
+    let format = detect_format( path )?;
+    if !format.is_supported() {
+        return Err(Box::new(UnsupportedFormatError{ format }));
+    }
+    file_metadata.mime_type = Some(format.mime_type().to_string());
+    if let Some(algorithm) = options.hash {
+        file_metadata.hashes = Some( hash_file( path, algorithm )? );
+    }
+
+    if format.is_video() {
+        let (image_metadata, video_metadata) = video::read_quicktime_metadata( path )?;
+        return Ok( MetadataOfInterest {
+            file_metadata,
+            image_metadata,
+            video_metadata: Some(video_metadata),
+            thumbnail: None,
+            color: None,
+            vendor_metadata: None,
+            frames: None,
+            sequence: None,
+        } );
+    }
+
+    let exif_fields = get_exif_fields( path )?;
+    let image_metadata = image_metadata_from_exif( &exif_fields, exif::In::PRIMARY, path );
+    let thumbnail = previews::read_thumbnail_info( path, format, &exif_fields )?;
+    let color = color::read_color_metadata( path, format, &exif_fields )?;
+    let vendor_metadata = makernote::read_vendor_metadata( &exif_fields );
+    let frames = if options.include_frames {
+        Some( frames::read_frames( path, format )? )
+    } else {
+        None
+    };
 
     Ok( MetadataOfInterest {
         file_metadata,
-        image_metadata
+        image_metadata,
+        video_metadata: None,
+        thumbnail,
+        color,
+        vendor_metadata,
+        frames,
+        sequence: None,
     } )
 }
 
 impl Config {
-    pub fn new(args: std::env::Args) -> Result<Config, &'static str> {
+    pub fn new(args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
         // First arg is skipped because it is the executable name
         let raw_args : Vec<String> = args.collect();
         if let Some(first_arg_string) = raw_args.get(1) {
             if first_arg_string == "-h" || first_arg_string == "--help" {
                 return Ok(Config {
+                    command: Command::Read,
                     image_paths: vec![],
                     print_help: true,
+                    read_options: ReadOptions::default(),
+                    redaction: Redaction::default(),
                 });
             }
         } else {
             return Err("Not enough arguments.  Provide at least one path to an image file to read.")
         }
-        let paths : Vec<PathBuf> = raw_args.iter().skip(1).map(|arg| { PathBuf::from(&arg) }).collect();
+
+        let mut remaining_args = raw_args.iter().skip(1).peekable();
+        let mut command = match remaining_args.peek().map(|arg| arg.as_str()) {
+            Some("previews") => {
+                remaining_args.next();
+                Command::ExtractPreviews { output_dir: None }
+            },
+            Some("set") => {
+                remaining_args.next();
+                Command::Set { patch: ImageMetadataPatch::default(), options: WriteOptions::default() }
+            },
+            Some("import") => {
+                remaining_args.next();
+                Command::Import { options: WriteOptions::default() }
+            },
+            Some("sync") => {
+                remaining_args.next();
+                Command::Sync { sync: ClockSync::default(), options: WriteOptions::default() }
+            },
+            Some("scrub") => {
+                remaining_args.next();
+                Command::Scrub { policy: ScrubPolicy::RemoveAll, options: WriteOptions::default() }
+            },
+            Some("normalize") => {
+                remaining_args.next();
+                Command::Normalize { options: WriteOptions::default() }
+            },
+            Some("copy") => {
+                remaining_args.next();
+                Command::Copy { source: None, groups: MetadataGroup::ALL.to_vec(), options: WriteOptions::default() }
+            },
+            Some("geotag") => {
+                remaining_args.next();
+                Command::Geotag { geotag: Geotag::default(), options: WriteOptions::default() }
+            },
+            Some("rename") => {
+                remaining_args.next();
+                Command::Rename { template: NameTemplate::default(), journal: None, options: WriteOptions::default() }
+            },
+            Some("undo-rename") => {
+                remaining_args.next();
+                Command::UndoRename { options: WriteOptions::default() }
+            },
+            Some("organize") => {
+                remaining_args.next();
+                Command::Organize { destination: None, organize: Organize::default(), options: WriteOptions::default() }
+            },
+            Some("dedupe") => {
+                remaining_args.next();
+                Command::Dedupe { dedupe: DedupeOptions::default(), report: None }
+            },
+            Some("index") => {
+                remaining_args.next();
+                Command::Index { catalog: None }
+            },
+            Some("query") => {
+                remaining_args.next();
+                Command::Query { filter: None, catalog: None }
+            },
+            Some("watch") => {
+                remaining_args.next();
+                Command::Watch { watch: Watch::default() }
+            },
+            Some("bursts") => {
+                remaining_args.next();
+                Command::Bursts { bursts: BurstOptions::default(), options: WriteOptions::default() }
+            },
+            Some("shift") => {
+                remaining_args.next();
+                Command::Shift { shift: TimeShift::new( chrono::Duration::zero() ), options: WriteOptions::default() }
+            },
+            _ => Command::Read,
+        };
+
+        let mut read_options = ReadOptions::default();
+        let mut redaction = Redaction::default();
+        let mut paths : Vec<PathBuf> = vec![];
+        while let Some(arg) = remaining_args.next() {
+            match (arg.as_str(), &mut command) {
+                ("--frames", Command::Read) | ("--frames", Command::Index { .. }) | ("--frames", Command::Watch { .. }) => read_options.include_frames = true,
+                ("--hash", Command::Read) | ("--hash", Command::Index { .. }) | ("--hash", Command::Watch { .. }) => {
+                    match remaining_args.next().and_then(|value| HashAlgorithm::from_name( value )) {
+                        Some(algorithm) => read_options.hash = Some(algorithm),
+                        None => return Err("--hash requires one of: sha256, blake3."),
+                    }
+                },
+                ("--output-dir", Command::ExtractPreviews { output_dir }) => {
+                    match remaining_args.next() {
+                        Some(dir) => *output_dir = Some(PathBuf::from(dir)),
+                        None => return Err("--output-dir requires a directory."),
+                    }
+                },
+                ("--dry-run", Command::Set { options, .. })
+                    | ("--dry-run", Command::Import { options })
+                    | ("--dry-run", Command::Shift { options, .. })
+                    | ("--dry-run", Command::Sync { options, .. })
+                    | ("--dry-run", Command::Scrub { options, .. })
+                    | ("--dry-run", Command::Normalize { options })
+                    | ("--dry-run", Command::Copy { options, .. })
+                    | ("--dry-run", Command::Geotag { options, .. })
+                    | ("--dry-run", Command::Rename { options, .. })
+                    | ("--dry-run", Command::UndoRename { options })
+                    | ("--dry-run", Command::Organize { options, .. })
+                    | ("--dry-run", Command::Bursts { options, .. }) => options.dry_run = true,
+                ("--to", Command::Organize { destination, .. }) => {
+                    match remaining_args.next() {
+                        Some(dir) => *destination = Some(PathBuf::from(dir)),
+                        None => return Err("--to requires the directory to organize the files into."),
+                    }
+                },
+                ("--layout", Command::Organize { organize, .. }) => {
+                    match remaining_args.next().and_then(|value| NameTemplate::layout( value ).ok()) {
+                        Some(layout) => organize.layout = layout,
+                        None => return Err("--layout requires a valid layout of folders, such as \"{year}/{year}-{month}-{day}/{camera_model}\"."),
+                    }
+                },
+                ("--move", Command::Organize { organize, .. }) => organize.move_files = true,
+                ("--keep", Command::Dedupe { dedupe, .. }) => {
+                    match remaining_args.next().and_then(|value| KeeperRule::parse_list( value )) {
+                        Some(rules) => dedupe.keeper_rules = rules,
+                        None => return Err("--keep requires a comma separated list of: raw, largest, oldest."),
+                    }
+                },
+                ("--hash", Command::Dedupe { dedupe, .. }) => {
+                    match remaining_args.next().and_then(|value| HashAlgorithm::from_name( value )) {
+                        Some(algorithm) => dedupe.algorithm = algorithm,
+                        None => return Err("--hash requires one of: sha256, blake3."),
+                    }
+                },
+                ("--report", Command::Dedupe { report, .. }) => {
+                    match remaining_args.next() {
+                        Some(path) => *report = Some(PathBuf::from(path)),
+                        None => return Err("--report requires the path to write the JSON report to."),
+                    }
+                },
+                ("--where", Command::Query { filter, .. }) => {
+                    match remaining_args.next().map(|value| value.parse::<Filter>()) {
+                        Some(Ok(parsed)) => *filter = Some(parsed),
+                        Some(Err(parse_err)) => {
+                            eprintln!("{}",parse_err);
+                            return Err("--where requires a valid filter expression, such as: orientation in [6, 8] && capture_time > 2020-01-30T09:30");
+                        },
+                        None => return Err("--where requires a filter expression."),
+                    }
+                },
+                ("--settle", Command::Watch { watch }) => {
+                    match remaining_args.next().and_then(|value| value.parse::<u64>().ok()) {
+                        Some(settle) => watch.settle = std::time::Duration::from_millis( settle ),
+                        None => return Err("--settle requires a number of milliseconds."),
+                    }
+                },
+                ("--catalog", Command::Index { catalog })
+                    | ("--catalog", Command::Query { catalog, .. })
+                    | ("--catalog", Command::Watch { watch: Watch { catalog, .. } }) => {
+                    match remaining_args.next() {
+                        Some(path) => *catalog = Some(PathBuf::from(path)),
+                        None => return Err("--catalog requires the path of the catalog file."),
+                    }
+                },
+                ("--max-gap", Command::Bursts { bursts, .. }) => {
+                    match remaining_args.next().and_then(|value| value.parse::<i64>().ok()).filter(|gap| *gap > 0) {
+                        Some(max_gap) => bursts.max_gap = chrono::Duration::milliseconds( max_gap ),
+                        None => return Err("--max-gap requires a number of milliseconds."),
+                    }
+                },
+                ("--template", Command::Rename { template, .. }) => {
+                    match remaining_args.next().and_then(|value| value.parse::<NameTemplate>().ok()) {
+                        Some(parsed) => *template = parsed,
+                        None => return Err("--template requires a valid name template, such as \"{capture_time:%Y%m%d_%H%M%S}_{seq}.{ext}\"."),
+                    }
+                },
+                ("--journal", Command::Rename { journal, .. }) => {
+                    match remaining_args.next() {
+                        Some(path) => *journal = Some(PathBuf::from(path)),
+                        None => return Err("--journal requires the path to write the undo journal to."),
+                    }
+                },
+                ("--track", Command::Geotag { geotag, .. }) => {
+                    match remaining_args.next() {
+                        Some(track) => geotag.tracks.push( PathBuf::from(track) ),
+                        None => return Err("--track requires the path of a GPX or NMEA file."),
+                    }
+                },
+                ("--timezone", Command::Geotag { geotag, .. }) => {
+                    match remaining_args.next().and_then(|value| timeshift::parse_time_offset( value )) {
+                        Some(utc_offset) => geotag.utc_offset = utc_offset,
+                        None => return Err("--timezone requires the cameras' offset from UTC, such as +02:00."),
+                    }
+                },
+                ("--clock-offset", Command::Geotag { geotag, .. }) => {
+                    match remaining_args.next().and_then(|value| timeshift::parse_time_offset( value )) {
+                        Some(clock_offset) => geotag.clock_offset = clock_offset,
+                        None => return Err("--clock-offset requires a signed offset such as -0:01:30."),
+                    }
+                },
+                ("--max-gap", Command::Geotag { geotag, .. }) => {
+                    match remaining_args.next().and_then(|value| value.parse::<i64>().ok()).and_then(chrono::Duration::try_seconds).filter(|gap| *gap >= chrono::Duration::zero()) {
+                        Some(max_gap) => geotag.max_gap = max_gap,
+                        None => return Err("--max-gap requires a number of seconds."),
+                    }
+                },
+                ("--write", Command::Geotag { geotag, .. }) => geotag.write_back = true,
+                ("--from", Command::Copy { source, .. }) => {
+                    match remaining_args.next() {
+                        Some(path) => *source = Some(CopySource::File(PathBuf::from(path))),
+                        None => return Err("--from requires the path of the file to copy from."),
+                    }
+                },
+                ("--from-dir", Command::Copy { source, .. }) => {
+                    match remaining_args.next() {
+                        Some(dir) => *source = Some(CopySource::MatchingStem(PathBuf::from(dir))),
+                        None => return Err("--from-dir requires a directory holding files named like the targets."),
+                    }
+                },
+                ("--groups", Command::Copy { groups, .. }) => {
+                    match remaining_args.next().and_then(|value| MetadataGroup::parse_list( value )) {
+                        Some(named_groups) => *groups = named_groups,
+                        None => return Err("--groups requires a comma separated list of: capture-time, camera, lens, gps, copyright, all."),
+                    }
+                },
+                ("--policy", Command::Scrub { policy, .. }) => {
+                    match remaining_args.next().and_then(|value| ScrubPolicy::from_name( value )) {
+                        Some(named_policy) => *policy = named_policy,
+                        None => return Err("--policy requires one of: all, keep-orientation-copyright, gps-serials."),
+                    }
+                },
+                ("--pair", Command::Sync { sync, .. }) => {
+                    match (remaining_args.next(), remaining_args.next()) {
+                        (Some(first), Some(second)) => sync.pairs.push( ReferencePair { first: PathBuf::from(first), second: PathBuf::from(second) } ),
+                        _ => return Err("--pair requires two paths, of files from different cameras taken at the same moment."),
+                    }
+                },
+                ("--reference", Command::Sync { sync, .. }) => {
+                    match remaining_args.next() {
+                        Some(reference_serial) => sync.reference_serial = Some(reference_serial.clone()),
+                        None => return Err("--reference requires a camera serial."),
+                    }
+                },
+                ("--write", Command::Sync { sync, .. }) => sync.write_back = true,
+                ("--orientation", Command::Set { patch, .. }) => {
+                    match remaining_args.next().and_then(|value| value.parse::<Orientation>().ok()) {
+                        Some(orientation) => patch.orientation = Some(orientation),
+                        None => return Err("--orientation requires an EXIF orientation value from 1 to 8, or its name such as quarter-rotation-cw."),
+                    }
+                },
+                ("--capture-time", Command::Set { patch, .. }) => {
+                    match remaining_args.next().and_then(|value| chrono::NaiveDateTime::parse_from_str( value, CAPTURE_TIME_FORMAT ).ok()) {
+                        Some(capture_time) => patch.capture_time = Some(capture_time),
+                        None => return Err("--capture-time requires a time formatted as \"YYYY:MM:DD HH:MM:SS\"."),
+                    }
+                },
+                ("--camera-model", Command::Set { patch, .. }) => {
+                    match remaining_args.next() {
+                        Some(camera_model) => patch.camera_model = Some(camera_model.clone()),
+                        None => return Err("--camera-model requires a value."),
+                    }
+                },
+                ("--camera-serial", Command::Set { patch, .. }) => {
+                    match remaining_args.next() {
+                        Some(camera_serial) => patch.camera_serial = Some(camera_serial.clone()),
+                        None => return Err("--camera-serial requires a value."),
+                    }
+                },
+                ("--copyright", Command::Set { patch, .. }) => {
+                    match remaining_args.next() {
+                        Some(copyright) => patch.copyright = Some(copyright.clone()),
+                        None => return Err("--copyright requires a value."),
+                    }
+                },
+                ("--by", Command::Shift { shift, .. }) => {
+                    match remaining_args.next().and_then(|value| timeshift::parse_time_offset( value )) {
+                        Some(offset) => shift.offset = offset,
+                        None => return Err("--by requires a signed offset such as -1:00 or +0:02:30."),
+                    }
+                },
+                ("--camera-serial", Command::Shift { shift, .. }) => {
+                    match remaining_args.next() {
+                        Some(camera_serial) => shift.camera_serial = Some(camera_serial.clone()),
+                        None => return Err("--camera-serial requires a value."),
+                    }
+                },
+                ("--camera-model", Command::Shift { shift, .. }) => {
+                    match remaining_args.next() {
+                        Some(camera_model) => shift.camera_model = Some(camera_model.clone()),
+                        None => return Err("--camera-model requires a value."),
+                    }
+                },
+                ("--offset-time", Command::Shift { shift, .. }) => {
+                    match remaining_args.next().filter(|value| timeshift::is_valid_offset_time( value )) {
+                        Some(offset_time) => shift.offset_time = Some(offset_time.clone()),
+                        None => return Err("--offset-time requires a time zone offset such as +02:00."),
+                    }
+                },
+                ("--serial-key-file", _) => {
+                    match remaining_args.next().and_then(|key_path| fs::read( key_path ).ok()) {
+                        Some(key) if !key.trim_ascii().is_empty() => redaction.serial_key = Some(key.trim_ascii().to_vec()),
+                        _ => return Err("--serial-key-file requires a readable file holding the secret."),
+                    }
+                },
+                ("--gps-precision", _) => {
+                    match remaining_args.next().and_then(|value| value.parse::<u32>().ok()).filter(|decimals| *decimals <= 8) {
+                        Some(decimals) => redaction.gps_decimals = Some(decimals),
+                        None => return Err("--gps-precision requires a number of decimal places from 0 to 8."),
+                    }
+                },
+                ("--remove-field", _) => {
+                    match remaining_args.next() {
+                        Some(field) => redaction.removed_fields.push(field.clone()),
+                        None => return Err("--remove-field requires a field name, such as vendor.internal_serial."),
+                    }
+                },
+                (option, _) if option.starts_with("--") => return Err("Unknown option.  Run with --help to see the supported options."),
+                _ => paths.push(PathBuf::from(&arg)),
+            }
+        }
+        if paths.is_empty() && !matches!(command, Command::Query { catalog: Some(_), .. }) {
+            return Err("Not enough arguments.  Provide at least one path to an image file to read.")
+        }
+        if let Command::Set { patch, .. } = &command {
+            if patch.is_empty() {
+                return Err("Nothing to set.  Provide at least one field to change, such as --capture-time.")
+            }
+        }
+        if let Command::Shift { shift, .. } = &command {
+            if shift.offset.is_zero() && shift.offset_time.is_none() {
+                return Err("Nothing to shift.  Provide an offset with --by, such as --by -1:00.")
+            }
+        }
+        if let Command::Sync { sync, .. } = &command {
+            if sync.pairs.is_empty() {
+                return Err("Nothing to synchronize with.  Provide at least one reference pair with --pair.")
+            }
+        }
+        if let Command::Geotag { geotag, .. } = &command {
+            if geotag.tracks.is_empty() {
+                return Err("Nothing to geotag with.  Provide at least one GPX or NMEA file with --track.")
+            }
+        }
+        if let Command::Organize { destination: None, .. } = &command {
+            return Err("Nowhere to organize into.  Provide the destination directory with --to.")
+        }
+        if let Command::Query { filter: None, .. } = &command {
+            return Err("Nothing to query with.  Provide a filter expression with --where.")
+        }
+        if let Command::Index { catalog: None } = &command {
+            return Err("No catalog to index into.  Provide the catalog file with --catalog.")
+        }
+        if let Command::Copy { source: None, .. } = &command {
+            return Err("Nothing to copy from.  Provide a source file with --from, or a directory with --from-dir.")
+        }
 
         Ok(Config { 
+            command,
             image_paths: paths,
             print_help: false,
+            read_options,
+            redaction,
         })
     }
 
     pub fn from_strings(strings: Vec<String>) -> Config {
         Config {
+            command: Command::Read,
             image_paths: strings.iter().map(|arg| { PathBuf::from(&arg) }).collect(),
             print_help: false,
+            read_options: ReadOptions::default(),
+            redaction: Redaction::default(),
         }
     }
 
+    pub fn with_command(mut self, command: Command) -> Config {
+        self.command = command;
+        self
+    }
+
+    pub fn with_read_options(mut self, read_options: ReadOptions) -> Config {
+        self.read_options = read_options;
+        self
+    }
+
+    pub fn with_redaction(mut self, redaction: Redaction) -> Config {
+        self.redaction = redaction;
+        self
+    }
+
+    pub fn command(&self) -> &Command {
+        &self.command
+    }
+
     pub fn image_paths(&self) -> &Vec<PathBuf> {
         &self.image_paths
     }
@@ -415,4 +1536,12 @@ impl Config {
     pub fn print_help(&self) -> bool {
         self.print_help
     }
+
+    pub fn read_options(&self) -> &ReadOptions {
+        &self.read_options
+    }
+
+    pub fn redaction(&self) -> &Redaction {
+        &self.redaction
+    }
 }
@@ -1,5 +1,4 @@
 use std::process;
-use rusimeta;
 
 fn main() {
     let cfg_result = rusimeta::Config::new( std::env::args() );
@@ -7,11 +6,183 @@ fn main() {
     if let Ok(cfg) = cfg_result {
         if cfg.print_help() {
             println!("\
-Provide paths (absolute or relative) to image files whose metadata should be read.
+Provide paths (absolute or relative) to image or video files whose metadata should be read.
 The read metadata will be written to JSON files in the same directory as the matching images.
+Videos in QuickTime/MP4 containers (.mov, .mp4) are supported alongside images. The movie
+header's creation time, which is UTC, is given in the local time zone, and the recorded
+position is also the \"location\" of the capture, as for images.
+File types are detected from the file contents; files which are not supported images or videos
+are skipped and counted separately from files which could not be read.
+Canon, Nikon and Sony MakerNotes are decoded under \"vendor\". The shutter count is only
+given for bodies which record it there; of Canon bodies only the EOS-1D Mark III does, so
+it is missing for others such as the 5D Mark IV.
+
+Options:
+--frames    List every image held in each file (TIFF pages, thumbnails, RAW previews,
+            MPO stereo/multi-angle images) with its own metadata, under \"frames\".
+--hash sha256|blake3
+            Fingerprint each file under \"hashes\": a hash of the whole file, and of its image
+            payload with the metadata left out (JPEG, PNG, WebP and TIFF-based files), which
+            stays the same when a copy is re-tagged.
+
+Redaction options, for JSON which will be shared without the images. They apply to every
+command which writes JSON: sidecars, the index and watch catalogs and the dedupe report.
+--serial-key-file FILE
+            Replace each camera_serial, and the serials in the vendor section, with a keyed
+            hash of it, using the secret in FILE, and leave out the Canon owner_name. The
+            same secret always gives the same pseudonym for a serial.
+--gps-precision N
+            Round GPS coordinates to N decimal places (2 is roughly a kilometre).
+--remove-field NAME
+            Leave a field out, by its JSON name; nested fields are joined with dots, such as
+            vendor.internal_serial or video.location (in the dedupe report, by their name
+            within each file, such as capture.camera_serial). May be given several times.
+Redacted JSON files are marked with \"redacted\": true, and can't be used with import, or
+updated by sync, shift, geotag or bursts.
+
+Commands:
+previews [--output-dir DIR] PATHS...
+            Extract the embedded EXIF thumbnail and any larger embedded previews (from RAW
+            IFDs, MakerNotes or MPO large thumbnails) of each file as JPEG files, named
+            <name>_thumbnail.jpg and <name>_preview1.jpg, ... in DIR or next to the file.
+set [--dry-run] [FIELDS...] PATHS...
+            Write fields into the EXIF data of JPEG and TIFF files, keeping every other tag
+            and the image data as they are. With --dry-run, only print what would change.
+            Fields: --orientation 1-8 or NAME, --capture-time \"YYYY:MM:DD HH:MM:SS\",
+            --camera-model TEXT, --camera-serial TEXT, --copyright TEXT.
+import [--dry-run] PATHS...
+            Read the JSON file next to each image, as written when reading, and write the
+            fields edited in it back into the image. Fields which can't be written, such as
+            the size and filesystem times, are reported as not applicable.
+shift --by OFFSET [--camera-serial TEXT] [--camera-model TEXT] [--offset-time ZONE] [--dry-run] PATHS...
+            Add a signed offset such as -1:00 or +0:02:30 (hours:minutes[:seconds]) to the
+            DateTimeOriginal, DateTimeDigitized and DateTime fields of JPEG and TIFF files, for
+            fixing a camera whose clock was wrong. Only files from the camera with the given
+            serial or model are changed. --offset-time also sets the OffsetTime fields, e.g.
+            +02:00. JSON files written when reading are updated to match.
+sync --pair FILE FILE [--pair FILE FILE...] [--reference SERIAL] [--write] [--dry-run] PATHS...
+            Line up the clocks of several cameras which shot one event. Each pair is two files
+            of the same moment from different cameras; several pairs per camera also correct
+            for drift. Times are brought onto the clock of the reference camera, by default the
+            camera of the first file. The corrected capture times are written to the JSON files,
+            keeping what else they hold, or with --write into the files themselves, and a
+            merged timeline is printed.
+scrub [--policy all|keep-orientation-copyright|gps-serials] [--dry-run] PATHS...
+            Remove metadata from JPEG, PNG, WebP and TIFF files before publishing them, without
+            re-encoding the image. \"all\" (the default) removes everything not needed to show
+            the image; \"keep-orientation-copyright\" keeps just those two fields; \"gps-serials\"
+            removes the location, serial numbers, owner name, MakerNote and XMP/IPTC packets.
+            Every image of a multi-picture (MPO) file is scrubbed. Each scrubbed file is read
+            back, and only replaces the original if none of the targeted fields remain.
+normalize [--dry-run] PATHS...
+            Losslessly rotate and mirror the image data of JPEG files to match their
+            Orientation tag, for viewers which ignore the tag, then reset the tag to 1. The
+            thumbnail and the dimension tags are updated to match. Edge blocks which would
+            end up at the left or top are trimmed off (at most 15 pixels per edge).
+            Baseline and extended sequential JPEGs are supported; progressive ones are not.
+copy (--from FILE | --from-dir DIR) [--groups LIST] [--dry-run] PATHS...
+            Copy metadata from a source image into each JPEG or TIFF file, such as an
+            export which lost its EXIF data. With --from-dir, the source of each file is
+            the file in DIR with the same name apart from the extension. LIST is a comma
+            separated list of: capture-time, camera, lens, gps, copyright, or all (the
+            default). The target's own dimensions and orientation are never changed.
+geotag --track FILE... [--timezone +HH:MM] [--clock-offset OFFSET] [--max-gap SECONDS]
+       [--write] [--dry-run] PATHS...
+            Find where each file was taken from GPS logs (GPX, or NMEA RMC/GGA sentences),
+            by the capture time converted to UTC with the cameras' --timezone, after adding
+            --clock-offset. Positions are interpolated between track points at most
+            --max-gap apart (default 300), and written into the JSON sidecar, and into the
+            file as well with --write. Files with a position of their own are kept as they
+            are, and the files no position was found for are listed at the end.
+rename [--template TEMPLATE] [--journal FILE] [--dry-run] PATHS...
+            Rename files, with their .json and .xmp sidecars, from their metadata. Fields are
+            written in braces, with an optional modifier after a colon:
+              {{capture_time:FORMAT}}  strftime FORMAT, %Y%m%d_%H%M%S by default
+              {{camera_model:N}} {{camera_serial:N}} {{filename:N}}
+                                     first N characters, or last N with -N
+              {{ext:lower|upper}}      original extension
+              {{counter:N}} {{seq:N}}    number of the file among all files, or among files which
+                                     would get the same name; padded to N digits
+            The default is {{capture_time:%Y%m%d_%H%M%S}}_{{camera_serial:-4}}_{{seq}}.{{ext}}.
+            Names which would collide get the next seq (or a _2, _3... suffix), in capture
+            time order. The renames are recorded in an undo journal, by default
+            rename-journal-TIME.json next to the first renamed file.
+undo-rename [--dry-run] JOURNALS...
+            Put back the files renamed according to undo journals written by rename.
+organize --to DIR [--layout LAYOUT] [--move] [--dry-run] PATHS...
+            Copy files, and the files in directories, into folders under DIR laid out from
+            their metadata, and write their JSON sidecars there. A file's own sidecar goes
+            with it, keeping its edits; sidecars already under DIR are otherwise left as they
+            are, and files with redacted sidecars are refused. LAYOUT takes the fields of
+            rename, folders separated by /, and {{year}} {{month}} {{day}} of the capture time
+            (or of the file's modification time); missing fields become \"unknown\". The
+            default is {{year}}/{{year}}-{{month}}-{{day}}/{{camera_model}}. Each copy is
+            checked by SHA-256 before --move removes the original. Files already there with
+            the same content are not copied again; other files with the same name get a _2,
+            _3... suffix.
+dedupe [--keep RULES] [--hash sha256|blake3] [--report FILE] PATHS...
+            Report groups of duplicates among files, and the files in directories: files with
+            the same content or image payload are identical or re-tagged copies, and files
+            with the same camera_serial, capture time, subsecond time, Canon file number and
+            dimensions are probably the same frame in another container or with edits. Frames
+            with neither a subsecond time nor a file number aren't grouped that way, as the
+            other frames of a burst share their capture second. The suggested file
+            to keep of each group is chosen by RULES, a comma separated list of raw, largest
+            and oldest (modification time), applied in order; raw,largest,oldest by default.
+            --report also writes the report as JSON. No file is changed.
+bursts [--max-gap MS] [--dry-run] PATHS...
+            List the bursts among files, and the files in directories: consecutive frames from
+            the same camera_serial less than MS milliseconds apart (1000 by default), timed with
+            their subsecond time when they have one. Canon frames must also have rising
+            sequence numbers, and consecutive numbers keep a burst going past MS. Files with
+            the same name but for the extension and the same capture time, such as a RAW and
+            its JPEG, are one frame. The sequence_id, position and length of each frame are
+            recorded as \"sequence\" in its JSON sidecar.
+index --catalog FILE [--frames] [--hash sha256|blake3] PATHS...
+            Keep the metadata of files, and the files in directories, in the catalog FILE, a
+            JSON Lines file: a header, then a record per file keyed by absolute path. Only
+            files which are new, or whose size or modification time changed, are read again;
+            entries for files which were deleted from under PATHS are removed. Their records
+            are appended, and FILE is only rewritten once most of its records are out of date.
+            --frames and --hash are recorded as for reading.
+query --where FILTER [--catalog FILE] [PATHS...]
+            List the files, files in directories and catalog entries whose metadata FILTER
+            matches; .json files are matched as sidecars. FILTER compares fields, by their JSON
+            name (nested ones joined with dots, such as location.latitude), using == != < <=
+            > >= and in [...], with strings in double quotes, numbers, times such as
+            2020-01-30T09:30 or 2020-01-30, true, false and null (for missing fields), and
+            combines them with && || ! and parentheses.
+watch [--settle MS] [--catalog FILE] [--frames] [--hash sha256|blake3] DIRECTORIES...
+            Keep the JSON sidecars of the files in the directories, or their entries in the
+            catalog FILE, in sync as files are added, changed and deleted, until Ctrl-C or
+            SIGTERM. A file is read once it has gone MS milliseconds (1000 by default)
+            without changes, and files changed while nothing was watching are read at the
+            start. An existing sidecar keeps its edits, location and burst. A RAW and the
+            JPEG of the same shot share a sidecar, which describes the RAW; changes to the
+            JPEG leave it alone. The .json sidecar of a deleted file is removed unless another
+            file, such as its RAW, still uses it, which then takes it over. Only on Linux.
 
 Example usage:
-rusimeta images/my_image1.jpg images/my_image2.tiff
+rusimeta images/my_image1.jpg images/my_image2.tiff videos/my_clip.mov
+rusimeta --frames images/stereo_pair.mpo
+rusimeta --hash blake3 images/*.jpg
+rusimeta --serial-key-file secret.txt --gps-precision 2 --remove-field vendor videos/my_clip.mov
+rusimeta previews --output-dir previews/ images/my_image1.cr2
+rusimeta set --dry-run --capture-time \"2020:01:30 09:28:07\" --copyright \"Jane Doe\" images/my_image1.jpg
+rusimeta import --dry-run images/my_image1.jpg
+rusimeta shift --dry-run --by -1:00 --camera-serial 025021000537 images/*.jpg
+rusimeta sync --pair a/IMG_0001.jpg b/DSC_0001.jpg a/*.jpg b/*.jpg
+rusimeta scrub --policy gps-serials images/my_image1.jpg
+rusimeta normalize images/rotated_CCW90.jpg
+rusimeta copy --from-dir raw --groups capture-time,gps,copyright exports/*.jpg
+rusimeta geotag --track logger.gpx --timezone +02:00 --write images/*.jpg
+rusimeta rename --template \"{{capture_time:%Y%m%d}}_{{filename}}.{{ext:lower}}\" images/*.JPG
+rusimeta organize --to ~/Pictures --move /media/card/DCIM
+rusimeta dedupe --keep oldest,largest --report duplicates.json ~/Pictures /media/backup
+rusimeta bursts --max-gap 500 --dry-run ~/Pictures/2019-07-26
+rusimeta index --catalog ~/Pictures/catalog.json --hash blake3 ~/Pictures
+rusimeta query --catalog ~/Pictures/catalog.json --where \"camera_serial == \\\"025021000535\\\" && capture_time > 2020-01-30T09:30 && orientation in [6, 8]\"
+rusimeta watch --settle 2000 ~/Tethered
 ");
             process::exit(0);
         }
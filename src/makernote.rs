@@ -0,0 +1,162 @@
+use serde::{Serialize, Deserialize};
+
+use crate::canon::{self, CanonMakerNote};
+use crate::nikon::{self, NikonMakerNote};
+use crate::sony::{self, SonyMakerNote};
+use crate::tiff::{self, ByteOrder, IfdEntry};
+
+const NIKON_HEADER : &[u8] = b"Nikon\0";
+// Nikon type 3 MakerNotes hold a complete TIFF header 10 bytes in, after "Nikon\0" and a version.
+const NIKON_TIFF_HEADER_OFFSET : usize = 10;
+const SONY_HEADERS : [&[u8]; 2] = [b"SONY DSC \0\0\0", b"SONY CAM \0\0\0"];
+const SONY_HEADER_LENGTH : usize = 12;
+
+// The IFD inside a MakerNote field. MakerNotes are vendor-defined, but most are an IFD
+// whose value offsets are relative either to the EXIF TIFF header or to a TIFF header
+// of their own. `data` is the buffer those offsets are relative to, and `data_position`
+// is where that buffer starts relative to the EXIF TIFF header.
+pub(crate) struct MakerNote<'a> {
+    pub data: &'a [u8],
+    pub data_position: usize,
+    pub order: ByteOrder,
+    pub entries: Vec<IfdEntry>,
+}
+
+impl<'a> MakerNote<'a> {
+    pub fn entry( &self, tag: u16 ) -> Option<&IfdEntry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+
+    // Reads the values of a SHORT or LONG field as unsigned integers.
+    pub fn uints( &self, tag: u16 ) -> Option<Vec<u32>> {
+        let entry = self.entry( tag )?;
+        let bytes = entry.value_bytes( self.data, self.order )?;
+        match entry.field_type {
+            3 | 8 => Some( (0..entry.count as usize).filter_map(|index| self.order.u16( bytes, index * 2 ).map(u32::from)).collect() ),
+            4 | 9 | 13 => Some( (0..entry.count as usize).filter_map(|index| self.order.u32( bytes, index * 4 )).collect() ),
+            _ => None,
+        }
+    }
+
+    // Reads the values of a RATIONAL field as (numerator, denominator) pairs.
+    pub fn rationals( &self, tag: u16 ) -> Option<Vec<(u32, u32)>> {
+        let entry = self.entry( tag )?;
+        if entry.field_type != 5 {
+            return None;
+        }
+        let bytes = entry.value_bytes( self.data, self.order )?;
+        Some( (0..entry.count as usize)
+            .filter_map(|index| Some( (self.order.u32( bytes, index * 8 )?, self.order.u32( bytes, index * 8 + 4 )?) ))
+            .collect() )
+    }
+
+    // Reads an ASCII field, trimmed of padding. Empty strings are treated as absent.
+    pub fn ascii( &self, tag: u16 ) -> Option<String> {
+        let bytes = self.entry( tag )?.value_bytes( self.data, self.order )?;
+        let end = bytes.iter().position(|byte| *byte == 0).unwrap_or( bytes.len() );
+        let text = String::from_utf8_lossy( &bytes[..end] ).trim().to_string();
+        if text.is_empty() { None } else { Some(text) }
+    }
+
+    // Offset relative to the EXIF TIFF header of a position given relative to `data`.
+    pub fn to_exif_offset( &self, offset: usize ) -> usize {
+        self.data_position + offset
+    }
+}
+
+// Locates the MakerNote in the EXIF data and parses its IFD, recognizing the header layouts
+// used by Nikon and Sony. Other makes, including Canon, write a bare IFD.
+pub(crate) fn parse_maker_note<'a>( exif_fields: &'a exif::Exif ) -> Option<MakerNote<'a>> {
+    let field = exif_fields.get_field( exif::Tag::MakerNote, exif::In::PRIMARY )?;
+    let (bytes, offset) = match &field.value {
+        exif::Value::Undefined(bytes, offset) => (bytes, *offset as usize),
+        _ => return None,
+    };
+    let buffer = exif_fields.buf();
+    let exif_order = if exif_fields.little_endian() { ByteOrder::LittleEndian } else { ByteOrder::BigEndian };
+
+    let (data, data_position, order, ifd_offset) = if bytes.starts_with( NIKON_HEADER ) {
+        let inner = buffer.get(offset + NIKON_TIFF_HEADER_OFFSET..offset + bytes.len())?;
+        let order = ByteOrder::from_tiff_header( inner )?;
+        (inner, offset + NIKON_TIFF_HEADER_OFFSET, order, order.u32( inner, 4 )? as usize)
+    } else if SONY_HEADERS.iter().any(|header| bytes.starts_with( header )) {
+        (buffer, 0, exif_order, offset + SONY_HEADER_LENGTH)
+    } else {
+        (buffer, 0, exif_order, offset)
+    };
+
+    let (entries, _) = tiff::read_ifd( data, ifd_offset, order )?;
+    // A bare IFD is only a guess for unknown vendors, so reject it if it is clearly not one.
+    if entries.is_empty() || entries.len() > 1000 {
+        return None;
+    }
+    Some( MakerNote { data, data_position, order, entries } )
+}
+
+// The fields every vendor decoder reports, plus that vendor's own fields in `details`.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct VendorMetadataOfInterest {
+    // The key the decoder is registered under, such as "canon".
+    pub make: String,
+    // Only for bodies which record it in their MakerNote; Canon bodies other than the
+    // EOS-1D Mark III, including the 5D Mark IV, don't, so it is left out for them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutter_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lens: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_serial: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firmware_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<VendorDetails>,
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VendorDetails {
+    Canon(CanonMakerNote),
+    Nikon(NikonMakerNote),
+    Sony(SonyMakerNote),
+}
+
+type MakerNoteDecoder = fn( &MakerNote, &exif::Exif ) -> VendorMetadataOfInterest;
+
+// MakerNote decoders, by the start of the upper-cased Make tag. Supporting another vendor
+// only needs a decoder module and an entry here.
+const VENDOR_DECODERS : [(&str, MakerNoteDecoder); 3] = [
+    ("CANON", canon::decode_maker_note),
+    ("NIKON", nikon::decode_maker_note),
+    ("SONY", sony::decode_maker_note),
+];
+
+// Decodes the MakerNote with the decoder registered for the camera make. Returns None for
+// makes without a decoder, and when the MakerNote is missing or can't be parsed.
+pub(crate) fn read_vendor_metadata( exif_fields: &exif::Exif ) -> Option<VendorMetadataOfInterest> {
+    let make = camera_make( exif_fields )?.to_uppercase();
+    let (_, decode) = VENDOR_DECODERS.iter().find(|(prefix, _)| make.starts_with( prefix ))?;
+    let maker_note = parse_maker_note( exif_fields )?;
+    let mut vendor_metadata = decode( &maker_note, exif_fields );
+    // Most bodies also record the lens in the standard EXIF LensModel tag.
+    if vendor_metadata.lens.is_none() {
+        vendor_metadata.lens = exif_ascii( exif_fields, exif::Tag::LensModel );
+    }
+    Some(vendor_metadata)
+}
+
+// Reads the first string of an ASCII field of the primary image, trimmed of padding.
+pub(crate) fn exif_ascii( exif_fields: &exif::Exif, tag: exif::Tag ) -> Option<String> {
+    match &exif_fields.get_field( tag, exif::In::PRIMARY )?.value {
+        exif::Value::Ascii(values) => values.first()
+            .map(|text| String::from_utf8_lossy( text ).trim().to_string())
+            .filter(|text| !text.is_empty()),
+        _ => None,
+    }
+}
+
+// Reads the Make tag, which decides how the MakerNote is interpreted.
+pub(crate) fn camera_make( exif_fields: &exif::Exif ) -> Option<String> {
+    exif_ascii( exif_fields, exif::Tag::Make )
+}
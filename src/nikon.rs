@@ -0,0 +1,155 @@
+use serde::{Serialize, Deserialize};
+
+use crate::makernote::{self, MakerNote, VendorDetails, VendorMetadataOfInterest};
+
+const TAG_SERIAL_NUMBER : u16 = 0x001D;
+const TAG_LENS : u16 = 0x0084;
+const TAG_LENS_DATA : u16 = 0x0098;
+const TAG_INTERNAL_SERIAL_NUMBER : u16 = 0x00A0;
+const TAG_SHUTTER_COUNT : u16 = 0x00A7;
+
+// Encrypted records keep a plain 4-character version, then encrypted data.
+const ENCRYPTED_DATA_START : usize = 4;
+
+// Where LensIDNumber is in each version of the LensData record. Version 0100 is not encrypted.
+const LENS_DATA_LENS_ID : [(&[u8], usize, bool); 5] = [
+    (b"0100", 0x06, false),
+    (b"0201", 0x0B, true),
+    (b"0202", 0x0B, true),
+    (b"0203", 0x0B, true),
+    (b"0204", 0x0C, true),
+];
+
+// Key used in place of the serial number for bodies whose serial isn't a number.
+const NON_NUMERIC_SERIAL_KEY : u32 = 0x60;
+const NON_NUMERIC_SERIAL_KEY_D50 : u32 = 0x22;
+
+// Substitution tables of the Nikon MakerNote cipher, as published with dcraw and ExifTool.
+const XLAT : [[u8; 256]; 2] = [
+    [
+        0xc1,0xbf,0x6d,0x0d,0x59,0xc5,0x13,0x9d,0x83,0x61,0x6b,0x4f,0xc7,0x7f,0x3d,0x3d,
+        0x53,0x59,0xe3,0xc7,0xe9,0x2f,0x95,0xa7,0x95,0x1f,0xdf,0x7f,0x2b,0x29,0xc7,0x0d,
+        0xdf,0x07,0xef,0x71,0x89,0x3d,0x13,0x3d,0x3b,0x13,0xfb,0x0d,0x89,0xc1,0x65,0x1f,
+        0xb3,0x0d,0x6b,0x29,0xe3,0xfb,0xef,0xa3,0x6b,0x47,0x7f,0x95,0x35,0xa7,0x47,0x4f,
+        0xc7,0xf1,0x59,0x95,0x35,0x11,0x29,0x61,0xf1,0x3d,0xb3,0x2b,0x0d,0x43,0x89,0xc1,
+        0x9d,0x9d,0x89,0x65,0xf1,0xe9,0xdf,0xbf,0x3d,0x7f,0x53,0x97,0xe5,0xe9,0x95,0x17,
+        0x1d,0x3d,0x8b,0xfb,0xc7,0xe3,0x67,0xa7,0x07,0xf1,0x71,0xa7,0x53,0xb5,0x29,0x89,
+        0xe5,0x2b,0xa7,0x17,0x29,0xe9,0x4f,0xc5,0x65,0x6d,0x6b,0xef,0x0d,0x89,0x49,0x2f,
+        0xb3,0x43,0x53,0x65,0x1d,0x49,0xa3,0x13,0x89,0x59,0xef,0x6b,0xef,0x65,0x1d,0x0b,
+        0x59,0x13,0xe3,0x4f,0x9d,0xb3,0x29,0x43,0x2b,0x07,0x1d,0x95,0x59,0x59,0x47,0xfb,
+        0xe5,0xe9,0x61,0x47,0x2f,0x35,0x7f,0x17,0x7f,0xef,0x7f,0x95,0x95,0x71,0xd3,0xa3,
+        0x0b,0x71,0xa3,0xad,0x0b,0x3b,0xb5,0xfb,0xa3,0xbf,0x4f,0x83,0x1d,0xad,0xe9,0x2f,
+        0x71,0x65,0xa3,0xe5,0x07,0x35,0x3d,0x0d,0xb5,0xe9,0xe5,0x47,0x3b,0x9d,0xef,0x35,
+        0xa3,0xbf,0xb3,0xdf,0x53,0xd3,0x97,0x53,0x49,0x71,0x07,0x35,0x61,0x71,0x2f,0x43,
+        0x2f,0x11,0xdf,0x17,0x97,0xfb,0x95,0x3b,0x7f,0x6b,0xd3,0x25,0xbf,0xad,0xc7,0xc5,
+        0xc5,0xb5,0x8b,0xef,0x2f,0xd3,0x07,0x6b,0x25,0x49,0x95,0x25,0x49,0x6d,0x71,0xc7,
+    ],
+    [
+        0xa7,0xbc,0xc9,0xad,0x91,0xdf,0x85,0xe5,0xd4,0x78,0xd5,0x17,0x46,0x7c,0x29,0x4c,
+        0x4d,0x03,0xe9,0x25,0x68,0x11,0x86,0xb3,0xbd,0xf7,0x6f,0x61,0x22,0xa2,0x26,0x34,
+        0x2a,0xbe,0x1e,0x46,0x14,0x68,0x9d,0x44,0x18,0xc2,0x40,0xf4,0x7e,0x5f,0x1b,0xad,
+        0x0b,0x94,0xb6,0x67,0xb4,0x0b,0xe1,0xea,0x95,0x9c,0x66,0xdc,0xe7,0x5d,0x6c,0x05,
+        0xda,0xd5,0xdf,0x7a,0xef,0xf6,0xdb,0x1f,0x82,0x4c,0xc0,0x68,0x47,0xa1,0xbd,0xee,
+        0x39,0x50,0x56,0x4a,0xdd,0xdf,0xa5,0xf8,0xc6,0xda,0xca,0x90,0xca,0x01,0x42,0x9d,
+        0x8b,0x0c,0x73,0x43,0x75,0x05,0x94,0xde,0x24,0xb3,0x80,0x34,0xe5,0x2c,0xdc,0x9b,
+        0x3f,0xca,0x33,0x45,0xd0,0xdb,0x5f,0xf5,0x52,0xc3,0x21,0xda,0xe2,0x22,0x72,0x6b,
+        0x3e,0xd0,0x5b,0xa8,0x87,0x8c,0x06,0x5d,0x0f,0xdd,0x09,0x19,0x93,0xd0,0xb9,0xfc,
+        0x8b,0x0f,0x84,0x60,0x33,0x1c,0x9b,0x45,0xf1,0xf0,0xa3,0x94,0x3a,0x12,0x77,0x33,
+        0x4d,0x44,0x78,0x28,0x3c,0x9e,0xfd,0x65,0x57,0x16,0x94,0x6b,0xfb,0x59,0xd0,0xc8,
+        0x22,0x36,0xdb,0xd2,0x63,0x98,0x43,0xa1,0x04,0x87,0x86,0xf7,0xa6,0x26,0xbb,0xd6,
+        0x59,0x4d,0xbf,0x6a,0x2e,0xaa,0x2b,0xef,0xe6,0x78,0xb6,0x4e,0xe0,0x2f,0xdc,0x7c,
+        0xbe,0x57,0x19,0x32,0x7e,0x2a,0xd0,0xb8,0xba,0x29,0x00,0x3c,0x52,0x7d,0xa8,0x49,
+        0x3b,0x2d,0xeb,0x25,0x49,0xfa,0xa3,0xaa,0x39,0xa7,0xc5,0xa7,0x50,0x11,0x36,0xfb,
+        0xc6,0x67,0x4a,0xf5,0xa5,0x12,0x65,0x7e,0xb0,0xdf,0xaf,0x4e,0xb3,0x61,0x7f,0x2f,
+    ],
+];
+
+// Nikon-specific fields, beyond those every vendor reports.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct NikonMakerNote {
+    // The body serial number, which is also part of the key for the encrypted records.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+    // Nikon's LensIDNumber, from the encrypted LensData record.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lens_id: Option<u8>,
+}
+
+// Decrypts a Nikon record. The key is made from the body serial number and the shutter count,
+// so both must be read first.
+fn decrypt( data: &[u8], serial_key: u32, shutter_count: u32 ) -> Vec<u8> {
+    let count_key = shutter_count.to_le_bytes().iter().fold(0u8, |key, byte| key ^ byte);
+    let ci = XLAT[0][(serial_key & 0xFF) as usize];
+    let mut cj = XLAT[1][count_key as usize];
+    let mut ck : u8 = 0x60;
+    data.iter().map(|byte| {
+        cj = cj.wrapping_add( ci.wrapping_mul( ck ) );
+        ck = ck.wrapping_add( 1 );
+        byte ^ cj
+    }).collect()
+}
+
+fn serial_key( serial_number: Option<&str>, model: Option<&str> ) -> u32 {
+    match serial_number.and_then(|serial| serial.parse::<u32>().ok()) {
+        Some(serial) => serial,
+        None if model.is_some_and(|model| model.contains("D50")) => NON_NUMERIC_SERIAL_KEY_D50,
+        None => NON_NUMERIC_SERIAL_KEY,
+    }
+}
+
+fn lens_id( lens_data: &[u8], serial_key: u32, shutter_count: Option<u32> ) -> Option<u8> {
+    let (_, offset, encrypted) = LENS_DATA_LENS_ID.iter().find(|(version, _, _)| lens_data.starts_with( version ))?;
+    if !encrypted {
+        return lens_data.get(*offset).cloned();
+    }
+    let decrypted = decrypt( lens_data.get(ENCRYPTED_DATA_START..)?, serial_key, shutter_count? );
+    decrypted.get(offset - ENCRYPTED_DATA_START).cloned()
+}
+
+fn format_number( value: f64 ) -> String {
+    let rounded = format!("{:.1}", value);
+    rounded.trim_end_matches( ".0" ).to_string()
+}
+
+// Describes the lens from the Lens tag: [min focal length, max focal length,
+// max aperture at min focal length, max aperture at max focal length], as in "18-55mm f/3.5-5.6".
+fn lens_description( lens: &[(u32, u32)] ) -> Option<String> {
+    let values : Vec<f64> = lens.iter()
+        .map(|(numerator, denominator)| if *denominator == 0 { 0.0 } else { *numerator as f64 / *denominator as f64 })
+        .collect();
+    if values.len() < 4 || values[0] <= 0.0 {
+        return None;
+    }
+    let focal = if values[0] == values[1] {
+        format!("{}mm", format_number( values[0] ))
+    } else {
+        format!("{}-{}mm", format_number( values[0] ), format_number( values[1] ))
+    };
+    let aperture = if values[2] == values[3] {
+        format!("f/{}", format_number( values[2] ))
+    } else {
+        format!("f/{}-{}", format_number( values[2] ), format_number( values[3] ))
+    };
+    Some( format!("{} {}", focal, aperture) )
+}
+
+pub(crate) fn decode_maker_note( maker_note: &MakerNote, exif_fields: &exif::Exif ) -> VendorMetadataOfInterest {
+    let model = makernote::exif_ascii( exif_fields, exif::Tag::Model );
+    let serial_number = maker_note.ascii( TAG_SERIAL_NUMBER );
+    let shutter_count = maker_note.uints( TAG_SHUTTER_COUNT ).and_then(|values| values.first().cloned());
+    let key = serial_key( serial_number.as_deref(), model.as_deref() );
+    let lens_id = maker_note.entry( TAG_LENS_DATA )
+        .and_then(|entry| entry.value_bytes( maker_note.data, maker_note.order ))
+        .and_then(|lens_data| lens_id( lens_data, key, shutter_count ));
+
+    VendorMetadataOfInterest {
+        make: "nikon".to_string(),
+        shutter_count,
+        lens: maker_note.rationals( TAG_LENS ).and_then(|lens| lens_description( &lens )),
+        internal_serial: maker_note.ascii( TAG_INTERNAL_SERIAL_NUMBER ),
+        // Nikon bodies write their firmware version ("Ver.1.10") to the standard Software tag.
+        firmware_version: makernote::exif_ascii( exif_fields, exif::Tag::Software ),
+        details: Some( VendorDetails::Nikon( NikonMakerNote { serial_number, lens_id } ) ),
+    }
+}
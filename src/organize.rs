@@ -0,0 +1,190 @@
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::hashing::{sha256_file, to_hex};
+use crate::rename::{MissingFieldError, NameTemplate};
+use crate::sidecar::sidecar_base;
+use crate::writer::{copy_file_checked, WriteOptions};
+use crate::{json_sidecar_path, read_metadata_of_interest, write_json_metadata_with_redaction, MetadataOfInterest, Redaction};
+
+pub const DEFAULT_ORGANIZE_LAYOUT : &str = "{year}/{year}-{month}-{day}/{camera_model}";
+
+// Written in the layout in place of a field a file has no value for.
+const UNKNOWN_FIELD : &str = "unknown";
+
+// How files are put into the destination: the layout of folders they go in, and whether the
+// originals are removed once their copies are verified.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Organize {
+    pub layout: NameTemplate,
+    pub move_files: bool,
+}
+
+impl Default for Organize {
+    fn default() -> Self {
+        Organize {
+            layout: NameTemplate::layout( DEFAULT_ORGANIZE_LAYOUT ).expect("the default layout is valid"),
+            move_files: false,
+        }
+    }
+}
+
+// What happened to one file. A file whose content is already at the destination isn't copied
+// again; when moving, the original is removed all the same.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum OrganizeOutcome {
+    Copied(PathBuf),
+    Moved(PathBuf),
+    AlreadyPresent(PathBuf),
+}
+
+impl OrganizeOutcome {
+    pub fn destination( &self ) -> &Path {
+        match self {
+            OrganizeOutcome::Copied(path) | OrganizeOutcome::Moved(path) | OrganizeOutcome::AlreadyPresent(path) => path,
+        }
+    }
+}
+
+// Raised when a copy doesn't read back with the checksum of its original.
+#[derive(Debug, Clone)]
+pub struct CopyVerificationError {
+    path: PathBuf,
+    expected: String,
+    actual: String,
+}
+
+impl fmt::Display for CopyVerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The copy at {} has SHA-256 {}, but the original has {}", self.path.to_string_lossy(), self.actual, self.expected)
+    }
+}
+
+impl Error for CopyVerificationError {
+    fn description(&self) -> &str {
+        "A copy doesn't match its original"
+    }
+}
+
+// The files to organize: files as they are, and the files in directories, recursively, in name
+// order. Hidden files, JSON sidecars, which go with their images, and anything already inside
+// the destination are left out.
+pub fn files_to_organize( paths: &[PathBuf], destination: &Path ) -> Vec<PathBuf> {
+    let destination = fs::canonicalize( destination ).unwrap_or_else(|_| destination.to_path_buf());
+    let mut files = vec![];
+    for path in paths.iter() {
+        collect_files( path, Some(&destination), &mut files );
+    }
+    files.retain(|file| !file.extension().is_some_and(|extension| extension.eq_ignore_ascii_case( "json" )));
+    files
+}
+
+// Adds a file, or the files in a directory recursively, leaving out hidden files and the
+// excluded directory, which is given canonicalized.
+pub(crate) fn collect_files( path: &Path, excluded: Option<&Path>, files: &mut Vec<PathBuf> ) {
+    if !path.is_dir() {
+        files.push( path.to_path_buf() );
+        return;
+    }
+    let is_excluded = |excluded: &Path| fs::canonicalize( path ).map(|canonical| canonical.starts_with( excluded )).unwrap_or(false);
+    if excluded.is_some_and(is_excluded) {
+        return;
+    }
+    let mut entries : Vec<PathBuf> = match fs::read_dir( path ) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for entry in entries {
+        let hidden = entry.file_name().map(|name| name.to_string_lossy().starts_with( '.' )).unwrap_or(true);
+        if !hidden {
+            collect_files( &entry, excluded, files );
+        }
+    }
+}
+
+// The folder under the destination a file goes in. Without a capture time, the time the file was
+// last modified, or else created, stands in for it, in the local time zone.
+fn layout_folder( metadata: &MetadataOfInterest, path: &Path, layout: &NameTemplate ) -> Result<String, Box<dyn error::Error>> {
+    let mut layout_metadata = metadata.clone();
+    if layout_metadata.image_metadata.capture_time.is_none() {
+        let file_metadata = &metadata.file_metadata;
+        layout_metadata.image_metadata.capture_time = file_metadata.modified_time.or( file_metadata.created_time )
+            .map(|file_time| file_time.with_timezone( &chrono::Local ).naive_local());
+    }
+    layout.render( &layout_metadata, path, 1, 1, Some(UNKNOWN_FIELD) )
+        .map_err(|field| Box::new( MissingFieldError { field } ) as Box<dyn error::Error>)
+}
+
+// The first free path in the folder for the file's name, adding _2, _3... to the stem of names
+// taken by other content. A path already holding the same content is returned as such.
+fn destination_for( folder: &Path, file_name: &Path, digest: &[u8; 32] ) -> Result<(PathBuf, bool), Box<dyn error::Error>> {
+    let stem = file_name.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = file_name.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+    for index in 1.. {
+        let candidate = match index {
+            1 => folder.join( file_name ),
+            _ => folder.join( format!("{}_{}{}", stem, index, extension) ),
+        };
+        if !candidate.exists() {
+            return Ok( (candidate, false) );
+        }
+        if candidate.is_file() && sha256_file( &candidate )? == *digest {
+            return Ok( (candidate, true) );
+        }
+    }
+    unreachable!()
+}
+
+// Copies or moves a file into its folder of the layout under the destination, with its JSON
+// sidecar, and the redaction applied to it. Each copy is read back and checked against the
+// original's SHA-256, and keeps its modification time, before the original is removed. With
+// dry_run set, the outcome is returned but nothing is written or removed.
+pub fn organize_file( path: &Path, destination: &Path, organize: &Organize, options: &WriteOptions, redaction: &Redaction ) -> Result<OrganizeOutcome, Box<dyn error::Error>> {
+    let metadata = read_metadata_of_interest( path )?;
+    let folder = destination.join( layout_folder( &metadata, path, &organize.layout )? );
+    let file_name = path.file_name().ok_or_else(|| crate::writer::write_error( "the path has no file name" ))?;
+    let digest = sha256_file( path )?;
+    let (target, already_present) = destination_for( &folder, Path::new( file_name ), &digest )?;
+    let (mut sidecar, source_sidecar_path) = sidecar_base( path, &metadata )?;
+    let outcome = match (already_present, organize.move_files) {
+        (true, _) => OrganizeOutcome::AlreadyPresent(target.clone()),
+        (false, true) => OrganizeOutcome::Moved(target.clone()),
+        (false, false) => OrganizeOutcome::Copied(target.clone()),
+    };
+    if options.dry_run {
+        return Ok(outcome);
+    }
+
+    if !already_present {
+        fs::create_dir_all( &folder )?;
+        let modified_time = fs::metadata( path )?.modified()?;
+        copy_file_checked( path, &target, &|copy_path| {
+            let copied = sha256_file( copy_path )?;
+            if copied != digest {
+                return Err( Box::new( CopyVerificationError { path: target.clone(), expected: to_hex( &digest ), actual: to_hex( &copied ) } ) );
+            }
+            fs::File::options().write( true ).open( copy_path )?.set_modified( modified_time )?;
+            Ok(())
+        } )?;
+    }
+    // The file's own sidecar goes with it, keeping the edits made in it. A sidecar already at the
+    // destination is only replaced by it: one written for a file whose content was already
+    // there, or shared with the other file of a RAW+JPEG pair, is otherwise left as it is.
+    let target_sidecar_path = json_sidecar_path( &target );
+    let carried = source_sidecar_path.is_some() && !already_present;
+    if let Some(json_path) = target_sidecar_path.as_ref().filter(|json_path| carried || !json_path.exists()) {
+        sidecar.file_metadata = read_metadata_of_interest( &target )?.file_metadata;
+        write_json_metadata_with_redaction( &sidecar, json_path, redaction )?;
+    }
+    if organize.move_files {
+        fs::remove_file( path )?;
+        if let Some(source_sidecar_path) = source_sidecar_path.filter(|source_sidecar_path| carried && target_sidecar_path.as_ref() != Some(source_sidecar_path)) {
+            fs::remove_file( source_sidecar_path )?;
+        }
+    }
+    Ok(outcome)
+}
@@ -0,0 +1,217 @@
+use std::error;
+use std::fs;
+use std::path::Path;
+
+use crate::dct::{self, coding_error, Block, CoefficientImage, ZIGZAG};
+use crate::format::{self, MediaFormat, UnsupportedFormatError};
+use crate::jpeg;
+use crate::writer::{apply_writes, current_value, edit_jpeg_exif, field_writes, long_field, replace_file, FieldChange, IfdLocation, ImageMetadataPatch, TiffEditor, WriteOptions};
+use crate::{read_metadata_of_interest, Orientation};
+
+const TAG_IMAGE_WIDTH : u16 = 0x0100;
+const TAG_IMAGE_LENGTH : u16 = 0x0101;
+const TAG_THUMBNAIL_OFFSET : u16 = 0x0201;
+const TAG_THUMBNAIL_LENGTH : u16 = 0x0202;
+const TAG_PIXEL_X_DIMENSION : u16 = 0xA002;
+const TAG_PIXEL_Y_DIMENSION : u16 = 0xA003;
+
+const TYPE_LONG : u16 = 4;
+
+// Maps a position in the upright image to the position in the stored image which shows there,
+// given the size of the stored image in positions. The inverse of the display map, in unit
+// coordinates, is scaled to positions 0 to size - 1.
+fn stored_position( orientation: Orientation, x: usize, y: usize, columns: usize, rows: usize ) -> (usize, usize) {
+    let [[a, b, c], [d, e, f]] = orientation.inverse().to_matrix();
+    let (x, y) = (x as i64, y as i64);
+    let stored_x = a as i64 * x + b as i64 * y + c as i64 * (columns as i64 - 1);
+    let stored_y = d as i64 * x + e as i64 * y + f as i64 * (rows as i64 - 1);
+    (stored_x as usize, stored_y as usize)
+}
+
+// Whether the columns and the rows of the stored image come out in reverse order.
+fn reverses( orientation: Orientation ) -> (bool, bool) {
+    let [[_, _, c], [_, _, f]] = orientation.inverse().to_matrix();
+    (c == 1, f == 1)
+}
+
+// Rearranges the coefficients of a block the way its pixels are rearranged. Mirroring a block
+// negates the coefficients of odd frequencies along the mirrored direction, and transposing it
+// swaps the two frequencies.
+fn transform_block( orientation: Orientation, block: &Block ) -> Block {
+    let (reverses_columns, reverses_rows) = reverses( orientation );
+    let mut output = [0; 64];
+    for (position, coefficient) in output.iter_mut().enumerate() {
+        let (u, v) = (position % 8, position / 8);
+        let (stored_u, stored_v) = if orientation.swaps_dimensions() { (v, u) } else { (u, v) };
+        let negate = (reverses_columns && stored_u % 2 == 1) != (reverses_rows && stored_v % 2 == 1);
+        let value = block[stored_v * 8 + stored_u];
+        *coefficient = if negate { -value } else { value };
+    }
+    output
+}
+
+// Transforms the blocks of an image. Partial MCUs at an edge which would end up at the left or
+// top can't be moved there losslessly, so they are trimmed off, as `jpegtran -trim` does.
+fn transform_image( image: &CoefficientImage, orientation: Orientation ) -> Result<CoefficientImage, Box<dyn error::Error>> {
+    let (mcu_width, mcu_height) = image.mcu_size();
+    let (reverses_columns, reverses_rows) = reverses( orientation );
+    let kept_width = if reverses_columns { image.width / mcu_width * mcu_width } else { image.width };
+    let kept_height = if reverses_rows { image.height / mcu_height * mcu_height } else { image.height };
+    if kept_width == 0 || kept_height == 0 {
+        return Err( coding_error( "image is too small to transform without trimming all of it" ) );
+    }
+
+    let (width, height) = if orientation.swaps_dimensions() { (kept_height, kept_width) } else { (kept_width, kept_height) };
+    let components = image.components.iter()
+        .map(|component| {
+            let mut component = component.clone();
+            if orientation.swaps_dimensions() {
+                std::mem::swap( &mut component.horizontal_sampling, &mut component.vertical_sampling );
+            }
+            component.blocks = vec![];
+            component
+        })
+        .collect();
+    let mut output = CoefficientImage::new( width, height, components, image.scan_order.clone() );
+
+    for (source, component) in image.components.iter().zip( output.components.iter_mut() ) {
+        // The source blocks which are kept; trimmed edges are a whole number of MCUs.
+        let columns = if reverses_columns { kept_width / mcu_width * source.horizontal_sampling } else { source.blocks_wide };
+        let rows = if reverses_rows { kept_height / mcu_height * source.vertical_sampling } else { source.blocks_high };
+        for y in 0..component.blocks_high {
+            for x in 0..component.blocks_wide {
+                let (source_x, source_y) = stored_position( orientation, x, y, columns, rows );
+                if source_x < source.blocks_wide && source_y < source.blocks_high {
+                    let block = &source.blocks[source_y * source.blocks_wide + source_x];
+                    component.blocks[y * component.blocks_wide + x] = transform_block( orientation, block );
+                }
+            }
+        }
+    }
+    Ok(output)
+}
+
+// Swaps the frequencies of the quantization tables in a DQT segment, to match transposed blocks.
+fn transpose_quantization_tables( payload: &[u8] ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let mut output = payload.to_vec();
+    let mut position = 0;
+    while position < payload.len() {
+        let size = if payload[position] >> 4 == 0 { 1 } else { 2 };
+        let values = payload.get( position + 1..position + 1 + 64 * size ).ok_or_else(|| coding_error( "quantization table is truncated" ))?;
+        for (index, natural) in ZIGZAG.iter().enumerate() {
+            let transposed = natural % 8 * 8 + natural / 8;
+            let source = ZIGZAG.iter().position(|other| *other == transposed).unwrap_or(index);
+            let start = position + 1 + index * size;
+            output[start..start + size].copy_from_slice( &values[source * size..(source + 1) * size] );
+        }
+        position += 1 + 64 * size;
+    }
+    Ok(output)
+}
+
+// A transformed JPEG file and its new width and height.
+type TransformedJpeg = (Vec<u8>, (usize, usize));
+
+// Transforms the image data of a JPEG without decoding it to pixels, returning the new file and
+// its dimensions. Everything else in the file is kept, but not yet updated for the transform.
+fn transform_jpeg( data: &[u8], orientation: Orientation ) -> Result<TransformedJpeg, Box<dyn error::Error>> {
+    let decoded = dct::decode( data )?;
+    let image = transform_image( &decoded.image, orientation )?;
+
+    let mut output = vec![0xFF, jpeg::MARKER_SOI];
+    for segment in decoded.segments.iter() {
+        match segment.marker {
+            // Restart markers aren't written, and the Huffman tables are made for the new scan.
+            dct::MARKER_DHT | dct::MARKER_DRI => {},
+            dct::MARKER_DQT if orientation.swaps_dimensions() => {
+                let payload = transpose_quantization_tables( &data[segment.payload.clone()] )?;
+                output.extend_from_slice( &data[segment.position..segment.payload.start] );
+                output.extend( payload );
+            },
+            marker if jpeg::is_start_of_frame( marker ) => output.extend( dct::frame_segment( &image, marker ) ),
+            _ => output.extend_from_slice( &data[segment.position..segment.payload.end] ),
+        }
+    }
+    output.extend( dct::encode_scan( &image ) );
+    output.extend_from_slice( &[0xFF, jpeg::MARKER_EOI] );
+    output.extend_from_slice( &data[decoded.trailer_start..] );
+    Ok( (output, (image.width, image.height)) )
+}
+
+// Transforms the JPEG thumbnail in IFD1 the same way as the image, if there is one.
+fn transform_thumbnail( editor: &mut TiffEditor, orientation: Orientation ) -> Result<Option<FieldChange>, Box<dyn error::Error>> {
+    let exif_fields = exif::Reader::new().read_raw( editor.data.clone() ).ok();
+    let thumbnail_field = |tag| current_value( exif_fields.as_ref(), IfdLocation::Thumbnail, tag ).and_then(|value| value.parse::<usize>().ok());
+    let (offset, length) = match (thumbnail_field( TAG_THUMBNAIL_OFFSET ), thumbnail_field( TAG_THUMBNAIL_LENGTH )) {
+        (Some(offset), Some(length)) => (offset, length),
+        _ => return Ok(None),
+    };
+    let thumbnail = match editor.data.get( offset..offset + length ) {
+        Some(thumbnail) => thumbnail.to_vec(),
+        None => return Err( coding_error( "thumbnail is outside the EXIF data" ) ),
+    };
+    let old_size = jpeg::jpeg_dimensions( &thumbnail ).map(|(width, height)| format!("{}x{}", width, height));
+    let (transformed, (width, height)) = transform_jpeg( &thumbnail, orientation )?;
+
+    // Clear the old thumbnail, so that no copy of it is left in the wrong orientation.
+    editor.data[offset..offset + length].iter_mut().for_each(|byte| *byte = 0);
+    let new_offset = editor.append( &transformed );
+    let order = editor.order;
+    editor.set_field( IfdLocation::Thumbnail, TAG_THUMBNAIL_OFFSET, TYPE_LONG, 1, &order.u32_bytes( new_offset as u32 ) )?;
+    editor.set_field( IfdLocation::Thumbnail, TAG_THUMBNAIL_LENGTH, TYPE_LONG, 1, &order.u32_bytes( transformed.len() as u32 ) )?;
+    Ok( Some( FieldChange { field: "thumbnail", old_value: old_size, new_value: format!("{}x{}", width, height) } ) )
+}
+
+// Resets the Orientation tag, updates the dimension tags which the EXIF data has, and
+// transforms the thumbnail.
+fn update_exif( editor: &mut TiffEditor, orientation: Orientation, width: usize, height: usize ) -> Result<Vec<FieldChange>, Box<dyn error::Error>> {
+    let patch = ImageMetadataPatch { orientation: Some(Orientation::Normal), ..Default::default() };
+    let dimension_tags = [
+        ("image_width", IfdLocation::Primary, TAG_IMAGE_WIDTH, width),
+        ("image_height", IfdLocation::Primary, TAG_IMAGE_LENGTH, height),
+        ("pixel_x_dimension", IfdLocation::Exif, TAG_PIXEL_X_DIMENSION, width),
+        ("pixel_y_dimension", IfdLocation::Exif, TAG_PIXEL_Y_DIMENSION, height),
+    ];
+    let mut changes = apply_writes( editor, &|exif_fields, order| {
+        let mut writes = field_writes( &patch, order );
+        for (field, location, tag, value) in dimension_tags.iter() {
+            if current_value( exif_fields, *location, *tag ).is_some() {
+                writes.push( long_field( field, *location, *tag, *value as u32, order ) );
+            }
+        }
+        writes
+    } )?;
+    changes.extend( transform_thumbnail( editor, orientation )? );
+    Ok(changes)
+}
+
+// Losslessly rotates and mirrors the image data of a JPEG to match its Orientation tag, then
+// resets the tag to Normal, so that viewers which ignore the tag show the image upright.
+// The DCT blocks are rearranged rather than decoded, so no quality is lost. Partial MCUs at
+// edges which would move to the left or top are trimmed off, at most 15 pixels of each.
+// Returns what changed, which is nothing if the image is already upright.
+pub fn normalize_orientation( path: &Path, options: &WriteOptions ) -> Result<Vec<FieldChange>, Box<dyn error::Error>> {
+    let format = format::detect_format( path )?;
+    if format != MediaFormat::Jpeg {
+        return Err(Box::new(UnsupportedFormatError{ format }));
+    }
+    let orientation = read_metadata_of_interest( path )?.image_metadata.orientation.unwrap_or( Orientation::Normal );
+    if orientation == Orientation::Normal {
+        return Ok(vec![]);
+    }
+
+    let data = fs::read( path )?;
+    if jpeg::find_mpf_segment( &data ).is_some() {
+        return Err( coding_error( "multi-picture files can't be transformed, as their image offsets would change" ) );
+    }
+    let old_size = jpeg::jpeg_dimensions( &data ).map(|(width, height)| format!("{}x{}", width, height));
+    let (transformed, (width, height)) = transform_jpeg( &data, orientation )?;
+    let (exif_changes, output) = edit_jpeg_exif( &transformed, &mut |editor| update_exif( editor, orientation, width, height ) )?;
+
+    let mut changes = vec![FieldChange { field: "dimensions", old_value: old_size, new_value: format!("{}x{}", width, height) }];
+    changes.extend( exif_changes );
+    if !options.dry_run {
+        replace_file( path, &output )?;
+    }
+    Ok(changes)
+}
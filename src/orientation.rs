@@ -0,0 +1,147 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Orientation;
+
+// An affine map of points of the image, in coordinates where the image spans 0 to 1 on both axes
+// with y pointing down: each row is [a, b, c] for a * x + b * y + c.
+pub type OrientationMatrix = [[i32; 3]; 2];
+
+const NAMES : [(Orientation, &str); 8] = [
+    (Orientation::Normal, "normal"),
+    (Orientation::Mirrored, "mirrored"),
+    (Orientation::UpsideDown, "upside-down"),
+    (Orientation::UpsideDownMirrored, "upside-down-mirrored"),
+    (Orientation::QuarterRotationCCWMirrored, "quarter-rotation-ccw-mirrored"),
+    (Orientation::QuarterRotationCCW, "quarter-rotation-ccw"),
+    (Orientation::QuarterRotationCWMirrored, "quarter-rotation-cw-mirrored"),
+    (Orientation::QuarterRotationCW, "quarter-rotation-cw"),
+];
+
+impl Orientation {
+    pub const ALL : [Orientation; 8] = [
+        Orientation::Normal,
+        Orientation::Mirrored,
+        Orientation::UpsideDown,
+        Orientation::UpsideDownMirrored,
+        Orientation::QuarterRotationCCWMirrored,
+        Orientation::QuarterRotationCCW,
+        Orientation::QuarterRotationCWMirrored,
+        Orientation::QuarterRotationCW,
+    ];
+
+    // The map from the image as stored to the image as it should be displayed.
+    pub fn to_matrix( self ) -> OrientationMatrix {
+        match self {
+            Orientation::Normal => [[1, 0, 0], [0, 1, 0]],
+            Orientation::Mirrored => [[-1, 0, 1], [0, 1, 0]],
+            Orientation::UpsideDown => [[-1, 0, 1], [0, -1, 1]],
+            Orientation::UpsideDownMirrored => [[1, 0, 0], [0, -1, 1]],
+            Orientation::QuarterRotationCCWMirrored => [[0, 1, 0], [1, 0, 0]],
+            Orientation::QuarterRotationCCW => [[0, -1, 1], [1, 0, 0]],
+            Orientation::QuarterRotationCWMirrored => [[0, -1, 1], [-1, 0, 1]],
+            Orientation::QuarterRotationCW => [[0, 1, 0], [-1, 0, 1]],
+        }
+    }
+
+    // The orientation with the given display map, if it is one of the eight.
+    pub fn from_matrix( matrix: OrientationMatrix ) -> Option<Orientation> {
+        Orientation::ALL.iter().copied().find(|orientation| orientation.to_matrix() == matrix)
+    }
+
+    // How to display the image as stored: mirror it left to right if the flag is set, then
+    // rotate it clockwise by the number of degrees, which is 0, 90, 180 or 270.
+    pub fn to_rotation( self ) -> (u16, bool) {
+        match self {
+            Orientation::Normal => (0, false),
+            Orientation::Mirrored => (0, true),
+            Orientation::UpsideDown => (180, false),
+            Orientation::UpsideDownMirrored => (180, true),
+            Orientation::QuarterRotationCCWMirrored => (270, true),
+            Orientation::QuarterRotationCCW => (90, false),
+            Orientation::QuarterRotationCWMirrored => (90, true),
+            Orientation::QuarterRotationCW => (270, false),
+        }
+    }
+
+    // The orientation displayed by a clockwise rotation, after mirroring if the flag is set.
+    // Negative rotations are anticlockwise; rotations which aren't quarter turns give None.
+    pub fn from_rotation( degrees: i32, mirrored: bool ) -> Option<Orientation> {
+        if degrees % 90 != 0 {
+            return None;
+        }
+        let degrees = degrees.rem_euclid( 360 ) as u16;
+        Orientation::ALL.iter().copied().find(|orientation| orientation.to_rotation() == (degrees, mirrored))
+    }
+
+    // The orientation of the image once `next` is applied on top of this orientation, such as
+    // rotating an already rotated image by another quarter turn.
+    pub fn then( self, next: Orientation ) -> Orientation {
+        let (first, second) = (self.to_matrix(), next.to_matrix());
+        let mut product = [[0; 3]; 2];
+        for (row, product_row) in product.iter_mut().enumerate() {
+            for (column, value) in product_row.iter_mut().enumerate() {
+                *value = second[row][0] * first[0][column] + second[row][1] * first[1][column];
+            }
+            product_row[2] += second[row][2];
+        }
+        // The product of two of the eight maps is always another of them.
+        Orientation::from_matrix( product ).unwrap_or(Orientation::Normal)
+    }
+
+    // The orientation which undoes this one.
+    pub fn inverse( self ) -> Orientation {
+        Orientation::ALL.iter().copied()
+            .find(|candidate| self.then( *candidate ) == Orientation::Normal)
+            .unwrap_or(Orientation::Normal)
+    }
+
+    // Whether the displayed width is the stored height, and the other way round.
+    pub fn swaps_dimensions( self ) -> bool {
+        self.to_matrix()[0][0] == 0
+    }
+}
+
+impl From<Orientation> for u16 {
+    fn from( orientation: Orientation ) -> u16 {
+        orientation as u16
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = NAMES.iter().find(|(orientation, _)| orientation == self).map_or( "", |(_, name)| name );
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseOrientationError {
+    text: String,
+}
+
+impl fmt::Display for ParseOrientationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Not an orientation: {:?}; expected 1-8 or a name such as quarter-rotation-cw", self.text)
+    }
+}
+
+impl Error for ParseOrientationError {
+    fn description(&self) -> &str {
+        "The text names no orientation"
+    }
+}
+
+// Parses the names Display gives, in any case, or the tag values 1 to 8.
+impl FromStr for Orientation {
+    type Err = ParseOrientationError;
+
+    fn from_str( text: &str ) -> Result<Self, Self::Err> {
+        let trimmed = text.trim();
+        let by_value = trimmed.parse::<u16>().ok().and_then(|value| Orientation::try_from( value ).ok());
+        let by_name = NAMES.iter().find(|(_, name)| name.eq_ignore_ascii_case( trimmed )).map(|(orientation, _)| *orientation);
+        by_value.or(by_name).ok_or_else(|| ParseOrientationError { text: text.to_string() })
+    }
+}
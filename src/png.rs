@@ -0,0 +1,64 @@
+use std::convert::TryInto;
+use std::ops::Range;
+
+pub(crate) const SIGNATURE : &[u8] = b"\x89PNG\r\n\x1A\n";
+
+// A chunk of a PNG file. `position` is the offset of its length field, and `payload` is the
+// range of its data; the 4-byte CRC follows the data.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub(crate) struct PngChunk {
+    pub kind: [u8; 4],
+    pub position: usize,
+    pub payload: Range<usize>,
+}
+
+impl PngChunk {
+    pub fn end( &self ) -> usize {
+        self.payload.end + 4
+    }
+}
+
+// Lists the chunks after the signature, up to and including IEND.
+pub(crate) fn chunks( data: &[u8] ) -> Option<Vec<PngChunk>> {
+    if !data.starts_with( SIGNATURE ) {
+        return None;
+    }
+    let mut chunks = vec![];
+    let mut position = SIGNATURE.len();
+    while position < data.len() {
+        let length = u32::from_be_bytes( data.get(position..position + 4)?.try_into().ok()? ) as usize;
+        let kind : [u8; 4] = data.get(position + 4..position + 8)?.try_into().ok()?;
+        let payload = position + 8..(position + 8).checked_add( length )?;
+        if payload.end + 4 > data.len() {
+            return None;
+        }
+        let chunk = PngChunk { kind, position, payload };
+        position = chunk.end();
+        chunks.push( chunk );
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    Some(chunks)
+}
+
+// The CRC-32 (ISO 3309) which PNG computes over the chunk type and data.
+pub(crate) fn crc32( bytes: &[u8] ) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub(crate) fn build_chunk( kind: &[u8; 4], payload: &[u8] ) -> Vec<u8> {
+    let mut chunk = (payload.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice( kind );
+    chunk.extend_from_slice( payload );
+    let crc = crc32( &chunk[4..] );
+    chunk.extend_from_slice( &crc.to_be_bytes() );
+    chunk
+}
@@ -0,0 +1,293 @@
+use std::error;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use crate::format::{self, MediaFormat, UnsupportedFormatError};
+use crate::frames::{self, FrameKind};
+use crate::jpeg;
+use crate::makernote;
+
+// MakerNote tags which locate a preview image, per vendor.
+const CANON_PREVIEW_IMAGE_INFO : u16 = 0x00B6;
+const NIKON_PREVIEW_IFD : u16 = 0x0011;
+const NIKON_PREVIEW_IMAGE_START : u16 = 0x0201;
+const NIKON_PREVIEW_IMAGE_LENGTH : u16 = 0x0202;
+const SONY_PREVIEW_IMAGE : u16 = 0x2001;
+
+// Number of leading bytes of the EXIF block used to find it in containers other than JPEG and TIFF.
+const EXIF_SEARCH_LENGTH : usize = 64;
+
+// Where the IFD1 thumbnail of a file is, as reported in MetadataOfInterest.
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+pub struct ThumbnailInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    pub byte_offset: u64,
+    pub byte_length: u64,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewSource {
+    // The small JPEG in IFD1 of the EXIF data.
+    ExifThumbnail,
+    // A JPEG-compressed IFD or SubIFD of a TIFF-based RAW file.
+    ImageFileDirectory,
+    // A preview located through the camera vendor's MakerNote.
+    MakerNote,
+    // A large thumbnail stored as an additional image of an MPO file.
+    MpoImage,
+}
+
+// A JPEG preview embedded in a file, which can be extracted without decoding the main image.
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+pub struct EmbeddedPreview {
+    pub source: PreviewSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    pub byte_offset: u64,
+    pub byte_length: u64,
+}
+
+fn exif_search( data: &[u8], exif_fields: &exif::Exif ) -> Option<usize> {
+    let needle = &exif_fields.buf()[..exif_fields.buf().len().min( EXIF_SEARCH_LENGTH )];
+    if needle.is_empty() {
+        return None;
+    }
+    data.windows( needle.len() ).position(|window| window == needle)
+}
+
+// Position in the file of the TIFF header of the EXIF block, which offsets inside EXIF are relative to.
+pub(crate) fn exif_block_position( data: &[u8], format: MediaFormat, exif_fields: &exif::Exif ) -> Option<usize> {
+    if data.starts_with( b"II*\0" ) || data.starts_with( b"MM\0*" ) {
+        Some(0)
+    } else if format == MediaFormat::Jpeg {
+        jpeg::header_segments( data )?.into_iter()
+            .find(|segment| segment.marker == jpeg::MARKER_APP1 && data[segment.payload.clone()].starts_with( jpeg::EXIF_IDENTIFIER ))
+            .map(|segment| segment.payload.start + jpeg::EXIF_IDENTIFIER.len())
+    } else {
+        exif_search( data, exif_fields )
+    }
+}
+
+// As exif_block_position, but reading as little of the file as possible: only the segment
+// headers of a JPEG, rather than the whole file.
+fn locate_exif_block( path: &Path, format: MediaFormat, exif_fields: &exif::Exif ) -> Result<Option<u64>, Box<dyn error::Error>> {
+    if format == MediaFormat::Jpeg {
+        let mut reader = BufReader::new( File::open( path )? );
+        return Ok( jpeg::find_exif_in_stream( &mut reader )? );
+    }
+    let mut header = [0u8; 4];
+    File::open( path )?.read_exact( &mut header )?;
+    if &header == b"II*\0" || &header == b"MM\0*" {
+        return Ok(Some(0));
+    }
+    let data = fs::read( path )?;
+    Ok( exif_search( &data, exif_fields ).map(|position| position as u64) )
+}
+
+// Finds the IFD1 thumbnail from already-read EXIF data.
+pub(crate) fn read_thumbnail_info( path: &Path, format: MediaFormat, exif_fields: &exif::Exif ) -> Result<Option<ThumbnailInfo>, Box<dyn error::Error>> {
+    let offset = exif_fields.get_field( exif::Tag::JPEGInterchangeFormat, exif::In::THUMBNAIL ).and_then(|field| field.value.get_uint( 0 ));
+    let length = exif_fields.get_field( exif::Tag::JPEGInterchangeFormatLength, exif::In::THUMBNAIL ).and_then(|field| field.value.get_uint( 0 ));
+    let (offset, length) = match (offset, length) {
+        (Some(offset), Some(length)) if length > 0 => (offset as usize, length as usize),
+        _ => return Ok(None),
+    };
+    let thumbnail = match exif_fields.buf().get(offset..offset.saturating_add(length)) {
+        Some(thumbnail) => thumbnail,
+        None => {
+            eprintln!("EXIF thumbnail extends past the end of the EXIF data, for file: {}",path.to_string_lossy());
+            return Ok(None);
+        }
+    };
+    let position = match locate_exif_block( path, format, exif_fields )? {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let dimensions = jpeg::jpeg_dimensions( thumbnail );
+
+    Ok( Some( ThumbnailInfo {
+        width: dimensions.map(|(width, _)| width),
+        height: dimensions.map(|(_, height)| height),
+        byte_offset: position + offset as u64,
+        byte_length: length as u64,
+    } ) )
+}
+
+// A possible preview before it has been checked to be a viewable JPEG. Offsets are in the file.
+struct PreviewCandidate {
+    source: PreviewSource,
+    offset: usize,
+    length: usize,
+    dimensions: Option<(u32, u32)>,
+}
+
+fn maker_note_previews( exif_fields: &exif::Exif, exif_position: usize ) -> Vec<PreviewCandidate> {
+    let maker_note = match makernote::parse_maker_note( exif_fields ) {
+        Some(maker_note) => maker_note,
+        None => return vec![],
+    };
+    let make = makernote::camera_make( exif_fields ).unwrap_or_default().to_uppercase();
+    let mut found = vec![];
+
+    if make.starts_with("CANON") {
+        // PreviewImageInfo: [record length, quality, length, width, height, start]
+        if let Some(info) = maker_note.uints( CANON_PREVIEW_IMAGE_INFO ) {
+            if info.len() >= 6 && info[2] > 0 {
+                found.push( PreviewCandidate {
+                    source: PreviewSource::MakerNote,
+                    offset: exif_position + info[5] as usize,
+                    length: info[2] as usize,
+                    dimensions: Some((info[3], info[4])),
+                } );
+            }
+        }
+    } else if make.starts_with("NIKON") {
+        // The PreviewIFD is a small IFD of its own, with offsets relative to the MakerNote's TIFF header.
+        let preview_ifd = maker_note.uints( NIKON_PREVIEW_IFD ).and_then(|offsets| offsets.first().cloned());
+        if let Some((entries, _)) = preview_ifd.and_then(|offset| crate::tiff::read_ifd( maker_note.data, offset as usize, maker_note.order )) {
+            let value = |tag: u16| entries.iter()
+                .find(|entry| entry.tag == tag)
+                .and_then(|entry| maker_note.order.u32( maker_note.data, entry.entry_offset + 8 ));
+            if let (Some(start), Some(length)) = (value( NIKON_PREVIEW_IMAGE_START ), value( NIKON_PREVIEW_IMAGE_LENGTH )) {
+                found.push( PreviewCandidate {
+                    source: PreviewSource::MakerNote,
+                    offset: exif_position + maker_note.to_exif_offset( start as usize ),
+                    length: length as usize,
+                    dimensions: None,
+                } );
+            }
+        }
+    } else if make.starts_with("SONY") {
+        if let Some(entry) = maker_note.entry( SONY_PREVIEW_IMAGE ) {
+            if let Some(offset) = entry.value_offset( maker_note.data, maker_note.order ) {
+                found.push( PreviewCandidate {
+                    source: PreviewSource::MakerNote,
+                    offset: exif_position + maker_note.to_exif_offset( offset ),
+                    length: entry.byte_length(),
+                    dimensions: None,
+                } );
+            }
+        }
+    }
+    found
+}
+
+// Lists the JPEG thumbnails and previews embedded in a file, smallest sources first:
+// the EXIF thumbnail, JPEG IFDs and SubIFDs of RAW files, MakerNote previews and MPO large thumbnails.
+// Only previews which are complete, viewable JPEGs are listed.
+pub fn list_embedded_previews( path: &Path ) -> Result<Vec<EmbeddedPreview>, Box<dyn error::Error>> {
+    let format = format::detect_format( path )?;
+    if !format.is_supported() {
+        return Err(Box::new(UnsupportedFormatError{ format }));
+    }
+    if format.is_video() {
+        return Ok(vec![]);
+    }
+    let data = fs::read( path )?;
+    let exif_fields = frames::read_exif_from_bytes( &data )?;
+    let exif_position = exif_fields.as_ref().and_then(|exif_fields| exif_block_position( &data, format, exif_fields ));
+
+    let mut candidates : Vec<PreviewCandidate> = vec![];
+    for frame in frames::frames_from_data( &data, format, exif_fields.as_ref(), exif_position, path ) {
+        let source = match frame.kind {
+            FrameKind::Thumbnail => PreviewSource::ExifThumbnail,
+            // The primary image of a JPEG is the file itself, not an embedded preview.
+            FrameKind::Primary if format == MediaFormat::Jpeg => continue,
+            FrameKind::MpoImage if frame.mp_type.as_deref().is_some_and(|mp_type| mp_type.starts_with("large_thumbnail")) => PreviewSource::MpoImage,
+            FrameKind::MpoImage => continue,
+            _ => PreviewSource::ImageFileDirectory,
+        };
+        if let (Some(offset), Some(length)) = (frame.byte_offset, frame.byte_length) {
+            candidates.push( PreviewCandidate {
+                source,
+                offset: offset as usize,
+                length: length as usize,
+                dimensions: frame.width.and_then(|width| frame.height.map(|height| (width, height))),
+            } );
+        }
+    }
+    if let (Some(exif_fields), Some(position)) = (exif_fields.as_ref(), exif_position) {
+        candidates.extend( maker_note_previews( exif_fields, position ) );
+    }
+
+    let mut previews : Vec<EmbeddedPreview> = vec![];
+    for PreviewCandidate { source, offset, length, dimensions } in candidates {
+        let bytes = match data.get(offset..offset.saturating_add(length)) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+        // RAW image data is often lossless JPEG, which is not a preview anyone can view.
+        if !jpeg::is_viewable_jpeg( bytes ) {
+            continue;
+        }
+        if previews.iter().any(|preview| preview.byte_offset == offset as u64 && preview.byte_length == length as u64) {
+            continue;
+        }
+        let dimensions = jpeg::jpeg_dimensions( bytes ).or(dimensions);
+        previews.push( EmbeddedPreview {
+            source,
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+            byte_offset: offset as u64,
+            byte_length: length as u64,
+        } );
+    }
+    previews.sort_by_key(|preview| (preview.source != PreviewSource::ExifThumbnail, preview.byte_length));
+    Ok(previews)
+}
+
+// An embedded preview together with its JPEG bytes.
+pub type ExtractedPreview = (EmbeddedPreview, Vec<u8>);
+
+// Returns each embedded preview together with its JPEG bytes.
+pub fn extract_embedded_previews( path: &Path ) -> Result<Vec<ExtractedPreview>, Box<dyn error::Error>> {
+    let previews = list_embedded_previews( path )?;
+    let mut file = File::open( path )?;
+    let mut extracted = vec![];
+    for preview in previews {
+        let mut bytes = vec![0u8; preview.byte_length as usize];
+        file.seek( SeekFrom::Start( preview.byte_offset ) )?;
+        file.read_exact( &mut bytes )?;
+        extracted.push( (preview, bytes) );
+    }
+    Ok(extracted)
+}
+
+// Writes each embedded preview to a JPEG file named after the image: "<stem>_thumbnail.jpg" for
+// the EXIF thumbnail and "<stem>_preview<N>.jpg" for the others. Files go in `output_dir`, or
+// next to the image if no directory is given. Returns the paths written.
+pub fn write_embedded_previews( path: &Path, output_dir: Option<&Path> ) -> Result<Vec<PathBuf>, Box<dyn error::Error>> {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let directory = match output_dir {
+        Some(directory) => directory.to_path_buf(),
+        None => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+
+    let mut written = vec![];
+    let mut preview_number = 0;
+    for (preview, bytes) in extract_embedded_previews( path )? {
+        let file_name = if preview.source == PreviewSource::ExifThumbnail {
+            format!("{}_thumbnail.jpg", stem)
+        } else {
+            preview_number += 1;
+            format!("{}_preview{}.jpg", stem, preview_number)
+        };
+        let output_path = directory.join( file_name );
+        fs::write( &output_path, bytes )?;
+        written.push( output_path );
+    }
+    Ok(written)
+}
@@ -0,0 +1,429 @@
+use std::cmp::Ordering;
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::organize::collect_files;
+use crate::{read_json_metadata, read_metadata_of_interest, MetadataOfInterest};
+
+// The fields a filter can name, by their name in the JSON output. Nested fields are joined with
+// dots, such as "location.latitude" or "vendor.lens".
+const FIELDS : [&str; 17] = [
+    "filename", "size", "mime_type", "created_time", "modified_time", "hashes",
+    "orientation", "capture_time", "camera_model", "camera_serial", "location",
+    "video", "thumbnail", "color", "vendor", "frames", "sequence",
+];
+
+// Formats of time literals, which are written without quotes.
+const TIME_FORMATS : [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"];
+const DATE_FORMAT : &str = "%Y-%m-%d";
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl CompareOp {
+    fn holds_for( self, ordering: Ordering ) -> bool {
+        match self {
+            CompareOp::Equal => ordering == Ordering::Equal,
+            CompareOp::NotEqual => ordering != Ordering::Equal,
+            CompareOp::Less => ordering == Ordering::Less,
+            CompareOp::LessOrEqual => ordering != Ordering::Greater,
+            CompareOp::Greater => ordering == Ordering::Greater,
+            CompareOp::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+// A literal of a filter. Times are compared with the capture time as the camera recorded it,
+// and with the UTC time of file times.
+#[derive(Debug,Clone,PartialEq)]
+pub enum FilterValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    Time(chrono::NaiveDateTime),
+}
+
+impl FilterValue {
+    // How a value of the metadata compares with the literal, if they can be compared at all.
+    fn compare( &self, value: &Value ) -> Option<Ordering> {
+        match self {
+            FilterValue::Null => Some(Ordering::Equal).filter(|_| value.is_null()),
+            FilterValue::Bool(literal) => value.as_bool().filter(|value| value == literal).map(|_| Ordering::Equal),
+            FilterValue::Number(literal) => value.as_f64()?.partial_cmp( literal ),
+            FilterValue::Text(literal) => Some( value.as_str()?.cmp( literal.as_str() ) ),
+            FilterValue::Time(literal) => {
+                let text = value.as_str()?;
+                let time = chrono::NaiveDateTime::from_str( text ).ok()
+                    .or_else(|| chrono::DateTime::parse_from_rfc3339( text ).ok().map(|time| time.naive_utc()))?;
+                Some( time.cmp( literal ) )
+            },
+        }
+    }
+}
+
+// A parsed filter expression, such as
+// `camera_serial == "025021000535" && capture_time > 2020-01-30T09:30 && orientation in [6, 8]`.
+// Comparisons take strings in double quotes, numbers, times as 2020-01-30T09:30[:00] or a date,
+// true, false and null, which a missing field equals. They can be combined with &&, || and !,
+// and grouped with parentheses. A field inside a list, such as "frames.width", matches if any
+// element does.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Filter {
+    Compare { field: String, op: CompareOp, value: FilterValue },
+    In { field: String, values: Vec<FilterValue> },
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+#[derive(Debug, Clone)]
+pub struct FilterParseError {
+    expression: String,
+    // In characters from the start of the expression.
+    position: usize,
+    reason: String,
+}
+
+impl FilterParseError {
+    pub fn position( &self ) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid filter at column {}: {}\n  {}\n  {}^", self.position + 1, self.reason, self.expression, " ".repeat( self.position ))
+    }
+}
+
+impl Error for FilterParseError {
+    fn description(&self) -> &str {
+        "A filter expression could not be parsed"
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+enum Token {
+    Field(String),
+    Literal(FilterValue),
+    Compare(CompareOp),
+    And,
+    Or,
+    Not,
+    In,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+}
+
+fn describe( token: Option<&Token> ) -> String {
+    match token {
+        None => "the end of the filter".to_string(),
+        Some(Token::Field(name)) => format!("\"{}\"", name),
+        Some(Token::Literal(_)) => "a value".to_string(),
+        Some(Token::Compare(_)) => "a comparison".to_string(),
+        Some(Token::And) => "\"&&\"".to_string(),
+        Some(Token::Or) => "\"||\"".to_string(),
+        Some(Token::Not) => "\"!\"".to_string(),
+        Some(Token::In) => "\"in\"".to_string(),
+        Some(Token::OpenParen) => "\"(\"".to_string(),
+        Some(Token::CloseParen) => "\")\"".to_string(),
+        Some(Token::OpenBracket) => "\"[\"".to_string(),
+        Some(Token::CloseBracket) => "\"]\"".to_string(),
+        Some(Token::Comma) => "\",\"".to_string(),
+    }
+}
+
+fn parse_time( text: &str ) -> Option<chrono::NaiveDateTime> {
+    TIME_FORMATS.iter().find_map(|format| chrono::NaiveDateTime::parse_from_str( text, format ).ok())
+        .or_else(|| chrono::NaiveDate::parse_from_str( text, DATE_FORMAT ).ok().and_then(|date| date.and_hms_opt( 0, 0, 0 )))
+}
+
+// Splits the expression into tokens, with the character each starts at.
+fn tokenize( expression: &str ) -> Result<Vec<(Token, usize)>, (usize, String)> {
+    let chars : Vec<char> = expression.chars().collect();
+    let mut tokens = vec![];
+    let mut index = 0;
+    while index < chars.len() {
+        let start = index;
+        let next = chars.get( index + 1 ).copied();
+        let (token, length) = match chars[index] {
+            c if c.is_whitespace() => { index += 1; continue },
+            '(' => (Token::OpenParen, 1),
+            ')' => (Token::CloseParen, 1),
+            '[' => (Token::OpenBracket, 1),
+            ']' => (Token::CloseBracket, 1),
+            ',' => (Token::Comma, 1),
+            '&' if next == Some('&') => (Token::And, 2),
+            '|' if next == Some('|') => (Token::Or, 2),
+            '=' if next == Some('=') => (Token::Compare(CompareOp::Equal), 2),
+            '!' if next == Some('=') => (Token::Compare(CompareOp::NotEqual), 2),
+            '<' if next == Some('=') => (Token::Compare(CompareOp::LessOrEqual), 2),
+            '>' if next == Some('=') => (Token::Compare(CompareOp::GreaterOrEqual), 2),
+            '!' => (Token::Not, 1),
+            '<' => (Token::Compare(CompareOp::Less), 1),
+            '>' => (Token::Compare(CompareOp::Greater), 1),
+            '"' => {
+                let mut text = String::new();
+                let mut end = index + 1;
+                loop {
+                    match chars.get( end ) {
+                        None => return Err( (start, "the string has no closing quote".to_string()) ),
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.get( end + 1 ), Some('"') | Some('\\')) => { text.push( chars[end + 1] ); end += 2 },
+                        Some(c) => { text.push( *c ); end += 1 },
+                    }
+                }
+                (Token::Literal(FilterValue::Text(text)), end + 1 - index)
+            },
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|next| next.is_ascii_digit())) => {
+                let length = 1 + chars[index + 1..].iter().take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | ':' | '.' | '+')).count();
+                let text : String = chars[index..index + length].iter().collect();
+                let value = match text.parse::<f64>() {
+                    Ok(number) => FilterValue::Number(number),
+                    Err(_) => match parse_time( &text ) {
+                        Some(time) => FilterValue::Time(time),
+                        None => return Err( (start, format!("\"{}\" is neither a number nor a time such as 2020-01-30T09:30", text)) ),
+                    },
+                };
+                (Token::Literal(value), length)
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let length = chars[index..].iter().take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.')).count();
+                let word : String = chars[index..index + length].iter().collect();
+                let token = match word.as_str() {
+                    "in" => Token::In,
+                    "true" => Token::Literal(FilterValue::Bool(true)),
+                    "false" => Token::Literal(FilterValue::Bool(false)),
+                    "null" => Token::Literal(FilterValue::Null),
+                    _ => Token::Field(word),
+                };
+                (token, length)
+            },
+            c => return Err( (start, format!("unexpected character {:?}", c)) ),
+        };
+        tokens.push( (token, start) );
+        index += length;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek( &self ) -> Option<&Token> {
+        self.tokens.get( self.index ).map(|(token, _)| token)
+    }
+
+    fn position( &self ) -> usize {
+        self.tokens.get( self.index ).map(|(_, position)| *position).unwrap_or( self.end )
+    }
+
+    fn error<T>( &self, reason: String ) -> Result<T, (usize, String)> {
+        Err( (self.position(), reason) )
+    }
+
+    fn next( &mut self ) -> Option<Token> {
+        let token = self.tokens.get( self.index ).map(|(token, _)| token.clone());
+        self.index += 1;
+        token
+    }
+
+    fn or( &mut self ) -> Result<Filter, (usize, String)> {
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            filter = Filter::Or( Box::new( filter ), Box::new( self.and()? ) );
+        }
+        Ok(filter)
+    }
+
+    fn and( &mut self ) -> Result<Filter, (usize, String)> {
+        let mut filter = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            filter = Filter::And( Box::new( filter ), Box::new( self.unary()? ) );
+        }
+        Ok(filter)
+    }
+
+    fn unary( &mut self ) -> Result<Filter, (usize, String)> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                Ok( Filter::Not( Box::new( self.unary()? ) ) )
+            },
+            Some(Token::OpenParen) => {
+                self.next();
+                let filter = self.or()?;
+                match self.peek() {
+                    Some(Token::CloseParen) => { self.next(); Ok(filter) },
+                    other => self.error( format!("expected \")\" but found {}", describe( other )) ),
+                }
+            },
+            Some(Token::Field(_)) => self.comparison(),
+            other => self.error( format!("expected a field name but found {}", describe( other )) ),
+        }
+    }
+
+    fn comparison( &mut self ) -> Result<Filter, (usize, String)> {
+        let field = match self.next() {
+            Some(Token::Field(field)) => field,
+            _ => unreachable!("comparisons start with a field"),
+        };
+        let top_level = field.split( '.' ).next().unwrap_or_default();
+        if !FIELDS.contains( &top_level ) {
+            self.index -= 1;
+            return self.error( format!("unknown field \"{}\"; the fields are {}", top_level, FIELDS.join( ", " )) );
+        }
+        match self.peek().cloned() {
+            Some(Token::Compare(op)) => {
+                self.next();
+                let value = self.value()?;
+                Ok( Filter::Compare { field, op, value } )
+            },
+            Some(Token::In) => {
+                self.next();
+                if self.peek() != Some(&Token::OpenBracket) {
+                    return self.error( format!("expected \"[\" after \"in\" but found {}", describe( self.peek() )) );
+                }
+                self.next();
+                let mut values = vec![self.value()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    values.push( self.value()? );
+                }
+                match self.peek() {
+                    Some(Token::CloseBracket) => { self.next(); Ok( Filter::In { field, values } ) },
+                    other => self.error( format!("expected \",\" or \"]\" but found {}", describe( other )) ),
+                }
+            },
+            other => self.error( format!("expected a comparison such as == or \"in\" after \"{}\" but found {}", field, describe( other.as_ref() )) ),
+        }
+    }
+
+    fn value( &mut self ) -> Result<FilterValue, (usize, String)> {
+        match self.peek().cloned() {
+            Some(Token::Literal(value)) => { self.next(); Ok(value) },
+            other => self.error( format!("expected a value but found {}; strings are written in double quotes", describe( other.as_ref() )) ),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = FilterParseError;
+
+    fn from_str( expression: &str ) -> Result<Self, Self::Err> {
+        let error = |(position, reason): (usize, String)| FilterParseError { expression: expression.to_string(), position, reason };
+        let tokens = tokenize( expression ).map_err(error)?;
+        let mut parser = Parser { tokens, index: 0, end: expression.chars().count() };
+        let filter = parser.or().map_err(error)?;
+        if let Some(token) = parser.peek() {
+            let reason = format!("expected \"&&\" or \"||\" but found {}", describe( Some(token) ));
+            return Err( error( (parser.position(), reason) ) );
+        }
+        Ok(filter)
+    }
+}
+
+// The values at the dotted path, going into every element of lists on the way. A list at the end
+// of the path gives its elements.
+fn field_values<'a>( value: &'a Value, path: &[&str], values: &mut Vec<&'a Value> ) {
+    match (value, path.split_first()) {
+        (Value::Array(elements), _) => elements.iter().for_each(|element| field_values( element, path, values )),
+        (_, None) => values.push( value ),
+        (Value::Object(fields), Some((name, rest))) => {
+            if let Some(field) = fields.get( *name ) {
+                field_values( field, rest, values );
+            }
+        },
+        _ => {},
+    }
+}
+
+impl Filter {
+    pub fn matches( &self, metadata: &MetadataOfInterest ) -> bool {
+        serde_json::to_value( metadata ).map(|value| self.matches_json( &value )).unwrap_or(false)
+    }
+
+    // Tests metadata in its JSON form, as in sidecars and catalogs.
+    pub fn matches_json( &self, metadata: &Value ) -> bool {
+        let values_of = |field: &str| {
+            let path : Vec<&str> = field.split( '.' ).collect();
+            let mut values = vec![];
+            field_values( metadata, &path, &mut values );
+            if values.is_empty() {
+                values.push( &Value::Null );
+            }
+            values
+        };
+        let equals = |value: &Value, literal: &FilterValue| literal.compare( value ) == Some(Ordering::Equal);
+        match self {
+            Filter::Compare { field, op: CompareOp::NotEqual, value: literal } => !values_of( field ).iter().any(|value| equals( value, literal )),
+            Filter::Compare { field, op, value: literal } => values_of( field ).iter().any(|value| literal.compare( value ).is_some_and(|ordering| op.holds_for( ordering ))),
+            Filter::In { field, values: literals } => values_of( field ).iter().any(|value| literals.iter().any(|literal| equals( value, literal ))),
+            Filter::Not(filter) => !filter.matches_json( metadata ),
+            Filter::And(left, right) => left.matches_json( metadata ) && right.matches_json( metadata ),
+            Filter::Or(left, right) => left.matches_json( metadata ) || right.matches_json( metadata ),
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct QueryReport {
+    pub files_scanned: usize,
+    pub matches: Vec<PathBuf>,
+}
+
+pub type QueryFailure = (PathBuf, Box<dyn error::Error>);
+
+// Reads a JSON sidecar as it is, or the metadata of an image.
+fn read_for_query( path: &Path ) -> Result<MetadataOfInterest, Box<dyn error::Error>> {
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case( "json" )) {
+        read_json_metadata( &path.to_string_lossy() )
+    } else {
+        read_metadata_of_interest( path )
+    }
+}
+
+// The files given, and those in directories recursively, whose metadata the filter matches. JSON
+// files are taken to be sidecars, and matched with the metadata they hold; other files are read.
+pub fn query_files( paths: &[PathBuf], filter: &Filter ) -> (QueryReport, Vec<QueryFailure>) {
+    let mut files = vec![];
+    for path in paths.iter() {
+        collect_files( path, None, &mut files );
+    }
+    let mut report = QueryReport::default();
+    let mut failures : Vec<QueryFailure> = vec![];
+    for path in files {
+        match read_for_query( &path ) {
+            Ok(metadata) => {
+                report.files_scanned += 1;
+                if filter.matches( &metadata ) {
+                    report.matches.push( path );
+                }
+            },
+            Err(boxed_err) => failures.push( (path, boxed_err) ),
+        }
+    }
+    (report, failures)
+}
@@ -0,0 +1,157 @@
+use hmac::{Hmac, Mac};
+use serde::{Serialize, Serializer};
+use serde::ser::Error;
+use sha2::Sha256;
+
+use crate::hashing::to_hex;
+use crate::{MetadataOfInterest, VendorDetails};
+
+// Marks redacted output, so that it is never mistaken for the real metadata of the file.
+const REDACTED_MARKER : &str = "redacted";
+
+// Length of the pseudonyms which replace camera serials, in hex digits.
+const PSEUDONYM_LENGTH : usize = 16;
+
+// How metadata is redacted when it is written out, for sharing catalogs without the images.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct Redaction {
+    // Secret for the keyed hash which replaces camera serials. The same secret always gives
+    // the same pseudonym for a serial, so files from one body can still be grouped.
+    pub serial_key: Option<Vec<u8>>,
+    // Number of decimal places GPS coordinates are rounded to; 2 is roughly a kilometre.
+    pub gps_decimals: Option<u32>,
+    // Fields to leave out, by their name in the JSON output. Nested fields are joined with
+    // dots, such as "vendor.internal_serial", and apply to every element of a list.
+    pub removed_fields: Vec<String>,
+}
+
+impl Redaction {
+    pub fn is_empty( &self ) -> bool {
+        *self == Redaction::default()
+    }
+
+    // Applies the serial and GPS redaction to a copy of the metadata.
+    pub fn redact( &self, metadata: &MetadataOfInterest ) -> MetadataOfInterest {
+        let mut redacted = metadata.clone();
+        if let Some(key) = &self.serial_key {
+            // The body and MakerNote serials are replaced, and the owner name, which identifies
+            // the photographer as surely, is left out.
+            let vendor = redacted.vendor_metadata.as_mut();
+            let mut vendor_serials = vec![];
+            if let Some(vendor) = vendor {
+                vendor_serials.push( &mut vendor.internal_serial );
+                match vendor.details.as_mut() {
+                    Some(VendorDetails::Canon(canon)) => canon.owner_name = None,
+                    Some(VendorDetails::Nikon(nikon)) => vendor_serials.push( &mut nikon.serial_number ),
+                    _ => {},
+                }
+            }
+            let serials = std::iter::once( &mut redacted.image_metadata.camera_serial )
+                .chain( redacted.frames.iter_mut().flatten().map(|frame| &mut frame.image_metadata.camera_serial) )
+                .chain( vendor_serials );
+            for serial in serials {
+                if let Some(value) = serial.as_mut() {
+                    *value = pseudonym( key, value );
+                }
+            }
+        }
+        if let Some(decimals) = self.gps_decimals {
+            let scale = 10f64.powi( decimals as i32 );
+            let coarsen = |coordinate: f64| (coordinate * scale).round() / scale;
+            let locations = redacted.video_metadata.as_mut().and_then(|video| video.location.as_mut()).into_iter()
+                .chain( redacted.image_metadata.location.as_mut() )
+                .chain( redacted.frames.iter_mut().flatten().filter_map(|frame| frame.image_metadata.location.as_mut()) );
+            for location in locations {
+                location.latitude = coarsen( location.latitude );
+                location.longitude = coarsen( location.longitude );
+            }
+        }
+        redacted
+    }
+
+    // The redacted metadata as JSON, with the removed fields left out and the redacted marker set.
+    pub fn to_json_value( &self, metadata: &MetadataOfInterest ) -> serde_json::Result<serde_json::Value> {
+        let mut value = serde_json::to_value( self.redact( metadata ) )?;
+        if self.is_empty() {
+            return Ok(value);
+        }
+        self.remove_fields( &mut value );
+        mark_redacted( &mut value );
+        Ok(value)
+    }
+
+    // The pseudonym for a camera serial, or the serial itself without a secret.
+    pub(crate) fn redact_serial( &self, serial: &str ) -> String {
+        match &self.serial_key {
+            Some(key) => pseudonym( key, serial ),
+            None => serial.to_string(),
+        }
+    }
+
+    // Leaves the removed fields out of JSON output.
+    pub(crate) fn remove_fields( &self, value: &mut serde_json::Value ) {
+        for field in self.removed_fields.iter() {
+            let path : Vec<&str> = field.split( '.' ).collect();
+            remove_path( value, &path );
+        }
+    }
+}
+
+pub(crate) fn mark_redacted( value: &mut serde_json::Value ) {
+    if let Some(object) = value.as_object_mut() {
+        object.insert( REDACTED_MARKER.to_string(), serde_json::Value::Bool(true) );
+    }
+}
+
+// The pseudonym for a camera serial: the start of its HMAC-SHA-256 under the secret.
+fn pseudonym( key: &[u8], serial: &str ) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice( key ).expect("HMAC takes keys of any length");
+    mac.update( serial.trim().as_bytes() );
+    let mut pseudonym = to_hex( &mac.finalize().into_bytes() );
+    pseudonym.truncate( PSEUDONYM_LENGTH );
+    pseudonym
+}
+
+fn remove_path( value: &mut serde_json::Value, path: &[&str] ) {
+    match value {
+        serde_json::Value::Array(elements) => elements.iter_mut().for_each(|element| remove_path( element, path )),
+        serde_json::Value::Object(object) => match path {
+            [field] => {
+                object.remove( *field );
+            },
+            [field, rest @ ..] => {
+                if let Some(child) = object.get_mut( *field ) {
+                    remove_path( child, rest );
+                }
+            },
+            [] => {},
+        },
+        _ => {},
+    }
+}
+
+// Metadata which serializes with a redaction applied, whatever the serializer.
+pub struct RedactedMetadata<'a> {
+    pub metadata: &'a MetadataOfInterest,
+    pub redaction: &'a Redaction,
+}
+
+impl<'a> Serialize for RedactedMetadata<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        self.redaction.to_json_value( self.metadata ).map_err( S::Error::custom )?.serialize( serializer )
+    }
+}
+
+// Whether JSON text was written with a redaction, so holds pseudonyms rather than real values.
+pub(crate) fn is_redacted_json( contents: &[u8] ) -> bool {
+    serde_json::from_slice::<serde_json::Value>( contents )
+        .map(|value| is_redacted_value( &value ))
+        .unwrap_or(false)
+}
+
+pub(crate) fn is_redacted_value( value: &serde_json::Value ) -> bool {
+    value.get( REDACTED_MARKER ) == Some(&serde_json::Value::Bool(true))
+}
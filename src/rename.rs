@@ -0,0 +1,486 @@
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::writer::WriteOptions;
+use crate::{json_sidecar_path, read_metadata_of_interest, MetadataOfInterest};
+
+// The archive naming rule: capture time, the last four characters of the camera serial, and a
+// number telling apart the files which would otherwise get the same name.
+pub const DEFAULT_RENAME_TEMPLATE : &str = "{capture_time:%Y%m%d_%H%M%S}_{camera_serial:-4}_{seq}.{ext}";
+
+const DEFAULT_TIME_FORMAT : &str = "%Y%m%d_%H%M%S";
+
+// Extensions of sidecars named after the stem of their image, which are moved with it.
+pub(crate) const STEM_SIDECAR_EXTENSIONS : [&str; 3] = ["json", "xmp", "XMP"];
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum TemplateField {
+    CaptureTime,
+    CameraModel,
+    CameraSerial,
+    Filename,
+    Extension,
+    Counter,
+    Sequence,
+    Year,
+    Month,
+    Day,
+}
+
+impl TemplateField {
+    fn from_name( name: &str ) -> Option<TemplateField> {
+        match name {
+            "capture_time" => Some(TemplateField::CaptureTime),
+            "camera_model" => Some(TemplateField::CameraModel),
+            "camera_serial" => Some(TemplateField::CameraSerial),
+            "filename" => Some(TemplateField::Filename),
+            "ext" => Some(TemplateField::Extension),
+            "counter" => Some(TemplateField::Counter),
+            "seq" => Some(TemplateField::Sequence),
+            "year" => Some(TemplateField::Year),
+            "month" => Some(TemplateField::Month),
+            "day" => Some(TemplateField::Day),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+enum Segment {
+    Literal(String),
+    Field { field: TemplateField, modifier: Option<String> },
+}
+
+#[derive(Debug, Clone)]
+pub struct TemplateError {
+    template: String,
+    reason: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid name template {:?}: {}", self.template, self.reason)
+    }
+}
+
+impl Error for TemplateError {
+    fn description(&self) -> &str {
+        "A name template could not be parsed"
+    }
+}
+
+// A file name made from metadata, such as "{capture_time:%Y%m%d}_{camera_model}.{ext}".
+// Fields are written in braces, optionally with a modifier after a colon:
+//   capture_time    strftime format, "%Y%m%d_%H%M%S" by default
+//   camera_model, camera_serial, filename (the original stem)
+//                   N for the first N characters, -N for the last N
+//   ext             the original extension; lower or upper to change its case
+//   counter         the position of the file among all the files, from 1; N pads to N digits
+//   seq             the position among the files which would otherwise get the same name,
+//                   from 1; N pads to N digits
+//   year, month, day
+//                   parts of capture_time, as %Y, %m and %d
+// Literal braces are written doubled. Layouts of folders, made with NameTemplate::layout, can
+// also have "/" between folder names.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct NameTemplate {
+    text: String,
+    segments: Vec<Segment>,
+}
+
+impl FromStr for NameTemplate {
+    type Err = TemplateError;
+
+    fn from_str( text: &str ) -> Result<Self, Self::Err> {
+        NameTemplate::parse( text, false )
+    }
+}
+
+impl NameTemplate {
+    // A template for a relative path of folders, such as "{year}/{year}-{month}-{day}".
+    pub fn layout( text: &str ) -> Result<NameTemplate, TemplateError> {
+        NameTemplate::parse( text, true )
+    }
+
+    fn parse( text: &str, allow_folders: bool ) -> Result<NameTemplate, TemplateError> {
+        let error = |reason: String| TemplateError { template: text.to_string(), reason };
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => { chars.next(); literal.push( '{' ); },
+                '}' if chars.peek() == Some(&'}') => { chars.next(); literal.push( '}' ); },
+                '}' => return Err( error( "unmatched }".to_string() ) ),
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push( c ),
+                            None => return Err( error( "unterminated {".to_string() ) ),
+                        }
+                    }
+                    let (name, modifier) = match spec.split_once( ':' ) {
+                        Some((name, modifier)) => (name, Some(modifier.to_string())),
+                        None => (spec.as_str(), None),
+                    };
+                    let field = TemplateField::from_name( name ).ok_or_else(|| error( format!("unknown field {:?}", name) ))?;
+                    if let Some(modifier) = &modifier {
+                        if !is_valid_modifier( field, modifier ) {
+                            return Err( error( format!("invalid modifier {:?} for {}", modifier, name) ) );
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push( Segment::Literal( std::mem::take( &mut literal ) ) );
+                    }
+                    segments.push( Segment::Field { field, modifier } );
+                },
+                _ => literal.push( c ),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push( Segment::Literal(literal) );
+        }
+        if !allow_folders && (text.contains( '/' ) || text.contains( '\\' )) {
+            return Err( error( "a file name can't contain a path separator".to_string() ) );
+        }
+        if allow_folders && (text.contains( '\\' ) || text.split( '/' ).any(|part| part.is_empty() || part == "." || part == "..")) {
+            return Err( error( "a layout must be a relative path of folders, separated by /".to_string() ) );
+        }
+        Ok( NameTemplate { text: text.to_string(), segments } )
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> NameTemplate {
+        DEFAULT_RENAME_TEMPLATE.parse().unwrap_or_else(|_| NameTemplate { text: String::new(), segments: vec![] })
+    }
+}
+
+fn is_valid_modifier( field: TemplateField, modifier: &str ) -> bool {
+    match field {
+        TemplateField::CaptureTime => {
+            !modifier.is_empty() && chrono::format::StrftimeItems::new( modifier ).all(|item| item != chrono::format::Item::Error)
+        },
+        TemplateField::CameraModel | TemplateField::CameraSerial | TemplateField::Filename => {
+            modifier.strip_prefix( '-' ).unwrap_or( modifier ).parse::<usize>().is_ok()
+        },
+        TemplateField::Extension => modifier == "lower" || modifier == "upper",
+        TemplateField::Counter | TemplateField::Sequence => modifier.parse::<usize>().is_ok(),
+        TemplateField::Year | TemplateField::Month | TemplateField::Day => false,
+    }
+}
+
+// Replaces the characters which aren't safe in file names on every platform, and whitespace.
+fn sanitize( value: &str ) -> String {
+    value.trim().chars()
+        .map(|c| if c.is_whitespace() || c.is_control() || "/\\:*?\"<>|".contains( c ) { '_' } else { c })
+        .collect()
+}
+
+// Keeps the first N characters, or the last N for -N.
+fn truncate( value: &str, modifier: Option<&str> ) -> String {
+    let chars : Vec<char> = value.chars().collect();
+    match modifier {
+        Some(modifier) => match modifier.strip_prefix( '-' ) {
+            Some(count) => chars[chars.len().saturating_sub( count.parse().unwrap_or(0) )..].iter().collect(),
+            None => chars[..chars.len().min( modifier.parse().unwrap_or(0) )].iter().collect(),
+        },
+        None => value.to_string(),
+    }
+}
+
+fn padded( number: usize, modifier: Option<&str> ) -> String {
+    format!("{:0width$}", number, width = modifier.and_then(|width| width.parse().ok()).unwrap_or(0))
+}
+
+impl NameTemplate {
+    fn uses( &self, wanted: TemplateField ) -> bool {
+        self.segments.iter().any(|segment| matches!(segment, Segment::Field { field, .. } if *field == wanted))
+    }
+
+    // The name of a file, or the name of the field the file has no value for. With a placeholder,
+    // missing fields are written as it instead.
+    pub(crate) fn render( &self, metadata: &MetadataOfInterest, path: &Path, counter: usize, seq: usize, placeholder: Option<&str> ) -> Result<String, &'static str> {
+        let image_metadata = &metadata.image_metadata;
+        let required = |value: Option<String>, field: &'static str| value.or_else(|| placeholder.map(str::to_string)).ok_or(field);
+        let time_part = |format: &str| image_metadata.capture_time.map(|capture_time| sanitize( &capture_time.format( format ).to_string() ));
+        let mut name = String::new();
+        for segment in self.segments.iter() {
+            let (field, modifier) = match segment {
+                Segment::Literal(text) => {
+                    name.push_str( text );
+                    continue;
+                },
+                Segment::Field { field, modifier } => (*field, modifier.as_deref()),
+            };
+            let value = match field {
+                TemplateField::CaptureTime => required( time_part( modifier.unwrap_or( DEFAULT_TIME_FORMAT ) ), "capture_time" )?,
+                TemplateField::Year => required( time_part( "%Y" ), "capture_time" )?,
+                TemplateField::Month => required( time_part( "%m" ), "capture_time" )?,
+                TemplateField::Day => required( time_part( "%d" ), "capture_time" )?,
+                TemplateField::CameraModel => truncate( &required( image_metadata.camera_model.as_deref().map(sanitize), "camera_model" )?, modifier ),
+                TemplateField::CameraSerial => truncate( &required( image_metadata.camera_serial.as_deref().map(sanitize), "camera_serial" )?, modifier ),
+                TemplateField::Filename => truncate( &sanitize( &path.file_stem().ok_or("filename")?.to_string_lossy() ), modifier ),
+                TemplateField::Extension => {
+                    let extension = sanitize( &path.extension().map(|extension| extension.to_string_lossy()).unwrap_or_default() );
+                    match modifier {
+                        Some("lower") => extension.to_lowercase(),
+                        Some("upper") => extension.to_uppercase(),
+                        _ => extension,
+                    }
+                },
+                TemplateField::Counter => padded( counter, modifier ),
+                TemplateField::Sequence => padded( seq, modifier ),
+            };
+            name.push_str( &value );
+        }
+        if name.split( '/' ).any(|part| part.is_empty() || part == "." || part == "..") {
+            return Err("filename");
+        }
+        Ok(name)
+    }
+}
+
+// Raised for a file the template needs a field for which the file doesn't have.
+#[derive(Debug, Clone)]
+pub struct MissingFieldError {
+    pub(crate) field: &'static str,
+}
+
+impl fmt::Display for MissingFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The name template needs {}, which the file doesn't have", self.field)
+    }
+}
+
+impl Error for MissingFieldError {
+    fn description(&self) -> &str {
+        "A file lacks a field which its new name is made from"
+    }
+}
+
+// One file moved, as recorded in the undo journal.
+#[derive(Debug,Clone,PartialEq,Eq)]
+#[derive(Serialize,Deserialize)]
+pub struct RenamedFile {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+// The renames of an image and its sidecars.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct RenamePlan {
+    pub image: RenamedFile,
+    pub sidecars: Vec<RenamedFile>,
+}
+
+// The sidecars of an image which exist: the ones named after its stem, such as IMG_0001.json,
+// and XMP files named after the whole file name, such as IMG_0001.CR2.xmp.
+fn existing_sidecars( image_path: &Path ) -> Vec<PathBuf> {
+    let mut sidecars : Vec<PathBuf> = STEM_SIDECAR_EXTENSIONS.iter()
+        .filter_map(|extension| json_sidecar_path( image_path ).map(|json_path| json_path.with_extension( extension )))
+        .collect();
+    if let Some(name) = image_path.file_name() {
+        sidecars.push( image_path.with_file_name( format!("{}.xmp", name.to_string_lossy()) ) );
+    }
+    sidecars.into_iter().filter(|sidecar| sidecar.is_file() && sidecar != image_path).collect()
+}
+
+// Where a sidecar goes when its image is renamed, keeping the part of its name after the
+// image's stem or file name.
+fn renamed_sidecar( sidecar: &Path, image_from: &Path, image_to: &Path ) -> Option<PathBuf> {
+    let sidecar_name = sidecar.file_name()?.to_string_lossy().into_owned();
+    let (from_name, to_name) = (image_from.file_name()?.to_string_lossy(), image_to.file_name()?.to_string_lossy());
+    let (from_stem, to_stem) = (image_from.file_stem()?.to_string_lossy(), image_to.file_stem()?.to_string_lossy());
+    let renamed = match sidecar_name.strip_prefix( from_name.as_ref() ) {
+        Some(rest) if rest.starts_with( '.' ) => format!("{}{}", to_name, rest),
+        _ => format!("{}{}", to_stem, sidecar_name.strip_prefix( from_stem.as_ref() )?),
+    };
+    Some( image_to.with_file_name( renamed ) )
+}
+
+// A file which can't be renamed, and why.
+pub type RenameFailure = (PathBuf, Box<dyn error::Error>);
+
+// Works out the new name of each image from the template, in the same directory, with the
+// renames of its sidecars. Files are numbered in capture time order, then path order, so the
+// same files always get the same names. Names which would collide with each other or with a
+// file which isn't being renamed are told apart by seq, or by a "_2", "_3"... suffix before the
+// extension when the template has no seq. Files which can't be renamed are returned with why.
+pub fn plan_renames( paths: &[PathBuf], template: &NameTemplate ) -> (Vec<RenamePlan>, Vec<RenameFailure>) {
+    let mut failures : Vec<RenameFailure> = vec![];
+    let mut files = vec![];
+    for path in paths {
+        match read_metadata_of_interest( path ) {
+            Ok(metadata) => files.push( (metadata.image_metadata.capture_time, path.clone(), metadata) ),
+            Err(boxed_err) => failures.push( (path.clone(), boxed_err) ),
+        }
+    }
+    files.sort_by(|first, second| (first.0, &first.1).cmp( &(second.0, &second.1) ));
+
+    // The name each file would get with a seq of 1, grouped by directory.
+    let mut named = vec![];
+    for (index, (_, path, metadata)) in files.iter().enumerate() {
+        match template.render( metadata, path, index + 1, 1, None ) {
+            Ok(name) => named.push( (index, path, metadata, path.with_file_name( name )) ),
+            Err(field) => failures.push( (path.clone(), Box::new( MissingFieldError { field } )) ),
+        }
+    }
+    let moving : HashSet<&PathBuf> = named.iter().map(|(_, path, _, _)| *path).collect();
+    let mut taken : HashSet<PathBuf> = HashSet::new();
+    let mut next_seq : HashMap<PathBuf, usize> = HashMap::new();
+    let uses_seq = template.uses( TemplateField::Sequence );
+    // Images with the same stem, such as a RAW and its JPEG, share stem sidecars; the first
+    // one renamed takes them.
+    let mut claimed_sidecars : HashSet<PathBuf> = HashSet::new();
+
+    let mut plans = vec![];
+    for (index, path, metadata, first_choice) in named {
+        // A name is free if no planned file has it, and no file which stays put has it.
+        let is_free = |candidate: &PathBuf, taken: &HashSet<PathBuf>| {
+            !taken.contains( candidate ) && (candidate == path || moving.contains( candidate ) || !candidate.exists())
+        };
+        let seq = next_seq.entry( first_choice.clone() ).or_insert( 1 );
+        let mut target = first_choice.clone();
+        loop {
+            if uses_seq {
+                if let Ok(name) = template.render( metadata, path, index + 1, *seq, None ) {
+                    target = path.with_file_name( name );
+                }
+            } else if *seq > 1 {
+                let stem = first_choice.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                target = match first_choice.extension() {
+                    Some(extension) => first_choice.with_file_name( format!("{}_{}.{}", stem, seq, extension.to_string_lossy()) ),
+                    None => first_choice.with_file_name( format!("{}_{}", stem, seq) ),
+                };
+            }
+            *seq += 1;
+            if is_free( &target, &taken ) {
+                break;
+            }
+        }
+        taken.insert( target.clone() );
+        if target == *path {
+            continue;
+        }
+        let sidecars = existing_sidecars( path ).into_iter()
+            .filter(|sidecar| claimed_sidecars.insert( sidecar.clone() ))
+            .filter_map(|sidecar| renamed_sidecar( &sidecar, path, &target ).map(|to| RenamedFile { from: sidecar, to }))
+            .collect();
+        plans.push( RenamePlan { image: RenamedFile { from: path.clone(), to: target }, sidecars } );
+    }
+
+    // A sidecar can't go where a file is which isn't being renamed.
+    let moving_sidecars : HashSet<PathBuf> = plans.iter().flat_map(|plan| plan.sidecars.iter().map(|sidecar| sidecar.from.clone())).collect();
+    let (plans, blocked) : (Vec<RenamePlan>, Vec<RenamePlan>) = plans.into_iter().partition(|plan| {
+        plan.sidecars.iter().all(|sidecar| !sidecar.to.exists() || moving_sidecars.contains( &sidecar.to ) || moving.contains( &sidecar.to ))
+    });
+    for plan in blocked {
+        failures.push( (plan.image.from, Box::new( RenameConflictError { path: plan.sidecars[0].to.clone() } )) );
+    }
+    (plans, failures)
+}
+
+// Raised when a file is in the way of a rename.
+#[derive(Debug, Clone)]
+pub struct RenameConflictError {
+    path: PathBuf,
+}
+
+impl fmt::Display for RenameConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "A file is already at {}", self.path.to_string_lossy())
+    }
+}
+
+impl Error for RenameConflictError {
+    fn description(&self) -> &str {
+        "A file is in the way of a rename"
+    }
+}
+
+// The undo journal: the renames in the order they were made.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct RenameJournal {
+    pub renames: Vec<RenamedFile>,
+}
+
+// Moves files, first moving the ones another file is going to aside, so that swaps and chains
+// of renames work. Nothing is moved if a file which isn't being moved is in the way.
+fn move_files( renames: &[RenamedFile] ) -> Result<(), Box<dyn error::Error>> {
+    let sources : HashSet<&PathBuf> = renames.iter().map(|rename| &rename.from).collect();
+    let targets : HashSet<&PathBuf> = renames.iter().map(|rename| &rename.to).collect();
+    if let Some(blocked) = renames.iter().find(|rename| rename.to.exists() && !sources.contains( &rename.to )) {
+        return Err( Box::new( RenameConflictError { path: blocked.to.clone() } ) );
+    }
+    let mut staged = vec![];
+    for (index, rename) in renames.iter().enumerate() {
+        if targets.contains( &rename.from ) {
+            let aside = rename.from.with_file_name( format!(".rusimeta-rename-{}-{}", std::process::id(), index) );
+            fs::rename( &rename.from, &aside )?;
+            staged.push( (aside, &rename.to) );
+        } else {
+            staged.push( (rename.from.clone(), &rename.to) );
+        }
+    }
+    for (from, to) in staged {
+        fs::rename( &from, to )?;
+    }
+    Ok(())
+}
+
+fn absolute( path: &Path ) -> PathBuf {
+    let parent = path.parent().filter(|parent| parent.components().next().is_some()).unwrap_or_else(|| Path::new("."));
+    match (fs::canonicalize( parent ), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join( name ),
+        _ => path.to_path_buf(),
+    }
+}
+
+// Renames the planned images and their sidecars. The journal is written first, with absolute
+// paths, so that undo_renames can put back whatever was renamed even if renaming stops part way.
+pub fn rename_files( plans: &[RenamePlan], journal_path: Option<&Path>, options: &WriteOptions ) -> Result<RenameJournal, Box<dyn error::Error>> {
+    let renames = plans.iter()
+        .flat_map(|plan| std::iter::once( &plan.image ).chain( plan.sidecars.iter() ))
+        .map(|rename| RenamedFile { from: absolute( &rename.from ), to: absolute( &rename.to ) })
+        .collect();
+    let journal = RenameJournal { renames };
+    if options.dry_run || journal.renames.is_empty() {
+        return Ok(journal);
+    }
+    if let Some(journal_path) = journal_path {
+        fs::write( journal_path, serde_json::to_string_pretty( &journal )? )?;
+    }
+    move_files( &journal.renames )?;
+    Ok(journal)
+}
+
+// Puts back the files renamed according to a journal, returning the renames made. Files which
+// aren't where the journal says, for example because renaming stopped part way, are skipped.
+pub fn undo_renames( journal_path: &Path, options: &WriteOptions ) -> Result<Vec<RenamedFile>, Box<dyn error::Error>> {
+    let journal : RenameJournal = serde_json::from_slice( &fs::read( journal_path )? )?;
+    let undone : Vec<RenamedFile> = journal.renames.iter().rev()
+        .filter(|rename| rename.to.is_file())
+        .map(|rename| RenamedFile { from: rename.to.clone(), to: rename.from.clone() })
+        .collect();
+    if !options.dry_run {
+        move_files( &undone )?;
+    }
+    Ok(undone)
+}
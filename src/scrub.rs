@@ -0,0 +1,522 @@
+use std::convert::TryFrom;
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::format::{self, MediaFormat, UnsupportedFormatError};
+use crate::tiff::{self, ByteOrder, IfdEntry};
+use crate::writer::{build_tiff, current_value, replace_file_checked, write_error, IfdLocation, ImageMetadataPatch, WriteOptions};
+use crate::{frames, jpeg, png, webp, read_metadata_of_interest, Orientation, VendorDetails};
+
+const TAG_ORIENTATION : u16 = 0x0112;
+const TAG_COPYRIGHT : u16 = 0x8298;
+const TAG_EXIF_IFD : u16 = 0x8769;
+const TAG_GPS_IFD : u16 = 0x8825;
+const TAG_INTEROP_IFD : u16 = 0xA005;
+const TAG_SUB_IFDS : u16 = 0x014A;
+
+// Tags whose values are the offsets of further IFDs, which are zeroed along with them.
+const IFD_POINTER_TAGS : [u16; 4] = [TAG_EXIF_IFD, TAG_GPS_IFD, TAG_INTEROP_IFD, TAG_SUB_IFDS];
+
+// Tags which describe how the image data of a TIFF is laid out, and so are kept by every policy.
+const STRUCTURAL_TAGS : [u16; 50] = [
+    0x00FE, 0x00FF, 0x0100, 0x0101, 0x0102, 0x0103, 0x0106, 0x0107, 0x0108, 0x0109,
+    0x010A, 0x0111, 0x0115, 0x0116, 0x0117, 0x0118, 0x0119, 0x011A, 0x011B, 0x011C,
+    0x0122, 0x0123, 0x0124, 0x0125, 0x0128, 0x012D, 0x013D, 0x013E, 0x013F, 0x0140,
+    0x0141, 0x0142, 0x0143, 0x0144, 0x0145, 0x0146, 0x0147, 0x0152, 0x0153, 0x0154,
+    0x0155, 0x0156, 0x015B, 0x0201, 0x0202, 0x0211, 0x0212, 0x0213, 0x0214, 0x8773,
+];
+
+// Tags of IFD0 which hold a location, a serial number or another copy of them.
+const PRIVATE_TIFF_TAGS : [(u16, &str); 5] = [
+    (TAG_GPS_IFD, "gps"),
+    (0xC62F, "camera_serial"),
+    (0x02BC, "xmp"),
+    (0x83BB, "iptc"),
+    (0x8649, "photoshop"),
+];
+
+// Tags of the Exif IFD which hold a serial number or the owner's name. MakerNotes hold both
+// on most bodies, and can't be edited field by field.
+const PRIVATE_EXIF_TAGS : [(u16, &str); 4] = [
+    (0xA430, "camera_owner"),
+    (0xA431, "camera_serial"),
+    (0xA435, "lens_serial"),
+    (0x927C, "maker_note"),
+];
+
+// Names for the other tags which are commonly removed.
+const TAG_NAMES : [(u16, &str); 8] = [
+    (TAG_EXIF_IFD, "exif"),
+    (TAG_ORIENTATION, "orientation"),
+    (TAG_COPYRIGHT, "copyright"),
+    (0x010E, "description"),
+    (0x010F, "camera_make"),
+    (0x0110, "camera_model"),
+    (0x0131, "software"),
+    (0x0132, "date_time"),
+];
+
+// Which metadata a scrub removes.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ScrubPolicy {
+    // Every metadata field, keeping only what is needed to display the image.
+    RemoveAll,
+    // Every metadata field apart from the orientation and the copyright notice.
+    KeepOrientationAndCopyright,
+    // The GPS location, serial numbers and owner name, and the MakerNote and XMP/IPTC
+    // packets which may repeat them. Everything else is kept.
+    RemoveGpsAndSerials,
+}
+
+impl ScrubPolicy {
+    pub fn from_name( name: &str ) -> Option<ScrubPolicy> {
+        match name {
+            "all" => Some(ScrubPolicy::RemoveAll),
+            "keep-orientation-copyright" => Some(ScrubPolicy::KeepOrientationAndCopyright),
+            "gps-serials" => Some(ScrubPolicy::RemoveGpsAndSerials),
+            _ => None,
+        }
+    }
+
+    // Whether a tag of IFD0 (or of a later IFD of a TIFF file) is removed.
+    fn removes_tiff_tag( self, tag: u16 ) -> bool {
+        match self {
+            ScrubPolicy::RemoveAll => !STRUCTURAL_TAGS.contains( &tag ),
+            ScrubPolicy::KeepOrientationAndCopyright => !STRUCTURAL_TAGS.contains( &tag ) && tag != TAG_ORIENTATION && tag != TAG_COPYRIGHT,
+            ScrubPolicy::RemoveGpsAndSerials => PRIVATE_TIFF_TAGS.iter().any(|(private_tag, _)| *private_tag == tag),
+        }
+    }
+}
+
+// Raised when a scrubbed file still has some of the fields which were meant to be removed.
+// The original file is left as it was.
+#[derive(Debug, Clone)]
+pub struct ScrubVerificationError {
+    path: PathBuf,
+    remaining: Vec<&'static str>,
+}
+
+impl fmt::Display for ScrubVerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Scrubbed copy of {} still has: {}", self.path.to_string_lossy(), self.remaining.join( ", " ))
+    }
+}
+
+impl Error for ScrubVerificationError {
+    fn description(&self) -> &str {
+        "Metadata was still found in a file after scrubbing it"
+    }
+}
+
+fn tag_name( tag: u16 ) -> String {
+    PRIVATE_TIFF_TAGS.iter().chain( PRIVATE_EXIF_TAGS.iter() ).chain( TAG_NAMES.iter() )
+        .find(|(known_tag, _)| *known_tag == tag)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("tag 0x{:04X}", tag))
+}
+
+// Removes entries from the IFDs of a TIFF structure in place. Removed entries and everything
+// they point at are overwritten with zeroes, so that no trace of them is left in the file,
+// and nothing which is kept moves.
+struct TiffScrubber<'a> {
+    data: &'a mut [u8],
+    order: ByteOrder,
+    removed: Vec<String>,
+}
+
+impl<'a> TiffScrubber<'a> {
+    fn zero( &mut self, start: usize, length: usize ) {
+        let end = start.saturating_add( length ).min( self.data.len() );
+        if start < end {
+            self.data[start..end].iter_mut().for_each(|byte| *byte = 0);
+        }
+    }
+
+    fn zero_entry_value( &mut self, entry: &IfdEntry, depth: usize ) {
+        if IFD_POINTER_TAGS.contains( &entry.tag ) && depth < 4 {
+            let pointers : Vec<usize> = entry.value_bytes( self.data, self.order )
+                .map(|value| value.chunks_exact( 4 ).filter_map(|pointer| self.order.u32( pointer, 0 )).map(|pointer| pointer as usize).collect())
+                .unwrap_or_default();
+            for pointer in pointers {
+                self.zero_ifd( pointer, depth + 1 );
+            }
+        }
+        if entry.byte_length() > 4 {
+            if let Some(offset) = entry.value_offset( self.data, self.order ) {
+                self.zero( offset, entry.byte_length() );
+            }
+        }
+    }
+
+    fn zero_ifd( &mut self, offset: usize, depth: usize ) {
+        if offset < 8 {
+            return;
+        }
+        if let Some((entries, _)) = tiff::read_ifd( self.data, offset, self.order ) {
+            for entry in entries.iter() {
+                self.zero_entry_value( entry, depth );
+            }
+            self.zero( offset, 2 + entries.len() * 12 + 4 );
+        }
+    }
+
+    // Rewrites the IFD at `offset` without the entries for which `remove` is true, and returns
+    // the entries which were kept, at their new positions.
+    fn remove_entries( &mut self, offset: usize, remove: &dyn Fn( u16 ) -> bool ) -> Option<Vec<IfdEntry>> {
+        let (entries, next) = tiff::read_ifd( self.data, offset, self.order )?;
+        let (removed, kept) : (Vec<IfdEntry>, Vec<IfdEntry>) = entries.iter().cloned().partition(|entry| remove( entry.tag ));
+        if removed.is_empty() {
+            return Some(entries);
+        }
+        let kept_bytes : Vec<Vec<u8>> = kept.iter().map(|entry| self.data[entry.entry_offset..entry.entry_offset + 12].to_vec()).collect();
+        for entry in removed.iter() {
+            self.removed.push( tag_name( entry.tag ) );
+            self.zero_entry_value( entry, 0 );
+        }
+
+        self.zero( offset, 2 + entries.len() * 12 + 4 );
+        let count = self.order.u16_bytes( kept.len() as u16 );
+        self.data[offset..offset + 2].copy_from_slice( &count );
+        for (index, entry_bytes) in kept_bytes.iter().enumerate() {
+            let entry_offset = offset + 2 + index * 12;
+            self.data[entry_offset..entry_offset + 12].copy_from_slice( entry_bytes );
+        }
+        let next_offset = offset + 2 + kept.len() * 12;
+        let next = self.order.u32_bytes( next );
+        self.data[next_offset..next_offset + 4].copy_from_slice( &next );
+        tiff::read_ifd( self.data, offset, self.order ).map(|(entries, _)| entries)
+    }
+
+    // Applies the policy to every IFD in the chain starting at IFD0.
+    fn scrub( &mut self, policy: ScrubPolicy ) -> Option<()> {
+        let mut offset = self.order.u32( self.data, 4 )? as usize;
+        let mut visited = vec![];
+        while offset != 0 && !visited.contains( &offset ) {
+            visited.push( offset );
+            let kept = self.remove_entries( offset, &|tag| policy.removes_tiff_tag( tag ) )?;
+            if policy == ScrubPolicy::RemoveGpsAndSerials {
+                if let Some(exif_pointer) = kept.iter().find(|entry| entry.tag == TAG_EXIF_IFD) {
+                    let exif_offset = self.order.u32( self.data, exif_pointer.entry_offset + 8 )? as usize;
+                    self.remove_entries( exif_offset, &|tag| PRIVATE_EXIF_TAGS.iter().any(|(private_tag, _)| *private_tag == tag) )?;
+                }
+            }
+            let (_, next) = tiff::read_ifd( self.data, offset, self.order )?;
+            offset = next as usize;
+        }
+        Some(())
+    }
+}
+
+// Scrubs a TIFF structure in place, returning the names of what was removed.
+fn scrub_tiff( data: &mut [u8], policy: ScrubPolicy ) -> Result<Vec<String>, Box<dyn error::Error>> {
+    let order = ByteOrder::from_tiff_header( data ).ok_or_else(|| write_error( "TIFF header is invalid" ))?;
+    let mut scrubber = TiffScrubber { data, order, removed: vec![] };
+    scrubber.scrub( policy ).ok_or_else(|| write_error( "an IFD is outside the TIFF data" ))?;
+    Ok(scrubber.removed)
+}
+
+// Scrubs the EXIF data embedded in a JPEG, PNG or WebP file. None means the whole of it is
+// removed; otherwise the returned data replaces it. The "Exif\0\0" prefix is kept if present.
+fn scrub_exif_blob( blob: &[u8], policy: ScrubPolicy, removed: &mut Vec<String> ) -> Result<Option<Vec<u8>>, Box<dyn error::Error>> {
+    let prefix_length = if blob.starts_with( jpeg::EXIF_IDENTIFIER ) { jpeg::EXIF_IDENTIFIER.len() } else { 0 };
+    let (prefix, tiff_data) = blob.split_at( prefix_length );
+    let scrubbed = match policy {
+        ScrubPolicy::RemoveAll => {
+            removed.push( "exif".to_string() );
+            return Ok(None);
+        },
+        ScrubPolicy::KeepOrientationAndCopyright => {
+            // Rebuilt from scratch, which also drops the thumbnail.
+            let exif_fields = exif::Reader::new().read_raw( tiff_data.to_vec() ).ok();
+            let patch = ImageMetadataPatch {
+                orientation: current_value( exif_fields.as_ref(), IfdLocation::Primary, TAG_ORIENTATION )
+                    .and_then(|value| value.parse::<u16>().ok())
+                    .and_then(|value| Orientation::try_from( value ).ok()),
+                copyright: current_value( exif_fields.as_ref(), IfdLocation::Primary, TAG_COPYRIGHT ),
+                ..Default::default()
+            };
+            removed.push( "exif".to_string() );
+            if patch.is_empty() {
+                return Ok(None);
+            }
+            build_tiff( &patch )?
+        },
+        ScrubPolicy::RemoveGpsAndSerials => {
+            let mut tiff_data = tiff_data.to_vec();
+            removed.extend( scrub_tiff( &mut tiff_data, policy )? );
+            tiff_data
+        },
+    };
+    let mut output = prefix.to_vec();
+    output.extend( scrubbed );
+    Ok( Some(output) )
+}
+
+const XMP_IDENTIFIER : &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const EXTENDED_XMP_IDENTIFIER : &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+const MARKER_APP13 : u8 = 0xED;
+const MARKER_APP14 : u8 = 0xEE;
+const MARKER_COM : u8 = 0xFE;
+
+fn scrub_jpeg_image( data: &[u8], policy: ScrubPolicy, removed: &mut Vec<String> ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let segments = jpeg::header_segments( data ).ok_or_else(|| write_error( "JPEG header segments could not be parsed" ))?;
+    let mut output = data[..2].to_vec();
+    for segment in segments.iter() {
+        let whole = &data[segment.position..segment.payload.end];
+        let payload = &data[segment.payload.clone()];
+        let is_app = (jpeg::MARKER_APP0..=0xEF).contains( &segment.marker );
+        if segment.marker == jpeg::MARKER_APP1 && payload.starts_with( jpeg::EXIF_IDENTIFIER ) {
+            if let Some(scrubbed) = scrub_exif_blob( payload, policy, removed )? {
+                output.extend_from_slice( &[0xFF, jpeg::MARKER_APP1] );
+                output.extend_from_slice( &((scrubbed.len() + 2) as u16).to_be_bytes() );
+                output.extend( scrubbed );
+            }
+        } else if segment.marker == jpeg::MARKER_APP1 && (payload.starts_with( XMP_IDENTIFIER ) || payload.starts_with( EXTENDED_XMP_IDENTIFIER )) {
+            removed.push( "xmp".to_string() );
+        } else if segment.marker == MARKER_APP13 {
+            removed.push( "iptc".to_string() );
+        } else if policy != ScrubPolicy::RemoveGpsAndSerials
+            && (segment.marker == MARKER_COM || (is_app && ![jpeg::MARKER_APP0, jpeg::MARKER_APP2, MARKER_APP14].contains( &segment.marker ))) {
+            // JFIF, ICC profiles, MPF indexes and the Adobe color transform are needed to show the image.
+            removed.push( if segment.marker == MARKER_COM { "comment".to_string() } else { format!("APP{} segment", segment.marker - jpeg::MARKER_APP0) } );
+        } else {
+            output.extend_from_slice( whole );
+        }
+    }
+    let image_data_start = segments.last().map(|segment| segment.payload.end).unwrap_or(2);
+    output.extend_from_slice( &data[image_data_start..] );
+    Ok(output)
+}
+
+const MPF_TAG_MP_ENTRY : u16 = 0xB002;
+const MP_ENTRY_LENGTH : usize = 16;
+
+// Finds the MP Entry table of an MPF index: its byte order, its position within the index and
+// the number of images it lists.
+fn mp_entry_table( mpf: &[u8] ) -> Option<(ByteOrder, usize, usize)> {
+    let order = ByteOrder::from_tiff_header( mpf )?;
+    let (entries, _) = tiff::read_ifd( mpf, order.u32( mpf, 4 )? as usize, order )?;
+    let entry = entries.iter().find(|entry| entry.tag == MPF_TAG_MP_ENTRY)?;
+    Some( (order, entry.value_offset( mpf, order )?, entry.byte_length() / MP_ENTRY_LENGTH) )
+}
+
+// The byte ranges of the images a multi-picture file holds, the primary image first. Offsets
+// in the index are relative to the TIFF header of the primary image's MPF segment.
+fn mpo_images( data: &[u8], mpf_position: usize, mpf: &[u8] ) -> Result<Vec<Range<usize>>, Box<dyn error::Error>> {
+    let (order, table, count) = mp_entry_table( mpf ).ok_or_else(|| write_error( "the multi-picture index could not be parsed" ))?;
+    (0..count).map(|index| {
+        let entry = table + index * MP_ENTRY_LENGTH;
+        let size = order.u32( mpf, entry + 4 ).map(|size| size as usize);
+        let offset = order.u32( mpf, entry + 8 ).map(|offset| offset as usize);
+        let start = match (index, offset) {
+            (0, _) => Some(0),
+            (_, Some(0)) | (_, None) => None,
+            (_, Some(offset)) => mpf_position.checked_add( offset ),
+        };
+        start.zip( size )
+            .and_then(|(start, size)| Some( start..start.checked_add( size )? ))
+            .filter(|range| range.end <= data.len() && !range.is_empty())
+            .ok_or_else(|| write_error( "the multi-picture index lists an image outside the file" ))
+    }).collect()
+}
+
+// Each image of a multi-picture file carries metadata of its own, so each is scrubbed, and the
+// index of the primary image is then pointed at where the images end up.
+fn scrub_jpeg( data: &[u8], policy: ScrubPolicy, removed: &mut Vec<String> ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let images = match jpeg::find_mpf_segment( data ) {
+        Some((mpf_position, mpf)) => mpo_images( data, mpf_position, mpf )?,
+        None => return scrub_jpeg_image( data, policy, removed ),
+    };
+    let mut output = scrub_jpeg_image( &data[images[0].clone()], policy, removed )?;
+    let mut secondary_images = vec![];
+    let mut sizes_and_offsets = vec![(output.len(), 0)];
+    let (mpf_position, (order, table, _)) = jpeg::find_mpf_segment( &output )
+        .and_then(|(mpf_position, mpf)| Some( (mpf_position, mp_entry_table( mpf )?) ))
+        .ok_or_else(|| write_error( "the multi-picture index was lost while scrubbing" ))?;
+    for image in images[1..].iter() {
+        let scrubbed = scrub_jpeg_image( &data[image.clone()], policy, removed )?;
+        sizes_and_offsets.push( (scrubbed.len(), output.len() + secondary_images.len() - mpf_position) );
+        secondary_images.extend( scrubbed );
+    }
+    for (index, (size, offset)) in sizes_and_offsets.into_iter().enumerate() {
+        let entry = mpf_position + table + index * MP_ENTRY_LENGTH;
+        let size = u32::try_from( size ).map_err(|_| write_error( "a scrubbed image is too large for the multi-picture index" ))?;
+        let offset = u32::try_from( offset ).map_err(|_| write_error( "a scrubbed image is too large for the multi-picture index" ))?;
+        output[entry + 4..entry + 8].copy_from_slice( &order.u32_bytes( size ) );
+        output[entry + 8..entry + 12].copy_from_slice( &order.u32_bytes( offset ) );
+    }
+    output.extend( secondary_images );
+    Ok(output)
+}
+
+// Chunks which only matter for how the image is shown are kept; text chunks, which can hold
+// XMP packets or an author's name, are always removed.
+fn scrub_png( data: &[u8], policy: ScrubPolicy, removed: &mut Vec<String> ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let chunks = png::chunks( data ).ok_or_else(|| write_error( "PNG chunks could not be parsed" ))?;
+    let mut output = png::SIGNATURE.to_vec();
+    for chunk in chunks.iter() {
+        match &chunk.kind {
+            b"eXIf" => {
+                if let Some(scrubbed) = scrub_exif_blob( &data[chunk.payload.clone()], policy, removed )? {
+                    output.extend( png::build_chunk( b"eXIf", &scrubbed ) );
+                }
+            },
+            b"tEXt" | b"zTXt" | b"iTXt" => removed.push( format!("{} chunk", String::from_utf8_lossy( &chunk.kind )) ),
+            b"tIME" if policy != ScrubPolicy::RemoveGpsAndSerials => removed.push( "tIME chunk".to_string() ),
+            _ => output.extend_from_slice( &data[chunk.position..chunk.end()] ),
+        }
+    }
+    Ok(output)
+}
+
+fn scrub_webp( data: &[u8], policy: ScrubPolicy, removed: &mut Vec<String> ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let chunks = webp::chunks( data ).ok_or_else(|| write_error( "WebP chunks could not be parsed" ))?;
+    let mut body = vec![];
+    let mut flags_position = None;
+    let mut has_exif = false;
+    for chunk in chunks.iter() {
+        match &chunk.fourcc {
+            b"EXIF" => {
+                if let Some(scrubbed) = scrub_exif_blob( &data[chunk.payload.clone()], policy, removed )? {
+                    body.extend( webp::build_chunk( b"EXIF", &scrubbed ) );
+                    has_exif = true;
+                }
+            },
+            b"XMP " => removed.push( "xmp".to_string() ),
+            fourcc => {
+                if fourcc == b"VP8X" && !chunk.payload.is_empty() {
+                    flags_position = Some( body.len() + 8 );
+                }
+                body.extend_from_slice( &data[chunk.position..chunk.end()] );
+            },
+        }
+    }
+    // The extended header says which of the optional chunks are present.
+    if let Some(position) = flags_position {
+        body[position] &= !webp::FLAG_XMP;
+        if !has_exif {
+            body[position] &= !webp::FLAG_EXIF;
+        }
+    }
+    Ok( webp::build_file( &body ) )
+}
+
+// The fields the policy targets which are left in an image's EXIF data.
+fn remaining_in_exif( exif_fields: &exif::Exif, policy: ScrubPolicy ) -> Vec<&'static str> {
+    let has_tag = |tag: exif::Tag| exif_fields.fields().any(|field| field.tag == tag);
+    let mut remaining = vec![];
+    if exif_fields.fields().any(|field| field.tag.context() == exif::Context::Gps) {
+        remaining.push( "gps" );
+    }
+    if has_tag( exif::Tag::BodySerialNumber ) {
+        remaining.push( "camera_serial" );
+    }
+    if has_tag( exif::Tag::CameraOwnerName ) {
+        remaining.push( "camera_owner" );
+    }
+    if has_tag( exif::Tag::LensSerialNumber ) {
+        remaining.push( "lens_serial" );
+    }
+    if has_tag( exif::Tag::MakerNote ) {
+        remaining.push( "maker_note" );
+    }
+    if policy != ScrubPolicy::RemoveGpsAndSerials {
+        if has_tag( exif::Tag::DateTimeOriginal ) || has_tag( exif::Tag::DateTime ) {
+            remaining.push( "capture_time" );
+        }
+        if has_tag( exif::Tag::Model ) {
+            remaining.push( "camera_model" );
+        }
+        if exif_fields.fields().any(|field| field.ifd_num == exif::In::THUMBNAIL) {
+            remaining.push( "thumbnail" );
+        }
+    }
+    if policy == ScrubPolicy::RemoveAll && has_tag( exif::Tag::Orientation ) {
+        remaining.push( "orientation" );
+    }
+    remaining
+}
+
+// Checks, by reading the scrubbed file back, that none of the fields the policy targets remain
+// in any of the images it holds.
+fn verify_scrubbed( path: &Path, policy: ScrubPolicy ) -> Result<(), Box<dyn error::Error>> {
+    let metadata = read_metadata_of_interest( path )?;
+    let data = fs::read( path )?;
+    let mut remaining = frames::read_exif_from_bytes( &data )?
+        .map(|exif_fields| remaining_in_exif( &exif_fields, policy ))
+        .unwrap_or_default();
+    if metadata.image_metadata.camera_serial.is_some() {
+        remaining.push( "camera_serial" );
+    }
+    if let Some(vendor) = &metadata.vendor_metadata {
+        if vendor.internal_serial.is_some() {
+            remaining.push( "internal_serial" );
+        }
+        if let Some(VendorDetails::Canon(canon)) = &vendor.details {
+            if canon.owner_name.is_some() {
+                remaining.push( "owner_name" );
+            }
+        }
+    }
+    if policy != ScrubPolicy::RemoveGpsAndSerials {
+        if metadata.image_metadata.capture_time.is_some() {
+            remaining.push( "capture_time" );
+        }
+        if metadata.image_metadata.camera_model.is_some() {
+            remaining.push( "camera_model" );
+        }
+        if metadata.thumbnail.is_some() {
+            remaining.push( "thumbnail" );
+        }
+    }
+    if policy == ScrubPolicy::RemoveAll && metadata.image_metadata.orientation.is_some() {
+        remaining.push( "orientation" );
+    }
+    if let Some((mpf_position, mpf)) = jpeg::find_mpf_segment( &data ) {
+        for image in mpo_images( &data, mpf_position, mpf )?.into_iter().skip( 1 ) {
+            if let Some(exif_fields) = frames::read_exif_from_bytes( &data[image] )? {
+                remaining.extend( remaining_in_exif( &exif_fields, policy ) );
+            }
+        }
+    }
+
+    let mut seen = vec![];
+    remaining.retain(|name| if seen.contains( name ) { false } else { seen.push( name ); true });
+    if remaining.is_empty() {
+        Ok(())
+    } else {
+        Err( Box::new( ScrubVerificationError { path: path.to_path_buf(), remaining } ) )
+    }
+}
+
+// Removes the metadata the policy targets from a JPEG, PNG, WebP or TIFF file, without
+// re-encoding the image data, and returns the names of what was removed. The scrubbed file
+// is read back before it replaces the original, and is rejected if any targeted field remains.
+// With dry_run set, nothing is written.
+pub fn scrub_metadata( path: &Path, policy: ScrubPolicy, options: &WriteOptions ) -> Result<Vec<String>, Box<dyn error::Error>> {
+    let format = format::detect_format( path )?;
+    let data = fs::read( path )?;
+    let mut removed = vec![];
+    let output = match format {
+        MediaFormat::Jpeg => scrub_jpeg( &data, policy, &mut removed )?,
+        MediaFormat::Png => scrub_png( &data, policy, &mut removed )?,
+        MediaFormat::Webp => scrub_webp( &data, policy, &mut removed )?,
+        MediaFormat::Tiff => {
+            let mut output = data.clone();
+            removed = scrub_tiff( &mut output, policy )?;
+            output
+        },
+        _ => return Err(Box::new(UnsupportedFormatError{ format })),
+    };
+    let mut seen = vec![];
+    removed.retain(|name| if seen.contains( name ) { false } else { seen.push( name.clone() ); true });
+
+    if !removed.is_empty() && !options.dry_run {
+        replace_file_checked( path, &output, &|scrubbed_path| verify_scrubbed( scrubbed_path, policy ) )?;
+    }
+    Ok(removed)
+}
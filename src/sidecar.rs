@@ -0,0 +1,177 @@
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::redact::is_redacted_json;
+use crate::writer::{write_image_metadata, FieldChange, ImageMetadataPatch, WriteOptions};
+use crate::{json_sidecar_path, read_json_metadata, read_metadata_of_interest_with_options, MetadataOfInterest, ReadOptions};
+
+const READ_ONLY : &str = "read-only";
+const REMOVAL_NOT_SUPPORTED : &str = "removing a field is not supported";
+const WRITTEN_BY_GEOTAG : &str = "written by the geotag command";
+
+// A field which differs between the sidecar and the image, but which can't be written back.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct NotApplicableField {
+    pub field: &'static str,
+    pub reason: &'static str,
+}
+
+// What importing a sidecar did to one image.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct ImportReport {
+    pub sidecar_path: PathBuf,
+    pub changes: Vec<FieldChange>,
+    pub not_applicable: Vec<NotApplicableField>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MissingSidecarError {
+    path: PathBuf,
+}
+
+impl fmt::Display for MissingSidecarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No JSON sidecar found at: {}", self.path.to_string_lossy())
+    }
+}
+
+impl Error for MissingSidecarError {
+    fn description(&self) -> &str {
+        "The JSON sidecar of an image was not found"
+    }
+}
+
+// Raised for sidecars written with a redaction, whose pseudonyms must not be written into the image,
+// nor rewritten as if they were real values.
+#[derive(Debug, Clone)]
+pub struct RedactedSidecarError {
+    pub(crate) path: PathBuf,
+}
+
+impl fmt::Display for RedactedSidecarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "JSON sidecar was written with redaction, so can't be imported or updated: {}", self.path.to_string_lossy())
+    }
+}
+
+impl Error for RedactedSidecarError {
+    fn description(&self) -> &str {
+        "A redacted JSON sidecar can't be imported into an image"
+    }
+}
+
+// The metadata to update the JSON sidecar of a file from, with the path of the sidecar if it
+// exists: the existing sidecar, so that edits made in it are kept, or else the current metadata
+// of the file. Redacted sidecars can't be updated.
+pub(crate) fn sidecar_base( path: &Path, current: &MetadataOfInterest ) -> Result<(MetadataOfInterest, Option<PathBuf>), Box<dyn error::Error>> {
+    let sidecar_path = json_sidecar_path( path ).filter(|sidecar_path| sidecar_path.is_file());
+    let metadata = match &sidecar_path {
+        Some(sidecar_path) => {
+            if is_redacted_json( &fs::read( sidecar_path )? ) {
+                return Err( Box::new( RedactedSidecarError { path: sidecar_path.clone() } ) );
+            }
+            read_json_metadata( &sidecar_path.to_string_lossy() )?
+        },
+        None => current.clone(),
+    };
+    Ok( (metadata, sidecar_path) )
+}
+
+// Works out the patch for the writable fields, and lists the other fields which were edited.
+fn diff_metadata( edited: &MetadataOfInterest, current: &MetadataOfInterest ) -> (ImageMetadataPatch, Vec<NotApplicableField>) {
+    let mut patch = ImageMetadataPatch::default();
+    let mut not_applicable = vec![];
+    let mut not_applied = |field: &'static str, reason: &'static str| not_applicable.push( NotApplicableField { field, reason } );
+
+    let edited_image = &edited.image_metadata;
+    let current_image = &current.image_metadata;
+    if edited_image.orientation != current_image.orientation {
+        match edited_image.orientation {
+            Some(orientation) => patch.orientation = Some(orientation),
+            None => not_applied( "orientation", REMOVAL_NOT_SUPPORTED ),
+        }
+    }
+    if edited_image.capture_time != current_image.capture_time {
+        match edited_image.capture_time {
+            Some(capture_time) => patch.capture_time = Some(capture_time),
+            None => not_applied( "capture_time", REMOVAL_NOT_SUPPORTED ),
+        }
+    }
+    if edited_image.camera_model != current_image.camera_model {
+        match &edited_image.camera_model {
+            Some(camera_model) => patch.camera_model = Some(camera_model.clone()),
+            None => not_applied( "camera_model", REMOVAL_NOT_SUPPORTED ),
+        }
+    }
+    if edited_image.camera_serial != current_image.camera_serial {
+        match &edited_image.camera_serial {
+            Some(camera_serial) => patch.camera_serial = Some(camera_serial.clone()),
+            None => not_applied( "camera_serial", REMOVAL_NOT_SUPPORTED ),
+        }
+    }
+
+    if edited_image.location != current_image.location {
+        not_applied( "location", WRITTEN_BY_GEOTAG );
+    }
+
+    let edited_file = &edited.file_metadata;
+    let current_file = &current.file_metadata;
+    if edited_file.filename != current_file.filename {
+        not_applied( "filename", READ_ONLY );
+    }
+    if edited_file.size != current_file.size {
+        not_applied( "size", READ_ONLY );
+    }
+    if edited_file.mime_type != current_file.mime_type {
+        not_applied( "mime_type", READ_ONLY );
+    }
+    if edited_file.created_time != current_file.created_time {
+        not_applied( "created_time", READ_ONLY );
+    }
+    if edited_file.modified_time != current_file.modified_time {
+        not_applied( "modified_time", READ_ONLY );
+    }
+    if edited.video_metadata != current.video_metadata {
+        not_applied( "video", READ_ONLY );
+    }
+    if edited.thumbnail != current.thumbnail {
+        not_applied( "thumbnail", READ_ONLY );
+    }
+    if edited.color != current.color {
+        not_applied( "color", READ_ONLY );
+    }
+    if edited.vendor_metadata != current.vendor_metadata {
+        not_applied( "vendor", READ_ONLY );
+    }
+    // Sidecars only list frames when they were requested, so a missing list is not an edit.
+    if edited.frames.is_some() && edited.frames != current.frames {
+        not_applied( "frames", READ_ONLY );
+    }
+
+    (patch, not_applicable)
+}
+
+// Reads the JSON sidecar of an image, as written by run, and writes the fields which were
+// edited in it back into the image. Fields which can't be written are reported instead.
+pub fn import_json_sidecar( image_path: &Path, options: &WriteOptions ) -> Result<ImportReport, Box<dyn error::Error>> {
+    let sidecar_path = json_sidecar_path( image_path ).filter(|path| path.is_file())
+        .ok_or_else(|| Box::new( MissingSidecarError { path: image_path.with_extension( "json" ) } ))?;
+    if is_redacted_json( &fs::read( &sidecar_path )? ) {
+        return Err( Box::new( RedactedSidecarError { path: sidecar_path } ) );
+    }
+    let edited = read_json_metadata( &sidecar_path.to_string_lossy() )?;
+    // Frames are listed when the sidecar lists them, so that they can be compared.
+    let read_options = ReadOptions { include_frames: edited.frames.is_some(), ..Default::default() };
+    let current = read_metadata_of_interest_with_options( image_path, &read_options )?;
+    let (patch, not_applicable) = diff_metadata( &edited, &current );
+
+    let changes = if patch.is_empty() {
+        vec![]
+    } else {
+        write_image_metadata( image_path, &patch, options )?
+    };
+    Ok( ImportReport { sidecar_path, changes, not_applicable } )
+}
@@ -0,0 +1,64 @@
+use serde::{Serialize, Deserialize};
+
+use crate::makernote::{self, MakerNote, VendorDetails, VendorMetadataOfInterest};
+
+const TAG_9050 : u16 = 0x9050;
+const TAG_LENS_TYPE : u16 = 0xB027;
+
+// Offsets in the deciphered Tag9050 record, as written by SLT, ILCA and ILCE bodies.
+const TAG_9050_SHUTTER_COUNT : usize = 0x003A;
+const TAG_9050_INTERNAL_SERIAL_NUMBER : usize = 0x0088;
+const INTERNAL_SERIAL_NUMBER_LENGTH : usize = 6;
+// Only the low three bytes of the shutter count field hold the count.
+const SHUTTER_COUNT_MASK : u32 = 0x00FF_FFFF;
+
+// Sony enciphers some records by replacing each byte b below 249 with b^3 mod 249.
+// Raising to the 55th power undoes it, since 3 * 55 = 1 modulo 82, the Carmichael number of 249.
+const CIPHER_MODULUS : u32 = 249;
+const DECIPHER_EXPONENT : u32 = 55;
+
+// Sony-specific fields, beyond those every vendor reports.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct SonyMakerNote {
+    // Sony's LensType number for A-mount lenses (and adapted lenses on E-mount bodies).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lens_id: Option<u32>,
+}
+
+fn decipher_byte( byte: u8 ) -> u8 {
+    if byte as u32 >= CIPHER_MODULUS {
+        return byte;
+    }
+    let mut result = 1u32;
+    for _ in 0..DECIPHER_EXPONENT {
+        result = result * byte as u32 % CIPHER_MODULUS;
+    }
+    result as u8
+}
+
+pub(crate) fn decode_maker_note( maker_note: &MakerNote, exif_fields: &exif::Exif ) -> VendorMetadataOfInterest {
+    let record : Option<Vec<u8>> = maker_note.entry( TAG_9050 )
+        .and_then(|entry| entry.value_bytes( maker_note.data, maker_note.order ))
+        .map(|bytes| bytes.iter().map(|byte| decipher_byte( *byte )).collect());
+    let shutter_count = record.as_ref()
+        .and_then(|record| maker_note.order.u32( record, TAG_9050_SHUTTER_COUNT ))
+        .map(|count| count & SHUTTER_COUNT_MASK)
+        .filter(|count| *count > 0);
+    let internal_serial = record.as_ref()
+        .and_then(|record| record.get(TAG_9050_INTERNAL_SERIAL_NUMBER..TAG_9050_INTERNAL_SERIAL_NUMBER + INTERNAL_SERIAL_NUMBER_LENGTH))
+        .map(|serial| serial.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+    let lens_id = maker_note.uints( TAG_LENS_TYPE )
+        .and_then(|values| values.first().cloned())
+        .filter(|lens_type| *lens_type != 0xFFFF);
+
+    VendorMetadataOfInterest {
+        make: "sony".to_string(),
+        shutter_count,
+        lens: None,
+        internal_serial,
+        // Sony bodies write their firmware version ("ILCE-7M3 v3.01") to the standard Software tag.
+        firmware_version: makernote::exif_ascii( exif_fields, exif::Tag::Software ),
+        details: Some( VendorDetails::Sony( SonyMakerNote { lens_id } ) ),
+    }
+}
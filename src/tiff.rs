@@ -0,0 +1,107 @@
+use std::convert::TryInto;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub(crate) enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    // Reads the byte order from a TIFF header ("II*\0" or "MM\0*").
+    pub fn from_tiff_header( data: &[u8] ) -> Option<ByteOrder> {
+        if data.starts_with( b"II*\0" ) {
+            Some(ByteOrder::LittleEndian)
+        } else if data.starts_with( b"MM\0*" ) {
+            Some(ByteOrder::BigEndian)
+        } else {
+            None
+        }
+    }
+
+    pub fn u16( self, data: &[u8], offset: usize ) -> Option<u16> {
+        let bytes : [u8; 2] = data.get(offset..offset.checked_add(2)?)?.try_into().ok()?;
+        Some( match self {
+            ByteOrder::LittleEndian => u16::from_le_bytes( bytes ),
+            ByteOrder::BigEndian => u16::from_be_bytes( bytes ),
+        } )
+    }
+
+    pub fn u32( self, data: &[u8], offset: usize ) -> Option<u32> {
+        let bytes : [u8; 4] = data.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+        Some( match self {
+            ByteOrder::LittleEndian => u32::from_le_bytes( bytes ),
+            ByteOrder::BigEndian => u32::from_be_bytes( bytes ),
+        } )
+    }
+
+    pub fn u16_bytes( self, value: u16 ) -> [u8; 2] {
+        match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        }
+    }
+
+    pub fn u32_bytes( self, value: u32 ) -> [u8; 4] {
+        match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        }
+    }
+}
+
+// Size in bytes of one value of each TIFF field type; unknown types are treated as bytes.
+pub(crate) fn field_type_size( field_type: u16 ) -> usize {
+    match field_type {
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    }
+}
+
+// One 12-byte entry of an IFD. `entry_offset` is where the entry itself starts, relative to the
+// start of the TIFF header, so the value/offset field is at `entry_offset + 8`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub(crate) struct IfdEntry {
+    pub tag: u16,
+    pub field_type: u16,
+    pub count: u32,
+    pub entry_offset: usize,
+}
+
+impl IfdEntry {
+    pub fn byte_length( &self ) -> usize {
+        field_type_size( self.field_type ).saturating_mul( self.count as usize )
+    }
+
+    // Offset of the value bytes, whether they are stored inline in the entry or elsewhere.
+    pub fn value_offset( &self, data: &[u8], order: ByteOrder ) -> Option<usize> {
+        if self.byte_length() <= 4 {
+            Some( self.entry_offset + 8 )
+        } else {
+            order.u32( data, self.entry_offset + 8 ).map(|offset| offset as usize)
+        }
+    }
+
+    pub fn value_bytes<'a>( &self, data: &'a [u8], order: ByteOrder ) -> Option<&'a [u8]> {
+        let offset = self.value_offset( data, order )?;
+        data.get(offset..offset.checked_add(self.byte_length())?)
+    }
+}
+
+// Parses the IFD at `offset`, returning its entries and the offset of the next IFD (0 if none).
+pub(crate) fn read_ifd( data: &[u8], offset: usize, order: ByteOrder ) -> Option<(Vec<IfdEntry>, u32)> {
+    let count = order.u16( data, offset )? as usize;
+    let mut entries = Vec::with_capacity( count );
+    for index in 0..count {
+        let entry_offset = offset + 2 + index * 12;
+        entries.push( IfdEntry {
+            tag: order.u16( data, entry_offset )?,
+            field_type: order.u16( data, entry_offset + 2 )?,
+            count: order.u32( data, entry_offset + 4 )?,
+            entry_offset,
+        } );
+    }
+    let next = order.u32( data, offset + 2 + count * 12 )?;
+    Some( (entries, next) )
+}
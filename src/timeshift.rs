@@ -0,0 +1,135 @@
+use std::error;
+use std::path::Path;
+
+use crate::sidecar::sidecar_base;
+use crate::writer::{ascii_field, current_value, write_fields, FieldChange, FieldWrite, IfdLocation, WriteOptions};
+use crate::{read_metadata_of_interest, read_metadata_of_interest_with_options, write_json_metadata_with_redaction, MetadataOfInterest, ReadOptions, Redaction, CAPTURE_TIME_FORMAT};
+
+const TAG_DATE_TIME : u16 = 0x0132;
+const TAG_DATE_TIME_ORIGINAL : u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED : u16 = 0x9004;
+const TAG_OFFSET_TIME : u16 = 0x9010;
+const TAG_OFFSET_TIME_ORIGINAL : u16 = 0x9011;
+const TAG_OFFSET_TIME_DIGITIZED : u16 = 0x9012;
+
+// Each date/time field, with the OffsetTime field which holds its time zone.
+const SHIFTED_FIELDS : [(&str, IfdLocation, u16, &str, u16); 3] = [
+    ("capture_time", IfdLocation::Exif, TAG_DATE_TIME_ORIGINAL, "offset_time_original", TAG_OFFSET_TIME_ORIGINAL),
+    ("digitized_time", IfdLocation::Exif, TAG_DATE_TIME_DIGITIZED, "offset_time_digitized", TAG_OFFSET_TIME_DIGITIZED),
+    ("date_time", IfdLocation::Primary, TAG_DATE_TIME, "offset_time", TAG_OFFSET_TIME),
+];
+
+// A correction for a camera whose clock was wrong, applied to the files it took.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct TimeShift {
+    // Added to DateTimeOriginal, DateTimeDigitized and DateTime.
+    pub offset: chrono::Duration,
+    // Only files whose camera_serial matches are shifted.
+    pub camera_serial: Option<String>,
+    // Only files whose camera_model matches are shifted.
+    pub camera_model: Option<String>,
+    // Written to the OffsetTime fields of the shifted times, formatted as "+HH:MM".
+    pub offset_time: Option<String>,
+}
+
+impl TimeShift {
+    pub fn new( offset: chrono::Duration ) -> TimeShift {
+        TimeShift { offset, camera_serial: None, camera_model: None, offset_time: None }
+    }
+
+    // Whether the camera filters match the metadata read from a file.
+    pub fn applies_to( &self, metadata: &MetadataOfInterest ) -> bool {
+        let image_metadata = &metadata.image_metadata;
+        let matches = |filter: &Option<String>, value: &Option<String>| match filter {
+            Some(filter) => value.as_deref().map(|value| value.trim()) == Some(filter.trim()),
+            None => true,
+        };
+        matches( &self.camera_serial, &image_metadata.camera_serial ) && matches( &self.camera_model, &image_metadata.camera_model )
+    }
+
+    fn field_writes( &self, exif_fields: Option<&exif::Exif>, path: &Path ) -> Vec<FieldWrite> {
+        let mut writes = vec![];
+        for &(field, location, tag, offset_field, offset_tag) in SHIFTED_FIELDS.iter() {
+            let old_value = match current_value( exif_fields, location, tag ) {
+                Some(old_value) => old_value,
+                None => continue,
+            };
+            let shifted = chrono::NaiveDateTime::parse_from_str( &old_value, CAPTURE_TIME_FORMAT ).ok()
+                .and_then(|old_time| old_time.checked_add_signed( self.offset ));
+            match shifted {
+                Some(new_time) => {
+                    let text = new_time.format( CAPTURE_TIME_FORMAT ).to_string();
+                    writes.push( ascii_field( field, location, tag, &text ) );
+                },
+                None => {
+                    eprintln!("Not shifting {} with value: {}, for file: {}",field,old_value,path.to_string_lossy());
+                    continue;
+                },
+            }
+            if let Some(offset_time) = &self.offset_time {
+                writes.push( ascii_field( offset_field, IfdLocation::Exif, offset_tag, offset_time ) );
+            }
+        }
+        writes
+    }
+}
+
+// Parses a signed offset of hours and minutes, with optional seconds, such as "-1:00",
+// "+0:02:30" or "+36:00".
+pub(crate) fn parse_time_offset( text: &str ) -> Option<chrono::Duration> {
+    let (sign, rest) = match text.chars().next()? {
+        '+' => (1, &text[1..]),
+        '-' => (-1, &text[1..]),
+        _ => return None,
+    };
+    let parts : Vec<&str> = rest.split( ':' ).collect();
+    if parts.len() < 2 || parts.len() > 3 || parts.iter().any(|part| part.is_empty() || !part.bytes().all(|byte| byte.is_ascii_digit())) {
+        return None;
+    }
+    let hours : i64 = parts[0].parse().ok()?;
+    let minutes : i64 = parts[1].parse().ok()?;
+    let seconds : i64 = parts.get(2).map_or( Some(0), |seconds| seconds.parse().ok() )?;
+    if minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+    chrono::Duration::try_seconds( sign * (hours * 3600 + minutes * 60 + seconds) )
+}
+
+// Whether a value can be written to the OffsetTime fields, which EXIF defines as "+HH:MM" or "-HH:MM".
+pub(crate) fn is_valid_offset_time( text: &str ) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() == 6
+        && (bytes[0] == b'+' || bytes[0] == b'-')
+        && bytes[3] == b':'
+        && [1, 2, 4, 5].iter().all(|index| bytes[*index].is_ascii_digit())
+        && bytes[4] < b'6'
+}
+
+// Shifts the date/time fields of a JPEG or TIFF file, if the camera filters match it, and
+// returns what changed; None means the file was left alone because it is from another camera.
+// An existing JSON sidecar is updated with the shifted capture time, keeping everything else in
+// it, such as a geotagged location or hand edits. Redacted sidecars can't be updated, so their
+// files aren't shifted.
+pub fn shift_capture_times( path: &Path, shift: &TimeShift, options: &WriteOptions, redaction: &Redaction ) -> Result<Option<Vec<FieldChange>>, Box<dyn error::Error>> {
+    let metadata = read_metadata_of_interest( path )?;
+    if !shift.applies_to( &metadata ) {
+        return Ok(None);
+    }
+    let (mut sidecar, sidecar_path) = sidecar_base( path, &metadata )?;
+    let changes = write_fields( path, &|exif_fields, _| shift.field_writes( exif_fields, path ), options )?;
+
+    if let (Some(sidecar_path), false, false) = (sidecar_path, changes.is_empty(), options.dry_run) {
+        // The file itself changed, so its size, times and hashes (and frames, if listed) are
+        // read again along with the capture time.
+        let read_options = ReadOptions {
+            include_frames: sidecar.frames.is_some(),
+            hash: sidecar.file_metadata.hashes.as_ref().map(|hashes| hashes.algorithm),
+        };
+        let shifted = read_metadata_of_interest_with_options( path, &read_options )?;
+        sidecar.image_metadata.capture_time = shifted.image_metadata.capture_time;
+        sidecar.file_metadata = shifted.file_metadata;
+        sidecar.frames = shifted.frames;
+        write_json_metadata_with_redaction( &sidecar, &sidecar_path, redaction )?;
+    }
+    Ok( Some(changes) )
+}
@@ -0,0 +1,394 @@
+use std::convert::{TryFrom, TryInto};
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{GpsLocation, ImageMetadataOfInterest};
+
+// QuickTime and ISO base media files count time in seconds since midnight, January 1st 1904 (UTC).
+const QUICKTIME_EPOCH_YEAR : i32 = 1904;
+
+#[derive(Debug,Clone,PartialEq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct VideoMetadataOfInterest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_make: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<GpsLocation>,
+}
+
+#[derive(Debug, Clone)]
+struct InvalidQuickTimeError {
+    reason: &'static str,
+}
+
+impl fmt::Display for InvalidQuickTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid QuickTime/ISO media file: {}", self.reason)
+    }
+}
+
+impl Error for InvalidQuickTimeError {
+    fn description(&self) -> &str {
+        "A QuickTime/ISO media file could not be parsed"
+    }
+}
+
+// A single atom ("box" in ISO terms) within an in-memory buffer.
+struct Atom<'a> {
+    kind: [u8; 4],
+    body: &'a [u8],
+}
+
+struct AtomIter<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> Iterator for AtomIter<'a> {
+    type Item = Atom<'a>;
+
+    fn next(&mut self) -> Option<Atom<'a>> {
+        if self.remaining.len() < 8 {
+            return None;
+        }
+        let size32 = read_u32( self.remaining, 0 )?;
+        let kind : [u8; 4] = self.remaining[4..8].try_into().ok()?;
+        let (header_len, size) = match size32 {
+            0 => (8, self.remaining.len() as u64),
+            1 => (16, read_u64( self.remaining, 8 )?),
+            _ => (8, size32 as u64),
+        };
+        if size < header_len as u64 || size > self.remaining.len() as u64 {
+            // Truncated or corrupt atom; stop rather than reading garbage.
+            self.remaining = &[];
+            return None;
+        }
+        let body = &self.remaining[header_len..size as usize];
+        self.remaining = &self.remaining[size as usize..];
+        Some( Atom { kind, body } )
+    }
+}
+
+fn atoms( data: &[u8] ) -> AtomIter<'_> {
+    AtomIter { remaining: data }
+}
+
+fn find_atom<'a>( data: &'a [u8], kind: &[u8; 4] ) -> Option<&'a [u8]> {
+    atoms( data ).find(|atom| &atom.kind == kind).map(|atom| atom.body)
+}
+
+fn read_u16( data: &[u8], offset: usize ) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some( u16::from_be_bytes( bytes.try_into().ok()? ) )
+}
+
+fn read_u32( data: &[u8], offset: usize ) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some( u32::from_be_bytes( bytes.try_into().ok()? ) )
+}
+
+fn read_u64( data: &[u8], offset: usize ) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some( u64::from_be_bytes( bytes.try_into().ok()? ) )
+}
+
+// Walks the top-level atoms of the file with seeks, so only the 'moov' atom is read into memory.
+// The media data itself can be gigabytes and is never loaded.
+fn read_moov_atom( path: &Path ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let mut file = File::open( path )?;
+    let file_len = file.metadata()?.len();
+    let mut position : u64 = 0;
+
+    while position + 8 <= file_len {
+        file.seek( SeekFrom::Start( position ) )?;
+        let mut header = [0u8; 16];
+        file.read_exact( &mut header[..8] )?;
+        let size32 = u32::from_be_bytes( header[0..4].try_into()? );
+        let (header_len, size) = match size32 {
+            0 => (8, file_len - position),
+            1 => {
+                file.read_exact( &mut header[8..16] )?;
+                (16, u64::from_be_bytes( header[8..16].try_into()? ))
+            },
+            _ => (8, size32 as u64),
+        };
+        if size < header_len || position + size > file_len {
+            return Err(Box::new(InvalidQuickTimeError{ reason: "atom extends past the end of the file" }));
+        }
+        if &header[4..8] == b"moov" {
+            let mut body = vec![0u8; (size - header_len) as usize];
+            file.read_exact( &mut body )?;
+            return Ok(body);
+        }
+        position += size;
+    }
+
+    Err(Box::new(InvalidQuickTimeError{ reason: "no 'moov' atom found" }))
+}
+
+struct MovieHeader {
+    creation_seconds: u64,
+    timescale: u32,
+    duration: u64,
+}
+
+fn parse_mvhd( body: &[u8] ) -> Option<MovieHeader> {
+    let version = *body.first()?;
+    if version == 1 {
+        Some( MovieHeader {
+            creation_seconds: read_u64( body, 4 )?,
+            timescale: read_u32( body, 20 )?,
+            duration: read_u64( body, 24 )?,
+        } )
+    } else {
+        Some( MovieHeader {
+            creation_seconds: read_u32( body, 4 )? as u64,
+            timescale: read_u32( body, 12 )?,
+            duration: read_u32( body, 16 )? as u64,
+        } )
+    }
+}
+
+// The time is UTC, while images record the wall-clock time they were shot at. Without the time
+// zone of the shoot, it is given in the local time zone of this computer, as file times are.
+fn quicktime_seconds_to_date_time( seconds: u64 ) -> Option<chrono::NaiveDateTime> {
+    // Zero is written by cameras which do not know the time; it does not mean 1904.
+    if seconds == 0 {
+        return None;
+    }
+    let epoch = chrono::NaiveDate::from_ymd_opt( QUICKTIME_EPOCH_YEAR, 1, 1 )?.and_hms_opt( 0, 0, 0 )?;
+    let offset = chrono::Duration::try_seconds( i64::try_from( seconds ).ok()? )?;
+    let utc = epoch.checked_add_signed( offset )?.and_utc();
+    Some( utc.with_timezone( &chrono::Local ).naive_local() )
+}
+
+struct VideoTrack {
+    codec: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+fn parse_video_track( trak: &[u8] ) -> Option<VideoTrack> {
+    let mdia = find_atom( trak, b"mdia" )?;
+    let hdlr = find_atom( mdia, b"hdlr" )?;
+    // hdlr: version/flags (4), predefined (4), handler type (4)
+    if hdlr.get(8..12)? != b"vide" {
+        return None;
+    }
+
+    let stsd = find_atom( mdia, b"minf" )
+        .and_then(|minf| find_atom( minf, b"stbl" ))
+        .and_then(|stbl| find_atom( stbl, b"stsd" ));
+
+    // stsd: version/flags (4), entry count (4), then sample entries which are laid out like atoms.
+    // A visual sample entry has 6 reserved bytes, a data reference index (2), 16 bytes of
+    // pre-defined/reserved fields, and then the width and height as 16-bit integers.
+    let sample_entry = stsd.and_then(|stsd| stsd.get(8..)).and_then(|entries| atoms( entries ).next());
+    if let Some(entry) = sample_entry {
+        Some( VideoTrack {
+            codec: str::from_utf8( &entry.kind ).ok().map(|codec| codec.trim().to_string()),
+            width: read_u16( entry.body, 24 ).map(u32::from),
+            height: read_u16( entry.body, 26 ).map(u32::from),
+        } )
+    } else {
+        // Fall back to the track header, which stores the presentation size as 16.16 fixed point.
+        let tkhd = find_atom( trak, b"tkhd" )?;
+        let dimensions_offset = if *tkhd.first()? == 1 { 88 } else { 76 };
+        Some( VideoTrack {
+            codec: None,
+            width: read_u32( tkhd, dimensions_offset ).map(|w| w >> 16),
+            height: read_u32( tkhd, dimensions_offset + 4 ).map(|h| h >> 16),
+        } )
+    }
+}
+
+// QuickTime user data text atoms ('©mak', '©mod', '©xyz', ...) hold a 16-bit length,
+// a 16-bit language code and then the text itself.
+fn parse_user_data_text( body: &[u8] ) -> Option<String> {
+    let length = read_u16( body, 0 )? as usize;
+    let text = body.get(4..4 + length)?;
+    str::from_utf8( text ).ok().map(|text| text.trim_end_matches('\0').to_string())
+}
+
+// Metadata in the newer QuickTime 'meta' atom is split in a 'keys' atom naming each entry
+// and an 'ilst' atom whose children are typed by the 1-based index of their key.
+fn parse_metadata_keys( meta: &[u8] ) -> Vec<(String, String)> {
+    // The QuickTime flavour of 'meta' has no version/flags, while the ISO flavour does.
+    let children = if meta.get(4..8) == Some(b"hdlr") { meta } else { meta.get(4..).unwrap_or(&[]) };
+
+    let keys : Vec<String> = match find_atom( children, b"keys" ) {
+        Some(keys) => atoms( keys.get(8..).unwrap_or(&[]) )
+            .map(|entry| {
+                // Each key entry is an atom whose type is the namespace (usually 'mdta').
+                String::from_utf8_lossy( entry.body ).into_owned()
+            })
+            .collect(),
+        None => return vec![],
+    };
+
+    let mut values = vec![];
+    if let Some(ilst) = find_atom( children, b"ilst" ) {
+        for item in atoms( ilst ) {
+            let key_index = u32::from_be_bytes( item.kind ) as usize;
+            let key = match key_index.checked_sub(1).and_then(|index| keys.get(index)) {
+                Some(key) => key,
+                None => continue,
+            };
+            // 'data' atom: type indicator (4), locale (4), value. Type 1 is UTF-8 text.
+            if let Some(data) = find_atom( item.body, b"data" ) {
+                if read_u32( data, 0 ) == Some(1) {
+                    if let Some(Ok(value)) = data.get(8..).map(str::from_utf8) {
+                        values.push( (key.clone(), value.to_string()) );
+                    }
+                }
+            }
+        }
+    }
+    values
+}
+
+// Parses a single ISO 6709 coordinate component. Degrees may be written as
+// DD.D, DDMM.M or DDMMSS.S depending on how many integer digits precede the decimal point;
+// longitude has one more degree digit than latitude.
+fn parse_iso6709_component( component: &str, degree_digits: usize ) -> Option<f64> {
+    let (sign, unsigned) = match component.chars().next()? {
+        '+' => (1.0, &component[1..]),
+        '-' => (-1.0, &component[1..]),
+        _ => return None,
+    };
+    let integer_digits = unsigned.find('.').unwrap_or(unsigned.len());
+    let value : f64 = unsigned.parse().ok()?;
+    let degrees = if integer_digits <= degree_digits {
+        value
+    } else if integer_digits == degree_digits + 2 {
+        let degrees = (value / 100.0).trunc();
+        degrees + (value - degrees * 100.0) / 60.0
+    } else if integer_digits == degree_digits + 4 {
+        let degrees = (value / 10_000.0).trunc();
+        let minutes = ((value - degrees * 10_000.0) / 100.0).trunc();
+        degrees + minutes / 60.0 + (value - degrees * 10_000.0 - minutes * 100.0) / 3600.0
+    } else {
+        return None;
+    };
+    Some( sign * degrees )
+}
+
+// Parses an ISO 6709 location such as "+37.7749-122.4194+010.000/" or "+37.7749-122.4194/".
+pub(crate) fn parse_iso6709( text: &str ) -> Option<GpsLocation> {
+    let text = text.trim().trim_end_matches('/');
+    let mut components = vec![];
+    let mut start = 0;
+    for (index, character) in text.char_indices().skip(1) {
+        if character == '+' || character == '-' {
+            components.push( &text[start..index] );
+            start = index;
+        }
+    }
+    components.push( &text[start..] );
+
+    if components.len() < 2 {
+        return None;
+    }
+    let latitude = parse_iso6709_component( components[0], 2 )?;
+    let longitude = parse_iso6709_component( components[1], 3 )?;
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return None;
+    }
+    // Altitude may carry a trailing "CRS..." suffix which is not of interest here.
+    let altitude = components.get(2)
+        .map(|altitude| altitude.split("CRS").next().unwrap_or(""))
+        .and_then(|altitude| altitude.parse::<f64>().ok());
+
+    Some( GpsLocation { latitude, longitude, altitude } )
+}
+
+fn parse_quicktime_date_time( text: &str ) -> Option<chrono::NaiveDateTime> {
+    // e.g. "2020-01-30T09:28:07+0100". The local wall-clock time is kept, as EXIF does for images.
+    if let Ok(with_offset) = chrono::DateTime::parse_from_str( text, "%Y-%m-%dT%H:%M:%S%z" ) {
+        return Some( with_offset.naive_local() );
+    }
+    chrono::NaiveDateTime::parse_from_str( text, "%Y-%m-%dT%H:%M:%S" ).ok()
+}
+
+pub(crate) fn read_quicktime_metadata( path: &Path ) -> Result<(ImageMetadataOfInterest, VideoMetadataOfInterest), Box<dyn error::Error>> {
+    let moov = read_moov_atom( path )?;
+
+    let movie_header = find_atom( &moov, b"mvhd" ).and_then(parse_mvhd);
+    if movie_header.is_none() {
+        return Err(Box::new(InvalidQuickTimeError{ reason: "missing or truncated 'mvhd' atom" }));
+    }
+    let movie_header = movie_header.unwrap();
+
+    let mut image_metadata = ImageMetadataOfInterest {
+        capture_time: quicktime_seconds_to_date_time( movie_header.creation_seconds ),
+        ..Default::default()
+    };
+    let mut video_metadata = VideoMetadataOfInterest {
+        duration_seconds: if movie_header.timescale > 0 {
+            Some( movie_header.duration as f64 / movie_header.timescale as f64 )
+        } else {
+            None
+        },
+        ..Default::default()
+    };
+
+    if let Some(track) = atoms( &moov ).filter(|atom| &atom.kind == b"trak").find_map(|trak| parse_video_track( trak.body )) {
+        video_metadata.codec = track.codec;
+        video_metadata.width = track.width;
+        video_metadata.height = track.height;
+    }
+
+    if let Some(udta) = find_atom( &moov, b"udta" ) {
+        for atom in atoms( udta ) {
+            match &atom.kind {
+                b"\xa9mak" => video_metadata.camera_make = parse_user_data_text( atom.body ),
+                b"\xa9mod" => image_metadata.camera_model = parse_user_data_text( atom.body ),
+                b"\xa9xyz" => video_metadata.location = parse_user_data_text( atom.body ).and_then(|text| parse_iso6709( &text )),
+                _ => {}
+            }
+        }
+    }
+
+    // The 'meta' keys are newer and more precise than 'udta', so they take precedence when present.
+    if let Some(meta) = find_atom( &moov, b"meta" ) {
+        for (key, value) in parse_metadata_keys( meta ) {
+            match key.as_str() {
+                "com.apple.quicktime.make" => video_metadata.camera_make = Some(value),
+                "com.apple.quicktime.model" => image_metadata.camera_model = Some(value),
+                "com.apple.quicktime.location.ISO6709" => {
+                    if let Some(location) = parse_iso6709( &value ) {
+                        video_metadata.location = Some(location);
+                    }
+                },
+                "com.apple.quicktime.creationdate" => {
+                    if let Some(capture_time) = parse_quicktime_date_time( &value ) {
+                        image_metadata.capture_time = Some(capture_time);
+                    } else {
+                        eprintln!("Creation date string: {} has wrong formatting, for file: {}",value,path.to_string_lossy());
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // The position is also given as the location of the capture, where geotagging, queries and
+    // redaction look for it, as for images.
+    image_metadata.location = video_metadata.location;
+
+    Ok( (image_metadata, video_metadata) )
+}
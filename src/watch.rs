@@ -0,0 +1,280 @@
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+use std::error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::time::Instant;
+
+#[cfg(target_os = "linux")]
+use crate::catalog::Catalog;
+#[cfg(target_os = "linux")]
+use crate::inotify::{catch_stop_signals, restore_stop_signals, stop_signalled, Inotify, IN_CREATE, IN_DELETE, IN_ISDIR, IN_MOVED_FROM, IN_MOVED_TO, IN_Q_OVERFLOW};
+#[cfg(target_os = "linux")]
+use crate::organize::collect_files;
+use crate::format;
+use crate::redact::Redaction;
+use crate::rename::STEM_SIDECAR_EXTENSIONS;
+#[cfg(target_os = "linux")]
+use crate::sidecar::{sidecar_base, RedactedSidecarError};
+#[cfg(target_os = "linux")]
+use crate::{read_metadata_of_interest_with_options, write_json_metadata_with_redaction};
+use crate::{json_sidecar_path, ReadOptions};
+
+// How long to wait for events before checking for files which have settled, and for a stop.
+#[cfg(target_os = "linux")]
+const POLL_INTERVAL_MILLISECONDS : i32 = 100;
+const DEFAULT_SETTLE_MILLISECONDS : u64 = 1000;
+
+// How hot folders are kept in sync.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Watch {
+    // How long a file must go without changes before it is read, so that files still being
+    // written aren't.
+    pub settle: Duration,
+    // The catalog to keep up to date instead of the JSON sidecars.
+    pub catalog: Option<PathBuf>,
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Watch { settle: Duration::from_millis( DEFAULT_SETTLE_MILLISECONDS ), catalog: None }
+    }
+}
+
+// What watching did about one file.
+#[derive(Debug)]
+pub enum WatchEvent {
+    // The sidecar or catalog entry of the file was written.
+    Updated(PathBuf),
+    // The file shares its sidecar with a RAW, owner, which the sidecar describes, so the sidecar
+    // was left alone.
+    Skipped { path: PathBuf, owner: PathBuf },
+    // The file was deleted, and its sidecar removed along with it if no other file with its
+    // name, such as the RAW of a JPEG, is left to use it.
+    Removed { path: PathBuf, sidecar: Option<PathBuf> },
+    Failed(PathBuf, Box<dyn error::Error>),
+}
+
+fn is_hidden( path: &Path ) -> bool {
+    path.file_name().map(|name| name.to_string_lossy().starts_with( '.' )).unwrap_or(true)
+}
+
+// Files whose changes are not watched for: hidden files, which include those written while
+// replacing a file, and sidecars.
+fn is_ignored( path: &Path ) -> bool {
+    is_hidden( path ) || path.extension().is_some_and(|extension| STEM_SIDECAR_EXTENSIONS.iter().any(|sidecar| extension == *sidecar))
+}
+
+// The other files in the folder with the same name apart from the extension, which share the
+// file's JSON sidecar, in name order.
+fn sharing_sidecar( path: &Path ) -> Vec<PathBuf> {
+    let stem = path.file_stem();
+    let mut siblings : Vec<PathBuf> = path.parent().and_then(|parent| fs::read_dir( parent ).ok()).into_iter().flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|sibling| sibling != path && sibling.file_stem() == stem && !is_ignored( sibling ))
+        .collect();
+    siblings.sort();
+    siblings
+}
+
+// The JSON sidecar of a deleted file, if it exists and no other file in the folder has the
+// same name apart from sidecars.
+fn orphaned_sidecar( path: &Path ) -> Option<PathBuf> {
+    let sidecar = json_sidecar_path( path ).filter(|sidecar| sidecar.is_file())?;
+    if sharing_sidecar( path ).is_empty() { Some(sidecar) } else { None }
+}
+
+fn is_raw( path: &Path ) -> bool {
+    format::detect_format( path ).is_ok_and(|format| format.is_raw())
+}
+
+// The file a shared sidecar describes, if it isn't this one. A RAW and the JPEG of the same shot
+// share a sidecar, which describes the RAW, so that it doesn't flip between the two as each
+// changes; files sharing a sidecar without a RAW among them each write it.
+fn sidecar_owner( path: &Path ) -> Option<PathBuf> {
+    if is_raw( path ) {
+        return None;
+    }
+    sharing_sidecar( path ).into_iter().find(|sibling| is_raw( sibling ))
+}
+
+// Writes the sidecar of a file which changed. What the file holds is read again, but the image
+// fields of an existing sidecar are kept, with those it lacks taken from the file, so that its
+// edits, a location from geotagging and the burst it was found in aren't lost. A redacted sidecar
+// can't be merged with, so it is written afresh.
+#[cfg(target_os = "linux")]
+fn update_sidecar( path: &Path, read_options: &ReadOptions, redaction: &Redaction ) -> Result<(), Box<dyn error::Error>> {
+    let current = read_metadata_of_interest_with_options( path, read_options )?;
+    let (mut sidecar, sidecar_path) = match sidecar_base( path, &current ) {
+        Err(boxed_err) if boxed_err.is::<RedactedSidecarError>() => (current.clone(), None),
+        result => result?,
+    };
+    let image_metadata = &mut sidecar.image_metadata;
+    image_metadata.orientation = image_metadata.orientation.or( current.image_metadata.orientation );
+    image_metadata.capture_time = image_metadata.capture_time.or( current.image_metadata.capture_time );
+    image_metadata.camera_model = image_metadata.camera_model.take().or( current.image_metadata.camera_model );
+    image_metadata.camera_serial = image_metadata.camera_serial.take().or( current.image_metadata.camera_serial );
+    image_metadata.location = image_metadata.location.or( current.image_metadata.location );
+    sidecar.file_metadata = current.file_metadata;
+    sidecar.video_metadata = current.video_metadata;
+    sidecar.thumbnail = current.thumbnail;
+    sidecar.color = current.color;
+    sidecar.vendor_metadata = current.vendor_metadata;
+    sidecar.frames = current.frames;
+    match sidecar_path.or_else(|| json_sidecar_path( path )) {
+        Some(json_path) => write_json_metadata_with_redaction( &sidecar, &json_path, redaction ),
+        None => Ok(()),
+    }
+}
+
+// Keeps the sidecars or catalog entries of the files in the directories in sync with them, until
+// the stop flag is set or the process gets SIGINT or SIGTERM. Files are read once they have gone
+// without changes for the settle time; files changed since their sidecar was written, or not in
+// the catalog, are read when watching starts. What is done about each file is passed to on_event.
+#[cfg(target_os = "linux")]
+pub fn watch_folders( directories: &[PathBuf], watch: &Watch, read_options: &ReadOptions, redaction: &Redaction, stop: &AtomicBool,
+    on_event: &mut dyn FnMut( WatchEvent ) ) -> Result<(), Box<dyn error::Error>>
+{
+    // Canonical paths, so that events give the paths the catalog has for the files.
+    let mut canonical_directories = vec![];
+    for directory in directories.iter() {
+        if !directory.is_dir() {
+            return Err( Box::new( io::Error::new( io::ErrorKind::InvalidInput, format!("Only directories can be watched: {}", directory.to_string_lossy()) ) ) );
+        }
+        canonical_directories.push( fs::canonicalize( directory )? );
+    }
+    let directories = &canonical_directories[..];
+    let mut inotify = Inotify::new()?;
+    for directory in directories.iter() {
+        inotify.add_directory( directory )?;
+    }
+    let mut catalog = match &watch.catalog {
+        Some(catalog_path) => Some( (Catalog::load( catalog_path )?, catalog_path) ),
+        None => None,
+    };
+
+    catch_stop_signals();
+    let result = (|| -> Result<(), Box<dyn error::Error>> {
+        // Catch up on what changed while nothing was watching: the catalog is refreshed, and files
+        // modified since their sidecar was written are read as soon as the loop starts.
+        let mut pending : HashMap<PathBuf, Instant> = HashMap::new();
+        match &mut catalog {
+            Some((catalog, catalog_path)) => {
+                let (report, failures) = catalog.refresh( directories, read_options );
+                report.added.into_iter().chain( report.updated ).for_each(|path| on_event( WatchEvent::Updated(path) ));
+                report.removed.into_iter().for_each(|path| on_event( WatchEvent::Removed { path, sidecar: None } ));
+                failures.into_iter().for_each(|(path, boxed_err)| on_event( WatchEvent::Failed(path, boxed_err) ));
+                catalog.save_with_redaction( catalog_path, redaction )?;
+            },
+            None => {
+                let mut files = vec![];
+                for directory in directories.iter() {
+                    collect_files( directory, None, &mut files );
+                }
+                let modified = |path: &Path| fs::metadata( path ).and_then(|metadata| metadata.modified()).ok();
+                let settled_long_ago = Instant::now().checked_sub( watch.settle ).unwrap_or_else( Instant::now );
+                for file in files.into_iter().filter(|file| !is_ignored( file )) {
+                    if json_sidecar_path( &file ).is_none_or(|sidecar| modified( &sidecar ) < modified( &file )) {
+                        pending.insert( file, settled_long_ago );
+                    }
+                }
+            },
+        }
+
+        while !stop.load( Ordering::SeqCst ) && !stop_signalled() {
+            let mut removed = vec![];
+            for (path, mask) in inotify.read_events( POLL_INTERVAL_MILLISECONDS )? {
+                if mask & IN_Q_OVERFLOW != 0 {
+                    // Events were lost, so every file may have changed.
+                    let mut files = vec![];
+                    for directory in directories.iter() {
+                        collect_files( directory, None, &mut files );
+                    }
+                    pending.extend( files.into_iter().filter(|file| !is_ignored( file )).map(|file| (file, Instant::now())) );
+                } else if is_hidden( &path ) {
+                    continue;
+                } else if mask & IN_ISDIR != 0 {
+                    if mask & (IN_CREATE | IN_MOVED_TO) != 0 && path.is_dir() {
+                        inotify.add_directory( &path )?;
+                        let mut files = vec![];
+                        collect_files( &path, None, &mut files );
+                        pending.extend( files.into_iter().filter(|file| !is_ignored( file )).map(|file| (file, Instant::now())) );
+                    }
+                } else if is_ignored( &path ) {
+                    continue;
+                } else if mask & (IN_DELETE | IN_MOVED_FROM) != 0 {
+                    pending.remove( &path );
+                    removed.push( path );
+                } else {
+                    pending.insert( path, Instant::now() );
+                }
+            }
+
+            let now = Instant::now();
+            let mut settled : Vec<PathBuf> = pending.iter()
+                .filter(|(_, changed)| now.duration_since( **changed ) >= watch.settle)
+                .map(|(path, _)| path.clone())
+                .collect();
+            settled.sort();
+            for path in settled.iter() {
+                pending.remove( path );
+            }
+            let catalog_changed = !settled.is_empty() || !removed.is_empty();
+
+            for path in settled.into_iter().filter(|path| path.is_file()) {
+                let written = match &mut catalog {
+                    Some((catalog, _)) => {
+                        let (_, mut failures) = catalog.refresh( std::slice::from_ref( &path ), read_options );
+                        match failures.pop() {
+                            Some((_, boxed_err)) => Err(boxed_err),
+                            None => Ok(()),
+                        }
+                    },
+                    None => match sidecar_owner( &path ) {
+                        Some(owner) => {
+                            on_event( WatchEvent::Skipped { path, owner } );
+                            continue;
+                        },
+                        None => update_sidecar( &path, read_options, redaction ),
+                    },
+                };
+                on_event( match written {
+                    Ok(()) => WatchEvent::Updated(path),
+                    Err(boxed_err) => WatchEvent::Failed(path, boxed_err),
+                } );
+            }
+            for path in removed.into_iter().filter(|path| !path.exists()) {
+                if let Some((catalog, _)) = &mut catalog {
+                    catalog.refresh( std::slice::from_ref( &path ), read_options );
+                }
+                let sidecar = orphaned_sidecar( &path );
+                if catalog.is_none() && sidecar.is_none() {
+                    // The files left sharing the sidecar, such as the JPEG of a deleted RAW, take it over.
+                    pending.extend( sharing_sidecar( &path ).into_iter().map(|sibling| (sibling, Instant::now())) );
+                }
+                match sidecar.as_ref().map(fs::remove_file) {
+                    Some(Err(unboxed_err)) => on_event( WatchEvent::Failed(path, Box::new( unboxed_err )) ),
+                    _ => on_event( WatchEvent::Removed { path, sidecar } ),
+                }
+            }
+            if let (Some((catalog, catalog_path)), true) = (&mut catalog, catalog_changed) {
+                catalog.save_with_redaction( catalog_path, redaction )?;
+            }
+        }
+        Ok(())
+    })();
+    restore_stop_signals();
+    result
+}
+
+// Watching relies on inotify, which only Linux has.
+#[cfg(not(target_os = "linux"))]
+pub fn watch_folders( _directories: &[PathBuf], _watch: &Watch, _read_options: &ReadOptions, _redaction: &Redaction, _stop: &AtomicBool,
+    _on_event: &mut dyn FnMut( WatchEvent ) ) -> Result<(), Box<dyn error::Error>>
+{
+    Err( Box::new( io::Error::new( io::ErrorKind::Unsupported, "Watching folders is only supported on Linux" ) ) )
+}
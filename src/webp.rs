@@ -0,0 +1,62 @@
+use std::convert::TryInto;
+use std::ops::Range;
+
+// Flags in the first byte of the VP8X chunk, saying which optional chunks the file has.
+pub(crate) const FLAG_EXIF : u8 = 0x08;
+pub(crate) const FLAG_XMP : u8 = 0x04;
+
+// A chunk of a WebP (RIFF) file. `position` is the offset of its FourCC, and `payload` is the
+// range of its data; odd-sized chunks are followed by a padding byte.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub(crate) struct RiffChunk {
+    pub fourcc: [u8; 4],
+    pub position: usize,
+    pub payload: Range<usize>,
+}
+
+impl RiffChunk {
+    pub fn end( &self ) -> usize {
+        self.payload.end + self.payload.len() % 2
+    }
+}
+
+// Lists the chunks after the "RIFF" size "WEBP" header.
+pub(crate) fn chunks( data: &[u8] ) -> Option<Vec<RiffChunk>> {
+    if !data.starts_with( b"RIFF" ) || data.get(8..12) != Some(b"WEBP") {
+        return None;
+    }
+    let riff_end = (u32::from_le_bytes( data.get(4..8)?.try_into().ok()? ) as usize).checked_add( 8 )?.min( data.len() );
+    let mut chunks = vec![];
+    let mut position = 12;
+    while position + 8 <= riff_end {
+        let fourcc : [u8; 4] = data.get(position..position + 4)?.try_into().ok()?;
+        let length = u32::from_le_bytes( data.get(position + 4..position + 8)?.try_into().ok()? ) as usize;
+        let payload = position + 8..(position + 8).checked_add( length )?;
+        if payload.end > riff_end {
+            return None;
+        }
+        let chunk = RiffChunk { fourcc, position, payload };
+        position = chunk.end();
+        chunks.push( chunk );
+    }
+    Some(chunks)
+}
+
+pub(crate) fn build_chunk( fourcc: &[u8; 4], payload: &[u8] ) -> Vec<u8> {
+    let mut chunk = fourcc.to_vec();
+    chunk.extend_from_slice( &(payload.len() as u32).to_le_bytes() );
+    chunk.extend_from_slice( payload );
+    if payload.len() % 2 == 1 {
+        chunk.push( 0 );
+    }
+    chunk
+}
+
+// Wraps already built chunks in the RIFF header.
+pub(crate) fn build_file( chunks: &[u8] ) -> Vec<u8> {
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice( &(chunks.len() as u32 + 4).to_le_bytes() );
+    data.extend_from_slice( b"WEBP" );
+    data.extend_from_slice( chunks );
+    data
+}
@@ -0,0 +1,503 @@
+use std::error;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::format::{self, MediaFormat, UnsupportedFormatError};
+use crate::jpeg;
+use crate::tiff::{self, ByteOrder};
+use crate::{Orientation, CAPTURE_TIME_FORMAT};
+
+const TAG_MODEL : u16 = 0x0110;
+const TAG_ORIENTATION : u16 = 0x0112;
+const TAG_COPYRIGHT : u16 = 0x8298;
+const TAG_EXIF_IFD : u16 = 0x8769;
+const TAG_GPS_IFD : u16 = 0x8825;
+const TAG_DATE_TIME_ORIGINAL : u16 = 0x9003;
+const TAG_BODY_SERIAL_NUMBER : u16 = 0xA431;
+
+const TYPE_BYTE : u16 = 1;
+const TYPE_ASCII : u16 = 2;
+const TYPE_SHORT : u16 = 3;
+const TYPE_LONG : u16 = 4;
+const TYPE_RATIONAL : u16 = 5;
+const TYPE_SBYTE : u16 = 6;
+const TYPE_UNDEFINED : u16 = 7;
+const TYPE_SSHORT : u16 = 8;
+const TYPE_SLONG : u16 = 9;
+const TYPE_SRATIONAL : u16 = 10;
+const TYPE_FLOAT : u16 = 11;
+const TYPE_DOUBLE : u16 = 12;
+
+// The length field of a JPEG segment counts itself, and can't exceed 0xFFFF.
+const MAX_SEGMENT_PAYLOAD : usize = 0xFFFF - 2;
+
+// Changes to write into an image, shaped like ImageMetadataOfInterest plus a copyright notice.
+// Fields left as None are not changed.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+#[derive(Serialize,Deserialize)]
+pub struct ImageMetadataPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<Orientation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_time: Option<chrono::NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_serial: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copyright: Option<String>,
+}
+
+impl ImageMetadataPatch {
+    pub fn is_empty( &self ) -> bool {
+        *self == ImageMetadataPatch::default()
+    }
+}
+
+// Opt-in behaviour for writing metadata.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct WriteOptions {
+    // Work out the changes without writing the file.
+    pub dry_run: bool,
+}
+
+// One field which differs between the file and the patch.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old_value: Option<String>,
+    pub new_value: String,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old_value.as_deref().unwrap_or("<none>"), self.new_value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetadataWriteError {
+    reason: String,
+}
+
+impl fmt::Display for MetadataWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Couldn't write metadata: {}", self.reason)
+    }
+}
+
+impl Error for MetadataWriteError {
+    fn description(&self) -> &str {
+        "Metadata could not be written to the file"
+    }
+}
+
+pub(crate) fn write_error( reason: &str ) -> Box<dyn error::Error> {
+    Box::new( MetadataWriteError { reason: reason.to_string() } )
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub(crate) enum IfdLocation {
+    Primary,
+    Exif,
+    Gps,
+    // IFD1, which describes the embedded thumbnail.
+    Thumbnail,
+}
+
+// One field to write: where it goes, its TIFF type and count, and its value bytes.
+pub(crate) struct FieldWrite {
+    field: &'static str,
+    location: IfdLocation,
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    value: Vec<u8>,
+    display: String,
+}
+
+// Works out the fields to write from the EXIF data currently in the file, if it has any.
+pub(crate) type FieldWrites<'a> = dyn Fn( Option<&exif::Exif>, ByteOrder ) -> Vec<FieldWrite> + 'a;
+
+// Edits the IFDs of a TIFF structure without moving any existing data: values which grow and
+// IFDs which gain entries are appended at the end, and only the offsets pointing at them change.
+// Everything else, including MakerNotes whose offsets are relative to the TIFF header and the
+// image data of TIFF files, stays at the same position.
+pub(crate) struct TiffEditor {
+    pub(crate) data: Vec<u8>,
+    pub(crate) order: ByteOrder,
+}
+
+impl TiffEditor {
+    pub(crate) fn new( data: Vec<u8> ) -> Option<TiffEditor> {
+        let order = ByteOrder::from_tiff_header( &data )?;
+        Some( TiffEditor { data, order } )
+    }
+
+    // A little-endian TIFF header followed by an empty IFD0.
+    fn empty() -> TiffEditor {
+        let mut data = b"II*\0".to_vec();
+        data.extend_from_slice( &8u32.to_le_bytes() );
+        data.extend_from_slice( &[0u8; 6] );
+        TiffEditor { data, order: ByteOrder::LittleEndian }
+    }
+
+    // Appends bytes at a word boundary, as TIFF requires, and returns their offset.
+    pub(crate) fn append( &mut self, bytes: &[u8] ) -> usize {
+        if self.data.len() % 2 == 1 {
+            self.data.push( 0 );
+        }
+        let offset = self.data.len();
+        self.data.extend_from_slice( bytes );
+        offset
+    }
+
+    fn put_u16( &mut self, offset: usize, value: u16 ) {
+        let bytes = self.order.u16_bytes( value );
+        self.data[offset..offset + 2].copy_from_slice( &bytes );
+    }
+
+    fn put_u32( &mut self, offset: usize, value: u32 ) {
+        let bytes = self.order.u32_bytes( value );
+        self.data[offset..offset + 4].copy_from_slice( &bytes );
+    }
+
+    // Where the offset of the IFD at `location` is stored.
+    fn pointer_position( &self, location: IfdLocation ) -> Option<usize> {
+        match location {
+            IfdLocation::Primary => Some(4),
+            IfdLocation::Exif | IfdLocation::Gps => {
+                let pointer_tag = if location == IfdLocation::Exif { TAG_EXIF_IFD } else { TAG_GPS_IFD };
+                let ifd0 = self.order.u32( &self.data, 4 )? as usize;
+                let (entries, _) = tiff::read_ifd( &self.data, ifd0, self.order )?;
+                entries.iter().find(|entry| entry.tag == pointer_tag).map(|entry| entry.entry_offset + 8)
+            },
+            IfdLocation::Thumbnail => {
+                // The pointer to IFD1 follows the entries of IFD0, and is zero if there is none.
+                let ifd0 = self.order.u32( &self.data, 4 )? as usize;
+                let (entries, next) = tiff::read_ifd( &self.data, ifd0, self.order )?;
+                Some( ifd0 + 2 + entries.len() * 12 ).filter(|_| next != 0)
+            },
+        }
+    }
+
+    fn ifd_offset( &mut self, location: IfdLocation ) -> Result<usize, Box<dyn error::Error>> {
+        if let Some(pointer) = self.pointer_position( location ) {
+            return Ok( self.order.u32( &self.data, pointer ).ok_or_else(|| write_error( "IFD offset is outside the TIFF data" ))? as usize );
+        }
+        let pointer_tag = match location {
+            IfdLocation::Exif => TAG_EXIF_IFD,
+            IfdLocation::Gps => TAG_GPS_IFD,
+            _ => return Err( write_error( "EXIF data has no thumbnail IFD" ) ),
+        };
+        // The Exif and GPS IFDs can be added: add an empty one and point IFD0 at it.
+        let empty_ifd = [0u8; 6];
+        let offset = self.append( &empty_ifd );
+        let value = self.order.u32_bytes( offset as u32 ).to_vec();
+        self.set_field( IfdLocation::Primary, pointer_tag, TYPE_LONG, 1, &value )?;
+        Ok(offset)
+    }
+
+    pub(crate) fn set_field( &mut self, location: IfdLocation, tag: u16, field_type: u16, count: u32, value: &[u8] ) -> Result<(), Box<dyn error::Error>> {
+        let ifd = self.ifd_offset( location )?;
+        let (entries, next) = tiff::read_ifd( &self.data, ifd, self.order ).ok_or_else(|| write_error( "IFD is outside the TIFF data" ))?;
+
+        if let Some(entry) = entries.iter().find(|entry| entry.tag == tag) {
+            let value_field = entry.entry_offset + 8;
+            let old_length = entry.byte_length();
+            if value.len() <= 4 {
+                let mut inline = value.to_vec();
+                inline.resize( 4, 0 );
+                self.data[value_field..value_field + 4].copy_from_slice( &inline );
+            } else if old_length >= value.len() && old_length > 4 {
+                // Overwrite in place, so that nothing else has to move.
+                let old_offset = self.order.u32( &self.data, value_field ).unwrap_or(0) as usize;
+                let old_value = self.data.get_mut(old_offset..old_offset + old_length).ok_or_else(|| write_error( "field value is outside the TIFF data" ))?;
+                old_value.iter_mut().for_each(|byte| *byte = 0);
+                old_value[..value.len()].copy_from_slice( value );
+            } else {
+                let offset = self.append( value );
+                self.put_u32( value_field, offset as u32 );
+            }
+            self.put_u16( entry.entry_offset + 2, field_type );
+            self.put_u32( entry.entry_offset + 4, count );
+            return Ok(());
+        }
+
+        // The IFD needs another entry, so write a copy of it with the entry added at the end
+        // and point at the copy. Existing entries are copied as they are, offsets included.
+        let value_field = if value.len() <= 4 {
+            let mut inline = value.to_vec();
+            inline.resize( 4, 0 );
+            inline
+        } else {
+            self.order.u32_bytes( self.append( value ) as u32 ).to_vec()
+        };
+        let mut new_entry = vec![];
+        new_entry.extend_from_slice( &self.order.u16_bytes( tag ) );
+        new_entry.extend_from_slice( &self.order.u16_bytes( field_type ) );
+        new_entry.extend_from_slice( &self.order.u32_bytes( count ) );
+        new_entry.extend( value_field );
+
+        let mut raw_entries : Vec<(u16, Vec<u8>)> = entries.iter()
+            .map(|entry| (entry.tag, self.data[entry.entry_offset..entry.entry_offset + 12].to_vec()))
+            .collect();
+        raw_entries.push( (tag, new_entry) );
+        raw_entries.sort_by_key(|(tag, _)| *tag);
+
+        let mut new_ifd = self.order.u16_bytes( raw_entries.len() as u16 ).to_vec();
+        for (_, raw_entry) in raw_entries {
+            new_ifd.extend( raw_entry );
+        }
+        new_ifd.extend_from_slice( &self.order.u32_bytes( next ) );
+        let new_offset = self.append( &new_ifd );
+        let pointer = self.pointer_position( location ).ok_or_else(|| write_error( "IFD pointer is missing" ))?;
+        self.put_u32( pointer, new_offset as u32 );
+        Ok(())
+    }
+}
+
+fn ascii_value( text: &str ) -> Vec<u8> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push( 0 );
+    bytes
+}
+
+pub(crate) fn ascii_field( field: &'static str, location: IfdLocation, tag: u16, text: &str ) -> FieldWrite {
+    let value = ascii_value( text );
+    FieldWrite { field, location, tag, field_type: TYPE_ASCII, count: value.len() as u32, value, display: text.to_string() }
+}
+
+pub(crate) fn long_field( field: &'static str, location: IfdLocation, tag: u16, value: u32, order: ByteOrder ) -> FieldWrite {
+    FieldWrite { field, location, tag, field_type: TYPE_LONG, count: 1, value: order.u32_bytes( value ).to_vec(), display: value.to_string() }
+}
+
+pub(crate) fn field_writes( patch: &ImageMetadataPatch, order: ByteOrder ) -> Vec<FieldWrite> {
+    let mut writes = vec![];
+    if let Some(orientation) = patch.orientation {
+        let value = u16::from( orientation );
+        writes.push( FieldWrite {
+            field: "orientation",
+            location: IfdLocation::Primary,
+            tag: TAG_ORIENTATION,
+            field_type: TYPE_SHORT,
+            count: 1,
+            value: order.u16_bytes( value ).to_vec(),
+            display: value.to_string(),
+        } );
+    }
+    if let Some(capture_time) = patch.capture_time {
+        let text = capture_time.format( CAPTURE_TIME_FORMAT ).to_string();
+        writes.push( ascii_field( "capture_time", IfdLocation::Exif, TAG_DATE_TIME_ORIGINAL, &text ) );
+    }
+    if let Some(camera_model) = &patch.camera_model {
+        writes.push( ascii_field( "camera_model", IfdLocation::Primary, TAG_MODEL, camera_model ) );
+    }
+    if let Some(camera_serial) = &patch.camera_serial {
+        writes.push( ascii_field( "camera_serial", IfdLocation::Exif, TAG_BODY_SERIAL_NUMBER, camera_serial ) );
+    }
+    if let Some(copyright) = &patch.copyright {
+        writes.push( ascii_field( "copyright", IfdLocation::Primary, TAG_COPYRIGHT, copyright ) );
+    }
+    writes
+}
+
+// Reads the current value of a field the same way it is displayed in a FieldChange.
+pub(crate) fn current_value( exif_fields: Option<&exif::Exif>, location: IfdLocation, tag: u16 ) -> Option<String> {
+    let (context, ifd) = match location {
+        IfdLocation::Primary => (exif::Context::Tiff, exif::In::PRIMARY),
+        IfdLocation::Exif => (exif::Context::Exif, exif::In::PRIMARY),
+        IfdLocation::Gps => (exif::Context::Gps, exif::In::PRIMARY),
+        IfdLocation::Thumbnail => (exif::Context::Tiff, exif::In::THUMBNAIL),
+    };
+    let field = exif_fields?.get_field( exif::Tag(context, tag), ifd )?;
+    value_text( &field.value )
+}
+
+fn join<T: ToString>( values: impl Iterator<Item = T> ) -> String {
+    values.map(|value| value.to_string()).collect::<Vec<String>>().join( ", " )
+}
+
+// A field value as text: ASCII as it is, numbers separated by commas and bytes in hex.
+fn value_text( value: &exif::Value ) -> Option<String> {
+    match value {
+        exif::Value::Ascii(values) => values.first().map(|text| String::from_utf8_lossy( text ).trim_end_matches( '\0' ).to_string()),
+        exif::Value::Rational(values) => Some( join( values.iter().map(|value| format!("{}/{}", value.num, value.denom)) ) ),
+        exif::Value::SRational(values) => Some( join( values.iter().map(|value| format!("{}/{}", value.num, value.denom)) ) ),
+        exif::Value::SByte(values) => Some( join( values.iter() ) ),
+        exif::Value::SShort(values) => Some( join( values.iter() ) ),
+        exif::Value::SLong(values) => Some( join( values.iter() ) ),
+        exif::Value::Float(values) => Some( join( values.iter() ) ),
+        exif::Value::Double(values) => Some( join( values.iter() ) ),
+        exif::Value::Undefined(bytes, _) => Some( bytes.iter().map(|byte| format!("{:02x}", byte)).collect() ),
+        other => Some( join( (0..).map_while(|index| other.get_uint( index )) ) ).filter(|text| !text.is_empty()),
+    }
+}
+
+// A write of a field read from another file, in the byte order of the file being written.
+pub(crate) fn copied_field( field: &'static str, location: IfdLocation, tag: u16, value: &exif::Value, order: ByteOrder ) -> Option<FieldWrite> {
+    let (field_type, count, bytes) : (u16, usize, Vec<u8>) = match value {
+        exif::Value::Byte(values) => (TYPE_BYTE, values.len(), values.clone()),
+        exif::Value::Ascii(values) => {
+            let bytes : Vec<u8> = values.iter().flat_map(|text| text.iter().copied().chain( std::iter::once( 0 ) )).collect();
+            (TYPE_ASCII, bytes.len(), bytes)
+        },
+        exif::Value::Short(values) => (TYPE_SHORT, values.len(), values.iter().flat_map(|value| order.u16_bytes( *value )).collect()),
+        exif::Value::Long(values) => (TYPE_LONG, values.len(), values.iter().flat_map(|value| order.u32_bytes( *value )).collect()),
+        exif::Value::Rational(values) => (TYPE_RATIONAL, values.len(),
+            values.iter().flat_map(|value| [order.u32_bytes( value.num ), order.u32_bytes( value.denom )].concat()).collect()),
+        exif::Value::SByte(values) => (TYPE_SBYTE, values.len(), values.iter().map(|value| *value as u8).collect()),
+        exif::Value::Undefined(values, _) => (TYPE_UNDEFINED, values.len(), values.clone()),
+        exif::Value::SShort(values) => (TYPE_SSHORT, values.len(), values.iter().flat_map(|value| order.u16_bytes( *value as u16 )).collect()),
+        exif::Value::SLong(values) => (TYPE_SLONG, values.len(), values.iter().flat_map(|value| order.u32_bytes( *value as u32 )).collect()),
+        exif::Value::SRational(values) => (TYPE_SRATIONAL, values.len(),
+            values.iter().flat_map(|value| [order.u32_bytes( value.num as u32 ), order.u32_bytes( value.denom as u32 )].concat()).collect()),
+        exif::Value::Float(values) => (TYPE_FLOAT, values.len(), values.iter().flat_map(|value| order.u32_bytes( value.to_bits() )).collect()),
+        exif::Value::Double(values) => (TYPE_DOUBLE, values.len(), values.iter().flat_map(|value| {
+            let bits = value.to_bits();
+            let (high, low) = ((bits >> 32) as u32, bits as u32);
+            match order {
+                ByteOrder::BigEndian => [order.u32_bytes( high ), order.u32_bytes( low )].concat(),
+                ByteOrder::LittleEndian => [order.u32_bytes( low ), order.u32_bytes( high )].concat(),
+            }
+        }).collect()),
+        _ => return None,
+    };
+    Some( FieldWrite { field, location, tag, field_type, count: count as u32, value: bytes, display: value_text( value )? } )
+}
+
+// Applies the field writes to a TIFF structure, returning the changes. Fields which already
+// have the written value are left alone.
+pub(crate) fn apply_writes( editor: &mut TiffEditor, writes_for: &FieldWrites ) -> Result<Vec<FieldChange>, Box<dyn error::Error>> {
+    let exif_fields = exif::Reader::new().read_raw( editor.data.clone() ).ok();
+    let mut changes = vec![];
+    for write in writes_for( exif_fields.as_ref(), editor.order ) {
+        let old_value = current_value( exif_fields.as_ref(), write.location, write.tag );
+        if old_value.as_deref() == Some(write.display.as_str()) {
+            continue;
+        }
+        editor.set_field( write.location, write.tag, write.field_type, write.count, &write.value )?;
+        changes.push( FieldChange { field: write.field, old_value, new_value: write.display } );
+    }
+    Ok(changes)
+}
+
+// Rewrites the APP1 EXIF segment of a JPEG, or adds one after SOI (and JFIF APP0) if there is none.
+// The other segments and the entropy-coded image data are copied unchanged.
+fn patch_jpeg( data: &[u8], writes_for: &FieldWrites ) -> Result<(Vec<FieldChange>, Vec<u8>), Box<dyn error::Error>> {
+    edit_jpeg_exif( data, &mut |editor| apply_writes( editor, writes_for ) )
+}
+
+// An edit of a TIFF structure, returning what it did.
+pub(crate) type TiffEdit<'a, T> = dyn FnMut( &mut TiffEditor ) -> Result<T, Box<dyn error::Error>> + 'a;
+
+// Edits the TIFF structure of the APP1 EXIF segment of a JPEG, as patch_jpeg does, returning
+// the result of the edit with the rewritten file.
+pub(crate) fn edit_jpeg_exif<T>( data: &[u8], edit: &mut TiffEdit<T> ) -> Result<(T, Vec<u8>), Box<dyn error::Error>> {
+    let segments = jpeg::header_segments( data ).ok_or_else(|| write_error( "JPEG header segments could not be parsed" ))?;
+    let exif_segment = segments.iter()
+        .find(|segment| segment.marker == jpeg::MARKER_APP1 && data[segment.payload.clone()].starts_with( jpeg::EXIF_IDENTIFIER ));
+
+    let mut editor = match exif_segment {
+        Some(segment) => TiffEditor::new( data[segment.payload.start + jpeg::EXIF_IDENTIFIER.len()..segment.payload.end].to_vec() )
+            .ok_or_else(|| write_error( "EXIF segment has no valid TIFF header" ))?,
+        None => TiffEditor::empty(),
+    };
+    let edited = edit( &mut editor )?;
+
+    let mut payload = jpeg::EXIF_IDENTIFIER.to_vec();
+    payload.extend( editor.data );
+    if payload.len() > MAX_SEGMENT_PAYLOAD {
+        return Err( write_error( "EXIF data would no longer fit in a JPEG APP1 segment" ) );
+    }
+    let mut segment = vec![0xFF, jpeg::MARKER_APP1];
+    segment.extend_from_slice( &((payload.len() + 2) as u16).to_be_bytes() );
+    segment.extend( payload );
+
+    let (start, end) = match exif_segment {
+        Some(exif_segment) => (exif_segment.position, exif_segment.payload.end),
+        None => {
+            // JFIF requires its APP0 segment to come first.
+            let position = segments.first()
+                .filter(|first| first.marker == jpeg::MARKER_APP0)
+                .map(|first| first.payload.end)
+                .unwrap_or(2);
+            (position, position)
+        }
+    };
+    let mut output = data[..start].to_vec();
+    output.extend( segment );
+    output.extend_from_slice( &data[end..] );
+    Ok( (edited, output) )
+}
+
+// Checks a file before it is allowed to replace another.
+pub(crate) type FileCheck<'a> = dyn Fn( &Path ) -> Result<(), Box<dyn error::Error>> + 'a;
+
+// Replaces the file by writing a sibling file first, so that a failed write can't leave it truncated.
+// The check runs on the sibling file, and the file is only replaced if it passes.
+pub(crate) fn replace_file_checked( path: &Path, contents: &[u8], check: &FileCheck ) -> Result<(), Box<dyn error::Error>> {
+    replace_file_with( path, &|temporary_path| fs::write( temporary_path, contents ), check )
+}
+
+// Replaces the file with a copy of another, in the same way, without reading it into memory.
+pub(crate) fn copy_file_checked( source: &Path, path: &Path, check: &FileCheck ) -> Result<(), Box<dyn error::Error>> {
+    replace_file_with( path, &|temporary_path| fs::copy( source, temporary_path ).map(|_| ()), check )
+}
+
+fn replace_file_with( path: &Path, write: &dyn Fn( &Path ) -> io::Result<()>, check: &FileCheck ) -> Result<(), Box<dyn error::Error>> {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temporary_path = path.with_file_name( format!(".{}.rusimeta-tmp", file_name) );
+    if let Err(unboxed_err) = write( &temporary_path ) {
+        let _ = fs::remove_file( &temporary_path );
+        return Err( unboxed_err.into() );
+    }
+    let result = check( &temporary_path ).and_then(|_| fs::rename( &temporary_path, path ).map_err(|unboxed_err| unboxed_err.into()));
+    if result.is_err() {
+        let _ = fs::remove_file( &temporary_path );
+    }
+    result
+}
+
+pub(crate) fn replace_file( path: &Path, contents: &[u8] ) -> Result<(), Box<dyn error::Error>> {
+    replace_file_checked( path, contents, &|_| Ok(()) )
+}
+
+// A little-endian TIFF structure holding only the patched fields.
+pub(crate) fn build_tiff( patch: &ImageMetadataPatch ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let mut editor = TiffEditor::empty();
+    apply_writes( &mut editor, &|_, order| field_writes( patch, order ) )?;
+    Ok(editor.data)
+}
+
+// Writes fields into the EXIF data of a JPEG or TIFF file, and returns what changed.
+// Every other tag is kept, nothing which existing offsets point at is moved, and the image data
+// is not recompressed. With dry_run set, the changes are returned but the file is not written.
+pub(crate) fn write_fields( path: &Path, writes_for: &FieldWrites, options: &WriteOptions ) -> Result<Vec<FieldChange>, Box<dyn error::Error>> {
+    let format = format::detect_format( path )?;
+    let data = fs::read( path )?;
+    let (changes, output) = match format {
+        MediaFormat::Jpeg => patch_jpeg( &data, writes_for )?,
+        MediaFormat::Tiff => {
+            let mut editor = TiffEditor::new( data ).ok_or_else(|| write_error( "TIFF header is invalid" ))?;
+            let changes = apply_writes( &mut editor, writes_for )?;
+            (changes, editor.data)
+        },
+        _ => return Err(Box::new(UnsupportedFormatError{ format })),
+    };
+
+    if !changes.is_empty() && !options.dry_run {
+        replace_file( path, &output )?;
+    }
+    Ok(changes)
+}
+
+// Writes the patched fields into the EXIF data of a JPEG or TIFF file, and returns what changed.
+pub fn write_image_metadata( path: &Path, patch: &ImageMetadataPatch, options: &WriteOptions ) -> Result<Vec<FieldChange>, Box<dyn error::Error>> {
+    write_fields( path, &|_, order| field_writes( patch, order ), options )
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use serial_test::serial;
+
+use rusimeta::{BurstOptions, SequenceMembership, VendorDetails};
+use common::{build_jpeg, build_with_maker_note, canon_exif, scratch_dir, TiffValue};
+
+// File name, camera serial, capture time, subsecond time and Canon sequence number.
+type FrameSpec = (&'static str, &'static str, &'static str, Option<&'static str>, Option<u16>);
+
+// A 5D frame from the camera with the serial, with its subsecond time and the sequence number
+// from the ShotInfo record of its MakerNote if given. A frame with a .cr2 path is written as a
+// bare TIFF standing in for the RAW.
+fn write_frame( path: &Path, serial: &'static str, capture_time: &'static str, sub_sec_time: Option<&'static str>, sequence_number: Option<u16> ) {
+    let mut exif = canon_exif();
+    exif.exif[0] = (0x9003, TiffValue::Ascii(capture_time));
+    exif.exif[2] = (0xA431, TiffValue::Ascii(serial));
+    if let Some(sub_sec_time) = sub_sec_time {
+        exif.exif.push( (0x9291, TiffValue::Ascii(sub_sec_time)) );
+    }
+    let tiff = match sequence_number {
+        Some(sequence_number) => {
+            let mut shot_info = vec![0u16; 34];
+            shot_info[9] = sequence_number;
+            build_with_maker_note( &exif, b"", &vec![(0x0004, TiffValue::Short(shot_info))] )
+        },
+        None => exif.build(),
+    };
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("cr2") => fs::write( path, tiff ).unwrap(),
+        _ => fs::write( path, build_jpeg( Some(&tiff), &[] ) ).unwrap(),
+    }
+}
+
+fn burst_files( report: &rusimeta::BurstReport ) -> Vec<Vec<String>> {
+    report.bursts.iter()
+        .map(|burst| burst.frames.iter().flatten().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect())
+        .collect()
+}
+
+#[test]
+#[serial]
+fn consecutive_frames_from_one_camera_are_grouped_into_bursts()
+{
+    // GIVEN two cameras shooting at the same time, a frame taken after a pause, frames only
+    // timed to the second, and Canon frames numbered in their burst
+    let dir = scratch_dir("bursts_grouping");
+    let frames : [FrameSpec; 11] = [
+        ("A_01.jpg", "025021000537", "2019:07:26 13:30:00", Some("10"), None),
+        ("A_02.jpg", "025021000537", "2019:07:26 13:30:00", Some("40"), None),
+        ("A_03.jpg", "025021000537", "2019:07:26 13:30:00", Some("70"), None),
+        ("A_04.jpg", "025021000537", "2019:07:26 13:30:01", Some("00"), None),
+        ("A_05.jpg", "025021000537", "2019:07:26 13:30:05", Some("00"), None),
+        ("B_01.jpg", "083024001234", "2019:07:26 13:30:00", Some("20"), None),
+        ("B_02.jpg", "083024001234", "2019:07:26 13:30:00", Some("50"), None),
+        ("C_01.jpg", "092021004444", "2019:07:26 13:31:10", None, None),
+        ("C_02.jpg", "092021004444", "2019:07:26 13:31:11", None, None),
+        ("D_01.jpg", "101022005555", "2019:07:26 13:32:00", Some("00"), Some(1)),
+        ("D_02.jpg", "101022005555", "2019:07:26 13:32:03", Some("00"), Some(2)),
+    ];
+    for (name, serial, capture_time, sub_sec_time, sequence_number) in frames.iter() {
+        write_frame( &dir.join(name), serial, capture_time, *sub_sec_time, *sequence_number );
+    }
+    write_frame( &dir.join("D_03.jpg"), "101022005555", "2019:07:26 13:32:03", Some("20"), Some(1) );
+    write_frame( &dir.join("D_04.jpg"), "101022005555", "2019:07:26 13:32:03", Some("40"), Some(2) );
+
+    // WHEN the directory is checked for bursts
+    let (report, failures) = rusimeta::find_bursts( std::slice::from_ref( &dir ), &BurstOptions::default() );
+
+    // THEN the frames within a second of each other are grouped per camera, the Canon frames by
+    // their sequence numbers, and the frame after the pause is on its own
+    assert!( failures.is_empty() );
+    assert_eq!( report.files_scanned, 13 );
+    assert_eq!( burst_files( &report ), vec![
+        vec!["A_01.jpg", "A_02.jpg", "A_03.jpg", "A_04.jpg"],
+        vec!["B_01.jpg", "B_02.jpg"],
+        vec!["C_01.jpg", "C_02.jpg"],
+        vec!["D_01.jpg", "D_02.jpg"],
+        vec!["D_03.jpg", "D_04.jpg"],
+    ] );
+    assert_eq!( report.single_files, vec![dir.join("A_05.jpg")] );
+    let first = &report.bursts[0];
+    assert_eq!( first.sequence_id, "20190726T133000.100_A_01" );
+    assert_eq!( first.camera_serial, "025021000537" );
+    assert_eq!( first.duration, chrono::Duration::milliseconds( 900 ) );
+    assert_eq!( first.membership( 1 ), SequenceMembership { sequence_id: first.sequence_id.clone(), position: 2, length: 4 } );
+
+    // AND the sequence number is decoded from the MakerNote
+    let vendor_metadata = rusimeta::read_metadata_of_interest( &dir.join("D_02.jpg") ).unwrap().vendor_metadata.unwrap();
+    match vendor_metadata.details {
+        Some(VendorDetails::Canon(canon)) => assert_eq!( canon.sequence_number, Some(2) ),
+        other => panic!("expected Canon details, got {:?}", other),
+    }
+
+    // AND a smaller gap splits the bursts shot more slowly
+    let (report, _) = rusimeta::find_bursts( std::slice::from_ref( &dir ), &BurstOptions { max_gap: chrono::Duration::milliseconds( 250 ) } );
+    assert_eq!( burst_files( &report ), vec![
+        vec!["C_01.jpg", "C_02.jpg"],
+        vec!["D_01.jpg", "D_02.jpg"],
+        vec!["D_03.jpg", "D_04.jpg"],
+    ] );
+}
+
+#[test]
+#[serial]
+fn the_raw_and_jpeg_of_one_shot_are_one_frame()
+{
+    // GIVEN a Canon shooting RAW+JPEG: a single shot, then a burst of three numbered frames,
+    // each written as a CR2 and a JPEG with the same time
+    let dir = scratch_dir("bursts_raw_jpeg");
+    write_frame( &dir.join("IMG_0001.cr2"), "025021000537", "2019:07:26 13:30:00", Some("00"), None );
+    write_frame( &dir.join("IMG_0001.jpg"), "025021000537", "2019:07:26 13:30:00", Some("00"), None );
+    for (index, sub_sec_time) in ["00", "20", "40"].iter().enumerate() {
+        for extension in ["cr2", "jpg"].iter() {
+            let name = format!("IMG_{:04}.{}", index + 2, extension);
+            write_frame( &dir.join(name), "025021000537", "2019:07:26 13:31:00", Some(sub_sec_time), Some(index as u16 + 1) );
+        }
+    }
+
+    // WHEN the directory is checked for bursts
+    let (report, failures) = rusimeta::find_bursts( std::slice::from_ref( &dir ), &BurstOptions::default() );
+
+    // THEN the single shot is in no burst, and the burst has three frames of two files each
+    assert!( failures.is_empty() );
+    assert_eq!( report.files_scanned, 8 );
+    assert_eq!( report.single_files, vec![dir.join("IMG_0001.cr2"), dir.join("IMG_0001.jpg")] );
+    assert_eq!( report.bursts.len(), 1 );
+    let burst = &report.bursts[0];
+    assert_eq!( burst.frames, vec![
+        vec![dir.join("IMG_0002.cr2"), dir.join("IMG_0002.jpg")],
+        vec![dir.join("IMG_0003.cr2"), dir.join("IMG_0003.jpg")],
+        vec![dir.join("IMG_0004.cr2"), dir.join("IMG_0004.jpg")],
+    ] );
+    assert_eq!( burst.membership( 2 ), SequenceMembership { sequence_id: "20190726T133100.000_IMG_0002".to_string(), position: 3, length: 3 } );
+}
+
+#[test]
+#[serial]
+fn burst_membership_is_recorded_in_sidecars_from_the_command_line()
+{
+    // GIVEN a burst of three frames, one of them with an edited sidecar, and a frame on its own
+    let dir = scratch_dir("bursts_cli");
+    let names = ["IMG_0001.jpg", "IMG_0002.jpg", "IMG_0003.jpg"];
+    for (name, sub_sec_time) in names.iter().zip( ["00", "25", "50"].iter() ) {
+        write_frame( &dir.join(name), "025021000537", "2019:07:26 13:30:00", Some(sub_sec_time), None );
+    }
+    write_frame( &dir.join("IMG_0004.jpg"), "025021000537", "2019:07:26 13:40:00", Some("00"), None );
+    let mut edited = rusimeta::read_metadata_of_interest( &dir.join("IMG_0002.jpg") ).unwrap();
+    edited.image_metadata.camera_model = Some("Canon EOS 5D Mark IV (rented)".to_string());
+    rusimeta::write_json_metadata( &edited, &dir.join("IMG_0002.json") ).unwrap();
+    let run_bursts = |args: &[&str]| {
+        let strings : Vec<String> = ["rusimeta", "bursts"].iter().chain( args.iter() ).map(|arg| arg.to_string()).collect();
+        rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap()
+    };
+    let sequence = |name: &str| -> Option<SequenceMembership> {
+        let sidecar : PathBuf = dir.join(name).with_extension( "json" );
+        rusimeta::read_json_metadata( sidecar.to_str().unwrap() ).unwrap().sequence
+    };
+
+    // WHEN the bursts are listed with --dry-run
+    let summary = run_bursts( &["--dry-run", dir.to_str().unwrap()] );
+
+    // THEN no sidecar is written
+    assert_eq!( (summary.read, summary.failed), (4, 0) );
+    assert!( !dir.join("IMG_0001.json").exists() );
+    assert_eq!( sequence( "IMG_0002.jpg" ), None );
+
+    // WHEN they are recorded
+    run_bursts( &[dir.to_str().unwrap()] );
+
+    // THEN each frame of the burst has its position in its sidecar, and the edit is kept
+    let expected = |position| Some( SequenceMembership { sequence_id: "20190726T133000.000_IMG_0001".to_string(), position, length: 3 } );
+    assert_eq!( sequence( "IMG_0001.jpg" ), expected( 1 ) );
+    assert_eq!( sequence( "IMG_0003.jpg" ), expected( 3 ) );
+    let json : serde_json::Value = serde_json::from_str( &fs::read_to_string( dir.join("IMG_0002.json") ).unwrap() ).unwrap();
+    assert_eq!( json["sequence"]["position"], 2 );
+    assert_eq!( json["camera_model"], "Canon EOS 5D Mark IV (rented)" );
+    assert!( !dir.join("IMG_0004.json").exists() );
+
+    // AND a gap too small for the burst takes the frames out of it again
+    run_bursts( &["--max-gap", "100", dir.to_str().unwrap()] );
+    assert_eq!( sequence( "IMG_0001.jpg" ), None );
+    assert_eq!( sequence( "IMG_0002.jpg" ), None );
+    assert!( rusimeta::Config::new( ["rusimeta", "bursts", "--max-gap", "soon", "a.jpg"].iter().map(|arg| arg.to_string()) ).is_err() );
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use serial_test::serial;
+
+use rusimeta::{Catalog, HashAlgorithm, ReadOptions};
+use common::{build_jpeg, canon_exif, jpeg_segment, scratch_dir};
+
+fn catalog_lines( path: &Path ) -> Vec<serde_json::Value> {
+    fs::read_to_string( path ).unwrap().lines().map(|line| serde_json::from_str( line ).unwrap()).collect()
+}
+
+#[test]
+#[serial]
+fn a_refresh_only_reads_new_and_changed_files_and_drops_deleted_ones()
+{
+    // GIVEN a catalog of a folder of three JPEGs
+    let dir = scratch_dir("catalog_refresh");
+    let folder = dir.join("shoot");
+    fs::create_dir_all( &folder ).unwrap();
+    for name in ["IMG_0001.jpg", "IMG_0002.jpg", "IMG_0003.jpg"].iter() {
+        fs::write( folder.join(name), build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    }
+    let mut catalog = Catalog::default();
+    let (report, failures) = catalog.refresh( std::slice::from_ref( &folder ), &ReadOptions::default() );
+    assert!( failures.is_empty() );
+    assert_eq!( (report.added.len(), report.updated.len(), report.unchanged, report.removed.len()), (3, 0, 0, 0) );
+
+    // WHEN one file is edited, another only touched, another deleted, a new one added, and the
+    // catalog is saved, loaded back and refreshed
+    fs::write( folder.join("IMG_0001.jpg"), build_jpeg( Some(&canon_exif().build()), &[jpeg_segment( 0xDB, &[1; 65] )] ) ).unwrap();
+    fs::File::options().write( true ).open( folder.join("IMG_0002.jpg") ).unwrap()
+        .set_modified( SystemTime::UNIX_EPOCH + Duration::from_secs( 1_600_000_000 ) ).unwrap();
+    fs::remove_file( folder.join("IMG_0003.jpg") ).unwrap();
+    fs::write( folder.join("IMG_0004.jpg"), build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    let catalog_path = dir.join("catalog.json");
+    catalog.save( &catalog_path ).unwrap();
+    let mut catalog = Catalog::load( &catalog_path ).unwrap();
+    let (report, _) = catalog.refresh( std::slice::from_ref( &folder ), &ReadOptions::default() );
+
+    // THEN the edited and touched files are read again, the new one added and the deleted one removed
+    let absolute = |name: &str| fs::canonicalize( &folder ).unwrap().join(name);
+    assert_eq!( report.added, vec![absolute( "IMG_0004.jpg" )] );
+    assert_eq!( report.updated, vec![absolute( "IMG_0001.jpg" ), absolute( "IMG_0002.jpg" )] );
+    assert_eq!( report.removed, vec![absolute( "IMG_0003.jpg" )] );
+    assert_eq!( catalog.len(), 3 );
+
+    // AND another refresh finds nothing to do, unless hashes are asked for
+    let (report, _) = catalog.refresh( std::slice::from_ref( &folder ), &ReadOptions::default() );
+    assert_eq!( (report.added.len(), report.updated.len(), report.unchanged, report.removed.len()), (0, 0, 3, 0) );
+    let hashed = ReadOptions { hash: Some(HashAlgorithm::Sha256), ..Default::default() };
+    let (report, _) = catalog.refresh( std::slice::from_ref( &folder ), &hashed );
+    assert_eq!( report.updated.len(), 3 );
+
+    // AND the entries can be looked up by path and queried
+    let entry = catalog.get( &folder.join("IMG_0002.jpg") ).unwrap();
+    assert_eq!( entry.image_metadata.camera_serial.as_deref(), Some("025021000537") );
+    assert!( entry.file_metadata.hashes.is_some() );
+    let larger = catalog.find(|_, metadata| metadata.file_metadata.size > entry.file_metadata.size);
+    assert_eq!( larger.len(), 1 );
+    assert_eq!( larger[0].0, absolute( "IMG_0001.jpg" ).as_path() );
+}
+
+#[test]
+#[serial]
+fn the_catalog_is_indexed_from_the_command_line()
+{
+    // GIVEN a JPEG and a text file
+    let dir = scratch_dir("catalog_cli");
+    let image = dir.join("IMG_0001.jpg");
+    fs::write( &image, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    fs::write( dir.join("notes.txt"), "shot list" ).unwrap();
+    let catalog_path = dir.with_extension( "catalog.json" );
+
+    // WHEN the folder is indexed with hashes from the command line
+    let strings : Vec<String> = ["rusimeta", "index", "--catalog", catalog_path.to_str().unwrap(), "--hash", "blake3", dir.to_str().unwrap()]
+        .iter().map(|arg| arg.to_string()).collect();
+    let summary = rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap();
+
+    // THEN the JPEG is in the catalog with its hashes, and the text file is skipped
+    assert_eq!( (summary.read, summary.skipped_unsupported, summary.failed), (1, 1, 0) );
+    let lines = catalog_lines( &catalog_path );
+    assert_eq!( lines.len(), 2 );
+    assert_eq!( lines[0]["version"], 2 );
+    assert_eq!( lines[1]["path"], fs::canonicalize( &image ).unwrap().to_str().unwrap() );
+    let entry = &lines[1]["entry"];
+    assert_eq!( entry["camera_serial"], "025021000537" );
+    assert_eq!( entry["hashes"]["algorithm"], "blake3" );
+    assert!( !dir.join("IMG_0001.json").exists() );
+
+    // AND a catalog file is required
+    assert!( rusimeta::Config::new( ["rusimeta", "index", "a.jpg"].iter().map(|arg| arg.to_string()) ).is_err() );
+}
+
+#[test]
+#[serial]
+fn saving_appends_the_changed_entries_until_most_records_are_out_of_date()
+{
+    // GIVEN a saved catalog of a folder of four JPEGs
+    let dir = scratch_dir("catalog_journal");
+    let folder = dir.join("shoot");
+    fs::create_dir_all( &folder ).unwrap();
+    let jpeg = build_jpeg( Some(&canon_exif().build()), &[] );
+    for name in ["IMG_0001.jpg", "IMG_0002.jpg", "IMG_0003.jpg", "IMG_0004.jpg"].iter() {
+        fs::write( folder.join(name), &jpeg ).unwrap();
+    }
+    let catalog_path = dir.join("catalog.json");
+    let mut catalog = Catalog::default();
+    catalog.refresh( std::slice::from_ref( &folder ), &ReadOptions::default() );
+    catalog.save( &catalog_path ).unwrap();
+    let saved = fs::read( &catalog_path ).unwrap();
+
+    // WHEN it is loaded, one file is deleted and another edited, and it is refreshed and saved
+    let mut catalog = Catalog::load( &catalog_path ).unwrap();
+    fs::remove_file( folder.join("IMG_0003.jpg") ).unwrap();
+    fs::write( folder.join("IMG_0001.jpg"), build_jpeg( Some(&canon_exif().build()), &[jpeg_segment( 0xDB, &[1; 65] )] ) ).unwrap();
+    catalog.refresh( std::slice::from_ref( &folder ), &ReadOptions::default() );
+    catalog.save( &catalog_path ).unwrap();
+
+    // THEN only their records are appended, and loading the file gives the catalog as it is now
+    let contents = fs::read( &catalog_path ).unwrap();
+    assert_eq!( &contents[..saved.len()], &saved[..] );
+    let lines = catalog_lines( &catalog_path );
+    let absolute = |name: &str| fs::canonicalize( &folder ).unwrap().join(name).to_string_lossy().into_owned();
+    assert_eq!( lines.len(), 7 );
+    assert_eq!( lines[5]["path"], absolute( "IMG_0001.jpg" ) );
+    assert!( lines[5]["entry"]["size"].as_u64().unwrap() > jpeg.len() as u64 );
+    assert_eq!( lines[6], serde_json::json!({ "path": absolute( "IMG_0003.jpg" ), "removed": true }) );
+    let loaded = Catalog::load( &catalog_path ).unwrap();
+    assert_eq!( loaded.len(), 3 );
+    assert_eq!( loaded.entries().collect::<Vec<_>>(), catalog.entries().collect::<Vec<_>>() );
+
+    // AND a save with nothing changed writes nothing, and a line cut short is left out
+    catalog.save( &catalog_path ).unwrap();
+    assert_eq!( fs::read( &catalog_path ).unwrap(), contents );
+    let mut torn = contents.clone();
+    torn.extend_from_slice( b"{\"path\": \"/cut" );
+    fs::write( &catalog_path, &torn ).unwrap();
+    assert_eq!( Catalog::load( &catalog_path ).unwrap().len(), 3 );
+
+    // AND once more records are out of date than up to date, the file is written whole again
+    let mut catalog = Catalog::load( &catalog_path ).unwrap();
+    catalog.save( &catalog_path ).unwrap();
+    assert_eq!( catalog_lines( &catalog_path ).len(), 4 );
+    for name in ["IMG_0001.jpg", "IMG_0002.jpg", "IMG_0004.jpg"].iter() {
+        fs::remove_file( folder.join(name) ).unwrap();
+    }
+    catalog.refresh( std::slice::from_ref( &folder ), &ReadOptions::default() );
+    catalog.save( &catalog_path ).unwrap();
+    assert_eq!( catalog_lines( &catalog_path ), vec![serde_json::json!({ "version": 2 })] );
+
+    // AND a catalog written by version 1 as a single JSON object is still loaded
+    fs::write( folder.join("IMG_0005.jpg"), &jpeg ).unwrap();
+    let entry = serde_json::to_value( rusimeta::read_metadata_of_interest( &folder.join("IMG_0005.jpg") ).unwrap() ).unwrap();
+    let old_catalog = serde_json::json!({ "version": 1, "entries": { absolute( "IMG_0005.jpg" ): entry } });
+    fs::write( &catalog_path, serde_json::to_string_pretty( &old_catalog ).unwrap() ).unwrap();
+    let catalog = Catalog::load( &catalog_path ).unwrap();
+    assert_eq!( catalog.get( &folder.join("IMG_0005.jpg") ).unwrap().image_metadata.camera_serial.as_deref(), Some("025021000537") );
+}
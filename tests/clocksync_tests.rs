@@ -0,0 +1,140 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use serial_test::serial;
+
+use rusimeta::{ClockSync, Command, ReferencePair, WriteOptions};
+use common::{build_jpeg, canon_exif, scratch_dir, TiffValue};
+
+fn capture_time( text: &str ) -> chrono::NaiveDateTime {
+    chrono::NaiveDateTime::parse_from_str( text, "%Y:%m:%d %H:%M:%S" ).unwrap()
+}
+
+// Writes a JPEG from the given camera with all of its date/time fields set to the given time.
+fn write_shot( dir: &Path, name: &str, camera_serial: &'static str, time: &'static str ) -> PathBuf {
+    let mut exif = canon_exif();
+    exif.ifd0[3] = (0x0132, TiffValue::Ascii(time));
+    exif.exif = vec![
+        (0x9003, TiffValue::Ascii(time)),
+        (0x9004, TiffValue::Ascii(time)),
+        (0xA431, TiffValue::Ascii(camera_serial)),
+    ];
+    let path = dir.join(name);
+    fs::write( &path, build_jpeg( Some(&exif.build()), &[] ) ).unwrap();
+    path
+}
+
+fn paths( paths: &[&PathBuf] ) -> Vec<String> {
+    paths.iter().map(|path| path.to_str().unwrap().to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn drifting_camera_is_fitted_from_two_pairs_and_sidecars_get_the_corrected_time()
+{
+    // GIVEN a second body which was 90s ahead at the start of the day and gained another 20s by the evening
+    let dir = scratch_dir("clocksync_drift");
+    let a_morning = write_shot( &dir, "A_0001.jpg", "025021000537", "2019:07:26 08:00:00" );
+    let b_morning = write_shot( &dir, "B_0001.jpg", "083021001234", "2019:07:26 08:01:30" );
+    let a_evening = write_shot( &dir, "A_0002.jpg", "025021000537", "2019:07:26 20:00:00" );
+    let b_evening = write_shot( &dir, "B_0002.jpg", "083021001234", "2019:07:26 20:01:50" );
+    let b_noon = write_shot( &dir, "B_0003.jpg", "083021001234", "2019:07:26 14:01:40" );
+    let b_noon_original = fs::read( &b_noon ).unwrap();
+    let sync = ClockSync {
+        pairs: vec![
+            ReferencePair { first: a_morning.clone(), second: b_morning.clone() },
+            ReferencePair { first: a_evening.clone(), second: b_evening.clone() },
+        ],
+        ..Default::default()
+    };
+
+    // WHEN the corrections are worked out, and the clocks synchronized into the sidecars
+    let corrections = rusimeta::compute_clock_corrections( &sync ).unwrap();
+    let summary = rusimeta::run( rusimeta::Config::from_strings( paths( &[&a_morning, &b_noon] ) )
+        .with_command( Command::Sync { sync, options: WriteOptions::default() } ) ).unwrap();
+
+    // THEN the second body has an offset and a drift, and the first (reference) body has no correction
+    assert_eq!( corrections.len(), 1 );
+    assert_eq!( corrections[0].camera_serial, "083021001234" );
+    assert_eq!( corrections[0].offset, chrono::Duration::seconds( -100 ) );
+    assert!( (corrections[0].drift_seconds_per_day - -40.0).abs() < 0.1 );
+
+    // AND the sidecar of the noon shot is on the reference clock, while the file itself is untouched
+    assert_eq!( summary.read, 2 );
+    let sidecar = rusimeta::read_json_metadata( dir.join("B_0003.json").to_str().unwrap() ).unwrap();
+    assert_eq!( sidecar.image_metadata.capture_time, Some(capture_time("2019:07:26 14:00:00")) );
+    let sidecar = rusimeta::read_json_metadata( dir.join("A_0001.json").to_str().unwrap() ).unwrap();
+    assert_eq!( sidecar.image_metadata.capture_time, Some(capture_time("2019:07:26 08:00:00")) );
+    assert_eq!( fs::read( &b_noon ).unwrap(), b_noon_original );
+}
+
+#[test]
+#[serial]
+fn synchronizing_into_sidecars_keeps_what_is_only_in_them_and_refuses_redacted_ones()
+{
+    // GIVEN a second body 90s ahead, a shot from it whose sidecar was geotagged and edited, and
+    // another whose sidecar was redacted
+    let dir = scratch_dir("clocksync_sidecars");
+    let a_morning = write_shot( &dir, "A_0001.jpg", "025021000537", "2019:07:26 08:00:00" );
+    let b_morning = write_shot( &dir, "B_0001.jpg", "083021001234", "2019:07:26 08:01:30" );
+    let b_noon = write_shot( &dir, "B_0002.jpg", "083021001234", "2019:07:26 12:01:30" );
+    let b_evening = write_shot( &dir, "B_0003.jpg", "083021001234", "2019:07:26 18:01:30" );
+    let mut edited = rusimeta::read_metadata_of_interest( &b_noon ).unwrap();
+    edited.image_metadata.camera_model = Some("Canon EOS 5D Mark IV (rented)".to_string());
+    edited.image_metadata.location = Some( rusimeta::GpsLocation { latitude: 51.5, longitude: -0.15, altitude: None } );
+    rusimeta::write_json_metadata( &edited, &dir.join("B_0002.json") ).unwrap();
+    let redaction = rusimeta::Redaction { serial_key: Some(b"secret".to_vec()), ..Default::default() };
+    rusimeta::run( rusimeta::Config::from_strings( paths( &[&b_evening] ) ).with_redaction( redaction ) ).unwrap();
+    let redacted = fs::read( dir.join("B_0003.json") ).unwrap();
+    let sync = ClockSync { pairs: vec![ReferencePair { first: a_morning, second: b_morning }], ..Default::default() };
+
+    // WHEN the clocks are synchronized into the sidecars
+    let summary = rusimeta::run( rusimeta::Config::from_strings( paths( &[&b_noon, &b_evening] ) )
+        .with_command( Command::Sync { sync, options: WriteOptions::default() } ) ).unwrap();
+
+    // THEN the edited sidecar gets the corrected time and keeps its location and edit
+    let sidecar = rusimeta::read_json_metadata( dir.join("B_0002.json").to_str().unwrap() ).unwrap();
+    assert_eq!( sidecar.image_metadata.capture_time, Some(capture_time("2019:07:26 12:00:00")) );
+    assert_eq!( sidecar.image_metadata.camera_model.as_deref(), Some("Canon EOS 5D Mark IV (rented)") );
+    assert_eq!( sidecar.image_metadata.location.map(|location| location.latitude), Some(51.5) );
+
+    // AND the redacted sidecar is left as it was, and counted as a failure
+    assert_eq!( (summary.read, summary.failed), (1, 1) );
+    assert_eq!( fs::read( dir.join("B_0003.json") ).unwrap(), redacted );
+}
+
+#[test]
+#[serial]
+fn chained_cameras_are_written_back_and_unconnected_pairs_fail()
+{
+    // GIVEN three bodies, where the third was only paired with the second
+    let dir = scratch_dir("clocksync_chain");
+    let a = write_shot( &dir, "A_0001.jpg", "025021000537", "2019:07:26 10:00:00" );
+    let b = write_shot( &dir, "B_0001.jpg", "083021001234", "2019:07:26 09:59:00" );
+    let b_later = write_shot( &dir, "B_0002.jpg", "083021001234", "2019:07:26 11:00:00" );
+    let c_later = write_shot( &dir, "C_0002.jpg", "012345678901", "2019:07:26 12:00:30" );
+    let sync = ClockSync {
+        pairs: vec![
+            ReferencePair { first: a.clone(), second: b.clone() },
+            ReferencePair { first: b_later.clone(), second: c_later.clone() },
+        ],
+        write_back: true,
+        ..Default::default()
+    };
+
+    // WHEN the clocks are synchronized and written back into the files
+    let summary = rusimeta::run( rusimeta::Config::from_strings( paths( &[&b_later, &c_later] ) )
+        .with_command( Command::Sync { sync: sync.clone(), options: WriteOptions::default() } ) ).unwrap();
+
+    // THEN both files now hold the time on the first body's clock
+    assert_eq!( summary.read, 2 );
+    for path in [&b_later, &c_later].iter() {
+        let metadata = rusimeta::read_metadata_of_interest( path ).unwrap();
+        assert_eq!( metadata.image_metadata.capture_time, Some(capture_time("2019:07:26 11:01:00")) );
+    }
+
+    // AND a reference camera which no pair connects to the others is an error
+    let unconnected = ClockSync { reference_serial: Some("999".to_string()), ..sync };
+    assert!( rusimeta::compute_clock_corrections( &unconnected ).is_err() );
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use common::{build_icc_profile, build_jpeg, canon_exif, icc_profile_segments, scratch_dir, ExifBuilder, TiffValue};
+
+#[test]
+#[serial]
+fn jpeg_icc_chunks_are_reassembled_and_mismatch_with_srgb_is_flagged()
+{
+    // GIVEN a JPEG tagged sRGB in EXIF, but carrying an Adobe RGB profile split over two APP2 segments stored out of order
+    let dir = scratch_dir("color_jpeg");
+    let path = dir.join("IMG_0201.jpg");
+    let mut exif = canon_exif();
+    exif.exif.push( (0xA001, TiffValue::Short(vec![1])) );
+    let profile = build_icc_profile( "Adobe RGB (1998)", b"RGB ", 2, 0 );
+    let mut segments = icc_profile_segments( &profile, 100 );
+    segments.reverse();
+    fs::write( &path, build_jpeg( Some(&exif.build()), &segments ) ).unwrap();
+
+    // WHEN the metadata is read
+    let color = rusimeta::read_metadata_of_interest( &path ).unwrap().color.expect("color should be reported");
+
+    // THEN the profile is summarized and the disagreement with the EXIF ColorSpace is flagged
+    assert_eq!( color.exif_color_space, Some("srgb".to_string()) );
+    assert_eq!( color.profile_description, Some("Adobe RGB (1998)".to_string()) );
+    assert_eq!( color.profile_color_space, Some("RGB".to_string()) );
+    assert_eq!( color.profile_version, Some("2.1.0".to_string()) );
+    assert_eq!( color.rendering_intent, Some("perceptual".to_string()) );
+    assert_eq!( color.warnings, vec!["EXIF ColorSpace is sRGB but the embedded ICC profile is \"Adobe RGB (1998)\"".to_string()] );
+}
+
+#[test]
+#[serial]
+fn tiff_inter_color_profile_is_read()
+{
+    // GIVEN a TIFF tagged sRGB with a matching version 4 profile in tag 34675
+    let dir = scratch_dir("color_tiff");
+    let path = dir.join("scan.tif");
+    let exif = ExifBuilder {
+        ifd0: vec![
+            (0x0100, TiffValue::Long(vec![64])),
+            (0x0101, TiffValue::Long(vec![48])),
+            (0x8773, TiffValue::Undefined(build_icc_profile( "sRGB IEC61966-2.1", b"RGB ", 4, 1 ))),
+        ],
+        exif: vec![(0xA001, TiffValue::Short(vec![1]))],
+        ..Default::default()
+    };
+    fs::write( &path, exif.build() ).unwrap();
+
+    // WHEN the metadata is read
+    let color = rusimeta::read_metadata_of_interest( &path ).unwrap().color.expect("color should be reported");
+
+    // THEN the profile is summarized with no warnings
+    assert_eq!( color.profile_description, Some("sRGB IEC61966-2.1".to_string()) );
+    assert_eq!( color.profile_version, Some("4.1.0".to_string()) );
+    assert_eq!( color.rendering_intent, Some("relative_colorimetric".to_string()) );
+    assert!( color.warnings.is_empty(), "{:?}", color.warnings );
+}
@@ -0,0 +1,792 @@
+// Builders for small synthetic media files, so that tests of container parsing
+// do not depend on large binary resources being present.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+
+// Returns an empty scratch directory for a test to write its files into.
+pub fn scratch_dir( name: &str ) -> PathBuf {
+    let dir = std::env::temp_dir().join( format!("rusimeta_test_{}_{}", std::process::id(), name) );
+    if dir.exists() {
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+    fs::create_dir_all( &dir ).unwrap();
+    dir
+}
+
+pub fn atom( kind: &[u8; 4], body: &[u8] ) -> Vec<u8> {
+    let mut bytes = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    bytes.extend_from_slice( kind );
+    bytes.extend_from_slice( body );
+    bytes
+}
+
+pub fn full_atom( kind: &[u8; 4], version: u8, body: &[u8] ) -> Vec<u8> {
+    let mut full_body = vec![version, 0, 0, 0];
+    full_body.extend_from_slice( body );
+    atom( kind, &full_body )
+}
+
+fn user_data_text( kind: &[u8; 4], text: &str ) -> Vec<u8> {
+    let mut body = (text.len() as u16).to_be_bytes().to_vec();
+    body.extend_from_slice( &0x55c4u16.to_be_bytes() ); // language code
+    body.extend_from_slice( text.as_bytes() );
+    atom( kind, &body )
+}
+
+pub struct Mp4Description<'a> {
+    pub creation_seconds: u32,
+    pub timescale: u32,
+    pub duration: u32,
+    pub codec: &'a [u8; 4],
+    pub width: u16,
+    pub height: u16,
+    pub make: Option<&'a str>,
+    pub model: Option<&'a str>,
+    pub iso6709_location: Option<&'a str>,
+    pub metadata_keys: Vec<(&'a str, &'a str)>,
+}
+
+pub fn build_mp4( description: &Mp4Description ) -> Vec<u8> {
+    let mut mvhd = vec![];
+    mvhd.extend_from_slice( &description.creation_seconds.to_be_bytes() );
+    mvhd.extend_from_slice( &description.creation_seconds.to_be_bytes() ); // modification time
+    mvhd.extend_from_slice( &description.timescale.to_be_bytes() );
+    mvhd.extend_from_slice( &description.duration.to_be_bytes() );
+    mvhd.extend_from_slice( &[0u8; 80] ); // rate, volume, matrix, next track id, ...
+
+    let mut hdlr = vec![0u8; 4];
+    hdlr.extend_from_slice( b"vide" );
+    hdlr.extend_from_slice( &[0u8; 13] );
+
+    let mut sample_entry = vec![0u8; 6];
+    sample_entry.extend_from_slice( &1u16.to_be_bytes() );
+    sample_entry.extend_from_slice( &[0u8; 16] );
+    sample_entry.extend_from_slice( &description.width.to_be_bytes() );
+    sample_entry.extend_from_slice( &description.height.to_be_bytes() );
+    sample_entry.extend_from_slice( &[0u8; 50] );
+    let mut stsd = 1u32.to_be_bytes().to_vec();
+    stsd.extend( atom( description.codec, &sample_entry ) );
+
+    let stbl = atom( b"stbl", &full_atom( b"stsd", 0, &stsd ) );
+    let minf = atom( b"minf", &stbl );
+    let mut mdia = full_atom( b"hdlr", 0, &hdlr );
+    mdia.extend( minf );
+    let trak = atom( b"trak", &atom( b"mdia", &mdia ) );
+
+    let mut udta = vec![];
+    if let Some(make) = description.make {
+        udta.extend( user_data_text( b"\xa9mak", make ) );
+    }
+    if let Some(model) = description.model {
+        udta.extend( user_data_text( b"\xa9mod", model ) );
+    }
+    if let Some(location) = description.iso6709_location {
+        udta.extend( user_data_text( b"\xa9xyz", location ) );
+    }
+
+    let mut moov = full_atom( b"mvhd", 0, &mvhd );
+    moov.extend( trak );
+    moov.extend( atom( b"udta", &udta ) );
+
+    if !description.metadata_keys.is_empty() {
+        let mut keys = (description.metadata_keys.len() as u32).to_be_bytes().to_vec();
+        let mut ilst = vec![];
+        for (index, (key, value)) in description.metadata_keys.iter().enumerate() {
+            keys.extend( atom( b"mdta", key.as_bytes() ) );
+            let mut data = 1u32.to_be_bytes().to_vec(); // UTF-8
+            data.extend_from_slice( &[0u8; 4] );
+            data.extend_from_slice( value.as_bytes() );
+            ilst.extend( atom( &((index + 1) as u32).to_be_bytes(), &atom( b"data", &data ) ) );
+        }
+        let mut meta_hdlr = vec![0u8; 4];
+        meta_hdlr.extend_from_slice( b"mdta" );
+        meta_hdlr.extend_from_slice( &[0u8; 13] );
+        let mut meta = full_atom( b"hdlr", 0, &meta_hdlr );
+        meta.extend( full_atom( b"keys", 0, &keys ) );
+        meta.extend( atom( b"ilst", &ilst ) );
+        moov.extend( atom( b"meta", &meta ) );
+    }
+
+    let mut ftyp = b"isom".to_vec();
+    ftyp.extend_from_slice( &0x200u32.to_be_bytes() );
+    ftyp.extend_from_slice( b"isomiso2avc1mp41" );
+
+    let mut file = atom( b"ftyp", &ftyp );
+    file.extend( atom( b"mdat", &[0u8; 64] ) );
+    file.extend( atom( b"moov", &moov ) );
+    file
+}
+
+#[derive(Debug,Clone)]
+pub enum TiffValue {
+    Ascii(&'static str),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    Undefined(Vec<u8>),
+}
+
+impl TiffValue {
+    fn type_and_count( &self ) -> (u16, u32) {
+        match self {
+            TiffValue::Ascii(text) => (2, text.len() as u32 + 1),
+            TiffValue::Short(values) => (3, values.len() as u32),
+            TiffValue::Long(values) => (4, values.len() as u32),
+            TiffValue::Rational(values) => (5, values.len() as u32),
+            TiffValue::Undefined(bytes) => (7, bytes.len() as u32),
+        }
+    }
+
+    fn to_le_bytes( &self ) -> Vec<u8> {
+        match self {
+            TiffValue::Ascii(text) => {
+                let mut bytes = text.as_bytes().to_vec();
+                bytes.push(0);
+                bytes
+            },
+            TiffValue::Short(values) => values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect(),
+            TiffValue::Long(values) => values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect(),
+            TiffValue::Rational(values) => values.iter()
+                .flat_map(|(numerator, denominator)| {
+                    let mut bytes = numerator.to_le_bytes().to_vec();
+                    bytes.extend_from_slice( &denominator.to_le_bytes() );
+                    bytes
+                })
+                .collect(),
+            TiffValue::Undefined(bytes) => bytes.clone(),
+        }
+    }
+}
+
+pub type TiffEntries = Vec<(u16, TiffValue)>;
+
+fn ifd_length( entries: &[(u16, TiffValue)] ) -> usize {
+    let data_length : usize = entries.iter()
+        .map(|(_, value)| value.to_le_bytes().len())
+        .filter(|length| *length > 4)
+        .map(|length| length + length % 2)
+        .sum();
+    2 + entries.len() * 12 + 4 + data_length
+}
+
+// Writes an IFD at the end of `out`, with its out-of-line values directly after it.
+fn write_ifd( out: &mut Vec<u8>, entries: &[(u16, TiffValue)], next_ifd_offset: u32 ) {
+    let mut sorted = entries.to_vec();
+    sorted.sort_by_key(|(tag, _)| *tag);
+    let start = out.len();
+    let mut data_offset = start + 2 + sorted.len() * 12 + 4;
+    let mut data = vec![];
+
+    out.extend_from_slice( &(sorted.len() as u16).to_le_bytes() );
+    for (tag, value) in &sorted {
+        let (value_type, count) = value.type_and_count();
+        let mut bytes = value.to_le_bytes();
+        out.extend_from_slice( &tag.to_le_bytes() );
+        out.extend_from_slice( &value_type.to_le_bytes() );
+        out.extend_from_slice( &count.to_le_bytes() );
+        if bytes.len() <= 4 {
+            bytes.resize( 4, 0 );
+            out.extend_from_slice( &bytes );
+        } else {
+            out.extend_from_slice( &(data_offset as u32).to_le_bytes() );
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            data_offset += bytes.len();
+            data.extend( bytes );
+        }
+    }
+    out.extend_from_slice( &next_ifd_offset.to_le_bytes() );
+    out.extend( data );
+}
+
+// Describes the EXIF block of a synthetic image as little-endian TIFF.
+// The IFD chain is IFD0, then an IFD for the thumbnail if there is one, then any further pages.
+#[derive(Debug,Clone,Default)]
+pub struct ExifBuilder {
+    pub ifd0: TiffEntries,
+    pub exif: TiffEntries,
+    pub gps: TiffEntries,
+    pub thumbnail: Option<Vec<u8>>,
+    pub pages: Vec<TiffEntries>,
+}
+
+impl ExifBuilder {
+    pub fn build( &self ) -> Vec<u8> {
+        let mut ifd0 = self.ifd0.clone();
+        if !self.exif.is_empty() {
+            ifd0.push( (0x8769, TiffValue::Long(vec![0])) );
+        }
+        if !self.gps.is_empty() {
+            ifd0.push( (0x8825, TiffValue::Long(vec![0])) );
+        }
+        let mut chain : Vec<TiffEntries> = vec![ifd0];
+        if self.thumbnail.is_some() {
+            chain.push( vec![
+                (0x0103, TiffValue::Short(vec![6])),
+                (0x0201, TiffValue::Long(vec![0])),
+                (0x0202, TiffValue::Long(vec![0])),
+            ] );
+        }
+        chain.extend( self.pages.iter().cloned() );
+
+        let mut chain_offsets = vec![];
+        let mut offset = 8;
+        for ifd in &chain {
+            chain_offsets.push( offset );
+            offset += ifd_length( ifd );
+        }
+        let exif_offset = offset;
+        let gps_offset = exif_offset + if self.exif.is_empty() { 0 } else { ifd_length( &self.exif ) };
+        let thumbnail_offset = gps_offset + if self.gps.is_empty() { 0 } else { ifd_length( &self.gps ) };
+
+        for (tag, value) in chain[0].iter_mut() {
+            match tag {
+                0x8769 => *value = TiffValue::Long(vec![exif_offset as u32]),
+                0x8825 => *value = TiffValue::Long(vec![gps_offset as u32]),
+                _ => {}
+            }
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            chain[1][1].1 = TiffValue::Long(vec![thumbnail_offset as u32]);
+            chain[1][2].1 = TiffValue::Long(vec![thumbnail.len() as u32]);
+        }
+
+        let mut out = b"II*\0".to_vec();
+        out.extend_from_slice( &8u32.to_le_bytes() );
+        for (index, ifd) in chain.iter().enumerate() {
+            let next = chain_offsets.get(index + 1).cloned().unwrap_or(0);
+            write_ifd( &mut out, ifd, next as u32 );
+        }
+        if !self.exif.is_empty() {
+            write_ifd( &mut out, &self.exif, 0 );
+        }
+        if !self.gps.is_empty() {
+            write_ifd( &mut out, &self.gps, 0 );
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            out.extend_from_slice( thumbnail );
+        }
+        out
+    }
+}
+
+pub fn jpeg_segment( marker: u8, body: &[u8] ) -> Vec<u8> {
+    let mut bytes = vec![0xFF, marker];
+    bytes.extend_from_slice( &((body.len() + 2) as u16).to_be_bytes() );
+    bytes.extend_from_slice( body );
+    bytes
+}
+
+// Stand-in for the compressed image: a frame header, scan header and entropy-coded bytes.
+// It is not decodable, but it lets tests check that image data is carried through untouched.
+pub const FAKE_JPEG_IMAGE_DATA : &[u8] = b"\xFF\xC0\x00\x0B\x08\x00\x10\x00\x10\x01\x01\x11\x00\
+\xFF\xDA\x00\x08\x01\x01\x00\x00\x3F\x00\x12\x34\xFF\x00\x56\x78\x9A\xBC\xDE\xF0\xFF\xD9";
+
+pub fn build_jpeg( exif_tiff: Option<&[u8]>, other_segments: &[Vec<u8>] ) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xD8];
+    if let Some(tiff) = exif_tiff {
+        let mut body = b"Exif\0\0".to_vec();
+        body.extend_from_slice( tiff );
+        bytes.extend( jpeg_segment( 0xE1, &body ) );
+    }
+    for segment in other_segments {
+        bytes.extend_from_slice( segment );
+    }
+    bytes.extend_from_slice( FAKE_JPEG_IMAGE_DATA );
+    bytes
+}
+
+// The EXIF of a typical 5D Mark IV frame, as in the image resources.
+pub fn canon_exif() -> ExifBuilder {
+    ExifBuilder {
+        ifd0: vec![
+            (0x010F, TiffValue::Ascii("Canon")),
+            (0x0110, TiffValue::Ascii("Canon EOS 5D Mark IV")),
+            (0x0112, TiffValue::Short(vec![1])),
+            (0x0132, TiffValue::Ascii("2019:07:26 13:25:33")),
+        ],
+        exif: vec![
+            (0x9003, TiffValue::Ascii("2019:07:26 13:25:33")),
+            (0x9004, TiffValue::Ascii("2019:07:26 13:25:33")),
+            (0xA431, TiffValue::Ascii("025021000537")),
+        ],
+        ..Default::default()
+    }
+}
+
+// Builds a two-image MPO file: the first image carries the APP2 "MPF" index pointing at the second.
+pub fn build_mpo( first_exif: &[u8], second_exif: &[u8], second_mp_type: u32 ) -> Vec<u8> {
+    let second = build_jpeg( Some(second_exif), &[] );
+    let mpf_segment = |first_size: u32, second_offset: u32| -> Vec<u8> {
+        let mut entries = vec![];
+        for (attribute, size, offset) in [(0x2003_0000u32, first_size, 0u32), (second_mp_type, second.len() as u32, second_offset)].iter() {
+            entries.extend_from_slice( &attribute.to_le_bytes() );
+            entries.extend_from_slice( &size.to_le_bytes() );
+            entries.extend_from_slice( &offset.to_le_bytes() );
+            entries.extend_from_slice( &[0u8; 4] );
+        }
+        let index = ExifBuilder {
+            ifd0: vec![
+                (0xB000, TiffValue::Undefined(b"0100".to_vec())),
+                (0xB001, TiffValue::Long(vec![2])),
+                (0xB002, TiffValue::Undefined(entries)),
+            ],
+            ..Default::default()
+        };
+        let mut body = b"MPF\0".to_vec();
+        body.extend( index.build() );
+        jpeg_segment( 0xE2, &body )
+    };
+
+    // The segment has the same length whatever the values, so lay the file out once to find the offsets.
+    let first_size = build_jpeg( Some(first_exif), &[mpf_segment( 0, 0 )] ).len();
+    let mpf_header_position = 2 + 4 + 6 + first_exif.len() + 4 + 4;
+    let second_offset = first_size - mpf_header_position;
+
+    let mut file = build_jpeg( Some(first_exif), &[mpf_segment( first_size as u32, second_offset as u32 )] );
+    file.extend( second );
+    file
+}
+
+// Builds a minimal ICC profile holding only a header and a 'desc' tag. Version 2 profiles
+// use a textDescriptionType description, version 4 profiles a multiLocalizedUnicodeType.
+pub fn build_icc_profile( description: &str, data_color_space: &[u8; 4], major_version: u8, rendering_intent: u32 ) -> Vec<u8> {
+    let desc = if major_version >= 4 {
+        let text : Vec<u8> = description.encode_utf16().flat_map(|unit| unit.to_be_bytes().to_vec()).collect();
+        let mut tag = b"mluc".to_vec();
+        tag.extend_from_slice( &[0u8; 4] );
+        tag.extend_from_slice( &1u32.to_be_bytes() );
+        tag.extend_from_slice( &12u32.to_be_bytes() );
+        tag.extend_from_slice( b"enUS" );
+        tag.extend_from_slice( &(text.len() as u32).to_be_bytes() );
+        tag.extend_from_slice( &28u32.to_be_bytes() );
+        tag.extend( text );
+        tag
+    } else {
+        let mut tag = b"desc".to_vec();
+        tag.extend_from_slice( &[0u8; 4] );
+        tag.extend_from_slice( &(description.len() as u32 + 1).to_be_bytes() );
+        tag.extend_from_slice( description.as_bytes() );
+        tag.push( 0 );
+        tag
+    };
+
+    let desc_offset = 128 + 4 + 12;
+    let mut profile = vec![0u8; 128];
+    profile[8] = major_version;
+    profile[9] = 0x10;
+    profile[12..16].copy_from_slice( b"mntr" );
+    profile[16..20].copy_from_slice( data_color_space );
+    profile[20..24].copy_from_slice( b"XYZ " );
+    profile[36..40].copy_from_slice( b"acsp" );
+    profile[64..68].copy_from_slice( &rendering_intent.to_be_bytes() );
+    profile.extend_from_slice( &1u32.to_be_bytes() );
+    profile.extend_from_slice( b"desc" );
+    profile.extend_from_slice( &(desc_offset as u32).to_be_bytes() );
+    profile.extend_from_slice( &(desc.len() as u32).to_be_bytes() );
+    profile.extend( desc );
+    let size = profile.len() as u32;
+    profile[0..4].copy_from_slice( &size.to_be_bytes() );
+    profile
+}
+
+// Splits an ICC profile into APP2 "ICC_PROFILE" segments of at most `chunk_size` bytes each.
+pub fn icc_profile_segments( profile: &[u8], chunk_size: usize ) -> Vec<Vec<u8>> {
+    let chunks : Vec<&[u8]> = profile.chunks( chunk_size ).collect();
+    chunks.iter().enumerate().map(|(index, chunk)| {
+        let mut body = b"ICC_PROFILE\0".to_vec();
+        body.push( index as u8 + 1 );
+        body.push( chunks.len() as u8 );
+        body.extend_from_slice( chunk );
+        jpeg_segment( 0xE2, &body )
+    }).collect()
+}
+
+// Builds the EXIF block with a MakerNote made of a vendor header followed by an IFD, as Canon
+// (with no header) and Sony write it. Offsets in such a MakerNote are relative to the EXIF TIFF
+// header, so the block is laid out once with a placeholder of the same length to find where it lands.
+pub fn build_with_maker_note( builder: &ExifBuilder, header: &[u8], maker_note: &TiffEntries ) -> Vec<u8> {
+    let placeholder = vec![0xA5u8; header.len() + ifd_length( maker_note )];
+    let mut with_placeholder = builder.clone();
+    with_placeholder.exif.push( (0x927C, TiffValue::Undefined(placeholder.clone())) );
+    let layout = with_placeholder.build();
+    let position = layout.windows( placeholder.len() ).position(|window| window == &placeholder[..]).unwrap();
+
+    let mut note = vec![0u8; position];
+    note.extend_from_slice( header );
+    write_ifd( &mut note, maker_note, 0 );
+    let note = note.split_off( position );
+    let mut with_note = builder.clone();
+    with_note.exif.push( (0x927C, TiffValue::Undefined(note)) );
+    with_note.build()
+}
+
+// Builds a Nikon type 3 MakerNote: "Nikon\0", a version, then a TIFF header of its own
+// which the offsets in the MakerNote are relative to.
+pub fn nikon_maker_note( maker_note: &TiffEntries ) -> Vec<u8> {
+    let mut note = b"Nikon\0\x02\x10\0\0".to_vec();
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend_from_slice( &8u32.to_le_bytes() );
+    write_ifd( &mut tiff, maker_note, 0 );
+    note.extend( tiff );
+    note
+}
+
+fn png_crc32( bytes: &[u8] ) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// Builds a PNG from (chunk type, data) pairs, adding the signature, lengths, CRCs and IEND.
+pub fn build_png( chunks: &[(&[u8; 4], Vec<u8>)] ) -> Vec<u8> {
+    let mut bytes = b"\x89PNG\r\n\x1A\n".to_vec();
+    for (kind, data) in chunks.iter().chain( [(b"IEND", vec![])].iter() ) {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice( data );
+        bytes.extend_from_slice( &(data.len() as u32).to_be_bytes() );
+        bytes.extend_from_slice( &chunk );
+        bytes.extend_from_slice( &png_crc32( &chunk ).to_be_bytes() );
+    }
+    bytes
+}
+
+// Builds a WebP (RIFF) file from (FourCC, data) pairs, padding odd-sized chunks.
+pub fn build_webp( chunks: &[(&[u8; 4], Vec<u8>)] ) -> Vec<u8> {
+    let mut body = b"WEBP".to_vec();
+    for (fourcc, data) in chunks {
+        body.extend_from_slice( *fourcc );
+        body.extend_from_slice( &(data.len() as u32).to_le_bytes() );
+        body.extend_from_slice( data );
+        if data.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend_from_slice( &(body.len() as u32).to_le_bytes() );
+    bytes.extend( body );
+    bytes
+}
+
+// The position in a block, in row-major order, of each coefficient in zigzag order.
+const ZIGZAG : [usize; 64] = [
+     0,  1,  8, 16,  9,  2,  3, 10, 17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// One component of a coded JPEG: its sampling factors and its quantized DCT coefficients, block
+// by block in row-major order, covering whole MCUs. Coefficients are in row-major order too.
+#[derive(Debug,Clone,PartialEq)]
+pub struct CodedComponent {
+    pub sampling: (usize, usize),
+    pub blocks_wide: usize,
+    pub blocks: Vec<[i16; 64]>,
+}
+
+// The image data of a baseline JPEG whose components all use quantization table 0.
+#[derive(Debug,Clone,PartialEq)]
+pub struct CodedJpeg {
+    pub width: usize,
+    pub height: usize,
+    // In row-major order.
+    pub quantization: Vec<u16>,
+    pub components: Vec<CodedComponent>,
+}
+
+impl CodedJpeg {
+    // A JPEG of the given size with blocks whose coefficients vary with their position, and a
+    // quantization table which isn't symmetric.
+    pub fn patterned( width: usize, height: usize, samplings: &[(usize, usize)] ) -> CodedJpeg {
+        let (mcu_width, mcu_height) = coded_mcu_size( samplings );
+        let (mcus_wide, mcus_high) = (width.div_ceil( mcu_width ), height.div_ceil( mcu_height ));
+        let components = samplings.iter().enumerate()
+            .map(|(index, sampling)| {
+                let (blocks_wide, blocks_high) = (mcus_wide * sampling.0, mcus_high * sampling.1);
+                let blocks = (0..blocks_wide * blocks_high)
+                    .map(|block| {
+                        let mut coefficients = [0i16; 64];
+                        coefficients[0] = (block * 7 % 50) as i16 - 25 + index as i16;
+                        coefficients[1] = (block % 5) as i16 - 2;
+                        coefficients[8] = (block % 3) as i16 + 1;
+                        coefficients[9] = -3;
+                        coefficients[2 + block % 6] = 2;
+                        coefficients[63 - block % 4 * 8] = -1;
+                        coefficients
+                    })
+                    .collect();
+                CodedComponent { sampling: *sampling, blocks_wide, blocks }
+            })
+            .collect();
+        CodedJpeg { width, height, quantization: (1..=64).collect(), components }
+    }
+
+    // The samples of a component after the inverse DCT, as rows of its plane.
+    pub fn samples( &self, index: usize ) -> Vec<Vec<f64>> {
+        let component = &self.components[index];
+        let samplings : Vec<(usize, usize)> = self.components.iter().map(|component| component.sampling).collect();
+        let (max_horizontal, max_vertical) = (coded_mcu_size( &samplings ).0 / 8, coded_mcu_size( &samplings ).1 / 8);
+        let plane_width = (self.width * component.sampling.0).div_ceil( max_horizontal );
+        let plane_height = (self.height * component.sampling.1).div_ceil( max_vertical );
+        let scale = |frequency: usize| if frequency == 0 { std::f64::consts::FRAC_1_SQRT_2 } else { 1.0 };
+        (0..plane_height).map(|y| (0..plane_width).map(|x| {
+            let block = &component.blocks[(y / 8) * component.blocks_wide + x / 8];
+            let (x, y) = ((x % 8) as f64, (y % 8) as f64);
+            let mut sample = 0.0;
+            for (position, coefficient) in block.iter().enumerate() {
+                let (u, v) = (position % 8, position / 8);
+                sample += scale( u ) * scale( v ) * (*coefficient as f64) * (self.quantization[position] as f64)
+                    * ((2.0 * x + 1.0) * u as f64 * std::f64::consts::PI / 16.0).cos()
+                    * ((2.0 * y + 1.0) * v as f64 * std::f64::consts::PI / 16.0).cos();
+            }
+            sample / 4.0
+        }).collect()).collect()
+    }
+}
+
+fn coded_mcu_size( samplings: &[(usize, usize)] ) -> (usize, usize) {
+    if samplings.len() == 1 {
+        return (8, 8);
+    }
+    (8 * samplings.iter().map(|sampling| sampling.0).max().unwrap(), 8 * samplings.iter().map(|sampling| sampling.1).max().unwrap())
+}
+
+// The blocks of an interleaved scan in order, as (MCU number, component, block index).
+fn coded_block_order( image: &CodedJpeg ) -> Vec<(usize, usize, usize)> {
+    let samplings : Vec<(usize, usize)> = image.components.iter().map(|component| component.sampling).collect();
+    let (mcu_width, mcu_height) = coded_mcu_size( &samplings );
+    let (mcus_wide, mcus_high) = (image.width.div_ceil( mcu_width ), image.height.div_ceil( mcu_height ));
+    let mut order = vec![];
+    for mcu in 0..mcus_wide * mcus_high {
+        let (column, row) = (mcu % mcus_wide, mcu / mcus_wide);
+        for (index, component) in image.components.iter().enumerate() {
+            let (horizontal, vertical) = if samplings.len() == 1 { (1, 1) } else { component.sampling };
+            for y in 0..vertical {
+                for x in 0..horizontal {
+                    order.push( (mcu, index, (row * vertical + y) * component.blocks_wide + column * horizontal + x) );
+                }
+            }
+        }
+    }
+    order
+}
+
+// Huffman codes for every DC size (4 bits each) and every AC symbol (8 or 9 bits each).
+const DC_CODE_COUNTS : [u8; 16] = [0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+const AC_CODE_COUNTS : [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 254, 2, 0, 0, 0, 0, 0, 0, 0];
+
+fn put_bits( output: &mut Vec<u8>, buffer: &mut Vec<bool>, value: u32, count: u32 ) {
+    buffer.extend( (0..count).rev().map(|bit| value >> bit & 1 == 1) );
+    while buffer.len() >= 8 {
+        let byte = buffer.drain( ..8 ).fold( 0u8, |byte, bit| byte << 1 | bit as u8 );
+        output.push( byte );
+        if byte == 0xFF {
+            output.push( 0 );
+        }
+    }
+}
+
+fn magnitude( value: i32 ) -> (u32, u32) {
+    let size = 32 - value.unsigned_abs().leading_zeros();
+    (size, if value < 0 { (value - 1) as u32 & ((1 << size) - 1) } else { value as u32 })
+}
+
+// A baseline JPEG holding the coefficients, optionally with EXIF data and with restart markers
+// every `restart_interval` MCUs.
+pub fn build_coded_jpeg( image: &CodedJpeg, exif_tiff: Option<&[u8]>, restart_interval: usize ) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xD8];
+    if let Some(tiff) = exif_tiff {
+        let mut body = b"Exif\0\0".to_vec();
+        body.extend_from_slice( tiff );
+        bytes.extend( jpeg_segment( 0xE1, &body ) );
+    }
+    let mut quantization = vec![0];
+    quantization.extend( ZIGZAG.iter().map(|position| image.quantization[*position] as u8) );
+    bytes.extend( jpeg_segment( 0xDB, &quantization ) );
+
+    let mut frame = vec![8];
+    frame.extend_from_slice( &(image.height as u16).to_be_bytes() );
+    frame.extend_from_slice( &(image.width as u16).to_be_bytes() );
+    frame.push( image.components.len() as u8 );
+    for (index, component) in image.components.iter().enumerate() {
+        frame.extend_from_slice( &[index as u8 + 1, (component.sampling.0 << 4 | component.sampling.1) as u8, 0] );
+    }
+    bytes.extend( jpeg_segment( 0xC0, &frame ) );
+
+    let mut tables = vec![0x00];
+    tables.extend_from_slice( &DC_CODE_COUNTS );
+    tables.extend( 0..12u8 );
+    tables.push( 0x10 );
+    tables.extend_from_slice( &AC_CODE_COUNTS );
+    tables.extend( 0..=255u8 );
+    bytes.extend( jpeg_segment( 0xC4, &tables ) );
+    if restart_interval > 0 {
+        bytes.extend( jpeg_segment( 0xDD, &(restart_interval as u16).to_be_bytes() ) );
+    }
+
+    let mut scan = vec![image.components.len() as u8];
+    for index in 0..image.components.len() {
+        scan.extend_from_slice( &[index as u8 + 1, 0x00] );
+    }
+    scan.extend_from_slice( &[0, 63, 0] );
+    bytes.extend( jpeg_segment( 0xDA, &scan ) );
+
+    let ac_code = |symbol: u8| if symbol < 254 { (symbol as u32, 8) } else { (symbol as u32 + 254, 9) };
+    let mut buffer = vec![];
+    let mut predictions = vec![0i32; image.components.len()];
+    let mut restarts = 0;
+    let mut current_mcu = 0;
+    for (mcu, index, block_index) in coded_block_order( image ) {
+        if mcu != current_mcu {
+            current_mcu = mcu;
+            if restart_interval > 0 && mcu % restart_interval == 0 {
+                let padding = (8 - buffer.len() % 8) % 8;
+                put_bits( &mut bytes, &mut buffer, (1 << padding) - 1, padding as u32 );
+                bytes.extend_from_slice( &[0xFF, 0xD0 + restarts % 8] );
+                restarts += 1;
+                predictions.iter_mut().for_each(|prediction| *prediction = 0);
+            }
+        }
+        let block = &image.components[index].blocks[block_index];
+        let (size, bits) = magnitude( block[0] as i32 - predictions[index] );
+        predictions[index] = block[0] as i32;
+        put_bits( &mut bytes, &mut buffer, size, 4 );
+        put_bits( &mut bytes, &mut buffer, bits, size );
+        let mut run = 0;
+        for position in ZIGZAG[1..].iter() {
+            let value = block[*position] as i32;
+            if value == 0 {
+                run += 1;
+                continue;
+            }
+            while run > 15 {
+                let (code, length) = ac_code( 0xF0 );
+                put_bits( &mut bytes, &mut buffer, code, length );
+                run -= 16;
+            }
+            let (size, bits) = magnitude( value );
+            let (code, length) = ac_code( (run << 4 | size) as u8 );
+            put_bits( &mut bytes, &mut buffer, code, length );
+            put_bits( &mut bytes, &mut buffer, bits, size );
+            run = 0;
+        }
+        if run > 0 {
+            let (code, length) = ac_code( 0x00 );
+            put_bits( &mut bytes, &mut buffer, code, length );
+        }
+    }
+    let padding = (8 - buffer.len() % 8) % 8;
+    put_bits( &mut bytes, &mut buffer, (1 << padding) - 1, padding as u32 );
+    bytes.extend_from_slice( &[0xFF, 0xD9] );
+    bytes
+}
+
+// Decodes the coefficients of a baseline JPEG with a single interleaved scan and no restarts,
+// whatever its Huffman tables.
+pub fn decode_coded_jpeg( data: &[u8] ) -> CodedJpeg {
+    let mut image = CodedJpeg { width: 0, height: 0, quantization: vec![0; 64], components: vec![] };
+    let mut tables : std::collections::HashMap<(u8, u32, u32), u8> = std::collections::HashMap::new();
+    let mut selectors = vec![];
+    let mut position = 2;
+    loop {
+        let marker = data[position + 1];
+        let length = u16::from_be_bytes( [data[position + 2], data[position + 3]] ) as usize;
+        let payload = &data[position + 4..position + 2 + length];
+        match marker {
+            0xDB => {
+                assert_eq!( payload[0], 0, "only 8-bit table 0 is expected" );
+                for (index, position) in ZIGZAG.iter().enumerate() {
+                    image.quantization[*position] = payload[1 + index] as u16;
+                }
+            },
+            0xC0 => {
+                image.height = u16::from_be_bytes( [payload[1], payload[2]] ) as usize;
+                image.width = u16::from_be_bytes( [payload[3], payload[4]] ) as usize;
+                let samplings : Vec<(usize, usize)> = payload[6..].chunks( 3 ).map(|entry| ((entry[1] >> 4) as usize, (entry[1] & 0x0F) as usize)).collect();
+                let (mcu_width, mcu_height) = coded_mcu_size( &samplings );
+                let (mcus_wide, mcus_high) = (image.width.div_ceil( mcu_width ), image.height.div_ceil( mcu_height ));
+                for sampling in samplings.iter() {
+                    let (horizontal, vertical) = if samplings.len() == 1 { (1, 1) } else { *sampling };
+                    let blocks = vec![[0i16; 64]; mcus_wide * horizontal * mcus_high * vertical];
+                    image.components.push( CodedComponent { sampling: *sampling, blocks_wide: mcus_wide * horizontal, blocks } );
+                }
+            },
+            0xC4 => {
+                let mut offset = 0;
+                while offset < payload.len() {
+                    let class_and_id = payload[offset];
+                    let counts = &payload[offset + 1..offset + 17];
+                    let mut symbols = payload[offset + 17..].iter();
+                    let mut code = 0;
+                    for length in 1..=16 {
+                        for _ in 0..counts[length as usize - 1] {
+                            tables.insert( (class_and_id, length, code), *symbols.next().unwrap() );
+                            code += 1;
+                        }
+                        code <<= 1;
+                    }
+                    offset += 17 + counts.iter().map(|count| *count as usize).sum::<usize>();
+                }
+            },
+            0xDA => {
+                selectors = payload[1..1 + 2 * payload[0] as usize].chunks( 2 ).map(|selector| selector[1]).collect();
+                position += 2 + length;
+                break;
+            },
+            _ => {},
+        }
+        position += 2 + length;
+    }
+
+    let mut bits = vec![];
+    while !(data[position] == 0xFF && data[position + 1] != 0) {
+        bits.extend( (0..8).rev().map(|bit| data[position] >> bit & 1 == 1) );
+        position += if data[position] == 0xFF { 2 } else { 1 };
+    }
+    let mut bits = bits.into_iter();
+    let mut read_bits = |count: u32| (0..count).fold( 0u32, |value, _| value << 1 | bits.next().unwrap() as u32 );
+    let decode = |class_and_id: u8, read_bits: &mut dyn FnMut( u32 ) -> u32| {
+        let mut code = 0;
+        for length in 1..=16 {
+            code = code << 1 | read_bits( 1 );
+            if let Some(symbol) = tables.get( &(class_and_id, length, code) ) {
+                return *symbol;
+            }
+        }
+        panic!("no Huffman code matches");
+    };
+    let extend = |bits: u32, size: u32| if size > 0 && bits < 1 << (size - 1) { bits as i32 - (1 << size) + 1 } else { bits as i32 };
+
+    let mut predictions = vec![0i32; image.components.len()];
+    for (_, index, block_index) in coded_block_order( &image ) {
+        let selector = selectors[index];
+        let mut block = [0i16; 64];
+        let size = decode( selector >> 4, &mut read_bits ) as u32;
+        predictions[index] += extend( read_bits( size ), size );
+        block[0] = predictions[index] as i16;
+        let mut coefficient = 1;
+        while coefficient < 64 {
+            let symbol = decode( 0x10 | selector & 0x0F, &mut read_bits );
+            let (run, size) = ((symbol >> 4) as usize, (symbol & 0x0F) as u32);
+            if size == 0 && run != 15 {
+                break;
+            }
+            coefficient += run;
+            if size > 0 {
+                block[ZIGZAG[coefficient]] = extend( read_bits( size ), size ) as i16;
+            }
+            coefficient += 1;
+        }
+        image.components[index].blocks[block_index] = block;
+    }
+    image
+}
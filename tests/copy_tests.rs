@@ -0,0 +1,120 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use serial_test::serial;
+
+use rusimeta::{MetadataGroup, Orientation, WriteOptions};
+use common::{build_jpeg, canon_exif, scratch_dir, ExifBuilder, TiffValue};
+
+// The EXIF of an original from the camera, with a location, lens and copyright.
+fn original_exif() -> ExifBuilder {
+    let mut exif = canon_exif();
+    exif.ifd0.push( (0x8298, TiffValue::Ascii("Jane Doe")) );
+    exif.exif.push( (0x829A, TiffValue::Rational(vec![(1, 250)])) );
+    exif.exif.push( (0x8827, TiffValue::Short(vec![400])) );
+    exif.exif.push( (0xA002, TiffValue::Long(vec![6720])) );
+    exif.exif.push( (0xA003, TiffValue::Long(vec![4480])) );
+    exif.exif.push( (0xA434, TiffValue::Ascii("EF24-70mm f/2.8L II USM")) );
+    exif.gps = vec![
+        (0x0000, TiffValue::Undefined(vec![2, 3, 0, 0])),
+        (0x0001, TiffValue::Ascii("N")),
+        (0x0002, TiffValue::Rational(vec![(51, 1), (30, 1), (1234, 100)])),
+        (0x0003, TiffValue::Ascii("W")),
+        (0x0004, TiffValue::Rational(vec![(0, 1), (7, 1), (3900, 100)])),
+    ];
+    exif
+}
+
+// The EXIF a retouching tool leaves on an export: its own dimensions, orientation and software.
+fn export_exif() -> ExifBuilder {
+    ExifBuilder {
+        ifd0: vec![
+            (0x0112, TiffValue::Short(vec![6])),
+            (0x0131, TiffValue::Ascii("Retoucher 2.1")),
+        ],
+        exif: vec![
+            (0xA002, TiffValue::Long(vec![2000])),
+            (0xA003, TiffValue::Long(vec![1333])),
+        ],
+        ..Default::default()
+    }
+}
+
+fn read_exif( path: &Path ) -> exif::Exif {
+    let data = fs::read( path ).unwrap();
+    exif::Reader::new().read_from_container( &mut std::io::Cursor::new( &data ) ).unwrap()
+}
+
+fn field_text( exif_fields: &exif::Exif, tag: exif::Tag ) -> Option<String> {
+    exif_fields.get_field( tag, exif::In::PRIMARY ).map(|field| field.display_value().to_string())
+}
+
+#[test]
+#[serial]
+fn every_group_is_copied_without_touching_the_targets_own_image_tags()
+{
+    // GIVEN an original from the camera, and an export of it which kept only its own tags
+    let dir = scratch_dir("copy_all");
+    let source = dir.join("IMG_0001.jpg");
+    let target = dir.join("IMG_0001_edit.jpg");
+    fs::write( &source, build_jpeg( Some(&original_exif().build()), &[] ) ).unwrap();
+    fs::write( &target, build_jpeg( Some(&export_exif().build()), &[] ) ).unwrap();
+
+    // WHEN every group is copied from the original into the export
+    let changes = rusimeta::copy_metadata( &source, &target, &MetadataGroup::ALL, &WriteOptions::default() ).unwrap();
+
+    // THEN the capture time, camera, lens, location and copyright read the same as the original's
+    let (copied, original) = (read_exif( &target ), read_exif( &source ));
+    for tag in [exif::Tag::DateTimeOriginal, exif::Tag::DateTime, exif::Tag::Make, exif::Tag::Model, exif::Tag::BodySerialNumber,
+                exif::Tag::ExposureTime, exif::Tag::PhotographicSensitivity, exif::Tag::LensModel, exif::Tag::Copyright,
+                exif::Tag::GPSVersionID, exif::Tag::GPSLatitudeRef, exif::Tag::GPSLatitude, exif::Tag::GPSLongitude].iter() {
+        assert!( field_text( &original, *tag ).is_some() );
+        assert_eq!( field_text( &copied, *tag ), field_text( &original, *tag ), "{}", tag );
+    }
+    assert!( changes.iter().any(|change| change.field == "gps_latitude" && change.old_value.is_none() && change.new_value == "51/1, 30/1, 1234/100") );
+
+    // AND the export keeps its own orientation, dimensions and other tags
+    let metadata = rusimeta::read_metadata_of_interest( &target ).unwrap();
+    assert_eq!( metadata.image_metadata.orientation, Some(Orientation::QuarterRotationCCW) );
+    let dimension = |tag| copied.get_field( tag, exif::In::PRIMARY ).and_then(|field| field.value.get_uint( 0 ));
+    assert_eq!( (dimension( exif::Tag::PixelXDimension ), dimension( exif::Tag::PixelYDimension )), (Some(2000), Some(1333)) );
+    assert_eq!( field_text( &copied, exif::Tag::Software ).as_deref(), Some("\"Retoucher 2.1\"") );
+
+    // AND copying again changes nothing
+    assert!( rusimeta::copy_metadata( &source, &target, &MetadataGroup::ALL, &WriteOptions::default() ).unwrap().is_empty() );
+}
+
+#[test]
+#[serial]
+fn selected_groups_are_copied_from_the_file_with_the_same_stem()
+{
+    // GIVEN a directory of originals, and exports of which only one has an original
+    let dir = scratch_dir("copy_by_stem");
+    let (originals, exports) = (dir.join("originals"), dir.join("exports"));
+    fs::create_dir_all( &originals ).unwrap();
+    fs::create_dir_all( &exports ).unwrap();
+    fs::write( originals.join("IMG_0001.jpg"), build_jpeg( Some(&original_exif().build()), &[] ) ).unwrap();
+    let matched = exports.join("IMG_0001.jpg");
+    let unmatched = exports.join("IMG_0002.jpg");
+    fs::write( &matched, build_jpeg( Some(&export_exif().build()), &[] ) ).unwrap();
+    fs::write( &unmatched, build_jpeg( Some(&export_exif().build()), &[] ) ).unwrap();
+
+    // WHEN the location and copyright are copied by stem from the command line
+    let strings : Vec<String> = ["rusimeta", "copy", "--from-dir", originals.to_str().unwrap(), "--groups", "gps,copyright",
+                                 matched.to_str().unwrap(), unmatched.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+    let summary = rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap();
+
+    // THEN the matched export gets them, but none of the other groups
+    assert_eq!( (summary.read, summary.failed), (1, 1) );
+    let copied = read_exif( &matched );
+    assert_eq!( field_text( &copied, exif::Tag::Copyright ).as_deref(), Some("\"Jane Doe\"") );
+    assert_eq!( field_text( &copied, exif::Tag::GPSLatitudeRef ).as_deref(), Some("N") );
+    assert_eq!( field_text( &copied, exif::Tag::DateTimeOriginal ), None );
+    assert_eq!( field_text( &copied, exif::Tag::Make ), None );
+
+    // AND the export without an original is left as it was
+    assert_eq!( fs::read( &unmatched ).unwrap(), build_jpeg( Some(&export_exif().build()), &[] ) );
+    assert!( rusimeta::find_source_by_stem( &originals, &unmatched ).is_err() );
+    assert!( MetadataGroup::parse_list( "gps,colour" ).is_none() );
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use serial_test::serial;
+
+use rusimeta::{DedupeOptions, DuplicateKind, KeeperRule};
+use common::{build_jpeg, build_with_maker_note, canon_exif, jpeg_segment, scratch_dir, ExifBuilder, TiffValue};
+
+// A 5D frame taken at the given second and hundredths, with its pixel dimensions.
+fn frame_exif( capture_time: &'static str, sub_sec_time: &'static str, width: u32 ) -> ExifBuilder {
+    let mut exif = canon_exif();
+    exif.exif[0] = (0x9003, TiffValue::Ascii(capture_time));
+    exif.exif.push( (0x9291, TiffValue::Ascii(sub_sec_time)) );
+    exif.exif.push( (0xA002, TiffValue::Long(vec![width])) );
+    exif.exif.push( (0xA003, TiffValue::Long(vec![4480])) );
+    exif
+}
+
+// A JPEG of the frame, with image data of its own.
+fn frame_jpeg( exif: &ExifBuilder, image: u8 ) -> Vec<u8> {
+    build_jpeg( Some(&exif.build()), &[jpeg_segment( 0xDB, &[image; 65] )] )
+}
+
+fn set_modified( path: &Path, seconds: u64 ) {
+    fs::File::options().write( true ).open( path ).unwrap().set_modified( SystemTime::UNIX_EPOCH + Duration::from_secs( seconds ) ).unwrap();
+}
+
+#[test]
+#[serial]
+fn copies_and_renderings_of_a_frame_are_grouped_with_a_keeper()
+{
+    // GIVEN a frame copied onto two drives, a frame as a DNG and a JPEG, and frames taken in the
+    // same second with another subsecond time or other dimensions
+    let dir = scratch_dir("dedupe_groups");
+    let (drive, backup) = (dir.join("drive"), dir.join("backup"));
+    fs::create_dir_all( &drive ).unwrap();
+    fs::create_dir_all( &backup ).unwrap();
+    let copied = frame_jpeg( &frame_exif( "2019:07:26 13:25:33", "10", 6720 ), 1 );
+    fs::write( drive.join("IMG_0001.jpg"), &copied ).unwrap();
+    fs::write( backup.join("IMG_0001.jpg"), &copied ).unwrap();
+    set_modified( &drive.join("IMG_0001.jpg"), 1_600_000_000 );
+    set_modified( &backup.join("IMG_0001.jpg"), 1_500_000_000 );
+    let mut raw = frame_exif( "2019:07:26 13:30:00", "25", 6720 );
+    raw.ifd0.push( (0xC612, TiffValue::Undefined(vec![1, 4, 0, 0])) );
+    fs::write( drive.join("IMG_0002.dng"), raw.build() ).unwrap();
+    fs::write( drive.join("IMG_0002.jpg"), frame_jpeg( &frame_exif( "2019:07:26 13:30:00", "25", 6720 ), 2 ) ).unwrap();
+    fs::write( drive.join("IMG_0003.jpg"), frame_jpeg( &frame_exif( "2019:07:26 13:30:00", "50", 6720 ), 3 ) ).unwrap();
+    fs::write( drive.join("IMG_0002_small.jpg"), frame_jpeg( &frame_exif( "2019:07:26 13:30:00", "25", 1920 ), 4 ) ).unwrap();
+
+    // WHEN the drives are checked for duplicates
+    let (report, failures) = rusimeta::find_duplicates( &[drive.clone(), backup.clone()], &DedupeOptions::default() );
+
+    // THEN the copies are identical and the older one is kept, and the DNG is kept over its JPEG
+    assert!( failures.is_empty() );
+    assert_eq!( report.files_scanned, 6 );
+    assert_eq!( report.groups.len(), 2 );
+    let (copies, renderings) = (&report.groups[0], &report.groups[1]);
+    assert_eq!( copies.kind, DuplicateKind::Identical );
+    assert_eq!( copies.keeper, backup.join("IMG_0001.jpg") );
+    assert_eq!( copies.files.len(), 2 );
+    assert_eq!( renderings.kind, DuplicateKind::Probable );
+    assert_eq!( renderings.keeper, drive.join("IMG_0002.dng") );
+    let paths : Vec<&Path> = renderings.files.iter().map(|file| file.path.as_path()).collect();
+    assert_eq!( paths, vec![drive.join("IMG_0002.dng"), drive.join("IMG_0002.jpg")] );
+    assert_eq!( renderings.files[0].capture.as_ref().unwrap().sub_sec_time.as_deref(), Some("25") );
+
+    // AND the rules decide the keeper in the order they are given
+    let largest_first = DedupeOptions { keeper_rules: vec![KeeperRule::PreferLargest], ..Default::default() };
+    let (report, _) = rusimeta::find_duplicates( &[drive.clone(), backup.clone()], &largest_first );
+    assert_eq!( report.groups[0].keeper, backup.join("IMG_0001.jpg") );
+    let largest = if fs::metadata( drive.join("IMG_0002.dng") ).unwrap().len() > fs::metadata( drive.join("IMG_0002.jpg") ).unwrap().len() { "IMG_0002.dng" } else { "IMG_0002.jpg" };
+    assert_eq!( report.groups[1].keeper, drive.join(largest) );
+    assert_eq!( KeeperRule::parse_list( "oldest, raw" ), Some(vec![KeeperRule::PreferOldest, KeeperRule::PreferRaw]) );
+    assert_eq!( KeeperRule::parse_list( "raw,newest" ), None );
+}
+
+#[test]
+#[serial]
+fn frames_of_one_second_are_only_grouped_by_a_subsecond_time_or_file_number()
+{
+    // GIVEN two frames of a burst shot in one second by a body which writes no subsecond time,
+    // and a frame with a Canon file number as a JPEG and an edited JPEG, next to the following
+    // frame of its burst
+    let dir = scratch_dir("dedupe_same_second");
+    let without_sub_sec_time = |capture_time: &'static str| {
+        let mut exif = frame_exif( capture_time, "", 6720 );
+        exif.exif.retain(|(tag, _)| *tag != 0x9291);
+        exif
+    };
+    let numbered = |file_number: u32, image: u8| build_jpeg(
+        Some(&build_with_maker_note( &without_sub_sec_time( "2019:07:26 13:40:00" ), b"", &vec![(0x0008, TiffValue::Long(vec![file_number]))] )),
+        &[jpeg_segment( 0xDB, &[image; 65] )] );
+    fs::write( dir.join("IMG_0001.jpg"), frame_jpeg( &without_sub_sec_time( "2019:07:26 13:25:33" ), 1 ) ).unwrap();
+    fs::write( dir.join("IMG_0002.jpg"), frame_jpeg( &without_sub_sec_time( "2019:07:26 13:25:33" ), 2 ) ).unwrap();
+    fs::write( dir.join("IMG_0101.jpg"), numbered( 1000101, 3 ) ).unwrap();
+    fs::write( dir.join("IMG_0101_edit.jpg"), numbered( 1000101, 4 ) ).unwrap();
+    fs::write( dir.join("IMG_0102.jpg"), numbered( 1000102, 5 ) ).unwrap();
+
+    // WHEN the directory is checked for duplicates
+    let (report, failures) = rusimeta::find_duplicates( std::slice::from_ref( &dir ), &DedupeOptions::default() );
+
+    // THEN the frames without a subsecond time or file number aren't grouped, and only the
+    // frame with the same file number is
+    assert!( failures.is_empty() );
+    assert_eq!( report.files_scanned, 5 );
+    assert_eq!( report.groups.len(), 1 );
+    let paths : Vec<&Path> = report.groups[0].files.iter().map(|file| file.path.as_path()).collect();
+    assert_eq!( paths, vec![dir.join("IMG_0101.jpg"), dir.join("IMG_0101_edit.jpg")] );
+    assert_eq!( report.groups[0].files[0].capture.as_ref().unwrap().file_number, Some(1000101) );
+}
+
+#[test]
+#[serial]
+fn the_report_is_written_as_json_from_the_command_line_without_changing_files()
+{
+    // GIVEN a re-tagged copy of a frame, and two copies of a file which isn't an image
+    let dir = scratch_dir("dedupe_cli");
+    let original = frame_jpeg( &frame_exif( "2019:07:26 13:25:33", "10", 6720 ), 1 );
+    let mut retagged_exif = frame_exif( "2019:07:26 13:25:33", "10", 6720 );
+    retagged_exif.ifd0.push( (0x8298, TiffValue::Ascii("Jane Doe")) );
+    fs::write( dir.join("IMG_0001.jpg"), &original ).unwrap();
+    fs::write( dir.join("IMG_0001_tagged.jpg"), frame_jpeg( &retagged_exif, 1 ) ).unwrap();
+    fs::write( dir.join("notes.txt"), "shot list" ).unwrap();
+    fs::write( dir.join("notes copy.txt"), "shot list" ).unwrap();
+    let report_path = dir.join("report").with_extension( "out" );
+
+    // WHEN the directory is checked from the command line
+    let strings : Vec<String> = ["rusimeta", "dedupe", "--keep", "oldest", "--hash", "sha256", "--report", report_path.to_str().unwrap(), dir.to_str().unwrap()]
+        .iter().map(|arg| arg.to_string()).collect();
+    let summary = rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap();
+
+    // THEN the report lists the re-tagged copy as probable, and the text files as identical
+    assert_eq!( (summary.read, summary.failed), (4, 0) );
+    let json : serde_json::Value = serde_json::from_str( &fs::read_to_string( &report_path ).unwrap() ).unwrap();
+    assert_eq!( json["files_scanned"], 4 );
+    let groups = json["groups"].as_array().unwrap();
+    assert_eq!( groups.len(), 2 );
+    assert_eq!( groups[0]["kind"], "probable" );
+    assert_eq!( groups[0]["files"][0]["hashes"]["payload"], groups[0]["files"][1]["hashes"]["payload"] );
+    assert_eq!( groups[0]["files"][0]["hashes"]["algorithm"], "sha256" );
+    assert_eq!( groups[1]["kind"], "identical" );
+    assert_eq!( groups[1]["files"].as_array().unwrap().len(), 2 );
+
+    // AND no file was changed
+    assert_eq!( fs::read( dir.join("IMG_0001.jpg") ).unwrap(), original );
+    assert_eq!( fs::read_to_string( dir.join("notes copy.txt") ).unwrap(), "shot list" );
+    assert!( rusimeta::Config::new( ["rusimeta", "dedupe", "--keep", "newest", "a.jpg"].iter().map(|arg| arg.to_string()) ).is_err() );
+}
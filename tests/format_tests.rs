@@ -0,0 +1,78 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::MediaFormat;
+use common::{build_jpeg, canon_exif, scratch_dir};
+
+#[test]
+fn formats_are_detected_from_magic_bytes()
+{
+    // GIVEN the leading bytes of files of various types
+    let cases : Vec<(&[u8], MediaFormat)> = vec![
+        (b"\xFF\xD8\xFF\xE1\x00\x10Exif", MediaFormat::Jpeg),
+        (b"\x89PNG\r\n\x1A\n\x00\x00\x00\x0DIHDR", MediaFormat::Png),
+        (b"II*\x00\x10\x00\x00\x00CR\x02\x00", MediaFormat::CanonCr2),
+        (b"MM\x00*\x00\x00\x00\x08\x00\x00", MediaFormat::Tiff),
+        (b"IIRO\x08\x00\x00\x00", MediaFormat::OlympusOrf),
+        (b"FUJIFILMCCD-RAW 0201", MediaFormat::FujifilmRaf),
+        (b"\x00\x00\x00\x18ftypheic\x00\x00\x00\x00heicmif1", MediaFormat::Heif),
+        (b"\x00\x00\x00\x18ftypcrx \x00\x00\x00\x01crx isom", MediaFormat::CanonCr3),
+        (b"\x00\x00\x00\x14ftypqt  \x00\x00\x02\x00qt  ", MediaFormat::QuickTime),
+        (b"\x00\x00\x00\x18ftypmp42\x00\x00\x00\x00mp42isom", MediaFormat::Mp4),
+        (b"RIFF\x24\x00\x00\x00WEBPVP8 ", MediaFormat::Webp),
+        (b"ID3\x03\x00\x00\x00\x00\x00\x00", MediaFormat::Mp3),
+        (b"%PDF-1.4\n", MediaFormat::Pdf),
+        (b"{\n  \"filename\": \"JAM19896.jpg\"", MediaFormat::Json),
+        (b"This is not an image.\n", MediaFormat::Text),
+        (b"\x00\x01\x02\x03\x04\x05\x06\x07", MediaFormat::Unknown),
+    ];
+
+    for (header, expected) in cases {
+        // WHEN the format is detected
+        let detected = rusimeta::detect_format_from_bytes( header );
+
+        // THEN it matches the expected format
+        assert_eq!( detected, expected, "for header {:?}", header );
+    }
+    assert_eq!( MediaFormat::Text.mime_type(), "text/plain" );
+    assert!( !MediaFormat::Mp3.is_supported() );
+    assert!( MediaFormat::CanonCr2.is_supported() && MediaFormat::CanonCr2.is_raw() );
+}
+
+#[test]
+#[serial]
+fn unsupported_files_are_skipped_and_counted_separately_from_failures()
+{
+    // GIVEN an image, two files which are not media, and a JPEG whose EXIF is corrupt
+    let dir = scratch_dir("format_skip");
+    let image_path = dir.join("IMG_0001.jpg");
+    fs::write( &image_path, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    let text_path = dir.join("notanimage.txt");
+    fs::write( &text_path, "This is not an image.\n" ).unwrap();
+    let music_path = dir.join("disguised.jpg");
+    fs::write( &music_path, b"ID3\x03\x00\x00\x00\x00\x00\x00not really music" ).unwrap();
+    let corrupt_path = dir.join("corrupt.jpg");
+    fs::write( &corrupt_path, build_jpeg( Some(b"II*\x00\xFF\xFF\xFF\xFF"), &[] ) ).unwrap();
+
+    let paths = [&image_path, &text_path, &music_path, &corrupt_path];
+    let cfg = rusimeta::Config::from_strings( paths.iter().map(|path| path.to_str().unwrap().to_string()).collect() );
+
+    // WHEN the metadata is requested for all of these files
+    let result = rusimeta::run( cfg );
+
+    // THEN the non-media files are skipped, and only the corrupt image counts as a failure
+    assert!(result.is_ok(),"{:?}",result.err());
+    assert_eq!(
+        result.unwrap(),
+        rusimeta::RunSummary { read: 1, skipped_unsupported: 2, failed: 1 }
+    );
+
+    // AND the detected MIME type is recorded for the image, with no JSON written for the skipped files
+    let metadata = rusimeta::read_json_metadata( dir.join("IMG_0001.json").to_str().unwrap() ).unwrap();
+    assert_eq!( metadata.file_metadata.mime_type, Some("image/jpeg".to_string()) );
+    assert_eq!( metadata.image_metadata.camera_serial, Some("025021000537".to_string()) );
+    assert!( !dir.join("notanimage.json").exists() );
+    assert!( !dir.join("disguised.json").exists() );
+}
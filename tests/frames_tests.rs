@@ -0,0 +1,137 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{FrameKind, ReadOptions};
+use common::{build_jpeg, build_mpo, canon_exif, scratch_dir, ExifBuilder, TiffValue, FAKE_JPEG_IMAGE_DATA};
+
+fn read_with_frames( path: &std::path::Path ) -> rusimeta::MetadataOfInterest {
+    rusimeta::read_metadata_of_interest_with_options( path, &ReadOptions { include_frames: true, ..Default::default() } ).unwrap()
+}
+
+#[test]
+#[serial]
+fn frames_are_only_listed_when_requested()
+{
+    // GIVEN a JPEG with an EXIF thumbnail
+    let dir = scratch_dir("frames_opt_in");
+    let path = dir.join("IMG_0001.jpg");
+    let mut exif = canon_exif();
+    exif.thumbnail = Some( build_jpeg( None, &[] ) );
+    fs::write( &path, build_jpeg( Some(&exif.build()), &[] ) ).unwrap();
+
+    // WHEN the metadata is read without and then with the frames option through the CLI config
+    let plain = rusimeta::read_metadata_of_interest( &path ).unwrap();
+    let cfg = rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] )
+        .with_read_options( ReadOptions { include_frames: true, ..Default::default() } );
+    let result = rusimeta::run( cfg );
+    assert!(result.is_ok(),"{:?}",result.err());
+
+    // THEN the frames are only present in the second case
+    assert_eq!( plain.frames, None );
+    let with_frames = rusimeta::read_json_metadata( dir.join("IMG_0001.json").to_str().unwrap() ).unwrap();
+    let frames = with_frames.frames.expect("frames should be listed");
+    assert_eq!( frames.iter().map(|frame| frame.kind).collect::<Vec<_>>(), vec![FrameKind::Primary, FrameKind::Thumbnail] );
+}
+
+#[test]
+#[serial]
+fn jpeg_thumbnail_frame_has_its_own_dimensions_and_location()
+{
+    // GIVEN a JPEG whose IFD1 holds an embedded JPEG thumbnail
+    let dir = scratch_dir("frames_thumbnail");
+    let path = dir.join("IMG_0002.jpg");
+    let thumbnail = build_jpeg( None, &[] );
+    let mut exif = canon_exif();
+    exif.thumbnail = Some( thumbnail.clone() );
+    let file = build_jpeg( Some(&exif.build()), &[] );
+    fs::write( &path, &file ).unwrap();
+
+    // WHEN the frames are read
+    let frames = read_with_frames( &path ).frames.unwrap();
+
+    // THEN the primary frame carries the camera metadata and the thumbnail frame points at the thumbnail bytes
+    assert_eq!( frames[0].image_metadata.camera_serial, Some("025021000537".to_string()) );
+    assert_eq!( (frames[0].width, frames[0].height), (Some(16), Some(16)) );
+    let thumbnail_frame = &frames[1];
+    assert_eq!( thumbnail_frame.index, 1 );
+    assert_eq!( (thumbnail_frame.width, thumbnail_frame.height), (Some(16), Some(16)) );
+    let offset = thumbnail_frame.byte_offset.unwrap() as usize;
+    let length = thumbnail_frame.byte_length.unwrap() as usize;
+    assert_eq!( &file[offset..offset + length], &thumbnail[..] );
+}
+
+#[test]
+#[serial]
+fn multi_page_tiff_lists_each_page_with_its_dimensions()
+{
+    // GIVEN a TIFF with three IFDs: two full pages and a reduced-resolution preview
+    let dir = scratch_dir("frames_tiff");
+    let path = dir.join("scan.tif");
+    let exif = ExifBuilder {
+        ifd0: vec![
+            (0x0100, TiffValue::Long(vec![2480])),
+            (0x0101, TiffValue::Long(vec![3508])),
+            (0x0110, TiffValue::Ascii("Scanner 1")),
+        ],
+        pages: vec![
+            vec![
+                (0x0100, TiffValue::Long(vec![3508])),
+                (0x0101, TiffValue::Long(vec![2480])),
+                (0x0112, TiffValue::Short(vec![6])),
+            ],
+            vec![
+                (0x00FE, TiffValue::Long(vec![1])),
+                (0x0100, TiffValue::Short(vec![160])),
+                (0x0101, TiffValue::Short(vec![120])),
+            ],
+        ],
+        ..Default::default()
+    };
+    fs::write( &path, exif.build() ).unwrap();
+
+    // WHEN the frames are read
+    let metadata = read_with_frames( &path );
+    let frames = metadata.frames.unwrap();
+
+    // THEN each IFD is listed in order, with its own dimensions and tags
+    assert_eq!( metadata.file_metadata.mime_type, Some("image/tiff".to_string()) );
+    assert_eq!( frames.iter().map(|frame| frame.kind).collect::<Vec<_>>(), vec![FrameKind::Primary, FrameKind::Page, FrameKind::Preview] );
+    assert_eq!( (frames[0].width, frames[0].height), (Some(2480), Some(3508)) );
+    assert_eq!( frames[0].image_metadata.camera_model, Some("Scanner 1".to_string()) );
+    assert_eq!( (frames[1].width, frames[1].height), (Some(3508), Some(2480)) );
+    assert_eq!( frames[1].image_metadata.orientation, Some(rusimeta::Orientation::QuarterRotationCCW) );
+    assert_eq!( frames[1].image_metadata.camera_model, None );
+    assert_eq!( (frames[2].width, frames[2].height), (Some(160), Some(120)) );
+}
+
+#[test]
+#[serial]
+fn mpo_stereo_pair_lists_both_images_from_the_mpf_index()
+{
+    // GIVEN an MPO file from a stereo rig, where each eye's image has its own EXIF
+    let dir = scratch_dir("frames_mpo");
+    let path = dir.join("pair.mpo");
+    let left = canon_exif();
+    let mut right = canon_exif();
+    right.exif[2] = (0xA431, TiffValue::Ascii("025021000535"));
+    let file = build_mpo( &left.build(), &right.build(), 0x0002_0002 );
+    fs::write( &path, &file ).unwrap();
+
+    // WHEN the frames are read
+    let frames = read_with_frames( &path ).frames.unwrap();
+
+    // THEN the second image is listed as a disparity image with its own metadata and byte range
+    assert_eq!( frames.len(), 2 );
+    assert_eq!( frames[0].mp_type, Some("baseline_primary".to_string()) );
+    assert_eq!( frames[0].image_metadata.camera_serial, Some("025021000537".to_string()) );
+    let second = &frames[1];
+    assert_eq!( second.kind, FrameKind::MpoImage );
+    assert_eq!( second.mp_type, Some("disparity".to_string()) );
+    assert_eq!( second.image_metadata.camera_serial, Some("025021000535".to_string()) );
+    assert_eq!( (second.width, second.height), (Some(16), Some(16)) );
+    let offset = second.byte_offset.unwrap() as usize;
+    assert_eq!( &file[offset..offset + 2], b"\xFF\xD8" );
+    assert!( file.ends_with( FAKE_JPEG_IMAGE_DATA ) );
+}
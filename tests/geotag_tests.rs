@@ -0,0 +1,159 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use serial_test::serial;
+
+use rusimeta::{Geotag, GeotagOutcome, Redaction, Track, UnmatchedReason, WriteOptions};
+use common::{build_jpeg, canon_exif, scratch_dir, ExifBuilder, TiffValue};
+
+// A 5D frame taken at the given local time.
+fn exif_taken_at( capture_time: &'static str ) -> ExifBuilder {
+    let mut exif = canon_exif();
+    exif.exif[0] = (0x9003, TiffValue::Ascii(capture_time));
+    exif
+}
+
+// An NMEA sentence with its checksum.
+fn nmea( body: &str ) -> String {
+    let checksum = body.bytes().fold( 0, |sum, byte| sum ^ byte );
+    format!("${}*{:02X}\r\n", body, checksum)
+}
+
+fn assert_near( location: &rusimeta::GpsLocation, latitude: f64, longitude: f64, altitude: Option<f64> ) {
+    assert!( (location.latitude - latitude).abs() < 1e-6, "latitude {} != {}", location.latitude, latitude );
+    assert!( (location.longitude - longitude).abs() < 1e-6, "longitude {} != {}", location.longitude, longitude );
+    match (location.altitude, altitude) {
+        (Some(actual), Some(expected)) => assert!( (actual - expected).abs() < 1e-2, "altitude {} != {}", actual, expected ),
+        (actual, expected) => assert_eq!( actual, expected ),
+    }
+}
+
+fn sidecar_location( path: &Path ) -> Option<rusimeta::GpsLocation> {
+    let sidecar = rusimeta::json_sidecar_path( path ).unwrap();
+    rusimeta::read_json_metadata( &sidecar.to_string_lossy() ).unwrap().image_metadata.location
+}
+
+#[test]
+#[serial]
+fn positions_are_interpolated_from_a_gpx_track_and_written_into_the_image()
+{
+    // GIVEN a GPX track with a gap, and frames from a camera set to UTC+2 taken during the
+    // track, during the gap, and without a capture time
+    let dir = scratch_dir("geotag_gpx");
+    let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="logger" xmlns="http://www.topografix.com/GPX/1/1">
+  <trk><trkseg>
+    <trkpt lat="51.5" lon="-0.1"><ele>10</ele><time>2019-07-26T11:25:00Z</time></trkpt>
+    <trkpt lon='-0.2' lat='51.6'><ele>30</ele><time>2019-07-26T11:27:00.000Z</time></trkpt>
+    <trkpt lat="52.0" lon="-1.0"><time>2019-07-26T12:30:00Z</time></trkpt>
+  </trkseg></trk>
+</gpx>"#;
+    let track = Track::from_gpx( gpx ).unwrap();
+    let during = dir.join("IMG_0001.jpg");
+    let in_gap = dir.join("IMG_0002.jpg");
+    let untimed = dir.join("IMG_0003.jpg");
+    fs::write( &during, build_jpeg( Some(&exif_taken_at( "2019:07:26 13:26:00" ).build()), &[] ) ).unwrap();
+    fs::write( &in_gap, build_jpeg( Some(&exif_taken_at( "2019:07:26 14:00:00" ).build()), &[] ) ).unwrap();
+    fs::write( &untimed, build_jpeg( None, &[] ) ).unwrap();
+
+    // WHEN they are geotagged, writing into the images
+    let geotag = Geotag { utc_offset: chrono::Duration::hours( 2 ), write_back: true, ..Default::default() };
+    let outcomes : Vec<GeotagOutcome> = [&during, &in_gap, &untimed].iter()
+        .map(|path| rusimeta::geotag_image( path, &track, &geotag, &WriteOptions::default(), &Redaction::default() ).unwrap())
+        .collect();
+
+    // THEN the frame taken during the track is half way between the points either side of it,
+    // in its GPS IFD and in its JSON sidecar
+    match &outcomes[0] {
+        GeotagOutcome::Tagged { location, changes } => {
+            assert_near( location, 51.55, -0.15, Some(20.0) );
+            assert!( changes.iter().any(|change| change.field == "gps_latitude_ref" && change.new_value == "N") );
+        },
+        other => panic!("not tagged: {:?}", other),
+    }
+    let written = rusimeta::read_metadata_of_interest( &during ).unwrap().image_metadata.location.unwrap();
+    assert_near( &written, 51.55, -0.15, Some(20.0) );
+    assert_near( &sidecar_location( &during ).unwrap(), 51.55, -0.15, Some(20.0) );
+    let data = fs::read( &during ).unwrap();
+    let exif_fields = exif::Reader::new().read_from_container( &mut std::io::Cursor::new( &data ) ).unwrap();
+    let date_stamp = exif_fields.get_field( exif::Tag::GPSDateStamp, exif::In::PRIMARY ).unwrap();
+    assert_eq!( date_stamp.display_value().to_string(), "2019-07-26" );
+
+    // AND the other frames are reported as unmatched, and left alone
+    assert_eq!( outcomes[1], GeotagOutcome::Unmatched(UnmatchedReason::GapTooLong(chrono::Duration::minutes( 63 ))) );
+    assert_eq!( outcomes[2], GeotagOutcome::Unmatched(UnmatchedReason::NoCaptureTime) );
+    assert_eq!( fs::read( &in_gap ).unwrap(), build_jpeg( Some(&exif_taken_at( "2019:07:26 14:00:00" ).build()), &[] ) );
+
+    // AND geotagging again keeps the position the file now has
+    assert_eq!( rusimeta::geotag_image( &during, &track, &geotag, &WriteOptions::default(), &Redaction::default() ).unwrap(), GeotagOutcome::AlreadyTagged(written) );
+}
+
+#[test]
+#[serial]
+fn nmea_logs_tag_the_json_sidecars_from_the_command_line()
+{
+    // GIVEN an NMEA log, with a corrupted sentence, and frames from a camera 30 seconds slow
+    let dir = scratch_dir("geotag_nmea");
+    let log = dir.join("logger.nmea");
+    let mut sentences = String::new();
+    sentences += &nmea( "GPRMC,112500,A,4807.000,N,01131.000,E,0.0,0.0,260719,,,A" );
+    sentences += &nmea( "GPGGA,112500,4807.000,N,01131.000,E,1,08,0.9,500.0,M,46.9,M,," );
+    sentences += "$GPRMC,112530,A,4900.000,N,01200.000,E,0.0,0.0,260719,,,A*00\r\n";
+    sentences += &nmea( "GPRMC,112600,A,4808.000,N,01132.000,E,0.0,0.0,260719,,,A" );
+    sentences += &nmea( "GPGGA,112600,4808.000,N,01132.000,E,1,08,0.9,520.0,M,46.9,M,," );
+    fs::write( &log, sentences ).unwrap();
+    let during = dir.join("IMG_0001.jpg");
+    let after = dir.join("IMG_0002.jpg");
+    let original = build_jpeg( Some(&exif_taken_at( "2019:07:26 11:25:00" ).build()), &[] );
+    fs::write( &during, &original ).unwrap();
+    fs::write( &after, build_jpeg( Some(&exif_taken_at( "2019:07:26 12:00:00" ).build()), &[] ) ).unwrap();
+
+    // WHEN they are geotagged from the command line, without writing into the images
+    let strings : Vec<String> = ["rusimeta", "geotag", "--track", log.to_str().unwrap(), "--clock-offset", "+0:00:30",
+                                 during.to_str().unwrap(), after.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+    let summary = rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap();
+
+    // THEN the frame during the log gets the position between the two good fixes, with the
+    // corrupted sentence ignored, in its sidecar only
+    assert_eq!( (summary.read, summary.failed), (2, 0) );
+    assert_near( &sidecar_location( &during ).unwrap(), 48.0 + 7.5 / 60.0, 11.0 + 31.5 / 60.0, Some(510.0) );
+    assert_eq!( fs::read( &during ).unwrap(), original );
+
+    // AND the frame long after the log gets no position, nor a sidecar
+    assert!( !rusimeta::json_sidecar_path( &after ).unwrap().is_file() );
+    assert!( rusimeta::Config::new( ["rusimeta", "geotag", "a.jpg"].iter().map(|arg| arg.to_string()) ).is_err() );
+}
+
+#[test]
+#[serial]
+fn a_position_only_in_the_sidecar_survives_a_later_shift()
+{
+    // GIVEN a frame geotagged into its sidecar only, with a hand edit made in the sidecar
+    let dir = scratch_dir("geotag_then_shift");
+    let log = dir.join("logger.nmea");
+    fs::write( &log, nmea( "GPRMC,112500,A,4807.000,N,01131.000,E,0.0,0.0,260719,,,A" ) + &nmea( "GPRMC,112600,A,4808.000,N,01132.000,E,0.0,0.0,260719,,,A" ) ).unwrap();
+    let path = dir.join("IMG_0001.jpg");
+    fs::write( &path, build_jpeg( Some(&exif_taken_at( "2019:07:26 11:25:30" ).build()), &[] ) ).unwrap();
+    let run = |args: &[&str]| {
+        let strings : Vec<String> = ["rusimeta"].iter().chain( args.iter() ).map(|arg| arg.to_string()).collect();
+        rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap()
+    };
+    run( &["geotag", "--track", log.to_str().unwrap(), path.to_str().unwrap()] );
+    let sidecar_path = rusimeta::json_sidecar_path( &path ).unwrap();
+    let mut edited = rusimeta::read_json_metadata( &sidecar_path.to_string_lossy() ).unwrap();
+    edited.image_metadata.camera_model = Some("Canon EOS 5D Mark IV (studio)".to_string());
+    rusimeta::write_json_metadata( &edited, &sidecar_path ).unwrap();
+
+    // WHEN the file's times are shifted
+    let summary = run( &["shift", "--by", "-0:10", path.to_str().unwrap()] );
+
+    // THEN the sidecar has the shifted capture time, and still holds the position and the edit
+    assert_eq!( summary.failed, 0 );
+    let sidecar = rusimeta::read_json_metadata( &sidecar_path.to_string_lossy() ).unwrap();
+    let shifted = chrono::NaiveDateTime::parse_from_str( "2019:07:26 11:15:30", "%Y:%m:%d %H:%M:%S" ).unwrap();
+    assert_eq!( sidecar.image_metadata.capture_time, Some(shifted) );
+    assert_near( &sidecar.image_metadata.location.unwrap(), 48.0 + 7.5 / 60.0, 11.0 + 31.5 / 60.0, None );
+    assert_eq!( sidecar.image_metadata.camera_model.as_deref(), Some("Canon EOS 5D Mark IV (studio)") );
+    assert_eq!( rusimeta::read_metadata_of_interest( &path ).unwrap().image_metadata.capture_time, Some(shifted) );
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{HashAlgorithm, ImageMetadataPatch, ReadOptions, WriteOptions};
+use common::{build_jpeg, build_png, build_webp, canon_exif, jpeg_segment, scratch_dir};
+
+#[test]
+#[serial]
+fn retagged_copies_keep_their_payload_hash_but_not_their_file_hash()
+{
+    // GIVEN a JPEG, a copy of it given a copyright, and a JPEG with other quantization tables,
+    // and a PNG and a WebP with and without their metadata
+    let dir = scratch_dir("hashing_payload");
+    let original = dir.join("IMG_0001.jpg");
+    let retagged = dir.join("IMG_0001_copy.jpg");
+    let other = dir.join("IMG_0002.jpg");
+    fs::write( &original, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    fs::copy( &original, &retagged ).unwrap();
+    let patch = ImageMetadataPatch { copyright: Some("Jane Doe".to_string()), ..Default::default() };
+    rusimeta::write_image_metadata( &retagged, &patch, &WriteOptions::default() ).unwrap();
+    fs::write( &other, build_jpeg( Some(&canon_exif().build()), &[jpeg_segment( 0xDB, &[0; 65] )] ) ).unwrap();
+    let ihdr = (b"IHDR", vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]);
+    let idat = (b"IDAT", vec![0x78, 0x9C, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01]);
+    let plain_png = dir.join("plain.png");
+    let tagged_png = dir.join("tagged.png");
+    fs::write( &plain_png, build_png( &[ihdr.clone(), idat.clone()] ) ).unwrap();
+    fs::write( &tagged_png, build_png( &[ihdr, (b"tEXt", b"Author\0Jane Doe".to_vec()), idat, (b"eXIf", canon_exif().build())] ) ).unwrap();
+    let vp8x = |flags: u8| (b"VP8X", vec![flags, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let vp8l = (b"VP8L", vec![0x2F, 0, 0, 0, 0x10, 0x07, 0x10, 0x11, 0x11, 0x88, 0x88, 0xFE, 0x07, 0x00]);
+    let plain_webp = dir.join("plain.webp");
+    let tagged_webp = dir.join("tagged.webp");
+    fs::write( &plain_webp, build_webp( &[vp8x( 0x00 ), vp8l.clone()] ) ).unwrap();
+    fs::write( &tagged_webp, build_webp( &[vp8x( 0x0C ), vp8l, (b"EXIF", canon_exif().build()), (b"XMP ", b"<x:xmpmeta/>".to_vec())] ) ).unwrap();
+
+    // WHEN they are hashed with each algorithm
+    for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3].iter() {
+        let hashes = |path| rusimeta::hash_file( path, *algorithm ).unwrap();
+        let (original_hashes, retagged_hashes, other_hashes) = (hashes( &original ), hashes( &retagged ), hashes( &other ));
+
+        // THEN the re-tagged copy only differs in its file hash, and the other image in both
+        assert_eq!( original_hashes.file.len(), 64 );
+        assert_ne!( original_hashes.file, retagged_hashes.file );
+        assert_eq!( original_hashes.payload, retagged_hashes.payload );
+        assert!( original_hashes.payload.is_some() );
+        assert_ne!( original_hashes.payload, other_hashes.payload );
+
+        // AND so do the PNG and the WebP with their metadata
+        assert_eq!( hashes( &plain_png ).payload, hashes( &tagged_png ).payload );
+        assert_ne!( hashes( &plain_png ).file, hashes( &tagged_png ).file );
+        assert_eq!( hashes( &plain_webp ).payload, hashes( &tagged_webp ).payload );
+    }
+
+    // AND hashes are only read when asked for
+    assert_eq!( rusimeta::read_metadata_of_interest( &original ).unwrap().file_metadata.hashes, None );
+    let read_options = ReadOptions { hash: Some(HashAlgorithm::Sha256), ..Default::default() };
+    let metadata = rusimeta::read_metadata_of_interest_with_options( &original, &read_options ).unwrap();
+    assert_eq!( metadata.file_metadata.hashes, Some(rusimeta::hash_file( &original, HashAlgorithm::Sha256 ).unwrap()) );
+}
+
+#[test]
+#[serial]
+fn hashes_are_written_to_the_json_sidecar_from_the_command_line()
+{
+    // GIVEN a JPEG, and an empty file
+    let dir = scratch_dir("hashing_cli");
+    let image = dir.join("IMG_0001.jpg");
+    fs::write( &image, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    let empty = dir.join("empty.bin");
+    fs::write( &empty, b"" ).unwrap();
+
+    // WHEN the JPEG is read with BLAKE3 hashes from the command line
+    let strings : Vec<String> = ["rusimeta", "--hash", "blake3", image.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+    let summary = rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap();
+
+    // THEN its sidecar holds both hashes
+    assert_eq!( summary.read, 1 );
+    let sidecar = fs::read_to_string( dir.join("IMG_0001.json") ).unwrap();
+    let json : serde_json::Value = serde_json::from_str( &sidecar ).unwrap();
+    assert_eq!( json["hashes"]["algorithm"], "blake3" );
+    let expected = rusimeta::hash_file( &image, HashAlgorithm::Blake3 ).unwrap();
+    assert_eq!( json["hashes"]["file"], expected.file.as_str() );
+    assert_eq!( json["hashes"]["payload"], expected.payload.unwrap().as_str() );
+
+    // AND the digests are the standard ones, with no payload for a file which isn't an image
+    let empty_sha256 = rusimeta::hash_file( &empty, HashAlgorithm::Sha256 ).unwrap();
+    assert_eq!( empty_sha256.file, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855" );
+    assert_eq!( empty_sha256.payload, None );
+    assert_eq!( rusimeta::hash_file( &empty, HashAlgorithm::Blake3 ).unwrap().file, "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262" );
+    assert!( rusimeta::Config::new( ["rusimeta", "--hash", "md5", "a.jpg"].iter().map(|arg| arg.to_string()) ).is_err() );
+}
+
+#[test]
+#[serial]
+fn files_larger_than_a_chunk_or_a_read_buffer_hash_to_the_standard_digests()
+{
+    // GIVEN files of the byte pattern of the BLAKE3 test vectors, spanning several BLAKE3 chunks
+    // and several read buffers
+    let dir = scratch_dir("hashing_streamed");
+    let expected = [
+        (1025, "bc0b6b10b89b9487a12fda2a8cc13194e7091c217aabf8b92846274026f4bcd0", "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444"),
+        (102400, "74588b7f0bcc354ac14d9cf199fa3a20c05f0c7293b9075b2f2e146e718de800", "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085"),
+        (200000, "e24bc62381f1224fbbb74688663f8f9743b9680b193edd666835e97b06e730eb", "55409142cced2ec79897459f170b6d22565daf883710b4ad7aeeddaef54244b4"),
+    ];
+    for (length, sha256, blake3) in expected.iter() {
+        let path = dir.join( format!("pattern_{}.bin", length) );
+        fs::write( &path, (0..*length).map(|index| (index % 251) as u8).collect::<Vec<u8>>() ).unwrap();
+
+        // WHEN they are hashed
+        // THEN each digest is the standard one
+        assert_eq!( rusimeta::hash_file( &path, HashAlgorithm::Sha256 ).unwrap().file, *sha256 );
+        assert_eq!( rusimeta::hash_file( &path, HashAlgorithm::Blake3 ).unwrap().file, *blake3 );
+    }
+}
@@ -2,7 +2,6 @@ use rusimeta::*;
 use std::path::Path;
 use std::fs;
 use serial_test::serial;
-use chrono;
 use chrono::Utc;
 
 #[derive(Debug)]
@@ -14,11 +13,11 @@ struct TestFile<'a> {
 
 impl<'a> TestFile<'a> {
     pub fn path(&self) -> &str {
-        &self.path
+        self.path
     }
 
     pub fn expected_json_path(&self) -> &str {
-        &self.expected_json_path
+        self.expected_json_path
     }
 
     pub fn expected_metadata(&self) -> rusimeta::MetadataOfInterest {
@@ -26,6 +25,7 @@ impl<'a> TestFile<'a> {
     }
 }
 
+#[allow(non_snake_case)]
 struct AllTestData<'a> {
     pub COMPLETE_METADATA_1 : TestFile<'a>,
     pub COMPLETE_METADATA_2 : TestFile<'a>,
@@ -44,7 +44,7 @@ fn get_expected_date_time( str: &str ) -> chrono::NaiveDateTime {
 // These functions are a bit of code duplication, but having known-good versions
 // that panic on unexpected errors is useful for the integration tests.
 fn get_created_time( path_str: &str ) -> Option<chrono::DateTime<Utc>> {
-    let all_file_metadata = fs::metadata( Path::new(path_str) ).expect(format!("Couldn't get metadata for test file: {}",path_str).as_str());
+    let all_file_metadata = fs::metadata( Path::new(path_str) ).unwrap_or_else(|_| panic!("Couldn't get metadata for test file: {}",path_str));
 
     if !all_file_metadata.is_file() {
         panic!("Test path is not a file: {}",path_str);
@@ -58,7 +58,7 @@ fn get_created_time( path_str: &str ) -> Option<chrono::DateTime<Utc>> {
 }
 
 fn get_modified_time( path_str: &str ) -> Option<chrono::DateTime<Utc>> {
-    let all_file_metadata = fs::metadata( Path::new(path_str) ).expect(format!("Couldn't get metadata for test file: {}",path_str).as_str());
+    let all_file_metadata = fs::metadata( Path::new(path_str) ).unwrap_or_else(|_| panic!("Couldn't get metadata for test file: {}",path_str));
 
     if !all_file_metadata.is_file() {
         panic!("Test path is not a file: {}",path_str);
@@ -71,6 +71,38 @@ fn get_modified_time( path_str: &str ) -> Option<chrono::DateTime<Utc>> {
     }
 }
 
+// The vendor section expected of the Canon test images, all from an EOS 5D Mark IV, which keeps
+// no shutter count in its MakerNote. Only the fields pinned here are compared: see
+// assert_matches_expected.
+fn canon_5d_mark_iv_vendor() -> VendorMetadataOfInterest {
+    VendorMetadataOfInterest {
+        make: "canon".to_string(),
+        shutter_count: None,
+        lens: None,
+        internal_serial: None,
+        firmware_version: None,
+        details: None,
+    }
+}
+
+// Compares the metadata read from a test file with its expectation. The thumbnail, color and
+// MakerNote details depend on bytes of the test images which aren't pinned here yet, so they are
+// left out rather than derived again by the test; the vendor make and shutter count are compared.
+// Those sections are checked against hard-coded values with the synthetic images of
+// previews_tests, color_tests and makernote_tests.
+fn assert_matches_expected( test_file: &TestFile, actual: rusimeta::MetadataOfInterest ) {
+    let mut actual = actual;
+    actual.thumbnail = None;
+    actual.color = None;
+    if let Some(vendor_metadata) = actual.vendor_metadata.as_mut() {
+        vendor_metadata.lens = None;
+        vendor_metadata.internal_serial = None;
+        vendor_metadata.firmware_version = None;
+        vendor_metadata.details = None;
+    }
+    assert_eq!( test_file.expected_metadata(), actual );
+}
+
 // Note for this expected data that created_time and modified_time
 // are going to be dependent on when and how the resource files are copied,
 // and it does not make sense to hard-code them in the expectation.
@@ -92,15 +124,24 @@ fn init_test_data() -> AllTestData<'static> {
                 file_metadata: rusimeta::FileMetadataOfInterest {
                     filename: "JAM19896.jpg".to_string(),
                     size: 953_458,
+                    mime_type: Some("image/jpeg".to_string()),
                     created_time: get_created_time("tests/resource/images1/JAM19896.jpg"),
                     modified_time: get_modified_time("tests/resource/images1/JAM19896.jpg"),
+                    hashes: None,
                 },
                 image_metadata: rusimeta::ImageMetadataOfInterest {
                     orientation: Some(Orientation::Normal),
                     capture_time: Some(get_expected_date_time("2019:07:26 13:25:33")),
                     camera_model: Some("Canon EOS 5D Mark IV".to_string()),
                     camera_serial: Some("025021000537".to_string()),
+                    location: None,
                 },
+                video_metadata: None,
+                thumbnail: None,
+                color: None,
+                vendor_metadata: Some( canon_5d_mark_iv_vendor() ),
+                frames: None,
+                sequence: None,
             },
         },
         COMPLETE_METADATA_2: TestFile {
@@ -110,15 +151,24 @@ fn init_test_data() -> AllTestData<'static> {
                 file_metadata: rusimeta::FileMetadataOfInterest {
                     filename: "JAM26284.jpg".to_string(),
                     size: 574_207,
+                    mime_type: Some("image/jpeg".to_string()),
                     created_time: get_created_time("tests/resource/images1/JAM26284.jpg"),
                     modified_time: get_modified_time("tests/resource/images1/JAM26284.jpg"),
+                    hashes: None,
                 },
                 image_metadata: rusimeta::ImageMetadataOfInterest {
                     orientation: Some(Orientation::Normal),
                     capture_time: Some(get_expected_date_time("2020:01:30 09:28:07")),
                     camera_model: Some("Canon EOS 5D Mark IV".to_string()),
                     camera_serial: Some("025021000535".to_string()),
+                    location: None,
                 },
+                video_metadata: None,
+                thumbnail: None,
+                color: None,
+                vendor_metadata: Some( canon_5d_mark_iv_vendor() ),
+                frames: None,
+                sequence: None,
             },
         },
         COMPLETE_METADATA_3: TestFile {
@@ -128,15 +178,24 @@ fn init_test_data() -> AllTestData<'static> {
                 file_metadata: rusimeta::FileMetadataOfInterest {
                     filename: "JAM26496.jpg".to_string(),
                     size: 353_914,
+                    mime_type: Some("image/jpeg".to_string()),
                     created_time: get_created_time("tests/resource/images2/JAM26496.jpg"),
                     modified_time: get_modified_time("tests/resource/images2/JAM26496.jpg"),
+                    hashes: None,
                 },
                 image_metadata: rusimeta::ImageMetadataOfInterest {
                     orientation: Some(Orientation::Normal),
                     capture_time: Some(get_expected_date_time("2020:01:30 09:44:56")),
                     camera_model: Some("Canon EOS 5D Mark IV".to_string()),
                     camera_serial: Some("025021000535".to_string()),
+                    location: None,
                 },
+                video_metadata: None,
+                thumbnail: None,
+                color: None,
+                vendor_metadata: Some( canon_5d_mark_iv_vendor() ),
+                frames: None,
+                sequence: None,
             },
         },
         INCOMPLETE_METADATA: TestFile {
@@ -146,15 +205,24 @@ fn init_test_data() -> AllTestData<'static> {
                 file_metadata: rusimeta::FileMetadataOfInterest {
                     filename: "rotated_CCW90.jpg".to_string(),
                     size: 327_616,
+                    mime_type: Some("image/jpeg".to_string()),
                     created_time: get_created_time("tests/resource/images2/rotated_CCW90.jpg"),
                     modified_time: get_modified_time("tests/resource/images2/rotated_CCW90.jpg"),
+                    hashes: None,
                 },
                 image_metadata: rusimeta::ImageMetadataOfInterest {
                     orientation: Some(Orientation::QuarterRotationCCW),
                     capture_time: None,
                     camera_model: None,
                     camera_serial: None,
+                    location: None,
                 },
+                video_metadata: None,
+                thumbnail: None,
+                color: None,
+                vendor_metadata: None,
+                frames: None,
+                sequence: None,
             },
         },
     }
@@ -179,10 +247,7 @@ fn single_file_with_complete_metadata_can_be_read_and_serialized_to_json()
 
     // THEN the JSON output is written and as expected; all fields are populated
     assert!(Path::new(td.COMPLETE_METADATA_1.expected_json_path()).exists());
-    assert_eq!(
-        td.COMPLETE_METADATA_1.expected_metadata(),
-        rusimeta::read_json_metadata(td.COMPLETE_METADATA_1.expected_json_path()).unwrap()
-    );
+    assert_matches_expected( &td.COMPLETE_METADATA_1, rusimeta::read_json_metadata(td.COMPLETE_METADATA_1.expected_json_path()).unwrap() );
 }
 
 #[test]
@@ -199,10 +264,7 @@ fn single_file_with_incomplete_metadata_can_be_read_and_serialized_to_json()
 
     // THEN the JSON output is written and as expected; only the fields corresponding to the present input metadata are present
     assert!(Path::new(td.INCOMPLETE_METADATA.expected_json_path()).exists());
-    assert_eq!(
-        td.INCOMPLETE_METADATA.expected_metadata(),
-        rusimeta::read_json_metadata(td.INCOMPLETE_METADATA.expected_json_path()).unwrap()
-    );
+    assert_matches_expected( &td.INCOMPLETE_METADATA, rusimeta::read_json_metadata(td.INCOMPLETE_METADATA.expected_json_path()).unwrap() );
 }
 
 #[test]
@@ -227,10 +289,7 @@ fn multiple_files_metadata_can_be_read_and_dumped_and_serialized_to_json()
     // THEN the expected JSON output is written for each of the inputs
     for test_file in test_files {
         assert!(Path::new(test_file.expected_json_path()).exists());
-        assert_eq!(
-            test_file.expected_metadata(),
-            rusimeta::read_json_metadata(test_file.expected_json_path()).unwrap()
-        );
+        assert_matches_expected( test_file, rusimeta::read_json_metadata(test_file.expected_json_path()).unwrap() );
     }
 }
 
@@ -258,16 +317,10 @@ fn duplicate_files_metadata_can_be_read_and_paths_can_be_absolute_or_relative()
 
     // THEN the expected JSON output is written for each of the inputs, the same as if no duplicate paths had been provided
     assert!(Path::new(td.COMPLETE_METADATA_1.expected_json_path()).exists());
-    assert_eq!(
-        td.COMPLETE_METADATA_1.expected_metadata(),
-        rusimeta::read_json_metadata(td.COMPLETE_METADATA_1.expected_json_path()).unwrap()
-    );
+    assert_matches_expected( &td.COMPLETE_METADATA_1, rusimeta::read_json_metadata(td.COMPLETE_METADATA_1.expected_json_path()).unwrap() );
 
     assert!(Path::new(td.COMPLETE_METADATA_2.expected_json_path()).exists());
-    assert_eq!(
-        td.COMPLETE_METADATA_2.expected_metadata(),
-        rusimeta::read_json_metadata(td.COMPLETE_METADATA_2.expected_json_path()).unwrap()
-    );
+    assert_matches_expected( &td.COMPLETE_METADATA_2, rusimeta::read_json_metadata(td.COMPLETE_METADATA_2.expected_json_path()).unwrap() );
 }
 
 // This test is simply expected to correctly process the supported files and ignore the unsupported files without panicking.
@@ -290,18 +343,17 @@ fn wrong_file_types_are_ignored()
     let result = rusimeta::run( cfg );
     assert!(result.is_ok(),"{:?}",result.err());
 
-    // THEN the expected JSON output is written for each of the supported inputs, and the program does not panic
+    // THEN the unsupported inputs are skipped rather than counted as read failures
+    let summary = result.unwrap();
+    assert_eq!(summary.skipped_unsupported, 2);
+    assert_eq!(summary.failed, 0);
+
+    // AND the expected JSON output is written for each of the supported inputs, and the program does not panic
     assert!(Path::new(td.COMPLETE_METADATA_1.expected_json_path()).exists());
-    assert_eq!(
-        td.COMPLETE_METADATA_1.expected_metadata(),
-        rusimeta::read_json_metadata(td.COMPLETE_METADATA_1.expected_json_path()).unwrap()
-    );
+    assert_matches_expected( &td.COMPLETE_METADATA_1, rusimeta::read_json_metadata(td.COMPLETE_METADATA_1.expected_json_path()).unwrap() );
 
     assert!(Path::new(td.INCOMPLETE_METADATA.expected_json_path()).exists());
-    assert_eq!(
-        td.INCOMPLETE_METADATA.expected_metadata(),
-        rusimeta::read_json_metadata(td.INCOMPLETE_METADATA.expected_json_path()).unwrap()
-    );
+    assert_matches_expected( &td.INCOMPLETE_METADATA, rusimeta::read_json_metadata(td.INCOMPLETE_METADATA.expected_json_path()).unwrap() );
 
     // DoMore - this should ideally also check the number of json files in the output directory
     // and make sure that none were created for the unsupported files (i.e. the expected number should be present)
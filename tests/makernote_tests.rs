@@ -0,0 +1,177 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use serial_test::serial;
+
+use rusimeta::{CanonMakerNote, NikonMakerNote, SonyMakerNote, VendorDetails, VendorMetadataOfInterest};
+use common::{build_jpeg, build_with_maker_note, canon_exif, nikon_maker_note, scratch_dir, ExifBuilder, TiffEntries, TiffValue};
+
+fn camera_exif( make: &'static str, model: &'static str, software: &'static str ) -> ExifBuilder {
+    ExifBuilder {
+        ifd0: vec![
+            (0x010F, TiffValue::Ascii(make)),
+            (0x0110, TiffValue::Ascii(model)),
+            (0x0131, TiffValue::Ascii(software)),
+        ],
+        exif: vec![(0x9003, TiffValue::Ascii("2021:05:01 10:00:00"))],
+        ..Default::default()
+    }
+}
+
+fn read_vendor_metadata( path: &Path ) -> VendorMetadataOfInterest {
+    rusimeta::read_metadata_of_interest( path ).unwrap().vendor_metadata.expect("vendor section should be present")
+}
+
+#[test]
+#[serial]
+fn canon_maker_note_fields_are_decoded_into_the_vendor_section()
+{
+    // GIVEN a Canon JPEG whose MakerNote has the firmware, owner, serial, lens and AF records
+    let dir = scratch_dir("makernote_canon");
+    let path = dir.join("IMG_0301.jpg");
+    let mut camera_settings = vec![0u16; 30];
+    camera_settings[22] = 61182;
+    // AFInfo2 for 3 points with points 0 and 2 in focus
+    let mut af_info = vec![0u16, 2, 3, 3, 6720, 4480, 6720, 4480];
+    af_info.extend( vec![100u16; 3 * 4] );
+    af_info.push( 0b101 );
+    let maker_note = vec![
+        (0x0001, TiffValue::Short(camera_settings)),
+        (0x0007, TiffValue::Ascii("Firmware Version 1.0.4")),
+        (0x0008, TiffValue::Long(vec![1_000_123])),
+        (0x0009, TiffValue::Ascii("Studio A")),
+        (0x0026, TiffValue::Short(af_info)),
+        (0x0095, TiffValue::Ascii("EF24-70mm f/2.8L II USM")),
+        (0x0096, TiffValue::Ascii("PE1234567")),
+    ];
+    fs::write( &path, build_jpeg( Some(&build_with_maker_note( &canon_exif(), b"", &maker_note )), &[] ) ).unwrap();
+
+    // WHEN the metadata is read and written to JSON
+    let cfg = rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] );
+    let result = rusimeta::run( cfg );
+    assert!(result.is_ok(),"{:?}",result.err());
+
+    // THEN the vendor section holds the decoded fields, and the shutter count is absent since the 5D Mark IV doesn't record it
+    let metadata = rusimeta::read_json_metadata( dir.join("IMG_0301.json").to_str().unwrap() ).unwrap();
+    assert_eq!( metadata.vendor_metadata, Some( VendorMetadataOfInterest {
+        make: "canon".to_string(),
+        shutter_count: None,
+        lens: Some("EF24-70mm f/2.8L II USM".to_string()),
+        internal_serial: Some("PE1234567".to_string()),
+        firmware_version: Some("Firmware Version 1.0.4".to_string()),
+        details: Some( VendorDetails::Canon( CanonMakerNote {
+            owner_name: Some("Studio A".to_string()),
+            file_number: Some(1_000_123),
+            lens_id: Some(61182),
+            af_area_mode: Some("single_point".to_string()),
+            af_points: Some(3),
+            af_points_in_focus: vec![0, 2],
+            sequence_number: None,
+        } ) ),
+    } ) );
+}
+
+#[test]
+#[serial]
+fn canon_shutter_count_is_read_from_camera_info_for_bodies_which_record_it()
+{
+    // GIVEN an EOS-1D Mark III JPEG whose CameraInfo record holds the shutter count
+    let dir = scratch_dir("makernote_canon_shutter");
+    let path = dir.join("IMG_0302.jpg");
+    let mut exif = canon_exif();
+    exif.ifd0[1] = (0x0110, TiffValue::Ascii("Canon EOS-1D Mark III"));
+    let mut camera_info = vec![0u8; 0x200];
+    camera_info[0x176..0x17A].copy_from_slice( &123_456u32.to_le_bytes() );
+    let maker_note = vec![(0x000D, TiffValue::Undefined(camera_info))];
+    fs::write( &path, build_jpeg( Some(&build_with_maker_note( &exif, b"", &maker_note )), &[] ) ).unwrap();
+
+    // WHEN the metadata is read
+    let metadata = rusimeta::read_metadata_of_interest( &path ).unwrap();
+
+    // THEN the shutter count is reported alongside the body serial
+    assert_eq!( metadata.image_metadata.camera_serial, Some("025021000537".to_string()) );
+    assert_eq!( metadata.vendor_metadata.unwrap().shutter_count, Some(123_456) );
+}
+
+#[test]
+#[serial]
+fn nikon_maker_note_is_decoded_including_its_encrypted_lens_data()
+{
+    // GIVEN Nikon JPEGs whose LensData record is encrypted with a key from the serial number and shutter count
+    let dir = scratch_dir("makernote_nikon");
+    let nikon_file = |name: &str, lens_data: Vec<u8>| {
+        let maker_note : TiffEntries = vec![
+            (0x001D, TiffValue::Ascii("4012345")),
+            (0x0084, TiffValue::Rational(vec![(240, 10), (700, 10), (28, 10), (28, 10)])),
+            (0x0098, TiffValue::Undefined(lens_data)),
+            (0x00A0, TiffValue::Ascii("NO= 3005ab31")),
+            (0x00A7, TiffValue::Long(vec![23_456])),
+        ];
+        let mut exif = camera_exif( "NIKON CORPORATION", "NIKON D750", "Ver.1.10" );
+        exif.exif.push( (0x927C, TiffValue::Undefined(nikon_maker_note( &maker_note ))) );
+        let path = dir.join(name);
+        fs::write( &path, build_jpeg( Some(&exif.build()), &[] ) ).unwrap();
+        path
+    };
+    // The cipher XORs the data with a keystream, so an all-zero record reveals the keystream byte at LensIDNumber
+    let mut lens_data = b"0204".to_vec();
+    lens_data.extend( vec![0u8; 32] );
+    let keystream = match read_vendor_metadata( &nikon_file( "DSC_0001.jpg", lens_data.clone() ) ).details {
+        Some(VendorDetails::Nikon(details)) => details.lens_id.unwrap(),
+        other => panic!("Expected Nikon details, got {:?}", other),
+    };
+    lens_data[0x0C] = keystream ^ 0xA2;
+    let path = nikon_file( "DSC_0002.jpg", lens_data );
+
+    // WHEN the metadata is read
+    let vendor_metadata = read_vendor_metadata( &path );
+
+    // THEN the common fields and the decrypted lens ID are reported
+    assert_eq!( vendor_metadata, VendorMetadataOfInterest {
+        make: "nikon".to_string(),
+        shutter_count: Some(23_456),
+        lens: Some("24-70mm f/2.8".to_string()),
+        internal_serial: Some("NO= 3005ab31".to_string()),
+        firmware_version: Some("Ver.1.10".to_string()),
+        details: Some( VendorDetails::Nikon( NikonMakerNote {
+            serial_number: Some("4012345".to_string()),
+            lens_id: Some(0xA2),
+        } ) ),
+    } );
+}
+
+#[test]
+#[serial]
+fn sony_maker_note_is_decoded_including_its_enciphered_record()
+{
+    // GIVEN a Sony JPEG whose Tag9050 record, enciphered byte by byte, holds the shutter count and internal serial
+    let dir = scratch_dir("makernote_sony");
+    let path = dir.join("DSC00001.jpg");
+    let mut record = vec![0u8; 0x100];
+    record[0x3A..0x3E].copy_from_slice( &(0x5500_0000u32 | 48_211).to_le_bytes() );
+    record[0x88..0x8E].copy_from_slice( &[0x03, 0x12, 0x00, 0x4f, 0x7a, 0x21] );
+    let enciphered = record.iter()
+        .map(|byte| if *byte as u32 >= 249 { *byte } else { ((*byte as u32).pow(3) % 249) as u8 })
+        .collect();
+    let maker_note = vec![
+        (0x9050, TiffValue::Undefined(enciphered)),
+        (0xB027, TiffValue::Long(vec![32_826])),
+    ];
+    let mut exif = camera_exif( "SONY", "ILCE-7M3", "ILCE-7M3 v3.01" );
+    exif.exif.push( (0xA434, TiffValue::Ascii("FE 24-105mm F4 G OSS")) );
+    fs::write( &path, build_jpeg( Some(&build_with_maker_note( &exif, b"SONY DSC \0\0\0", &maker_note )), &[] ) ).unwrap();
+
+    // WHEN the metadata is read
+    let vendor_metadata = read_vendor_metadata( &path );
+
+    // THEN the deciphered fields are reported, with the lens from the standard LensModel tag
+    assert_eq!( vendor_metadata, VendorMetadataOfInterest {
+        make: "sony".to_string(),
+        shutter_count: Some(48_211),
+        lens: Some("FE 24-105mm F4 G OSS".to_string()),
+        internal_serial: Some("0312004f7a21".to_string()),
+        firmware_version: Some("ILCE-7M3 v3.01".to_string()),
+        details: Some( VendorDetails::Sony( SonyMakerNote { lens_id: Some(32_826) } ) ),
+    } );
+}
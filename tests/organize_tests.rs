@@ -0,0 +1,167 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use serial_test::serial;
+
+use rusimeta::{NameTemplate, Organize, OrganizeOutcome, Redaction, WriteOptions};
+use common::{build_jpeg, canon_exif, scratch_dir, ExifBuilder, TiffValue};
+
+fn exif_taken_at( capture_time: &'static str ) -> ExifBuilder {
+    let mut exif = canon_exif();
+    exif.exif[0] = (0x9003, TiffValue::Ascii(capture_time));
+    exif
+}
+
+fn files_under( dir: &Path ) -> Vec<String> {
+    let mut files = vec![];
+    for entry in fs::read_dir( dir ).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend( files_under( &path ).into_iter().map(|file| format!("{}/{}", path.file_name().unwrap().to_string_lossy(), file)) );
+        } else {
+            files.push( path.file_name().unwrap().to_string_lossy().into_owned() );
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+#[serial]
+fn a_card_dump_is_moved_into_dated_camera_folders_with_sidecars()
+{
+    // GIVEN a card dump with frames from two days in a subfolder, and a frame without EXIF data
+    // last modified in the middle of 2021
+    let dir = scratch_dir("organize_move");
+    let (card, library) = (dir.join("card"), dir.join("library"));
+    fs::create_dir_all( card.join("DCIM/100CANON") ).unwrap();
+    let first_day = build_jpeg( Some(&exif_taken_at( "2019:07:26 13:25:33" ).build()), &[] );
+    fs::write( card.join("DCIM/100CANON/IMG_0001.JPG"), &first_day ).unwrap();
+    fs::write( card.join("DCIM/100CANON/IMG_0002.JPG"), build_jpeg( Some(&exif_taken_at( "2019:07:27 08:00:00" ).build()), &[] ) ).unwrap();
+    let untimed = card.join("DCIM/100CANON/IMG_0003.JPG");
+    fs::write( &untimed, build_jpeg( None, &[] ) ).unwrap();
+    let modified_time = SystemTime::UNIX_EPOCH + Duration::from_secs( 1_625_140_800 );
+    fs::File::options().write( true ).open( &untimed ).unwrap().set_modified( modified_time ).unwrap();
+
+    // WHEN the card is moved into the library
+    let organize = Organize { move_files: true, ..Default::default() };
+    let outcomes : Vec<OrganizeOutcome> = rusimeta::files_to_organize( std::slice::from_ref( &card ), &library ).iter()
+        .map(|path| rusimeta::organize_file( path, &library, &organize, &WriteOptions::default(), &Redaction::default() ).unwrap())
+        .collect();
+
+    // THEN each frame is in the folder of its day and camera, with its sidecar, and the card is
+    // empty
+    assert_eq!( files_under( &library ), vec![
+        "2019/2019-07-26/Canon_EOS_5D_Mark_IV/IMG_0001.JPG", "2019/2019-07-26/Canon_EOS_5D_Mark_IV/IMG_0001.json",
+        "2019/2019-07-27/Canon_EOS_5D_Mark_IV/IMG_0002.JPG", "2019/2019-07-27/Canon_EOS_5D_Mark_IV/IMG_0002.json",
+        "2021/2021-07-01/unknown/IMG_0003.JPG", "2021/2021-07-01/unknown/IMG_0003.json",
+    ] );
+    assert!( files_under( &card ).is_empty() );
+    let moved = library.join("2019/2019-07-26/Canon_EOS_5D_Mark_IV/IMG_0001.JPG");
+    assert_eq!( outcomes[0], OrganizeOutcome::Moved(moved.clone()) );
+    assert_eq!( fs::read( &moved ).unwrap(), first_day );
+    let sidecar = rusimeta::read_json_metadata( &library.join("2019/2019-07-26/Canon_EOS_5D_Mark_IV/IMG_0001.json").to_string_lossy() ).unwrap();
+    assert_eq!( sidecar.image_metadata.camera_serial.as_deref(), Some("025021000537") );
+
+    // AND the frame without EXIF data keeps its modification time
+    let copied_untimed = library.join("2021/2021-07-01/unknown/IMG_0003.JPG");
+    assert_eq!( fs::metadata( &copied_untimed ).unwrap().modified().unwrap(), modified_time );
+}
+
+#[test]
+#[serial]
+fn existing_copies_are_skipped_and_other_files_with_the_same_name_are_kept()
+{
+    // GIVEN a library holding a copy of one frame, and another file with the name of a second
+    let dir = scratch_dir("organize_copy");
+    let (card, library) = (dir.join("card"), dir.join("library"));
+    fs::create_dir_all( &card ).unwrap();
+    let folder = library.join("Canon_EOS_5D_Mark_IV/2019-07");
+    fs::create_dir_all( &folder ).unwrap();
+    let first = build_jpeg( Some(&exif_taken_at( "2019:07:26 13:25:33" ).build()), &[] );
+    let second = build_jpeg( Some(&exif_taken_at( "2019:07:26 13:30:00" ).build()), &[] );
+    fs::write( card.join("IMG_0001.JPG"), &first ).unwrap();
+    fs::write( card.join("IMG_0002.JPG"), &second ).unwrap();
+    fs::write( folder.join("IMG_0001.JPG"), &first ).unwrap();
+    fs::write( folder.join("IMG_0002.JPG"), "from another card" ).unwrap();
+    let args = |extra: &[&str]| -> Vec<String> {
+        let mut args = vec!["rusimeta", "organize", "--to", library.to_str().unwrap(), "--layout", "{camera_model}/{year}-{month}"];
+        args.extend_from_slice( extra );
+        args.push( card.to_str().unwrap() );
+        args.into_iter().map(|arg| arg.to_string()).collect()
+    };
+
+    // WHEN the card is organized from the command line, first as a dry run
+    rusimeta::run( rusimeta::Config::new( args( &["--dry-run"] ).into_iter() ).unwrap() ).unwrap();
+    let after_dry_run = files_under( &library );
+    let summary = rusimeta::run( rusimeta::Config::new( args( &[] ).into_iter() ).unwrap() ).unwrap();
+
+    // THEN the dry run wrote nothing, the frame already there isn't copied again, and the other
+    // frame gets a suffix rather than replacing the other file
+    assert_eq!( after_dry_run, vec!["Canon_EOS_5D_Mark_IV/2019-07/IMG_0001.JPG", "Canon_EOS_5D_Mark_IV/2019-07/IMG_0002.JPG"] );
+    assert_eq!( (summary.read, summary.failed), (2, 0) );
+    assert_eq!( files_under( &folder ), vec!["IMG_0001.JPG", "IMG_0001.json", "IMG_0002.JPG", "IMG_0002_2.JPG", "IMG_0002_2.json"] );
+    assert_eq!( fs::read( folder.join("IMG_0002.JPG") ).unwrap(), b"from another card" );
+    assert_eq!( fs::read( folder.join("IMG_0002_2.JPG") ).unwrap(), second );
+    assert_eq!( files_under( &card ), vec!["IMG_0001.JPG", "IMG_0002.JPG"] );
+
+    // AND layouts must stay inside the destination, which must be given
+    for invalid in ["../{year}", "/{year}", "{year}//{month}", "{lens}"].iter() {
+        assert!( NameTemplate::layout( invalid ).is_err(), "{}", invalid );
+    }
+    let without_destination : Vec<String> = ["rusimeta", "organize", card.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+    assert!( rusimeta::Config::new( without_destination.into_iter() ).is_err() );
+}
+
+#[test]
+#[serial]
+fn sidecars_are_moved_with_their_images_and_existing_ones_are_left_alone()
+{
+    // GIVEN a card with a frame whose sidecar was geotagged, a frame already in the library with
+    // an edited sidecar there, and a frame whose sidecar was redacted
+    let dir = scratch_dir("organize_sidecars");
+    let (card, library) = (dir.join("card"), dir.join("library"));
+    let folder = library.join("Canon_EOS_5D_Mark_IV/2019-07");
+    fs::create_dir_all( &card ).unwrap();
+    fs::create_dir_all( &folder ).unwrap();
+    for (name, capture_time) in [("IMG_0001.JPG", "2019:07:26 13:25:33"), ("IMG_0002.JPG", "2019:07:26 13:30:00"), ("IMG_0003.JPG", "2019:07:26 13:35:00")].iter() {
+        fs::write( card.join(name), build_jpeg( Some(&exif_taken_at( capture_time ).build()), &[] ) ).unwrap();
+    }
+    let mut geotagged = rusimeta::read_metadata_of_interest( &card.join("IMG_0001.JPG") ).unwrap();
+    geotagged.image_metadata.location = Some( rusimeta::GpsLocation { latitude: 51.5, longitude: -0.15, altitude: None } );
+    rusimeta::write_json_metadata( &geotagged, &card.join("IMG_0001.json") ).unwrap();
+    fs::copy( card.join("IMG_0002.JPG"), folder.join("IMG_0002.JPG") ).unwrap();
+    let mut edited = rusimeta::read_metadata_of_interest( &folder.join("IMG_0002.JPG") ).unwrap();
+    edited.image_metadata.camera_model = Some("Canon EOS 5D Mark IV (rented)".to_string());
+    rusimeta::write_json_metadata( &edited, &folder.join("IMG_0002.json") ).unwrap();
+    rusimeta::write_json_metadata( &geotagged, &card.join("IMG_0002.json") ).unwrap();
+    let library_sidecar = fs::read( folder.join("IMG_0002.json") ).unwrap();
+    let redaction = Redaction { serial_key: Some(b"secret".to_vec()), ..Default::default() };
+    rusimeta::run( rusimeta::Config::from_strings( vec![card.join("IMG_0003.JPG").to_str().unwrap().to_string()] ).with_redaction( redaction ) ).unwrap();
+    let redacted = fs::read( card.join("IMG_0003.json") ).unwrap();
+
+    // WHEN the card is moved into the library
+    let organize = Organize { layout: NameTemplate::layout( "{camera_model}/{year}-{month}" ).unwrap(), move_files: true };
+    let outcomes : Vec<Result<OrganizeOutcome, String>> = rusimeta::files_to_organize( std::slice::from_ref( &card ), &library ).iter()
+        .map(|path| rusimeta::organize_file( path, &library, &organize, &WriteOptions::default(), &Redaction::default() ).map_err(|err| err.to_string()))
+        .collect();
+
+    // THEN the sidecars on the card aren't organized as files of their own
+    assert_eq!( outcomes.len(), 3 );
+    assert_eq!( outcomes[0], Ok( OrganizeOutcome::Moved(folder.join("IMG_0001.JPG")) ) );
+    assert_eq!( outcomes[1], Ok( OrganizeOutcome::AlreadyPresent(folder.join("IMG_0002.JPG")) ) );
+    assert!( outcomes[2].is_err() );
+
+    // AND the geotagged sidecar moved with its frame
+    let moved = rusimeta::read_json_metadata( folder.join("IMG_0001.json").to_str().unwrap() ).unwrap();
+    assert_eq!( moved.image_metadata.location.map(|location| location.latitude), Some(51.5) );
+    assert_eq!( moved.file_metadata.filename, "IMG_0001.JPG" );
+
+    // AND the sidecar of the frame already in the library is untouched, the one on the card is
+    // kept, and the frame with a redacted sidecar stays on the card
+    assert_eq!( fs::read( folder.join("IMG_0002.json") ).unwrap(), library_sidecar );
+    assert_eq!( files_under( &card ), vec!["IMG_0002.json", "IMG_0003.JPG", "IMG_0003.json"] );
+    assert_eq!( fs::read( card.join("IMG_0003.json") ).unwrap(), redacted );
+}
@@ -0,0 +1,75 @@
+use std::convert::TryFrom;
+
+use rusimeta::{Command, ImageMetadataPatch, Orientation, WriteOptions};
+
+// Where a point of the stored image, in unit coordinates, is displayed.
+fn display( orientation: Orientation, point: (i32, i32) ) -> (i32, i32) {
+    let [[a, b, c], [d, e, f]] = orientation.to_matrix();
+    (a * point.0 + b * point.1 + c, d * point.0 + e * point.1 + f)
+}
+
+#[test]
+fn orientations_compose_invert_and_convert_consistently()
+{
+    // GIVEN an image stored a quarter turn counter-clockwise, which is displayed by turning it clockwise
+    let rotated = Orientation::QuarterRotationCCW;
+
+    // WHEN it is turned clockwise again, or mirrored twice
+    let turned = rotated.then( Orientation::from_rotation( 90, false ).unwrap() );
+    let mirrored_twice = Orientation::Mirrored.then( Orientation::Mirrored );
+
+    // THEN the orientations compose as the rotations do
+    assert_eq!( turned, Orientation::UpsideDown );
+    assert_eq!( mirrored_twice, Orientation::Normal );
+    assert_eq!( rotated.to_rotation(), (90, false) );
+    assert_eq!( display( rotated, (0, 0) ), (1, 0) );
+    assert_eq!( Orientation::from_rotation( -90, false ), Some(Orientation::QuarterRotationCW) );
+    assert_eq!( Orientation::from_rotation( 45, false ), None );
+
+    // AND every orientation is undone by its inverse, round trips through its matrix and rotation,
+    // is a mirror followed by a rotation, and swaps the dimensions exactly for the quarter turns
+    for orientation in Orientation::ALL.iter().copied() {
+        assert_eq!( orientation.then( orientation.inverse() ), Orientation::Normal );
+        assert_eq!( orientation.inverse().then( orientation ), Orientation::Normal );
+        assert_eq!( Orientation::from_matrix( orientation.to_matrix() ), Some(orientation) );
+        let (degrees, mirrored) = orientation.to_rotation();
+        assert_eq!( Orientation::from_rotation( degrees as i32, mirrored ), Some(orientation) );
+        let mirror = if mirrored { Orientation::Mirrored } else { Orientation::Normal };
+        assert_eq!( mirror.then( Orientation::from_rotation( degrees as i32, false ).unwrap() ), orientation );
+        assert_eq!( orientation.swaps_dimensions(), degrees % 180 == 90 );
+        for point in [(0, 0), (1, 0), (0, 1)].iter() {
+            assert_eq!( display( orientation.inverse(), display( orientation, *point ) ), *point );
+        }
+    }
+}
+
+#[test]
+fn orientations_have_names_and_tag_values_which_round_trip()
+{
+    // GIVEN every orientation
+    for orientation in Orientation::ALL.iter().copied() {
+        // WHEN it is written as its name and as its tag value
+        let name = orientation.to_string();
+        let value = u16::from( orientation );
+
+        // THEN both parse back to it, the name in any case
+        assert_eq!( name.parse::<Orientation>().unwrap(), orientation );
+        assert_eq!( name.to_uppercase().parse::<Orientation>().unwrap(), orientation );
+        assert_eq!( value.to_string().parse::<Orientation>().unwrap(), orientation );
+        assert_eq!( Orientation::try_from( value ), Ok(orientation) );
+
+        // AND the deprecated conversion still gives the same tag value
+        #[allow(deprecated)]
+        let deprecated_value = rusimeta::orientation_as_u16( orientation );
+        assert_eq!( deprecated_value, value );
+    }
+    assert_eq!( Orientation::QuarterRotationCW.to_string(), "quarter-rotation-cw" );
+    assert!( "sideways".parse::<Orientation>().is_err() );
+    assert!( "9".parse::<Orientation>().is_err() );
+
+    // AND the set command accepts either form
+    let strings : Vec<String> = ["rusimeta", "set", "--orientation", "quarter-rotation-cw", "a.jpg"].iter().map(|arg| arg.to_string()).collect();
+    let config = rusimeta::Config::new( strings.into_iter() ).unwrap();
+    let patch = ImageMetadataPatch { orientation: Some(Orientation::QuarterRotationCW), ..Default::default() };
+    assert_eq!( config.command(), &Command::Set { patch, options: WriteOptions::default() } );
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{Orientation, WriteOptions};
+use common::{build_coded_jpeg, canon_exif, decode_coded_jpeg, scratch_dir, CodedJpeg, TiffValue};
+
+// Where the upright pixel (x, y) of an image is in the stored image, which is `width` by
+// `height`, for each Orientation tag value as the EXIF specification defines them.
+fn stored_position( orientation: u16, x: usize, y: usize, width: usize, height: usize ) -> (usize, usize) {
+    match orientation {
+        1 => (x, y),
+        2 => (width - 1 - x, y),
+        3 => (width - 1 - x, height - 1 - y),
+        4 => (x, height - 1 - y),
+        5 => (y, x),
+        6 => (y, height - 1 - x),
+        7 => (width - 1 - y, height - 1 - x),
+        8 => (width - 1 - y, x),
+        _ => panic!("not an orientation"),
+    }
+}
+
+// Checks that every component of the normalized image shows the stored image upright.
+fn assert_upright( stored: &CodedJpeg, normalized: &CodedJpeg, orientation: u16 ) {
+    for index in 0..stored.components.len() {
+        let stored_samples = stored.samples( index );
+        let upright_samples = normalized.samples( index );
+        // The part of the stored plane which was kept, in stored terms.
+        let (kept_width, kept_height) = if orientation >= 5 {
+            (upright_samples.len(), upright_samples[0].len())
+        } else {
+            (upright_samples[0].len(), upright_samples.len())
+        };
+        for (y, row) in upright_samples.iter().enumerate() {
+            for (x, sample) in row.iter().enumerate() {
+                let (stored_x, stored_y) = stored_position( orientation, x, y, kept_width, kept_height );
+                let expected = stored_samples[stored_y][stored_x];
+                assert!( (sample - expected).abs() < 1e-6, "orientation {} component {} at {},{}: {} != {}", orientation, index, x, y, sample, expected );
+            }
+        }
+    }
+}
+
+fn oriented_exif( orientation: u16, width: u32, height: u32 ) -> common::ExifBuilder {
+    let mut exif = canon_exif();
+    exif.ifd0[2] = (0x0112, TiffValue::Short(vec![orientation]));
+    exif.exif.push( (0xA002, TiffValue::Long(vec![width])) );
+    exif.exif.push( (0xA003, TiffValue::Long(vec![height])) );
+    exif
+}
+
+#[test]
+#[serial]
+fn each_orientation_is_undone_losslessly_and_the_tag_reset()
+{
+    // GIVEN a greyscale JPEG a whole number of blocks in size, tagged with each orientation in turn
+    let dir = scratch_dir("orientation_all");
+    let stored = CodedJpeg::patterned( 24, 16, &[(1, 1)] );
+    for orientation in 1..=8u16 {
+        let path = dir.join( format!("IMG_08{:02}.jpg", orientation) );
+        fs::write( &path, build_coded_jpeg( &stored, Some(&oriented_exif( orientation, 24, 16 ).build()), 0 ) ).unwrap();
+
+        // WHEN the orientation is normalized
+        let changes = rusimeta::normalize_orientation( &path, &WriteOptions::default() ).unwrap();
+
+        // THEN the image data shows the stored image upright, with nothing trimmed
+        if orientation == 1 {
+            assert!( changes.is_empty() );
+            continue;
+        }
+        let normalized = decode_coded_jpeg( &fs::read( &path ).unwrap() );
+        let expected_size = if orientation >= 5 { (16, 24) } else { (24, 16) };
+        assert_eq!( (normalized.width, normalized.height), expected_size );
+        assert_upright( &stored, &normalized, orientation );
+
+        // AND the tags describe the image as it now is
+        let metadata = rusimeta::read_metadata_of_interest( &path ).unwrap();
+        assert_eq!( metadata.image_metadata.orientation, Some(Orientation::Normal) );
+        let data = fs::read( &path ).unwrap();
+        let exif_fields = exif::Reader::new().read_from_container( &mut std::io::Cursor::new( &data ) ).unwrap();
+        let dimension = |tag| exif_fields.get_field( tag, exif::In::PRIMARY ).and_then(|field| field.value.get_uint( 0 ));
+        assert_eq!( (dimension( exif::Tag::PixelXDimension ), dimension( exif::Tag::PixelYDimension )), (Some(expected_size.0 as u32), Some(expected_size.1 as u32)) );
+    }
+}
+
+#[test]
+#[serial]
+fn partial_edge_mcus_are_trimmed_and_the_thumbnail_follows()
+{
+    // GIVEN a 4:2:2 colour JPEG with restart markers, whose size is not a whole number of MCUs,
+    // rotated a quarter turn counter-clockwise, with a thumbnail stored the same way
+    let dir = scratch_dir("orientation_trim");
+    let path = dir.join("rotated_CCW90.jpg");
+    let stored = CodedJpeg::patterned( 40, 20, &[(2, 1), (1, 1), (1, 1)] );
+    let stored_thumbnail = CodedJpeg::patterned( 16, 8, &[(2, 1), (1, 1), (1, 1)] );
+    let mut exif = oriented_exif( 6, 40, 20 );
+    exif.thumbnail = Some( build_coded_jpeg( &stored_thumbnail, None, 0 ) );
+    fs::write( &path, build_coded_jpeg( &stored, Some(&exif.build()), 1 ) ).unwrap();
+
+    // WHEN the orientation is normalized
+    let changes = rusimeta::normalize_orientation( &path, &WriteOptions::default() ).unwrap();
+
+    // THEN the partial MCU row at the bottom, which would end up on the left, is trimmed off,
+    // and the rest is turned upright with the sampling factors swapped
+    let normalized = decode_coded_jpeg( &fs::read( &path ).unwrap() );
+    assert_eq!( (normalized.width, normalized.height), (16, 40) );
+    assert_eq!( normalized.components[0].sampling, (1, 2) );
+    assert_upright( &stored, &normalized, 6 );
+    let dimensions = changes.iter().find(|change| change.field == "dimensions").unwrap();
+    assert_eq!( (dimensions.old_value.as_deref(), dimensions.new_value.as_str()), (Some("40x20"), "16x40") );
+
+    // AND the thumbnail is turned the same way, and the tags are updated
+    let metadata = rusimeta::read_metadata_of_interest( &path ).unwrap();
+    assert_eq!( metadata.image_metadata.orientation, Some(Orientation::Normal) );
+    let thumbnail = metadata.thumbnail.unwrap();
+    assert_eq!( (thumbnail.width, thumbnail.height), (Some(8), Some(16)) );
+    let data = fs::read( &path ).unwrap();
+    let start = thumbnail.byte_offset as usize;
+    let normalized_thumbnail = decode_coded_jpeg( &data[start..start + thumbnail.byte_length as usize] );
+    assert_upright( &stored_thumbnail, &normalized_thumbnail, 6 );
+    let exif_fields = exif::Reader::new().read_from_container( &mut std::io::Cursor::new( &data ) ).unwrap();
+    let dimension = |tag| exif_fields.get_field( tag, exif::In::PRIMARY ).and_then(|field| field.value.get_uint( 0 ));
+    assert_eq!( (dimension( exif::Tag::PixelXDimension ), dimension( exif::Tag::PixelYDimension )), (Some(16), Some(40)) );
+}
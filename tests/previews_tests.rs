@@ -0,0 +1,72 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{Command, PreviewSource, ThumbnailInfo};
+use common::{build_jpeg, build_mpo, canon_exif, scratch_dir, ExifBuilder, TiffValue};
+
+#[test]
+#[serial]
+fn exif_thumbnail_is_reported_with_the_metadata()
+{
+    // GIVEN a JPEG whose IFD1 holds an embedded JPEG thumbnail
+    let dir = scratch_dir("previews_thumbnail_info");
+    let path = dir.join("IMG_0101.jpg");
+    let thumbnail = build_jpeg( None, &[] );
+    let mut exif = canon_exif();
+    exif.thumbnail = Some( thumbnail.clone() );
+    let file = build_jpeg( Some(&exif.build()), &[] );
+    fs::write( &path, &file ).unwrap();
+
+    // WHEN the metadata is read
+    let metadata = rusimeta::read_metadata_of_interest( &path ).unwrap();
+
+    // THEN the thumbnail's dimensions and location in the file are reported
+    let info = metadata.thumbnail.expect("thumbnail should be reported");
+    assert_eq!( (info.width, info.height), (Some(16), Some(16)) );
+    let offset = info.byte_offset as usize;
+    assert_eq!( &file[offset..offset + info.byte_length as usize], &thumbnail[..] );
+
+    // AND a JPEG without a thumbnail reports none
+    let bare_path = dir.join("IMG_0102.jpg");
+    fs::write( &bare_path, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    assert_eq!( rusimeta::read_metadata_of_interest( &bare_path ).unwrap().thumbnail, None::<ThumbnailInfo> );
+}
+
+#[test]
+#[serial]
+fn previews_command_writes_thumbnail_and_large_mpo_thumbnail()
+{
+    // GIVEN an MPO whose first image has an EXIF thumbnail and whose second image is a VGA large thumbnail
+    let dir = scratch_dir("previews_command");
+    let output_dir = dir.join("out");
+    fs::create_dir_all( &output_dir ).unwrap();
+    let path = dir.join("DSC_0103.jpg");
+    let thumbnail = build_jpeg( None, &[] );
+    let mut first_exif = canon_exif();
+    first_exif.thumbnail = Some( thumbnail.clone() );
+    let second_exif = ExifBuilder {
+        ifd0: vec![(0x0110, TiffValue::Ascii("Canon EOS 5D Mark IV"))],
+        ..Default::default()
+    };
+    let file = build_mpo( &first_exif.build(), &second_exif.build(), 0x01_0001 );
+    fs::write( &path, &file ).unwrap();
+
+    // WHEN the previews are listed, and then extracted through the previews command
+    let previews = rusimeta::list_embedded_previews( &path ).unwrap();
+    let cfg = rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] )
+        .with_command( Command::ExtractPreviews { output_dir: Some(output_dir.clone()) } );
+    let result = rusimeta::run( cfg );
+    assert!(result.is_ok(),"{:?}",result.err());
+
+    // THEN the EXIF thumbnail comes first, followed by the MPO large thumbnail
+    assert_eq!( previews.iter().map(|preview| preview.source).collect::<Vec<_>>(), vec![PreviewSource::ExifThumbnail, PreviewSource::MpoImage] );
+    assert_eq!( result.unwrap().read, 1 );
+
+    // AND both were written to the output directory as standalone JPEG files
+    assert_eq!( fs::read( output_dir.join("DSC_0103_thumbnail.jpg") ).unwrap(), thumbnail );
+    let large = fs::read( output_dir.join("DSC_0103_preview1.jpg") ).unwrap();
+    let offset = previews[1].byte_offset as usize;
+    assert_eq!( &large[..], &file[offset..offset + previews[1].byte_length as usize] );
+}
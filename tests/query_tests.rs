@@ -0,0 +1,96 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use serial_test::serial;
+
+use rusimeta::{Catalog, CompareOp, Filter, FilterValue, ReadOptions};
+use common::{build_jpeg, canon_exif, scratch_dir, TiffValue};
+
+fn write_frame( path: &Path, serial: &'static str, capture_time: &'static str, orientation: u16 ) {
+    let mut exif = canon_exif();
+    exif.ifd0[2] = (0x0112, TiffValue::Short(vec![orientation]));
+    exif.exif[0] = (0x9003, TiffValue::Ascii(capture_time));
+    exif.exif[2] = (0xA431, TiffValue::Ascii(serial));
+    fs::write( path, build_jpeg( Some(&exif.build()), &[] ) ).unwrap();
+}
+
+#[test]
+#[serial]
+fn filters_are_parsed_with_helpful_errors_and_match_images_and_sidecars()
+{
+    // GIVEN portrait and landscape frames from two cameras, and a sidecar edited to another camera model
+    let dir = scratch_dir("query_filters");
+    write_frame( &dir.join("IMG_0001.jpg"), "025021000535", "2020:01:30 09:28:07", 6 );
+    write_frame( &dir.join("IMG_0002.jpg"), "025021000535", "2020:01:30 09:45:00", 8 );
+    write_frame( &dir.join("IMG_0003.jpg"), "025021000535", "2020:01:30 09:46:00", 1 );
+    write_frame( &dir.join("IMG_0004.jpg"), "025021000537", "2020:01:30 10:00:00", 6 );
+    let mut edited = rusimeta::read_metadata_of_interest( &dir.join("IMG_0003.jpg") ).unwrap();
+    edited.image_metadata.camera_model = Some("Canon EOS R5".to_string());
+    rusimeta::write_json_metadata( &edited, &dir.join("IMG_0003.json") ).unwrap();
+
+    // WHEN the portrait images from one serial shot after 09:30 are queried
+    let filter : Filter = "camera_serial == \"025021000535\" && capture_time > 2020-01-30T09:30 && orientation in [6,8]".parse().unwrap();
+    let (report, failures) = rusimeta::query_files( std::slice::from_ref( &dir ), &filter );
+
+    // THEN only the matching image is listed, and the filter parses into its comparisons
+    assert!( failures.is_empty() );
+    assert_eq!( report.files_scanned, 5 );
+    assert_eq!( report.matches, vec![dir.join("IMG_0002.jpg")] );
+    let time = chrono::NaiveDate::from_ymd_opt( 2020, 1, 30 ).unwrap().and_hms_opt( 9, 30, 0 ).unwrap();
+    assert_eq!( filter, Filter::And(
+        Box::new( Filter::And(
+            Box::new( Filter::Compare { field: "camera_serial".to_string(), op: CompareOp::Equal, value: FilterValue::Text("025021000535".to_string()) } ),
+            Box::new( Filter::Compare { field: "capture_time".to_string(), op: CompareOp::Greater, value: FilterValue::Time(time) } ),
+        ) ),
+        Box::new( Filter::In { field: "orientation".to_string(), values: vec![FilterValue::Number(6.0), FilterValue::Number(8.0)] } ),
+    ) );
+
+    // AND sidecars are matched with the metadata they hold, and missing fields equal null
+    let query = |expression: &str| rusimeta::query_files( std::slice::from_ref( &dir ), &expression.parse().unwrap() ).0.matches;
+    assert_eq!( query( "camera_model == \"Canon EOS R5\"" ), vec![dir.join("IMG_0003.json")] );
+    assert_eq!( query( "!(camera_serial != \"025021000537\") || location != null" ), vec![dir.join("IMG_0004.jpg")] );
+    assert_eq!( query( "capture_time >= 2020-01-30 && capture_time < 2020-01-30T09:30 && vendor == null" ).len(), 1 );
+
+    // AND parse errors say where and what went wrong
+    let error = |expression: &str| expression.parse::<Filter>().unwrap_err();
+    assert_eq!( error( "serial == \"1\"" ).position(), 0 );
+    assert!( error( "serial == \"1\"" ).to_string().contains( "unknown field \"serial\"" ) );
+    assert_eq!( error( "orientation == && size > 1" ).position(), 15 );
+    assert!( error( "orientation == && size > 1" ).to_string().contains( "expected a value but found \"&&\"" ) );
+    assert_eq!( error( "camera_model == \"EOS" ).position(), 16 );
+    assert_eq!( error( "orientation in [6, 8" ).position(), 20 );
+    assert!( error( "capture_time > 2020-13-45" ).to_string().contains( "neither a number nor a time" ) );
+}
+
+#[test]
+#[serial]
+fn a_catalog_is_queried_from_the_command_line()
+{
+    // GIVEN a catalog of two frames
+    let dir = scratch_dir("query_cli");
+    let folder = dir.join("shoot");
+    fs::create_dir_all( &folder ).unwrap();
+    write_frame( &folder.join("IMG_0001.jpg"), "025021000535", "2020:01:30 09:28:07", 6 );
+    write_frame( &folder.join("IMG_0002.jpg"), "025021000537", "2020:01:30 09:45:00", 1 );
+    let catalog_path = dir.join("catalog.json");
+    let mut catalog = Catalog::default();
+    catalog.refresh( std::slice::from_ref( &folder ), &ReadOptions::default() );
+    catalog.save( &catalog_path ).unwrap();
+    assert_eq!( catalog.find(|_, metadata| "orientation == 6".parse::<Filter>().unwrap().matches( metadata )).len(), 1 );
+
+    // WHEN it is queried from the command line, without any other path
+    let strings : Vec<String> = ["rusimeta", "query", "--catalog", catalog_path.to_str().unwrap(), "--where", "size > 0 && camera_model == \"Canon EOS 5D Mark IV\""]
+        .iter().map(|arg| arg.to_string()).collect();
+    let summary = rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap();
+
+    // THEN every entry was considered, and nothing was written
+    assert_eq!( (summary.read, summary.failed), (2, 0) );
+    assert!( !folder.join("IMG_0001.json").exists() );
+
+    // AND a filter is required, and must parse
+    let config = |args: &[&str]| rusimeta::Config::new( ["rusimeta", "query"].iter().chain( args.iter() ).map(|arg| arg.to_string()) );
+    assert!( config( &["a.jpg"] ).is_err() );
+    assert!( config( &["--where", "orientation ==", "a.jpg"] ).is_err() );
+    assert!( config( &["--where", "orientation == 6"] ).is_err() );
+}
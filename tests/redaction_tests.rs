@@ -0,0 +1,147 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{Command, ReadOptions, Redaction, WriteOptions};
+use common::{build_jpeg, build_mp4, build_with_maker_note, canon_exif, scratch_dir, Mp4Description, TiffValue};
+
+fn read_json( path: &std::path::Path ) -> serde_json::Value {
+    serde_json::from_slice( &fs::read( path ).unwrap() ).unwrap()
+}
+
+#[test]
+#[serial]
+fn serials_become_stable_keyed_hashes_in_every_frame_and_redacted_sidecars_are_not_imported()
+{
+    // GIVEN a JPEG whose serial is the message of the RFC 4231 HMAC-SHA-256 test case 2
+    let dir = scratch_dir("redaction_serial");
+    let path = dir.join("IMG_0801.jpg");
+    let mut exif = canon_exif();
+    exif.exif[2] = (0xA431, TiffValue::Ascii("what do ya want for nothing?"));
+    fs::write( &path, build_jpeg( Some(&exif.build()), &[] ) ).unwrap();
+    let redaction = Redaction {
+        serial_key: Some(b"Jefe".to_vec()),
+        removed_fields: vec!["mime_type".to_string(), "frames.width".to_string()],
+        ..Default::default()
+    };
+    let read = || rusimeta::run( rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] )
+        .with_read_options( ReadOptions { include_frames: true, ..Default::default() } )
+        .with_redaction( redaction.clone() ) ).unwrap();
+
+    // WHEN the metadata is written with the redaction, twice
+    read();
+    let first = read_json( &dir.join("IMG_0801.json") );
+    read();
+    let second = read_json( &dir.join("IMG_0801.json") );
+
+    // THEN the serial is replaced by the start of its HMAC under the secret, the same each run,
+    // in the top level fields and in every frame
+    assert_eq!( first["camera_serial"], "5bdcc146bf60754e" );
+    assert_eq!( first, second );
+    for frame in first["frames"].as_array().unwrap() {
+        assert_eq!( frame["camera_serial"], "5bdcc146bf60754e" );
+        assert!( frame.get("width").is_none() );
+    }
+
+    // AND the removed fields are left out, and the output is marked as redacted
+    assert!( first.get("mime_type").is_none() );
+    assert_eq!( first["redacted"], true );
+
+    // AND the redacted sidecar can't be imported back into the image
+    let import = rusimeta::run( rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] )
+        .with_command( Command::Import { options: WriteOptions::default() } ) ).unwrap();
+    assert_eq!( import.failed, 1 );
+}
+
+#[test]
+#[serial]
+fn gps_is_coarsened_and_nested_fields_are_removed_for_any_serializer()
+{
+    // GIVEN a video recorded with a location
+    let dir = scratch_dir("redaction_gps");
+    let path = dir.join("clip.mp4");
+    fs::write( &path, build_mp4( &Mp4Description {
+        creation_seconds: 3_663_052_087,
+        timescale: 600,
+        duration: 6_300,
+        codec: b"avc1",
+        width: 1920,
+        height: 1080,
+        make: Some("Canon"),
+        model: Some("Canon EOS 5D Mark IV"),
+        iso6709_location: Some("+51.5074-000.1278+011.000/"),
+        metadata_keys: vec![],
+    } ) ).unwrap();
+    let redaction = Redaction {
+        gps_decimals: Some(1),
+        removed_fields: vec!["video.location.altitude".to_string()],
+        ..Default::default()
+    };
+
+    // WHEN the metadata is serialized with the redaction, both as the JSON sidecar and through another serializer
+    rusimeta::run( rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] ).with_redaction( redaction.clone() ) ).unwrap();
+    let metadata = rusimeta::read_metadata_of_interest( &path ).unwrap();
+    let compact = serde_json::to_string( &rusimeta::RedactedMetadata { metadata: &metadata, redaction: &redaction } ).unwrap();
+
+    // THEN the coordinates are rounded, the altitude is gone, and both serializers agree
+    let sidecar = read_json( &dir.join("clip.json") );
+    assert_eq!( sidecar["video"]["location"], serde_json::json!({ "latitude": 51.5, "longitude": -0.1 }) );
+    assert_eq!( serde_json::from_str::<serde_json::Value>( &compact ).unwrap(), sidecar );
+}
+
+#[test]
+#[serial]
+fn every_command_writing_json_redacts_it_including_the_maker_note_serial_and_owner()
+{
+    // GIVEN two copies of a Canon JPEG whose MakerNote holds the owner name and internal serial
+    let dir = scratch_dir("redaction_everywhere");
+    let shoot = dir.join("shoot");
+    fs::create_dir_all( &shoot ).unwrap();
+    let maker_note = vec![
+        (0x0009, TiffValue::Ascii("Jane Doe")),
+        (0x0096, TiffValue::Ascii("PE1234567")),
+    ];
+    let jpeg = build_jpeg( Some(&build_with_maker_note( &canon_exif(), b"", &maker_note )), &[] );
+    fs::write( shoot.join("IMG_0001.jpg"), &jpeg ).unwrap();
+    fs::write( shoot.join("IMG_0002.jpg"), &jpeg ).unwrap();
+    let key_path = dir.join("secret.txt");
+    fs::write( &key_path, "secret" ).unwrap();
+    let run = |args: &[&str]| {
+        let strings : Vec<String> = ["rusimeta"].iter().chain( args.iter() ).map(|arg| arg.to_string())
+            .chain( vec!["--serial-key-file".to_string(), key_path.to_str().unwrap().to_string()] ).collect();
+        rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap()
+    };
+
+    // WHEN a catalog is indexed twice, a duplicate report written and the files organized, all with the serial key
+    let catalog_path = dir.join("catalog.json");
+    run( &["index", "--catalog", catalog_path.to_str().unwrap(), shoot.to_str().unwrap()] );
+    let first_index = fs::read( &catalog_path ).unwrap();
+    run( &["index", "--catalog", catalog_path.to_str().unwrap(), shoot.to_str().unwrap()] );
+    let report_path = dir.join("duplicates.json");
+    run( &["dedupe", "--report", report_path.to_str().unwrap(), shoot.to_str().unwrap()] );
+    let library = dir.join("library");
+    run( &["organize", "--to", library.to_str().unwrap(), shoot.join("IMG_0001.jpg").to_str().unwrap()] );
+    let organized_sidecar = library.join("2019").join("2019-07-26").join("Canon_EOS_5D_Mark_IV").join("IMG_0001.json");
+
+    // THEN none of them holds the serials or the owner, and all are marked as redacted
+    for path in [&catalog_path, &report_path, &organized_sidecar].iter() {
+        let text = fs::read_to_string( path ).unwrap();
+        for private in ["025021000537", "PE1234567", "Jane Doe"].iter() {
+            assert!( !text.contains( private ), "{} holds {}", path.to_string_lossy(), private );
+        }
+    }
+    let catalog_lines : Vec<serde_json::Value> = fs::read_to_string( &catalog_path ).unwrap().lines().map(|line| serde_json::from_str( line ).unwrap()).collect();
+    assert_eq!( catalog_lines[0]["redacted"], true );
+    assert_eq!( read_json( &report_path )["redacted"], true );
+    assert_eq!( read_json( &organized_sidecar )["redacted"], true );
+
+    // AND the catalog entries are pseudonymised once, as sidecars are, rather than again on each refresh
+    assert_eq!( fs::read( &catalog_path ).unwrap(), first_index );
+    let sidecar = read_json( &organized_sidecar );
+    let entry = &catalog_lines[1]["entry"];
+    assert_eq!( entry["redacted"], true );
+    assert_eq!( entry["camera_serial"], sidecar["camera_serial"] );
+    assert_eq!( entry["vendor"]["internal_serial"], sidecar["vendor"]["internal_serial"] );
+    assert!( entry["vendor"]["details"]["canon"].as_object().is_some_and(|canon| !canon.contains_key( "owner_name" )) );
+}
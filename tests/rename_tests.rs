@@ -0,0 +1,112 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use serial_test::serial;
+
+use rusimeta::{NameTemplate, WriteOptions};
+use common::{build_jpeg, canon_exif, scratch_dir, ExifBuilder, TiffValue};
+
+fn exif_taken_at( capture_time: &'static str ) -> ExifBuilder {
+    let mut exif = canon_exif();
+    exif.exif[0] = (0x9003, TiffValue::Ascii(capture_time));
+    exif
+}
+
+fn names_in( dir: &std::path::Path ) -> Vec<String> {
+    let mut names : Vec<String> = fs::read_dir( dir ).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+#[serial]
+fn files_are_renamed_by_the_archive_rule_with_their_sidecars_and_can_be_put_back()
+{
+    // GIVEN two frames taken in the same second, one later frame with JSON and XMP sidecars,
+    // and a frame without a serial
+    let dir = scratch_dir("rename_archive");
+    let burst = [dir.join("IMG_0002.jpg"), dir.join("IMG_0001.jpg")];
+    for path in burst.iter() {
+        fs::write( path, build_jpeg( Some(&exif_taken_at( "2019:07:26 13:25:33" ).build()), &[] ) ).unwrap();
+    }
+    let later = dir.join("IMG_0003.jpg");
+    fs::write( &later, build_jpeg( Some(&exif_taken_at( "2019:07:26 13:30:00" ).build()), &[] ) ).unwrap();
+    fs::write( dir.join("IMG_0003.json"), "{}" ).unwrap();
+    fs::write( dir.join("IMG_0003.jpg.xmp"), "<x:xmpmeta/>" ).unwrap();
+    let mut without_serial = canon_exif();
+    without_serial.exif.truncate( 2 );
+    let anonymous = dir.join("DSC_0001.jpg");
+    fs::write( &anonymous, build_jpeg( Some(&without_serial.build()), &[] ) ).unwrap();
+    let before = names_in( &dir );
+
+    // WHEN they are renamed with the default template
+    let paths : Vec<PathBuf> = burst.iter().cloned().chain( vec![later.clone(), anonymous.clone()] ).collect();
+    let (plans, failures) = rusimeta::plan_renames( &paths, &NameTemplate::default() );
+    let journal_path = dir.join("journal.json");
+    let journal = rusimeta::rename_files( &plans, Some(&journal_path), &WriteOptions::default() ).unwrap();
+
+    // THEN the burst is numbered in path order, the sidecars follow their image, and the frame
+    // without a serial is left alone
+    assert_eq!( failures.len(), 1 );
+    assert_eq!( failures[0].0, anonymous );
+    assert_eq!( names_in( &dir ), vec![
+        "20190726_132533_0537_1.jpg", "20190726_132533_0537_2.jpg",
+        "20190726_133000_0537_1.jpg", "20190726_133000_0537_1.jpg.xmp", "20190726_133000_0537_1.json",
+        "DSC_0001.jpg", "journal.json",
+    ] );
+    assert_eq!( journal.renames.len(), 5 );
+    assert!( journal.renames[0].from.ends_with( "IMG_0001.jpg" ) && journal.renames[0].to.ends_with( "20190726_132533_0537_1.jpg" ) );
+
+    // AND renaming again changes nothing
+    let renamed : Vec<PathBuf> = ["20190726_132533_0537_1.jpg", "20190726_132533_0537_2.jpg", "20190726_133000_0537_1.jpg"].iter().map(|name| dir.join( name )).collect();
+    assert!( rusimeta::plan_renames( &renamed, &NameTemplate::default() ).0.is_empty() );
+
+    // AND the journal puts every file back
+    let undone = rusimeta::undo_renames( &journal_path, &WriteOptions::default() ).unwrap();
+    assert_eq!( undone.len(), 5 );
+    let mut expected = before;
+    expected.push( "journal.json".to_string() );
+    expected.sort();
+    assert_eq!( names_in( &dir ), expected );
+}
+
+#[test]
+#[serial]
+fn colliding_names_get_suffixes_and_dry_runs_change_nothing()
+{
+    // GIVEN two frames from one day, and a file outside the batch which has the name they'd get
+    let dir = scratch_dir("rename_collisions");
+    let first = dir.join("IMG_0001.JPG");
+    let second = dir.join("IMG_0002.JPG");
+    fs::write( &first, build_jpeg( Some(&exif_taken_at( "2019:07:26 09:00:00" ).build()), &[] ) ).unwrap();
+    fs::write( &second, build_jpeg( Some(&exif_taken_at( "2019:07:26 08:00:00" ).build()), &[] ) ).unwrap();
+    fs::write( dir.join("20190726_Canon.jpg"), "not part of the batch" ).unwrap();
+    let journal = dir.join("undo.json");
+    let args = |extra: &[&str]| -> Vec<String> {
+        let mut args = vec!["rusimeta", "rename", "--template", "{capture_time:%Y%m%d}_{camera_model:5}.{ext:lower}", "--journal", journal.to_str().unwrap()];
+        args.extend_from_slice( extra );
+        args.push( first.to_str().unwrap() );
+        args.push( second.to_str().unwrap() );
+        args.into_iter().map(|arg| arg.to_string()).collect()
+    };
+
+    // WHEN they are renamed from the command line, first as a dry run
+    rusimeta::run( rusimeta::Config::new( args( &["--dry-run"] ).into_iter() ).unwrap() ).unwrap();
+    let after_dry_run = names_in( &dir );
+    let summary = rusimeta::run( rusimeta::Config::new( args( &[] ).into_iter() ).unwrap() ).unwrap();
+
+    // THEN the dry run changed nothing, and the real run numbers the names in capture time order
+    // without touching the other file
+    assert_eq!( after_dry_run, vec!["20190726_Canon.jpg", "IMG_0001.JPG", "IMG_0002.JPG"] );
+    assert_eq!( summary.read, 2 );
+    assert_eq!( names_in( &dir ), vec!["20190726_Canon.jpg", "20190726_Canon_2.jpg", "20190726_Canon_3.jpg", "undo.json"] );
+    assert_eq!( fs::read( dir.join("20190726_Canon.jpg") ).unwrap(), b"not part of the batch" );
+    assert_eq!( fs::read( dir.join("20190726_Canon_2.jpg") ).unwrap(), build_jpeg( Some(&exif_taken_at( "2019:07:26 08:00:00" ).build()), &[] ) );
+
+    // AND templates are checked when they are parsed
+    for invalid in ["{capture_time", "{lens}", "{ext:title}", "{seq:x}", "{filename}/{ext}"].iter() {
+        assert!( invalid.parse::<NameTemplate>().is_err(), "{}", invalid );
+    }
+    assert!( "{{literal}}_{counter:4}".parse::<NameTemplate>().is_ok() );
+}
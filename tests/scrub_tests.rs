@@ -0,0 +1,176 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{Command, FrameKind, Orientation, ReadOptions, ScrubPolicy, WriteOptions};
+use common::{build_jpeg, build_mpo, build_png, build_webp, build_with_maker_note, canon_exif, jpeg_segment, scratch_dir, ExifBuilder, TiffValue, FAKE_JPEG_IMAGE_DATA};
+
+fn contains( haystack: &[u8], needle: &[u8] ) -> bool {
+    haystack.windows( needle.len() ).any(|window| window == needle)
+}
+
+fn gps_exif() -> ExifBuilder {
+    let mut exif = canon_exif();
+    exif.exif.push( (0xA430, TiffValue::Ascii("Jane Doe")) );
+    exif.gps = vec![
+        (0x0001, TiffValue::Ascii("N")),
+        (0x0002, TiffValue::Rational(vec![(48, 1), (51, 1), (2953, 100)])),
+        (0x0003, TiffValue::Ascii("E")),
+        (0x0004, TiffValue::Rational(vec![(2, 1), (17, 1), (4012, 100)])),
+    ];
+    exif
+}
+
+#[test]
+#[serial]
+fn gps_and_serials_are_zeroed_out_of_a_jpeg_keeping_everything_else()
+{
+    // GIVEN a JPEG with a location, a body serial, an owner name, a Canon MakerNote, an XMP packet and a thumbnail
+    let dir = scratch_dir("scrub_jpeg");
+    let path = dir.join("IMG_0701.jpg");
+    let mut exif = gps_exif();
+    exif.thumbnail = Some( build_jpeg( None, &[] ) );
+    let maker_note = vec![
+        (0x0009, TiffValue::Ascii("Jane Doe")),
+        (0x0096, TiffValue::Ascii("PE1234567")),
+    ];
+    let xmp = jpeg_segment( 0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta><aux:SerialNumber>025021000537</aux:SerialNumber></x:xmpmeta>" );
+    fs::write( &path, build_jpeg( Some(&build_with_maker_note( &exif, b"", &maker_note )), &[xmp] ) ).unwrap();
+
+    // WHEN GPS and serials are scrubbed
+    let removed = rusimeta::scrub_metadata( &path, ScrubPolicy::RemoveGpsAndSerials, &WriteOptions::default() ).unwrap();
+
+    // THEN each of them is reported, and none of their bytes are left anywhere in the file
+    for name in ["gps", "camera_owner", "camera_serial", "maker_note", "xmp"].iter() {
+        assert!( removed.contains( &name.to_string() ), "{} not in {:?}", name, removed );
+    }
+    let written = fs::read( &path ).unwrap();
+    for secret in [&b"025021000537"[..], b"Jane Doe", b"PE1234567"].iter() {
+        assert!( !contains( &written, secret ) );
+    }
+
+    // AND the other fields, the thumbnail and the image data are kept
+    let metadata = rusimeta::read_metadata_of_interest( &path ).unwrap();
+    assert_eq!( metadata.image_metadata.camera_serial, None );
+    assert_eq!( metadata.image_metadata.camera_model, Some("Canon EOS 5D Mark IV".to_string()) );
+    assert!( metadata.image_metadata.capture_time.is_some() );
+    assert!( metadata.thumbnail.is_some() );
+    assert_eq!( metadata.vendor_metadata, None );
+    assert!( written.ends_with( FAKE_JPEG_IMAGE_DATA ) );
+}
+
+#[test]
+#[serial]
+fn every_image_of_a_multi_picture_file_is_scrubbed_and_still_indexed()
+{
+    // GIVEN an MPO whose second image has a location, a body serial and an owner name of its own
+    let dir = scratch_dir("scrub_mpo");
+    let path = dir.join("DSCF0001.jpg");
+    let mut second_exif = gps_exif();
+    second_exif.exif[2] = (0xA431, TiffValue::Ascii("025021000535"));
+    second_exif.exif.retain(|(tag, _)| *tag != 0xA430);
+    second_exif.exif.push( (0xA430, TiffValue::Ascii("John Roe")) );
+    fs::write( &path, build_mpo( &gps_exif().build(), &second_exif.build(), 0x0002_0002 ) ).unwrap();
+
+    // WHEN GPS and serials are scrubbed
+    let removed = rusimeta::scrub_metadata( &path, ScrubPolicy::RemoveGpsAndSerials, &WriteOptions::default() ).unwrap();
+
+    // THEN none of the bytes of either image's location, serial or owner are left in the file
+    assert!( removed.contains( &"gps".to_string() ) && removed.contains( &"camera_serial".to_string() ) );
+    let written = fs::read( &path ).unwrap();
+    for secret in [&b"025021000537"[..], b"025021000535", b"Jane Doe", b"John Roe"].iter() {
+        assert!( !contains( &written, secret ) );
+    }
+
+    // AND the index still points at the second image, which keeps its other fields
+    let frames = rusimeta::read_metadata_of_interest_with_options( &path, &ReadOptions { include_frames: true, ..Default::default() } )
+        .unwrap().frames.unwrap();
+    assert_eq!( frames.len(), 2 );
+    let second = &frames[1];
+    assert_eq!( second.kind, FrameKind::MpoImage );
+    assert_eq!( second.image_metadata.camera_serial, None );
+    assert_eq!( second.image_metadata.location, None );
+    assert_eq!( second.image_metadata.camera_model, Some("Canon EOS 5D Mark IV".to_string()) );
+    let offset = second.byte_offset.unwrap() as usize;
+    assert_eq!( &written[offset..offset + 2], b"\xFF\xD8" );
+    assert_eq!( offset + second.byte_length.unwrap() as usize, written.len() );
+    assert!( written.ends_with( FAKE_JPEG_IMAGE_DATA ) );
+}
+
+#[test]
+#[serial]
+fn scrub_command_handles_png_webp_and_tiff_with_each_policy()
+{
+    // GIVEN a PNG with EXIF and a text chunk, a WebP with EXIF and XMP, and a TIFF with camera fields
+    let dir = scratch_dir("scrub_command");
+    let mut exif = gps_exif();
+    exif.ifd0.push( (0x8298, TiffValue::Ascii("Copyright 2019 Jane Doe")) );
+    exif.ifd0[2] = (0x0112, TiffValue::Short(vec![6]));
+    let png_path = dir.join("export.png");
+    let png = build_png( &[
+        (b"IHDR", vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]),
+        (b"eXIf", exif.build()),
+        (b"tEXt", b"Author\0Jane Doe".to_vec()),
+        (b"IDAT", vec![0x78, 0x9C, 0x63, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00, 0x04, 0x00, 0x01]),
+    ] );
+    fs::write( &png_path, &png ).unwrap();
+    let webp_path = dir.join("export.webp");
+    fs::write( &webp_path, build_webp( &[
+        (b"VP8X", vec![0x0C, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        (b"VP8 ", vec![0x10, 0x02, 0x00]),
+        (b"EXIF", exif.build()),
+        (b"XMP ", b"<x:xmpmeta/>".to_vec()),
+    ] ) ).unwrap();
+    let tiff_path = dir.join("scan.tif");
+    let tiff = ExifBuilder {
+        ifd0: vec![
+            (0x0100, TiffValue::Long(vec![1])),
+            (0x0101, TiffValue::Long(vec![1])),
+            (0x0110, TiffValue::Ascii("Scanner 1")),
+            (0x0112, TiffValue::Short(vec![3])),
+            (0x0111, TiffValue::Long(vec![8])),
+            (0x0117, TiffValue::Long(vec![1])),
+        ],
+        exif: vec![(0x9003, TiffValue::Ascii("2019:07:26 13:25:33"))],
+        ..Default::default()
+    }.build();
+    fs::write( &tiff_path, &tiff ).unwrap();
+    let scrub = |path: &std::path::PathBuf, policy: ScrubPolicy, dry_run: bool| rusimeta::run(
+        rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] )
+            .with_command( Command::Scrub { policy, options: WriteOptions { dry_run } } ) ).unwrap();
+
+    // WHEN the PNG is scrubbed as a dry run and then for real, keeping orientation and copyright,
+    // the WebP likewise, and everything is removed from the TIFF
+    let dry_run = scrub( &png_path, ScrubPolicy::KeepOrientationAndCopyright, true );
+    let dry_run_png = fs::read( &png_path ).unwrap();
+    let summaries = [
+        scrub( &png_path, ScrubPolicy::KeepOrientationAndCopyright, false ),
+        scrub( &webp_path, ScrubPolicy::KeepOrientationAndCopyright, false ),
+        scrub( &tiff_path, ScrubPolicy::RemoveAll, false ),
+    ];
+
+    // THEN the dry run changed nothing, and every file was scrubbed
+    assert_eq!( dry_run.read, 1 );
+    assert_eq!( dry_run_png, png );
+    assert!( summaries.iter().all(|summary| summary.read == 1) );
+
+    // AND the PNG and WebP keep only their orientation and copyright, without the text or XMP
+    for path in [&png_path, &webp_path].iter() {
+        let metadata = rusimeta::read_metadata_of_interest( path ).unwrap();
+        assert_eq!( metadata.image_metadata.orientation, Some(Orientation::QuarterRotationCCW) );
+        assert_eq!( metadata.image_metadata.capture_time, None );
+        assert_eq!( metadata.image_metadata.camera_model, None );
+        let written = fs::read( path ).unwrap();
+        assert!( contains( &written, b"Copyright 2019 Jane Doe" ) );
+        assert!( !contains( &written, b"Author" ) && !contains( &written, b"xmpmeta" ) );
+    }
+    assert_eq!( fs::read( &webp_path ).unwrap()[20], 0x08 );
+
+    // AND the TIFF keeps its layout tags in place, with every other field gone
+    let metadata = rusimeta::read_metadata_of_interest( &tiff_path ).unwrap();
+    assert_eq!( metadata.image_metadata, rusimeta::ImageMetadataOfInterest::default() );
+    let written = fs::read( &tiff_path ).unwrap();
+    assert_eq!( written.len(), tiff.len() );
+    assert!( !contains( &written, b"Scanner 1" ) && !contains( &written, b"2019:07:26" ) );
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{Command, FieldChange, NotApplicableField, Orientation, WriteOptions};
+use common::{build_jpeg, canon_exif, scratch_dir};
+
+#[test]
+#[serial]
+fn edited_sidecar_is_written_back_and_read_only_edits_are_reported()
+{
+    // GIVEN a JPEG whose JSON sidecar was written by a read, then hand-edited
+    let dir = scratch_dir("sidecar_import");
+    let path = dir.join("IMG_0501.jpg");
+    fs::write( &path, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    let result = rusimeta::run( rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] ) );
+    assert!(result.is_ok(),"{:?}",result.err());
+    let json_path = dir.join("IMG_0501.json");
+    let mut edited = rusimeta::read_json_metadata( json_path.to_str().unwrap() ).unwrap();
+    edited.image_metadata.capture_time = Some( chrono::NaiveDateTime::parse_from_str( "2019:07:26 14:25:33", "%Y:%m:%d %H:%M:%S" ).unwrap() );
+    edited.image_metadata.orientation = Some(Orientation::QuarterRotationCCW);
+    edited.file_metadata.size = 1;
+    rusimeta::write_json_metadata( &edited, &json_path ).unwrap();
+    let original = fs::read( &path ).unwrap();
+
+    // WHEN the sidecar is imported as a dry run, and then for real
+    let dry_run = rusimeta::import_json_sidecar( &path, &WriteOptions { dry_run: true } ).unwrap();
+    let unchanged = fs::read( &path ).unwrap();
+    let summary = rusimeta::run( rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] )
+        .with_command( Command::Import { options: WriteOptions::default() } ) ).unwrap();
+
+    // THEN the report lists the writable changes and the read-only field, and the dry run wrote nothing
+    assert_eq!( dry_run.sidecar_path, json_path );
+    assert_eq!( dry_run.changes, vec![
+        FieldChange { field: "orientation", old_value: Some("1".to_string()), new_value: "6".to_string() },
+        FieldChange { field: "capture_time", old_value: Some("2019:07:26 13:25:33".to_string()), new_value: "2019:07:26 14:25:33".to_string() },
+    ] );
+    assert_eq!( dry_run.not_applicable, vec![NotApplicableField { field: "size", reason: "read-only" }] );
+    assert_eq!( unchanged, original );
+
+    // AND the import wrote the edited fields into the image
+    assert_eq!( summary.read, 1 );
+    let metadata = rusimeta::read_metadata_of_interest( &path ).unwrap();
+    assert_eq!( metadata.image_metadata.orientation, edited.image_metadata.orientation );
+    assert_eq!( metadata.image_metadata.capture_time, edited.image_metadata.capture_time );
+    assert_eq!( metadata.image_metadata.camera_serial, Some("025021000537".to_string()) );
+}
+
+#[test]
+#[serial]
+fn import_without_a_sidecar_fails()
+{
+    // GIVEN an image which was never read, so it has no sidecar
+    let dir = scratch_dir("sidecar_missing");
+    let path = dir.join("IMG_0502.jpg");
+    fs::write( &path, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+
+    // WHEN its sidecar is imported through the import command
+    let summary = rusimeta::run( rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] )
+        .with_command( Command::Import { options: WriteOptions::default() } ) ).unwrap();
+
+    // THEN the file is counted as failed
+    assert_eq!( summary.failed, 1 );
+    assert!( rusimeta::import_json_sidecar( &path, &WriteOptions::default() ).is_err() );
+}
+
+#[test]
+#[serial]
+fn a_sidecar_listing_frames_imports_without_reporting_them_as_edited()
+{
+    // GIVEN an image read with its frames listed, whose sidecar has an edited camera model
+    let dir = scratch_dir("sidecar_frames");
+    let path = dir.join("IMG_0503.jpg");
+    fs::write( &path, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    let strings : Vec<String> = ["rusimeta", "--frames", path.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+    rusimeta::run( rusimeta::Config::new( strings.into_iter() ).unwrap() ).unwrap();
+    let json_path = dir.join("IMG_0503.json");
+    let mut edited = rusimeta::read_json_metadata( json_path.to_str().unwrap() ).unwrap();
+    assert!( edited.frames.is_some() );
+    edited.image_metadata.camera_model = Some("Canon EOS R5".to_string());
+    rusimeta::write_json_metadata( &edited, &json_path ).unwrap();
+
+    // WHEN it is imported
+    let report = rusimeta::import_json_sidecar( &path, &WriteOptions::default() ).unwrap();
+
+    // THEN only the camera model is written, and the unchanged frames aren't reported as edited
+    assert_eq!( report.changes.len(), 1 );
+    assert!( report.not_applicable.is_empty(), "{:?}", report.not_applicable );
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{Command, FieldChange, Redaction, TimeShift, WriteOptions};
+use common::{build_jpeg, canon_exif, scratch_dir, TiffValue};
+
+fn capture_time( text: &str ) -> chrono::NaiveDateTime {
+    chrono::NaiveDateTime::parse_from_str( text, "%Y:%m:%d %H:%M:%S" ).unwrap()
+}
+
+#[test]
+#[serial]
+fn only_files_from_the_filtered_camera_are_shifted_and_sidecars_follow()
+{
+    // GIVEN two JPEGs with JSON sidecars, one from the camera whose clock was an hour ahead
+    let dir = scratch_dir("timeshift_filter");
+    let wrong_clock = dir.join("IMG_0601.jpg");
+    fs::write( &wrong_clock, build_jpeg( Some(&canon_exif().build()), &[] ) ).unwrap();
+    let other_camera = dir.join("IMG_0602.jpg");
+    let mut other_exif = canon_exif();
+    other_exif.exif[2] = (0xA431, TiffValue::Ascii("083021001234"));
+    let other_original = build_jpeg( Some(&other_exif.build()), &[] );
+    fs::write( &other_camera, &other_original ).unwrap();
+    let paths = vec![wrong_clock.to_str().unwrap().to_string(), other_camera.to_str().unwrap().to_string()];
+    rusimeta::run( rusimeta::Config::from_strings( paths.clone() ) ).unwrap();
+
+    // WHEN the times are shifted back an hour for that camera, setting the time zone
+    let mut shift = TimeShift::new( chrono::Duration::hours( -1 ) );
+    shift.camera_serial = Some("025021000537".to_string());
+    shift.offset_time = Some("+02:00".to_string());
+    let summary = rusimeta::run( rusimeta::Config::from_strings( paths )
+        .with_command( Command::Shift { shift: shift.clone(), options: WriteOptions::default() } ) ).unwrap();
+
+    // THEN the three date/time fields of the matching file moved, and its sidecar was rewritten
+    assert_eq!( summary.read, 2 );
+    let metadata = rusimeta::read_metadata_of_interest( &wrong_clock ).unwrap();
+    assert_eq!( metadata.image_metadata.capture_time, Some(capture_time("2019:07:26 12:25:33")) );
+    let sidecar = rusimeta::read_json_metadata( dir.join("IMG_0601.json").to_str().unwrap() ).unwrap();
+    assert_eq!( sidecar.image_metadata.capture_time, Some(capture_time("2019:07:26 12:25:33")) );
+    let exif_fields = exif::Reader::new().read_from_container( &mut std::io::BufReader::new( fs::File::open( &wrong_clock ).unwrap() ) ).unwrap();
+    for (tag, expected) in [(exif::Tag::DateTimeDigitized, "2019-07-26 12:25:33"), (exif::Tag::DateTime, "2019-07-26 12:25:33"), (exif::Tag::OffsetTimeOriginal, "\"+02:00\"")].iter() {
+        let field = exif_fields.get_field( *tag, exif::In::PRIMARY ).unwrap();
+        assert_eq!( field.display_value().to_string(), *expected );
+    }
+
+    // AND the file from the other camera is untouched
+    assert_eq!( fs::read( &other_camera ).unwrap(), other_original );
+
+    // AND shifting the already shifted file again, as a dry run, reports old -> new without writing
+    let changes = rusimeta::shift_capture_times( &wrong_clock, &shift, &WriteOptions { dry_run: true }, &Redaction::default() ).unwrap().unwrap();
+    assert_eq!( changes[0], FieldChange { field: "capture_time", old_value: Some("2019:07:26 12:25:33".to_string()), new_value: "2019:07:26 11:25:33".to_string() } );
+    assert_eq!( rusimeta::read_metadata_of_interest( &wrong_clock ).unwrap().image_metadata.capture_time, Some(capture_time("2019:07:26 12:25:33")) );
+}
+
+#[test]
+#[serial]
+fn shift_command_line_is_parsed_and_rejects_bad_offsets()
+{
+    // GIVEN shift command lines, written the way the CLI receives them
+    let parse = |args: &[&str]| {
+        let strings : Vec<String> = std::iter::once("rusimeta").chain( args.iter().cloned() ).map(String::from).collect();
+        rusimeta::Config::new( strings.into_iter() )
+    };
+
+    // WHEN they are parsed
+    let config = parse( &["shift", "--by", "+0:02:30", "--camera-model", "Canon EOS 5D Mark IV", "--dry-run", "a.jpg"] ).unwrap();
+
+    // THEN the offset, the filter and the options are read
+    let mut expected = TimeShift::new( chrono::Duration::seconds( 150 ) );
+    expected.camera_model = Some("Canon EOS 5D Mark IV".to_string());
+    assert_eq!( config.command(), &Command::Shift { shift: expected, options: WriteOptions { dry_run: true } } );
+
+    // AND offsets without a sign or with too many minutes, bad time zones and missing offsets are rejected
+    assert!( parse( &["shift", "--by", "1:00", "a.jpg"] ).is_err() );
+    assert!( parse( &["shift", "--by", "-1:75", "a.jpg"] ).is_err() );
+    assert!( parse( &["shift", "--by", "-1:00", "--offset-time", "+2:00", "a.jpg"] ).is_err() );
+    assert!( parse( &["shift", "a.jpg"] ).is_err() );
+}
+
+#[test]
+#[serial]
+fn a_redacted_sidecar_is_not_overwritten_with_the_real_serial()
+{
+    // GIVEN a JPEG whose sidecar was written with its serial pseudonymised
+    let dir = scratch_dir("timeshift_redacted");
+    let path = dir.join("IMG_0603.jpg");
+    let original = build_jpeg( Some(&canon_exif().build()), &[] );
+    fs::write( &path, &original ).unwrap();
+    let redaction = Redaction { serial_key: Some(b"secret".to_vec()), ..Default::default() };
+    rusimeta::run( rusimeta::Config::from_strings( vec![path.to_str().unwrap().to_string()] ).with_redaction( redaction ) ).unwrap();
+    let sidecar_path = dir.join("IMG_0603.json");
+    let redacted = fs::read( &sidecar_path ).unwrap();
+
+    // WHEN the file is shifted
+    let result = rusimeta::shift_capture_times( &path, &TimeShift::new( chrono::Duration::hours( -1 ) ), &WriteOptions::default(), &Redaction::default() );
+
+    // THEN the shift is refused, leaving both the file and the redacted sidecar as they were
+    assert!( result.unwrap_err().to_string().contains( "redacted" ) );
+    assert_eq!( fs::read( &path ).unwrap(), original );
+    assert_eq!( fs::read( &sidecar_path ).unwrap(), redacted );
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use serial_test::serial;
+
+use common::{build_mp4, scratch_dir, Mp4Description};
+
+const CAPTURE_TIME_EXPECTATION_FORMAT : &str = "%Y:%m:%d %H:%M:%S";
+
+fn seconds_since_quicktime_epoch( str: &str ) -> u32 {
+    let epoch = chrono::NaiveDate::from_ymd_opt(1904, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    let date_time = chrono::NaiveDateTime::parse_from_str(str, CAPTURE_TIME_EXPECTATION_FORMAT).unwrap();
+    (date_time - epoch).num_seconds() as u32
+}
+
+fn write_mp4( dir: &Path, name: &str, description: &Mp4Description ) -> String {
+    let path = dir.join( name );
+    fs::write( &path, build_mp4( description ) ).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+#[serial]
+fn video_creation_time_duration_and_location_are_read_from_udta()
+{
+    // GIVEN an MP4 file with a movie header, a video track and QuickTime user data
+    let dir = scratch_dir("video_udta");
+    let path = write_mp4( &dir, "clip.mp4", &Mp4Description {
+        creation_seconds: seconds_since_quicktime_epoch("2020:01:30 09:28:07"),
+        timescale: 600,
+        duration: 6_300,
+        codec: b"avc1",
+        width: 1920,
+        height: 1080,
+        make: Some("Canon"),
+        model: Some("Canon EOS 5D Mark IV"),
+        iso6709_location: Some("+51.5074-000.1278+011.000/"),
+        metadata_keys: vec![],
+    } );
+
+    // WHEN the metadata is requested
+    let result = rusimeta::run( rusimeta::Config::from_strings( vec![path] ) );
+    assert!(result.is_ok(),"{:?}",result.err());
+
+    // THEN the JSON output holds the shared capture fields, with the UTC creation time in local
+    // time, and a video section
+    let metadata = rusimeta::read_json_metadata( dir.join("clip.json").to_str().unwrap() ).unwrap();
+    assert_eq!( metadata.file_metadata.filename, "clip.mp4" );
+    let creation_time = chrono::NaiveDateTime::parse_from_str("2020:01:30 09:28:07", CAPTURE_TIME_EXPECTATION_FORMAT).unwrap().and_utc();
+    assert_eq!(
+        metadata.image_metadata.capture_time,
+        Some(creation_time.with_timezone( &chrono::Local ).naive_local())
+    );
+    assert_eq!( metadata.image_metadata.camera_model, Some("Canon EOS 5D Mark IV".to_string()) );
+    assert_eq!( metadata.image_metadata.orientation, None );
+
+    let video = metadata.video_metadata.expect("video section should be present");
+    assert_eq!( video.duration_seconds, Some(10.5) );
+    assert_eq!( video.width, Some(1920) );
+    assert_eq!( video.height, Some(1080) );
+    assert_eq!( video.codec, Some("avc1".to_string()) );
+    assert_eq!( video.camera_make, Some("Canon".to_string()) );
+    assert_eq!(
+        video.location,
+        Some(rusimeta::GpsLocation { latitude: 51.5074, longitude: -0.1278, altitude: Some(11.0) })
+    );
+
+    // AND the position is also the location of the capture, as for images
+    assert_eq!( metadata.image_metadata.location, video.location );
+}
+
+#[test]
+#[serial]
+fn video_metadata_keys_take_precedence_over_udta()
+{
+    // GIVEN an MP4 file with both user data and 'meta' keys, where the keys disagree
+    let dir = scratch_dir("video_keys");
+    let path = write_mp4( &dir, "phone.mov", &Mp4Description {
+        creation_seconds: seconds_since_quicktime_epoch("2020:01:30 08:28:07"),
+        timescale: 1000,
+        duration: 2_000,
+        codec: b"hvc1",
+        width: 3840,
+        height: 2160,
+        make: Some("Unknown"),
+        model: None,
+        iso6709_location: None,
+        metadata_keys: vec![
+            ("com.apple.quicktime.make", "Apple"),
+            ("com.apple.quicktime.model", "iPhone 11"),
+            ("com.apple.quicktime.creationdate", "2020-01-30T09:28:07+0100"),
+            ("com.apple.quicktime.location.ISO6709", "+3746.4940-12225.1640/"),
+        ],
+    } );
+
+    // WHEN the metadata is requested
+    let result = rusimeta::run( rusimeta::Config::from_strings( vec![path] ) );
+    assert!(result.is_ok(),"{:?}",result.err());
+
+    // THEN the values from the keys are used, with the local creation time and DDMM.M coordinates decoded
+    let metadata = rusimeta::read_json_metadata( dir.join("phone.json").to_str().unwrap() ).unwrap();
+    assert_eq!(
+        metadata.image_metadata.capture_time,
+        Some(chrono::NaiveDateTime::parse_from_str("2020:01:30 09:28:07", CAPTURE_TIME_EXPECTATION_FORMAT).unwrap())
+    );
+    assert_eq!( metadata.image_metadata.camera_model, Some("iPhone 11".to_string()) );
+
+    let video = metadata.video_metadata.expect("video section should be present");
+    assert_eq!( video.camera_make, Some("Apple".to_string()) );
+    assert_eq!( video.codec, Some("hvc1".to_string()) );
+    let location = video.location.expect("location should be present");
+    assert!( (location.latitude - 37.7749).abs() < 1e-4, "{:?}", location );
+    assert!( (location.longitude + 122.4194).abs() < 1e-4, "{:?}", location );
+    assert_eq!( location.altitude, None );
+}
@@ -0,0 +1,180 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use serial_test::serial;
+
+use rusimeta::{Catalog, ReadOptions, Redaction, Watch, WatchEvent};
+use common::{build_jpeg, canon_exif, scratch_dir, TiffValue};
+
+// Polls until the condition holds, failing the test if it doesn't within a few seconds.
+fn wait_until<F: Fn() -> bool>( description: &str, condition: F ) {
+    let deadline = Instant::now() + Duration::from_secs( 10 );
+    while !condition() {
+        assert!( Instant::now() < deadline, "timed out waiting until {}", description );
+        thread::sleep( Duration::from_millis( 20 ) );
+    }
+}
+
+fn watch_in_thread( folder: &Path, watch: Watch, stop: Arc<AtomicBool> ) -> (thread::JoinHandle<Result<(), String>>, mpsc::Receiver<String>) {
+    let (sender, receiver) = mpsc::channel();
+    let directories = vec![folder.to_path_buf()];
+    let handle = thread::spawn( move || {
+        rusimeta::watch_folders( &directories, &watch, &ReadOptions::default(), &Redaction::default(), &stop, &mut |event| {
+            let description = match event {
+                WatchEvent::Updated(path) => format!("updated {}", path.file_name().unwrap().to_string_lossy()),
+                WatchEvent::Skipped { path, .. } => format!("skipped {}", path.file_name().unwrap().to_string_lossy()),
+                WatchEvent::Removed { path, .. } => format!("removed {}", path.file_name().unwrap().to_string_lossy()),
+                WatchEvent::Failed(path, _) => format!("failed {}", path.file_name().unwrap().to_string_lossy()),
+            };
+            sender.send( description ).unwrap();
+        } ).map_err(|boxed_err| boxed_err.to_string())
+    } );
+    (handle, receiver)
+}
+
+#[test]
+#[serial]
+fn sidecars_follow_files_once_they_settle_and_go_with_them()
+{
+    // GIVEN a hot folder with a JPEG without a sidecar, and a JPEG whose RAW is beside it
+    let dir = scratch_dir("watch_sidecars");
+    let folder = dir.join("tethered");
+    fs::create_dir_all( &folder ).unwrap();
+    let jpeg = build_jpeg( Some(&canon_exif().build()), &[] );
+    fs::write( folder.join("IMG_0001.jpg"), &jpeg ).unwrap();
+    fs::write( folder.join("IMG_0002.jpg"), &jpeg ).unwrap();
+    fs::write( folder.join("IMG_0002.cr2"), b"not really a raw" ).unwrap();
+
+    // WHEN it is watched
+    let stop = Arc::new( AtomicBool::new( false ) );
+    let watch = Watch { settle: Duration::from_millis( 500 ), catalog: None };
+    let (handle, events) = watch_in_thread( &folder, watch, stop.clone() );
+
+    // THEN the sidecars missing at the start are written
+    wait_until( "the existing files have sidecars", || folder.join("IMG_0001.json").exists() && folder.join("IMG_0002.json").exists() );
+
+    // AND a file being written gets its sidecar only once it has stopped changing
+    let new_path = folder.join("IMG_0003.jpg");
+    let mut file = fs::File::create( &new_path ).unwrap();
+    file.write_all( &jpeg[..jpeg.len() / 2] ).unwrap();
+    thread::sleep( Duration::from_millis( 250 ) );
+    assert!( !folder.join("IMG_0003.json").exists() );
+    file.write_all( &jpeg[jpeg.len() / 2..] ).unwrap();
+    drop( file );
+    wait_until( "the new file has a sidecar", || folder.join("IMG_0003.json").exists() );
+    let metadata = rusimeta::read_json_metadata( folder.join("IMG_0003.json").to_str().unwrap() ).unwrap();
+    assert_eq!( metadata.image_metadata.camera_serial.as_deref(), Some("025021000537") );
+
+    // AND deleting a file removes its sidecar, unless its RAW still uses it
+    fs::remove_file( folder.join("IMG_0001.jpg") ).unwrap();
+    fs::remove_file( folder.join("IMG_0002.jpg") ).unwrap();
+    wait_until( "the orphaned sidecar is removed", || !folder.join("IMG_0001.json").exists() );
+    stop.store( true, Ordering::SeqCst );
+    assert_eq!( handle.join().unwrap(), Ok(()) );
+    assert!( folder.join("IMG_0002.json").exists() );
+    let events : Vec<String> = events.try_iter().collect();
+    assert!( events.contains( &"updated IMG_0003.jpg".to_string() ) );
+    assert!( events.contains( &"removed IMG_0002.jpg".to_string() ) );
+    assert!( !events.iter().any(|event| event.starts_with( "failed IMG_000" ) && event.ends_with( ".jpg" )) );
+}
+
+#[test]
+#[serial]
+fn the_sidecar_of_a_raw_and_jpeg_pair_describes_the_raw_and_keeps_its_edits()
+{
+    // GIVEN a hot folder with the DNG and JPEG of one shot
+    let dir = scratch_dir("watch_pair");
+    let folder = dir.join("tethered");
+    fs::create_dir_all( &folder ).unwrap();
+    let jpeg = build_jpeg( Some(&canon_exif().build()), &[] );
+    let mut raw = canon_exif();
+    raw.ifd0.push( (0xC612, TiffValue::Undefined(vec![1, 4, 0, 0])) );
+    let raw = raw.build();
+    fs::write( folder.join("IMG_0005.dng"), &raw ).unwrap();
+    fs::write( folder.join("IMG_0005.jpg"), &jpeg ).unwrap();
+    let sidecar_path = folder.join("IMG_0005.json");
+    let read_sidecar = || rusimeta::read_json_metadata( sidecar_path.to_str().unwrap() ).unwrap();
+
+    // WHEN it is watched
+    let stop = Arc::new( AtomicBool::new( false ) );
+    let watch = Watch { settle: Duration::from_millis( 200 ), catalog: None };
+    let (handle, events) = watch_in_thread( &folder, watch, stop.clone() );
+    let next_event = || events.recv_timeout( Duration::from_secs( 10 ) ).unwrap();
+
+    // THEN the shared sidecar is written from the DNG, and the JPEG leaves it alone
+    assert_eq!( (next_event(), next_event()), ("updated IMG_0005.dng".to_string(), "skipped IMG_0005.jpg".to_string()) );
+    assert_eq!( read_sidecar().file_metadata.filename, "IMG_0005.dng" );
+
+    // AND once the sidecar is geotagged, rewriting the JPEG and then the DNG keeps the location
+    let mut geotagged = read_sidecar();
+    geotagged.image_metadata.location = Some( rusimeta::GpsLocation { latitude: 51.5, longitude: -0.15, altitude: None } );
+    rusimeta::write_json_metadata( &geotagged, &sidecar_path ).unwrap();
+    fs::write( folder.join("IMG_0005.jpg"), &jpeg ).unwrap();
+    assert_eq!( next_event(), "skipped IMG_0005.jpg" );
+    assert_eq!( read_sidecar(), geotagged );
+    fs::write( folder.join("IMG_0005.dng"), &raw ).unwrap();
+    assert_eq!( next_event(), "updated IMG_0005.dng" );
+    assert_eq!( read_sidecar().image_metadata.location, geotagged.image_metadata.location );
+
+    // AND when the DNG is deleted, the JPEG takes the sidecar over, keeping the location
+    fs::remove_file( folder.join("IMG_0005.dng") ).unwrap();
+    assert_eq!( (next_event(), next_event()), ("removed IMG_0005.dng".to_string(), "updated IMG_0005.jpg".to_string()) );
+    stop.store( true, Ordering::SeqCst );
+    assert_eq!( handle.join().unwrap(), Ok(()) );
+    let sidecar = read_sidecar();
+    assert_eq!( sidecar.file_metadata.filename, "IMG_0005.jpg" );
+    assert_eq!( sidecar.image_metadata.location, geotagged.image_metadata.location );
+}
+
+#[test]
+#[serial]
+fn a_catalog_is_kept_in_sync_until_interrupted()
+{
+    // GIVEN a catalog which knows of a JPEG that has since been deleted, and a folder with a new one
+    let dir = scratch_dir("watch_catalog");
+    let folder = dir.join("tethered");
+    fs::create_dir_all( folder.join("card") ).unwrap();
+    let jpeg = build_jpeg( Some(&canon_exif().build()), &[] );
+    fs::write( folder.join("IMG_0001.jpg"), &jpeg ).unwrap();
+    let catalog_path = dir.join("catalog.json");
+    let mut catalog = Catalog::default();
+    catalog.refresh( std::slice::from_ref( &folder ), &ReadOptions::default() );
+    catalog.save( &catalog_path ).unwrap();
+    fs::remove_file( folder.join("IMG_0001.jpg") ).unwrap();
+    fs::write( folder.join("IMG_0002.jpg"), &jpeg ).unwrap();
+
+    // WHEN it is watched into the catalog, a file is added to a subfolder, and the process is interrupted
+    let stop = Arc::new( AtomicBool::new( false ) );
+    let watch = Watch { settle: Duration::from_millis( 200 ), catalog: Some(catalog_path.clone()) };
+    let (handle, events) = watch_in_thread( &folder, watch, stop );
+    assert_eq!( events.recv_timeout( Duration::from_secs( 10 ) ).unwrap(), "updated IMG_0002.jpg" );
+    assert_eq!( events.recv_timeout( Duration::from_secs( 10 ) ).unwrap(), "removed IMG_0001.jpg" );
+    fs::write( folder.join("card").join("IMG_0003.jpg"), &jpeg ).unwrap();
+    assert_eq!( events.recv_timeout( Duration::from_secs( 10 ) ).unwrap(), "updated IMG_0003.jpg" );
+    let status = std::process::Command::new( "kill" ).args( ["-INT", &std::process::id().to_string()] ).status().unwrap();
+    assert!( status.success() );
+
+    // THEN watching stops cleanly, with the catalog matching the folder and no sidecars written
+    assert_eq!( handle.join().unwrap(), Ok(()) );
+    let catalog = Catalog::load( &catalog_path ).unwrap();
+    let paths : Vec<PathBuf> = catalog.entries().map(|(path, _)| path.to_path_buf()).collect();
+    let absolute = fs::canonicalize( &folder ).unwrap();
+    assert_eq!( paths, vec![absolute.join("IMG_0002.jpg"), absolute.join("card").join("IMG_0003.jpg")] );
+    assert!( !folder.join("IMG_0002.json").exists() );
+
+    // AND the settle time must be a number of milliseconds, and only directories are watched
+    let config = |args: &[&str]| rusimeta::Config::new( ["rusimeta", "watch"].iter().chain( args.iter() ).map(|arg| arg.to_string()) );
+    assert!( config( &["--settle", "soon", "tethered"] ).is_err() );
+    assert!( config( &["--settle", "2000", "--catalog", "catalog.json", "tethered"] ).is_ok() );
+    let file = dir.join("catalog.json");
+    let result = rusimeta::watch_folders( &[file], &Watch::default(), &ReadOptions::default(), &Redaction::default(), &AtomicBool::new( false ), &mut |_| {} );
+    assert!( result.unwrap_err().to_string().contains( "Only directories can be watched" ) );
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::fs;
+use serial_test::serial;
+
+use rusimeta::{Command, FieldChange, ImageMetadataPatch, Orientation, WriteOptions};
+use common::{build_jpeg, build_with_maker_note, canon_exif, scratch_dir, ExifBuilder, TiffValue, FAKE_JPEG_IMAGE_DATA};
+
+fn capture_time( text: &str ) -> chrono::NaiveDateTime {
+    chrono::NaiveDateTime::parse_from_str( text, "%Y:%m:%d %H:%M:%S" ).unwrap()
+}
+
+#[test]
+#[serial]
+fn jpeg_fields_are_rewritten_keeping_maker_note_thumbnail_and_image_data()
+{
+    // GIVEN a Canon JPEG with a MakerNote and a thumbnail
+    let dir = scratch_dir("writer_jpeg");
+    let path = dir.join("IMG_0401.jpg");
+    let mut exif = canon_exif();
+    exif.thumbnail = Some( build_jpeg( None, &[] ) );
+    let maker_note = vec![
+        (0x0007, TiffValue::Ascii("Firmware Version 1.0.4")),
+        (0x0096, TiffValue::Ascii("PE1234567")),
+    ];
+    let original = build_jpeg( Some(&build_with_maker_note( &exif, b"", &maker_note )), &[] );
+    fs::write( &path, &original ).unwrap();
+    let before = rusimeta::read_metadata_of_interest( &path ).unwrap();
+
+    // WHEN the capture time and orientation are fixed, and a copyright (a tag the file doesn't have yet) is added
+    let patch = ImageMetadataPatch {
+        orientation: Some(Orientation::QuarterRotationCW),
+        capture_time: Some(capture_time("2020:01:30 09:28:07")),
+        copyright: Some("Copyright 2020 Jane Doe".to_string()),
+        ..Default::default()
+    };
+    let changes = rusimeta::write_image_metadata( &path, &patch, &WriteOptions::default() ).unwrap();
+
+    // THEN the changes are reported and read back
+    assert_eq!( changes, vec![
+        FieldChange { field: "orientation", old_value: Some("1".to_string()), new_value: "8".to_string() },
+        FieldChange { field: "capture_time", old_value: Some("2019:07:26 13:25:33".to_string()), new_value: "2020:01:30 09:28:07".to_string() },
+        FieldChange { field: "copyright", old_value: None, new_value: "Copyright 2020 Jane Doe".to_string() },
+    ] );
+    let after = rusimeta::read_metadata_of_interest( &path ).unwrap();
+    assert_eq!( after.image_metadata.orientation, Some(Orientation::QuarterRotationCW) );
+    assert_eq!( after.image_metadata.capture_time, Some(capture_time("2020:01:30 09:28:07")) );
+    assert_eq!( after.image_metadata.camera_serial, before.image_metadata.camera_serial );
+
+    // AND the MakerNote still decodes, the thumbnail is unchanged and the image data is byte for byte the same
+    assert_eq!( after.vendor_metadata, before.vendor_metadata );
+    assert_eq!( after.vendor_metadata.unwrap().internal_serial, Some("PE1234567".to_string()) );
+    let written = fs::read( &path ).unwrap();
+    let thumbnail = after.thumbnail.unwrap();
+    let old_thumbnail = before.thumbnail.unwrap();
+    assert_eq!(
+        &written[thumbnail.byte_offset as usize..(thumbnail.byte_offset + thumbnail.byte_length) as usize],
+        &original[old_thumbnail.byte_offset as usize..(old_thumbnail.byte_offset + old_thumbnail.byte_length) as usize]
+    );
+    assert!( written.ends_with( FAKE_JPEG_IMAGE_DATA ) );
+}
+
+#[test]
+#[serial]
+fn set_command_dry_run_leaves_files_untouched_and_tiff_is_edited_in_place()
+{
+    // GIVEN a TIFF without an Exif IFD and a JPEG without any EXIF data
+    let dir = scratch_dir("writer_set_command");
+    let tiff_path = dir.join("scan.tif");
+    let tiff = ExifBuilder {
+        ifd0: vec![
+            (0x0100, TiffValue::Long(vec![64])),
+            (0x0101, TiffValue::Long(vec![48])),
+            (0x0110, TiffValue::Ascii("Scanner 1")),
+        ],
+        thumbnail: Some( build_jpeg( None, &[] ) ),
+        ..Default::default()
+    }.build();
+    fs::write( &tiff_path, &tiff ).unwrap();
+    let jpeg_path = dir.join("export.jpg");
+    let jpeg = build_jpeg( None, &[] );
+    fs::write( &jpeg_path, &jpeg ).unwrap();
+    let paths = vec![tiff_path.to_str().unwrap().to_string(), jpeg_path.to_str().unwrap().to_string()];
+    let patch = ImageMetadataPatch {
+        camera_serial: Some("SN-0042".to_string()),
+        camera_model: Some("Scanner 2".to_string()),
+        ..Default::default()
+    };
+
+    // WHEN the set command is run as a dry run, and then for real
+    let dry_run = rusimeta::run( rusimeta::Config::from_strings( paths.clone() )
+        .with_command( Command::Set { patch: patch.clone(), options: WriteOptions { dry_run: true } } ) ).unwrap();
+    let dry_run_tiff = fs::read( &tiff_path ).unwrap();
+    let dry_run_jpeg = fs::read( &jpeg_path ).unwrap();
+    let summary = rusimeta::run( rusimeta::Config::from_strings( paths )
+        .with_command( Command::Set { patch, options: WriteOptions::default() } ) ).unwrap();
+
+    // THEN the dry run changed nothing
+    assert_eq!( dry_run.read, 2 );
+    assert_eq!( dry_run_tiff, tiff );
+    assert_eq!( dry_run_jpeg, jpeg );
+
+    // AND the real run wrote the fields, creating the Exif IFD of the TIFF and the EXIF segment of the JPEG
+    assert_eq!( summary.read, 2 );
+    for path in [&tiff_path, &jpeg_path].iter() {
+        let metadata = rusimeta::read_metadata_of_interest( path ).unwrap();
+        assert_eq!( metadata.image_metadata.camera_serial, Some("SN-0042".to_string()) );
+        assert_eq!( metadata.image_metadata.camera_model, Some("Scanner 2".to_string()) );
+    }
+
+    // AND the TIFF was only appended to: everything before the old end of the file is where it was,
+    // apart from the pointers and values which were updated
+    let written = fs::read( &tiff_path ).unwrap();
+    assert!( written.len() > tiff.len() );
+    let thumbnail = build_jpeg( None, &[] );
+    assert!( written[..tiff.len()].ends_with( &thumbnail ) );
+}